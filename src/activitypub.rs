@@ -0,0 +1,150 @@
+use crate::config::AppConfig;
+use crate::index::index_title;
+use crate::manifest::Post;
+use crate::render::escape_html;
+use serde_json::{json, Value};
+use std::error::Error;
+
+/// Where WebFinger clients look up `acct:user@host`. Static hosting ignores
+/// the `?resource=` query, which is fine with a single account.
+pub const WEBFINGER_PATH: &str = ".well-known/webfinger";
+pub const ACTOR_PATH: &str = "activitypub/actor.json";
+pub const OUTBOX_PATH: &str = "activitypub/outbox.json";
+pub const FOLLOWERS_PATH: &str = "activitypub/followers.json";
+
+const CONTEXT: &str = "https://www.w3.org/ns/activitystreams";
+const PUBLIC: &str = "https://www.w3.org/ns/activitystreams#Public";
+
+/// A JSON document to upload.
+pub struct Document {
+    pub path: &'static str,
+    pub content_type: &'static str,
+    pub json: String,
+}
+
+impl Document {
+    fn new(
+        path: &'static str,
+        content_type: &'static str,
+        value: &Value,
+    ) -> Result<Self, Box<dyn Error>> {
+        Ok(Self {
+            path,
+            content_type,
+            json: serde_json::to_string_pretty(value)?,
+        })
+    }
+}
+
+/// The documents a fediverse server (or a bridge) reads to find the site's
+/// account and its posts: the WebFinger answer, the actor, its outbox with a
+/// `Create` for each of `posts`, and an empty followers collection.
+/// `site_url` is the bucket's public URL and `host` its domain.
+pub fn documents(
+    posts: &[&Post],
+    config: &AppConfig,
+    site_url: &str,
+    host: &str,
+) -> Result<Vec<Document>, Box<dyn Error>> {
+    let username = &config.activitypub.username;
+    let actor = format!("{}{}", site_url, ACTOR_PATH);
+    let outbox = format!("{}{}", site_url, OUTBOX_PATH);
+    let followers = format!("{}{}", site_url, FOLLOWERS_PATH);
+
+    let webfinger = json!({
+        "subject": format!("acct:{}@{}", username, host),
+        "aliases": [actor, site_url],
+        "links": [
+            {"rel": "self", "type": "application/activity+json", "href": actor},
+            {"rel": "http://webfinger.net/rel/profile-page", "type": "text/html", "href": site_url},
+        ],
+    });
+
+    let mut person = json!({
+        "@context": CONTEXT,
+        "id": actor,
+        "type": "Person",
+        "preferredUsername": username,
+        "name": index_title(config),
+        "url": site_url,
+        // Nothing can be delivered to a static site; a bridge follows the
+        // outbox instead.
+        "inbox": format!("{}activitypub/inbox", site_url),
+        "outbox": outbox,
+        "followers": followers,
+        "discoverable": true,
+    });
+    if let Some(summary) = &config.activitypub.summary {
+        person["summary"] = Value::from(format!("<p>{}</p>", escape_html(summary)));
+    }
+    if let Some(icon) = &config.activitypub.icon {
+        person["icon"] = json!({"type": "Image", "url": icon});
+    }
+
+    let activities: Vec<Value> = posts
+        .iter()
+        .map(|post| create_activity(post, &actor, &followers))
+        .collect();
+    let outbox_document = json!({
+        "@context": CONTEXT,
+        "id": outbox,
+        "type": "OrderedCollection",
+        "totalItems": activities.len(),
+        "orderedItems": activities,
+    });
+    let followers_document = json!({
+        "@context": CONTEXT,
+        "id": followers,
+        "type": "OrderedCollection",
+        "totalItems": 0,
+        "orderedItems": [],
+    });
+
+    Ok(vec![
+        Document::new(WEBFINGER_PATH, "application/jrd+json", &webfinger)?,
+        Document::new(ACTOR_PATH, "application/activity+json", &person)?,
+        Document::new(OUTBOX_PATH, "application/activity+json", &outbox_document)?,
+        Document::new(
+            FOLLOWERS_PATH,
+            "application/activity+json",
+            &followers_document,
+        )?,
+    ])
+}
+
+/// A post as an `Article`, wrapped in the `Create` that published it. The
+/// description goes in the content rather than `summary`, which Mastodon
+/// shows as a content warning.
+fn create_activity(post: &Post, actor: &str, followers: &str) -> Value {
+    let mut content = format!(
+        "<p><a href=\"{}\">{}</a></p>",
+        escape_html(&post.url),
+        escape_html(&post.title)
+    );
+    if let Some(description) = &post.description {
+        content.push_str(&format!("<p>{}</p>", escape_html(description)));
+    }
+    let mut article = json!({
+        "id": post.url,
+        "type": "Article",
+        "attributedTo": actor,
+        "name": post.title,
+        "content": content,
+        "url": post.url,
+        "published": post.published.to_rfc3339(),
+        "to": [PUBLIC],
+        "cc": [followers],
+    });
+    if let Some(updated) = post.updated {
+        article["updated"] = Value::from(updated.to_rfc3339());
+    }
+    json!({
+        "id": format!("{}#create", post.url),
+        "type": "Create",
+        "actor": actor,
+        "published": post.published.to_rfc3339(),
+        "to": [PUBLIC],
+        "cc": [followers],
+        "object": article,
+    })
+}
@@ -0,0 +1,125 @@
+use crate::config::{AnnounceConfig, BlueskyConfig, MastodonConfig};
+use serde::Deserialize;
+use serde_json::json;
+use std::error::Error;
+
+/// Fills in the `{title}` and `{url}` placeholders of the announcement template.
+pub fn format_message(template: &str, title: &str, url: &str) -> String {
+    template.replace("{title}", title).replace("{url}", url)
+}
+
+/// Posts the announcement to every configured service. Failures are reported
+/// but don't fail the publish, since the post itself is already live.
+pub async fn announce(
+    client: &reqwest::Client,
+    config: &AnnounceConfig,
+    title: &str,
+    url: &str,
+    dry_run: bool,
+) {
+    let message = format_message(&config.message, title, url);
+
+    if let Some(mastodon) = &config.mastodon {
+        if dry_run {
+            eprintln!(
+                "Would post to Mastodon ({}): {}",
+                mastodon.instance, message
+            );
+        } else {
+            match post_to_mastodon(client, mastodon, &message).await {
+                Ok(status_url) => eprintln!("Announced on Mastodon: {}", status_url),
+                Err(e) => eprintln!("Could not announce on Mastodon: {}", e),
+            }
+        }
+    }
+
+    if let Some(bluesky) = &config.bluesky {
+        if dry_run {
+            eprintln!("Would post to Bluesky ({}): {}", bluesky.handle, message);
+        } else {
+            match post_to_bluesky(client, bluesky, &message, url).await {
+                Ok(()) => eprintln!("Announced on Bluesky as {}", bluesky.handle),
+                Err(e) => eprintln!("Could not announce on Bluesky: {}", e),
+            }
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct MastodonStatus {
+    url: Option<String>,
+}
+
+async fn post_to_mastodon(
+    client: &reqwest::Client,
+    config: &MastodonConfig,
+    message: &str,
+) -> Result<String, Box<dyn Error>> {
+    let status: MastodonStatus = client
+        .post(format!(
+            "{}/api/v1/statuses",
+            config.instance.trim_end_matches('/')
+        ))
+        .bearer_auth(&config.access_token)
+        .json(&json!({ "status": message }))
+        .send()
+        .await?
+        .error_for_status()?
+        .json()
+        .await?;
+    Ok(status.url.unwrap_or_else(|| config.instance.clone()))
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct BlueskySession {
+    access_jwt: String,
+    did: String,
+}
+
+async fn post_to_bluesky(
+    client: &reqwest::Client,
+    config: &BlueskyConfig,
+    message: &str,
+    url: &str,
+) -> Result<(), Box<dyn Error>> {
+    let service = config.service.trim_end_matches('/');
+    let session: BlueskySession = client
+        .post(format!("{}/xrpc/com.atproto.server.createSession", service))
+        .json(&json!({
+            "identifier": config.handle,
+            "password": config.app_password,
+        }))
+        .send()
+        .await?
+        .error_for_status()?
+        .json()
+        .await?;
+
+    // Bluesky doesn't autolink, so the URL needs a link facet (byte offsets).
+    let facets = match message.find(url) {
+        Some(start) => json!([{
+            "index": { "byteStart": start, "byteEnd": start + url.len() },
+            "features": [{ "$type": "app.bsky.richtext.facet#link", "uri": url }],
+        }]),
+        None => json!([]),
+    };
+
+    client
+        .post(format!("{}/xrpc/com.atproto.repo.createRecord", service))
+        .bearer_auth(&session.access_jwt)
+        .json(&json!({
+            "repo": session.did,
+            "collection": "app.bsky.feed.post",
+            "record": {
+                "$type": "app.bsky.feed.post",
+                "text": message,
+                "facets": facets,
+                "createdAt": chrono::Utc::now().to_rfc3339(),
+            },
+        }))
+        .send()
+        .await?
+        .error_for_status()?;
+    Ok(())
+}
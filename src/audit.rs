@@ -0,0 +1,332 @@
+use crate::config::AppConfig;
+use crate::frontmatter::Labels;
+use crate::lock::LOCK_PATH;
+use crate::manifest::Manifest;
+use crate::publisher::{Precondition, Publisher};
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::{
+    error::Error,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+use tokio::{fs, io::AsyncWriteExt};
+
+/// Where entries go in the bucket with `[audit] remote`, one object each.
+pub const REMOTE_DIR: &str = "audit/";
+
+/// Something that removed or replaced objects in the bucket.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Operation {
+    Delete,
+    DeletePrefix,
+    Move,
+    Overwrite,
+}
+
+/// One line of the audit log.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Entry {
+    pub time: DateTime<Utc>,
+    /// `[team] author`, or the login, at the machine's hostname.
+    pub user: String,
+    /// The klistra command line that did it.
+    pub command: String,
+    pub operation: Operation,
+    /// Every object affected, before a move.
+    pub keys: Vec<String>,
+    /// Where a move put them.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub moved_to: Option<String>,
+    /// Size of what replaced an overwritten object, when known.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub size: Option<u64>,
+}
+
+/// The audit log next to the manifest at `manifest_path`: `audit.jsonl` for
+/// `manifest.json`, `audit-memory.jsonl` for the memory backend's scratch
+/// manifest.
+pub fn path_for(manifest_path: &Path) -> PathBuf {
+    let stem = manifest_path
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().replacen("manifest", "audit", 1))
+        .unwrap_or_else(|| "audit".to_string());
+    manifest_path.with_file_name(format!("{}.jsonl", stem))
+}
+
+/// Who to credit entries to: `user@host`.
+fn user(config: &AppConfig) -> String {
+    let name = config
+        .team
+        .author
+        .clone()
+        .or_else(|| std::env::var("USER").ok())
+        .or_else(|| std::env::var("USERNAME").ok())
+        .unwrap_or_else(|| "unknown".to_string());
+    let host = std::fs::read_to_string("/etc/hostname")
+        .ok()
+        .map(|host| host.trim().to_string())
+        .filter(|host| !host.is_empty())
+        .or_else(|| std::env::var("HOSTNAME").ok())
+        .or_else(|| std::env::var("COMPUTERNAME").ok());
+    match host {
+        Some(host) => format!("{}@{}", name, host),
+        None => name,
+    }
+}
+
+/// Adds `entry` to the end of the log at `path`. Earlier lines are never
+/// rewritten.
+async fn append(path: &Path, entry: &Entry) -> Result<(), Box<dyn Error>> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).await?;
+    }
+    let mut line = serde_json::to_string(entry)?;
+    line.push('\n');
+    let mut file = fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .await?;
+    file.write_all(line.as_bytes()).await?;
+    // Tokio finishes the write in the background otherwise.
+    file.flush().await?;
+    Ok(())
+}
+
+/// A [`Publisher`] that logs every delete, move and overwrite that goes
+/// through it as soon as it succeeds. Failing to log is only reported, so
+/// publishing never stops for the log.
+pub struct Auditor {
+    inner: Box<dyn Publisher>,
+    path: PathBuf,
+    remote: bool,
+    user: String,
+    command: String,
+}
+
+impl Auditor {
+    /// Logs to `[audit] path`, or [`path_for`] the configured manifest.
+    pub fn new(inner: Box<dyn Publisher>, config: &AppConfig) -> Result<Self, Box<dyn Error>> {
+        let path = match &config.audit.path {
+            Some(path) => path.clone(),
+            None => path_for(&Manifest::path_for(config)?),
+        };
+        Ok(Self {
+            inner,
+            path,
+            remote: config.audit.remote,
+            user: user(config),
+            command: std::env::args().collect::<Vec<_>>().join(" "),
+        })
+    }
+
+    /// Whether an object is stored at exactly `path`. The lock object comes
+    /// and goes with every index rebuild, so it isn't worth a line.
+    async fn exists(&self, path: &str) -> bool {
+        if path == LOCK_PATH || path.starts_with(REMOTE_DIR) {
+            return false;
+        }
+        let found = self.inner.list(path).await;
+        found.is_ok_and(|paths| paths.iter().any(|found| found == path))
+    }
+
+    async fn record(
+        &self,
+        operation: Operation,
+        keys: Vec<String>,
+        moved_to: Option<&str>,
+        size: Option<u64>,
+    ) {
+        let keys: Vec<String> = keys.into_iter().filter(|key| key != LOCK_PATH).collect();
+        if keys.is_empty() {
+            return;
+        }
+        let entry = Entry {
+            time: Utc::now(),
+            user: self.user.clone(),
+            command: self.command.clone(),
+            operation,
+            keys,
+            moved_to: moved_to.map(str::to_string),
+            size,
+        };
+        if let Err(e) = append(&self.path, &entry).await {
+            eprintln!("Could not write to {}: {}", self.path.display(), e);
+        }
+        if self.remote {
+            let key = format!(
+                "{}{}-{}.json",
+                REMOTE_DIR,
+                entry.time.format("%Y%m%dT%H%M%S%.3fZ"),
+                uuid::Uuid::new_v4()
+            );
+            let body = match serde_json::to_vec_pretty(&entry) {
+                Ok(body) => body,
+                Err(e) => {
+                    eprintln!("Could not write {}: {}", key, e);
+                    return;
+                }
+            };
+            let uploaded = self
+                .inner
+                .put(&key, body, "application/json", &Labels::new())
+                .await
+                .map_err(|e| e.to_string());
+            if let Err(e) = uploaded {
+                eprintln!("Could not upload {}: {}", key, e);
+            }
+        }
+    }
+
+    async fn record_overwrite(&self, path: &str, replaced: bool, size: Option<u64>) {
+        if replaced {
+            self.record(Operation::Overwrite, vec![path.to_string()], None, size)
+                .await;
+        }
+    }
+}
+
+#[async_trait]
+impl Publisher for Auditor {
+    fn host(&self) -> &str {
+        self.inner.host()
+    }
+
+    fn public_url(&self, path: &str) -> String {
+        self.inner.public_url(path)
+    }
+
+    async fn put(
+        &self,
+        path: &str,
+        body: Vec<u8>,
+        content_type: &str,
+        labels: &Labels,
+    ) -> Result<(), Box<dyn Error>> {
+        let size = body.len() as u64;
+        let replaced = self.exists(path).await;
+        self.inner.put(path, body, content_type, labels).await?;
+        self.record_overwrite(path, replaced, Some(size)).await;
+        Ok(())
+    }
+
+    async fn put_download(
+        &self,
+        path: &str,
+        body: Vec<u8>,
+        content_type: &str,
+        file_name: &str,
+        labels: &Labels,
+    ) -> Result<(), Box<dyn Error>> {
+        let size = body.len() as u64;
+        let replaced = self.exists(path).await;
+        self.inner
+            .put_download(path, body, content_type, file_name, labels)
+            .await?;
+        self.record_overwrite(path, replaced, Some(size)).await;
+        Ok(())
+    }
+
+    async fn put_redirect(&self, path: &str, location: &str) -> Result<(), Box<dyn Error>> {
+        let replaced = self.exists(path).await;
+        self.inner.put_redirect(path, location).await?;
+        self.record_overwrite(path, replaced, Some(0)).await;
+        Ok(())
+    }
+
+    async fn delete(&self, path: &str) -> Result<(), Box<dyn Error>> {
+        self.inner.delete(path).await?;
+        self.record(Operation::Delete, vec![path.to_string()], None, None)
+            .await;
+        Ok(())
+    }
+
+    async fn list(&self, path: &str) -> Result<Vec<String>, Box<dyn Error>> {
+        self.inner.list(path).await
+    }
+
+    async fn fetch(&self, path: &str) -> Result<Option<Vec<u8>>, Box<dyn Error>> {
+        self.inner.fetch(path).await
+    }
+
+    async fn fetch_tagged(&self, path: &str) -> Result<Option<(Vec<u8>, String)>, Box<dyn Error>> {
+        self.inner.fetch_tagged(path).await
+    }
+
+    async fn put_if(
+        &self,
+        path: &str,
+        body: Vec<u8>,
+        content_type: &str,
+        precondition: &Precondition,
+    ) -> Result<bool, Box<dyn Error>> {
+        let size = body.len() as u64;
+        let stored = self
+            .inner
+            .put_if(path, body, content_type, precondition)
+            .await?;
+        let replaced = stored && matches!(precondition, Precondition::Matches(_));
+        self.record_overwrite(path, replaced, Some(size)).await;
+        Ok(stored)
+    }
+
+    async fn presign(&self, path: &str, expires: Duration) -> Result<String, Box<dyn Error>> {
+        self.inner.presign(path, expires).await
+    }
+
+    async fn delete_prefix(&self, path: &str) -> Result<usize, Box<dyn Error>> {
+        let keys = self.inner.list(path).await?;
+        let removed = self.inner.delete_prefix(path).await?;
+        self.record(Operation::DeletePrefix, keys, None, None).await;
+        Ok(removed)
+    }
+
+    async fn move_prefix(&self, from: &str, to: &str) -> Result<usize, Box<dyn Error>> {
+        let keys = self.inner.list(from).await?;
+        let moved = self.inner.move_prefix(from, to).await?;
+        self.record(Operation::Move, keys, Some(to), None).await;
+        Ok(moved)
+    }
+
+    async fn create_multipart(
+        &self,
+        path: &str,
+        content_type: &str,
+        labels: &Labels,
+    ) -> Result<String, Box<dyn Error>> {
+        self.inner
+            .create_multipart(path, content_type, labels)
+            .await
+    }
+
+    async fn upload_part(
+        &self,
+        path: &str,
+        upload_id: &str,
+        number: i32,
+        body: Vec<u8>,
+    ) -> Result<String, Box<dyn Error>> {
+        self.inner.upload_part(path, upload_id, number, body).await
+    }
+
+    async fn abort_multipart(&self, path: &str, upload_id: &str) -> Result<(), Box<dyn Error>> {
+        self.inner.abort_multipart(path, upload_id).await
+    }
+
+    async fn complete_multipart(
+        &self,
+        path: &str,
+        upload_id: &str,
+        parts: &[(i32, String)],
+    ) -> Result<(), Box<dyn Error>> {
+        let replaced = self.exists(path).await;
+        self.inner
+            .complete_multipart(path, upload_id, parts)
+            .await?;
+        self.record_overwrite(path, replaced, None).await;
+        Ok(())
+    }
+}
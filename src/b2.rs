@@ -0,0 +1,192 @@
+use crate::config::{S3Config, TlsConfig};
+use crate::http;
+use aws_credential_types::provider::{error::CredentialsError, future, ProvideCredentials};
+use aws_sdk_s3::config::Credentials;
+use serde::Deserialize;
+use serde_json::json;
+use std::{
+    error::Error,
+    time::{Duration, SystemTime},
+};
+
+/// What a scoped key may do: everything publishing needs, in one bucket.
+const CAPABILITIES: [&str; 6] = [
+    "listBuckets",
+    "listFiles",
+    "readFiles",
+    "shareFiles",
+    "writeFiles",
+    "deleteFiles",
+];
+
+/// Credentials for a B2 application key limited to the configured bucket and
+/// prefix, created with the configured key when first needed and again
+/// whenever the last one runs out.
+#[derive(Debug)]
+pub struct ScopedKey {
+    http: reqwest::Client,
+    api_url: String,
+    key_id: String,
+    key: String,
+    bucket: String,
+    prefix: String,
+    ttl: Duration,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Authorization {
+    account_id: String,
+    authorization_token: String,
+    api_info: ApiInfo,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ApiInfo {
+    storage_api: StorageApi,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct StorageApi {
+    api_url: String,
+}
+
+#[derive(Deserialize)]
+struct Buckets {
+    buckets: Vec<BucketInfo>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct BucketInfo {
+    bucket_id: String,
+    bucket_name: String,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct CreatedKey {
+    application_key_id: String,
+    application_key: String,
+}
+
+impl ScopedKey {
+    pub fn new(config: &S3Config, tls: &TlsConfig) -> Result<Self, Box<dyn Error>> {
+        let prefix = config.prefix.trim_matches('/');
+        Ok(Self {
+            http: http::client(tls)?,
+            api_url: config.b2_api_url.trim_end_matches('/').to_string(),
+            key_id: config.access_key_id.clone(),
+            key: config.secret_access_key.clone(),
+            bucket: config.bucket.clone(),
+            prefix: if prefix.is_empty() {
+                String::new()
+            } else {
+                format!("{}/", prefix)
+            },
+            ttl: Duration::from_secs(config.scoped_key_ttl.max(1)),
+        })
+    }
+
+    /// Creates a new scoped key with the B2 native API.
+    async fn create(&self) -> Result<Credentials, Box<dyn Error + Send + Sync>> {
+        let auth: Authorization = self
+            .http
+            .get(format!("{}/b2api/v3/b2_authorize_account", self.api_url))
+            .basic_auth(&self.key_id, Some(&self.key))
+            .send()
+            .await?
+            .error_for_status()
+            .map_err(|e| format!("B2 rejected the key in [s3]: {}", e))?
+            .json()
+            .await?;
+        let api_url = auth.api_info.storage_api.api_url.trim_end_matches('/');
+
+        let buckets: Buckets = self
+            .call(
+                &auth,
+                api_url,
+                "b2_list_buckets",
+                json!({ "accountId": auth.account_id, "bucketName": self.bucket }),
+            )
+            .await?;
+        let bucket = buckets
+            .buckets
+            .into_iter()
+            .find(|bucket| bucket.bucket_name == self.bucket)
+            .ok_or_else(|| format!("No B2 bucket named '{}'", self.bucket))?;
+
+        let name = format!(
+            "klistra-{}",
+            SystemTime::now()
+                .duration_since(SystemTime::UNIX_EPOCH)
+                .map_or(0, |since| since.as_secs())
+        );
+        let mut request = json!({
+            "accountId": auth.account_id,
+            "capabilities": CAPABILITIES,
+            "keyName": name,
+            "validDurationInSeconds": self.ttl.as_secs(),
+            "bucketId": bucket.bucket_id,
+        });
+        if !self.prefix.is_empty() {
+            request["namePrefix"] = self.prefix.clone().into();
+        }
+        let created: CreatedKey = self
+            .call(&auth, api_url, "b2_create_key", request)
+            .await
+            .map_err(|e| {
+                format!(
+                    "Could not create a scoped key (does the key have writeKeys?): {}",
+                    e
+                )
+            })?;
+        // Renewed a little early, so a request never starts with a key that
+        // runs out on the way.
+        let expires = SystemTime::now() + self.ttl.mul_f64(0.9);
+        Ok(Credentials::new(
+            created.application_key_id,
+            created.application_key,
+            None,
+            Some(expires),
+            "b2-scoped-key",
+        ))
+    }
+
+    async fn call<T: serde::de::DeserializeOwned>(
+        &self,
+        auth: &Authorization,
+        api_url: &str,
+        operation: &str,
+        body: serde_json::Value,
+    ) -> Result<T, Box<dyn Error + Send + Sync>> {
+        let response = self
+            .http
+            .post(format!("{}/b2api/v3/{}", api_url, operation))
+            .header("Authorization", &auth.authorization_token)
+            .json(&body)
+            .send()
+            .await?;
+        let status = response.status();
+        if !status.is_success() {
+            let body = response.text().await.unwrap_or_default();
+            return Err(format!("{} failed with {}: {}", operation, status, body.trim()).into());
+        }
+        Ok(response.json().await?)
+    }
+}
+
+impl ProvideCredentials for ScopedKey {
+    fn provide_credentials<'a>(&'a self) -> future::ProvideCredentials<'a>
+    where
+        Self: 'a,
+    {
+        future::ProvideCredentials::new(async move {
+            self.create()
+                .await
+                .map_err(CredentialsError::provider_error)
+        })
+    }
+}
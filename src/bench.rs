@@ -0,0 +1,165 @@
+use crate::config::{AppConfig, MarkdownBackend};
+use crate::formats::{self, InputFormat};
+use crate::frontmatter::{Dir, FrontMatter, Labels};
+use crate::publisher;
+use crate::quick;
+use crate::render::{markdown_options, markdown_to_html, Page};
+use crate::throttle;
+use aws_sdk_s3::primitives::ByteStream;
+use pulldown_cmark::Parser;
+use std::{
+    error::Error,
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
+use tokio::fs;
+
+/// Stages of publishing a post, timed separately.
+const STAGES: [&str; 4] = ["parse", "render", "template", "upload"];
+
+/// Times each stage of publishing the notes at `target` (a file or a
+/// directory) over `iterations` runs and prints percentiles per stage.
+///
+/// Parsing covers the front matter and a full pass over the markdown events,
+/// rendering the backend or converter, and templating wrapping the body in
+/// the page. Uploads go to `bench/` in the bucket (removed afterwards) with
+/// `upload`; otherwise the body is only read through the configured rate
+/// limit, which shows what throttling costs without touching the network.
+pub async fn run(
+    config: &AppConfig,
+    target: &Path,
+    iterations: u32,
+    upload: bool,
+) -> Result<(), Box<dyn Error>> {
+    let files: Vec<PathBuf> = if fs::metadata(target).await?.is_dir() {
+        let mut files: Vec<PathBuf> = quick::note_files(target)
+            .await?
+            .into_iter()
+            .map(|(path, _)| path)
+            .collect();
+        files.sort();
+        files
+    } else {
+        vec![target.to_path_buf()]
+    };
+    if files.is_empty() {
+        return Err(format!("No notes found in {}", target.display()).into());
+    }
+
+    let bucket = if upload {
+        Some(publisher::from_config(config)?)
+    } else {
+        None
+    };
+
+    let mut timings: [Vec<Duration>; 4] = Default::default();
+    let mut bytes = 0;
+    for iteration in 0..iterations {
+        for (index, path) in files.iter().enumerate() {
+            let source = formats::read_source(path)
+                .await
+                .map_err(|e| format!("Could not read {}: {}", path.display(), e))?;
+            let format = InputFormat::from_path(path);
+
+            let start = Instant::now();
+            let (_, body) = FrontMatter::parse(&source)?;
+            if format == InputFormat::Markdown {
+                Parser::new_ext(body, markdown_options()).for_each(drop);
+            }
+            timings[0].push(start.elapsed());
+
+            let start = Instant::now();
+            let html = match format {
+                InputFormat::Markdown => markdown_to_html(body, false, config.page.parser)?,
+                format => formats::convert(format, body)?.html,
+            };
+            timings[1].push(start.elapsed());
+
+            let start = Instant::now();
+            let page = Page {
+                title: "Benchmark",
+                heading: Some("Benchmark"),
+                description: None,
+                lang: &config.page.lang,
+                dir: Dir::from_lang(&config.page.lang),
+                date: "",
+                body: &html,
+                alternates: &[],
+                image_url: None,
+                site: &config.site,
+                authors: &[],
+                source_url: None,
+            }
+            .render();
+            timings[2].push(start.elapsed());
+
+            if iteration == 0 {
+                bytes += page.len();
+            }
+            let start = Instant::now();
+            match &bucket {
+                Some(bucket) => {
+                    bucket
+                        .put_html(&format!("bench/{}.html", index), page, &Labels::new())
+                        .await?
+                }
+                None => {
+                    let stream = match config.upload.limit_rate {
+                        Some(rate) => throttle::throttled(page.into_bytes(), rate),
+                        None => ByteStream::from(page.into_bytes()),
+                    };
+                    stream.collect().await?;
+                }
+            }
+            timings[3].push(start.elapsed());
+        }
+    }
+
+    if let Some(bucket) = &bucket {
+        bucket.delete_prefix("bench/").await?;
+    }
+
+    println!(
+        "{} file(s), {} KB of HTML, {} iteration(s), {} parser{}",
+        files.len(),
+        bytes / 1024,
+        iterations,
+        match config.page.parser {
+            MarkdownBackend::Pulldown => "pulldown",
+            MarkdownBackend::Comrak => "comrak",
+            MarkdownBackend::Pandoc => "pandoc",
+        },
+        if upload { "" } else { ", mocked upload" }
+    );
+    println!(
+        "{:<10}{:>10}{:>10}{:>10}{:>10}{:>12}",
+        "stage", "p50", "p90", "p99", "max", "total"
+    );
+    for (stage, mut samples) in STAGES.into_iter().zip(timings) {
+        samples.sort();
+        let total: Duration = samples.iter().sum();
+        println!(
+            "{:<10}{:>10}{:>10}{:>10}{:>10}{:>12}",
+            stage,
+            millis(percentile(&samples, 50)),
+            millis(percentile(&samples, 90)),
+            millis(percentile(&samples, 99)),
+            millis(samples.last().copied().unwrap_or_default()),
+            millis(total),
+        );
+    }
+    Ok(())
+}
+
+/// The `p`th percentile of sorted samples, by nearest rank.
+fn percentile(sorted: &[Duration], p: usize) -> Duration {
+    if sorted.is_empty() {
+        return Duration::ZERO;
+    }
+    let rank = (p * sorted.len()).div_ceil(100).max(1);
+    sorted[rank - 1]
+}
+
+fn millis(duration: Duration) -> String {
+    format!("{:.2}ms", duration.as_secs_f64() * 1000.0)
+}
@@ -0,0 +1,325 @@
+use crate::config::{AppConfig, TelegramConfig};
+use crate::manifest::Manifest;
+use crate::shutdown;
+use crate::site::{Announce, Published, Site};
+use serde::{de::DeserializeOwned, Deserialize};
+use serde_json::{json, Value};
+use std::{error::Error, path::Path, str::FromStr, time::Duration};
+use tokio::fs;
+use tracing::{error, info, warn};
+
+/// Seconds Telegram holds a `getUpdates` request open waiting for messages.
+const POLL_TIMEOUT: u64 = 50;
+
+/// Chat services `klistra bot` can run on.
+#[derive(Debug, Clone, Copy)]
+pub enum Platform {
+    Telegram,
+}
+
+impl FromStr for Platform {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "telegram" => Ok(Platform::Telegram),
+            other => Err(format!("Unknown bot '{}', expected telegram", other)),
+        }
+    }
+}
+
+/// Runs the bot until interrupted: text sent to it is published as a post,
+/// files as raw shares, and each message is answered with the URL.
+pub async fn run(app_config: &AppConfig, platform: Platform) -> Result<(), Box<dyn Error>> {
+    match platform {
+        Platform::Telegram => telegram(app_config).await,
+    }
+}
+
+#[derive(Deserialize)]
+struct Response<T> {
+    ok: bool,
+    result: Option<T>,
+    description: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct Update {
+    update_id: i64,
+    message: Option<Message>,
+}
+
+/// A Telegram message, with only the parts the bot looks at.
+#[derive(Debug, Deserialize)]
+pub struct Message {
+    pub message_id: i64,
+    pub from: Option<User>,
+    pub chat: Chat,
+    pub text: Option<String>,
+    pub document: Option<Attachment>,
+    #[serde(default)]
+    pub photo: Vec<Attachment>,
+    pub video: Option<Attachment>,
+    pub audio: Option<Attachment>,
+    pub voice: Option<Attachment>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct User {
+    pub id: i64,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Chat {
+    pub id: i64,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Attachment {
+    pub file_id: String,
+    pub file_name: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct File {
+    file_path: Option<String>,
+}
+
+impl Message {
+    /// The file sent with the message and the name to share it under.
+    fn attachment(&self) -> Option<(&Attachment, &str)> {
+        if let Some(document) = &self.document {
+            return Some((document, "file"));
+        }
+        // Telegram sends each photo in several sizes, smallest first.
+        if let Some(photo) = self.photo.last() {
+            return Some((photo, "photo.jpg"));
+        }
+        [
+            (&self.video, "video.mp4"),
+            (&self.audio, "audio.mp3"),
+            (&self.voice, "voice.ogg"),
+        ]
+        .into_iter()
+        .find_map(|(attachment, name)| attachment.as_ref().map(|a| (a, name)))
+    }
+}
+
+/// The Bot API for one bot.
+struct Telegram<'a> {
+    http: &'a reqwest::Client,
+    config: &'a TelegramConfig,
+}
+
+impl Telegram<'_> {
+    async fn call<T: DeserializeOwned>(
+        &self,
+        method: &str,
+        params: Value,
+    ) -> Result<T, Box<dyn Error>> {
+        let url = format!(
+            "{}/bot{}/{}",
+            self.config.api_url, self.config.token, method
+        );
+        let response: Response<T> = self
+            .http
+            .post(url)
+            .json(&params)
+            .send()
+            .await
+            .map_err(|e| format!("Telegram {} failed: {}", method, e.without_url()))?
+            .json()
+            .await?;
+        match response.result {
+            Some(result) if response.ok => Ok(result),
+            _ => Err(format!(
+                "Telegram {} failed: {}",
+                method,
+                response.description.unwrap_or_default()
+            )
+            .into()),
+        }
+    }
+
+    async fn download(&self, file_id: &str) -> Result<Vec<u8>, Box<dyn Error>> {
+        let file: File = self.call("getFile", json!({ "file_id": file_id })).await?;
+        let path = file.file_path.ok_or("Telegram has no copy of the file")?;
+        let url = format!(
+            "{}/file/bot{}/{}",
+            self.config.api_url, self.config.token, path
+        );
+        let response = self
+            .http
+            .get(url)
+            .send()
+            .await
+            .and_then(|response| response.error_for_status())
+            .map_err(|e| format!("Could not download the file: {}", e.without_url()))?;
+        Ok(response.bytes().await?.to_vec())
+    }
+}
+
+async fn telegram(app_config: &AppConfig) -> Result<(), Box<dyn Error>> {
+    let config = app_config
+        .telegram
+        .as_ref()
+        .ok_or("bot telegram requires a [telegram] section in the config")?;
+    let site = Site::new(app_config)?;
+    let manifest_path = Manifest::path_for(app_config)?;
+    let api = Telegram {
+        http: site.http(),
+        config,
+    };
+    shutdown::listen();
+
+    info!("Telegram bot running, send it something to publish");
+    let mut offset = 0;
+    loop {
+        let params = json!({
+            "offset": offset,
+            "timeout": POLL_TIMEOUT,
+            "allowed_updates": ["message"],
+        });
+        let updates = tokio::select! {
+            updates = api.call::<Vec<Update>>("getUpdates", params) => updates,
+            _ = shutdown::wait() => break,
+        };
+        let updates = match updates {
+            Ok(updates) => updates,
+            Err(e) => {
+                error!(error = %e, "Could not get updates");
+                tokio::time::sleep(Duration::from_secs(5)).await;
+                continue;
+            }
+        };
+        for update in updates {
+            // Later updates are fetched again on the next start.
+            if shutdown::requested() {
+                break;
+            }
+            offset = update.update_id + 1;
+            let Some(message) = update.message else {
+                continue;
+            };
+            let reply = handle(&api, &site, &message, &manifest_path).await;
+            let sent = api
+                .call::<Value>(
+                    "sendMessage",
+                    json!({
+                        "chat_id": message.chat.id,
+                        "text": reply,
+                        "reply_parameters": { "message_id": message.message_id },
+                    }),
+                )
+                .await;
+            if let Err(e) = sent {
+                warn!(error = %e, "Could not reply");
+            }
+        }
+        if shutdown::requested() {
+            break;
+        }
+    }
+
+    // Tells Telegram what was handled, or it would all come again next time.
+    let confirmed = api
+        .call::<Vec<Update>>(
+            "getUpdates",
+            json!({ "offset": offset, "timeout": 0, "limit": 1 }),
+        )
+        .await;
+    if let Err(e) = confirmed {
+        warn!(error = %e, "Could not confirm the handled messages");
+    }
+    info!("Telegram bot stopped");
+    Ok(())
+}
+
+/// Publishes one message, returning what to answer with.
+async fn handle(
+    api: &Telegram<'_>,
+    site: &Site<'_>,
+    message: &Message,
+    manifest_path: &Path,
+) -> String {
+    let user = message.from.as_ref().map_or(0, |from| from.id);
+    if !api.config.allowed_users.contains(&user) {
+        return format!(
+            "You can't publish here. Your Telegram user ID is {}; add it to allowed_users in [telegram] if this is your bot.",
+            user
+        );
+    }
+
+    let result = async {
+        let mut manifest = Manifest::load(manifest_path).await?;
+        let file = match message.attachment() {
+            Some((attachment, default_name)) => {
+                let bytes = api.download(&attachment.file_id).await?;
+                Some((
+                    bytes,
+                    attachment.file_name.as_deref().unwrap_or(default_name),
+                ))
+            }
+            None => None,
+        };
+        // Raw shares aren't announced, just like `klistra raw`.
+        let announce = if file.is_some() {
+            Announce::No
+        } else {
+            Announce::Yes
+        };
+        let published = publish_message(site, api.config, message, file, &mut manifest).await?;
+        site.finish_publish(&published, &mut manifest, manifest_path, announce)
+            .await?;
+        Ok::<_, Box<dyn Error>>(published.post.url)
+    }
+    .await;
+    match result {
+        Ok(url) => {
+            info!(url = %url, "Published via Telegram");
+            url
+        }
+        Err(e) => {
+            error!(error = %e, "Telegram publish failed");
+            format!("Could not publish: {}", e)
+        }
+    }
+}
+
+/// Publishes `message` from an allowed user: its `file` (the downloaded
+/// bytes and the file name) as a raw share, or else its text as a post.
+pub async fn publish_message(
+    site: &Site<'_>,
+    config: &TelegramConfig,
+    message: &Message,
+    file: Option<(Vec<u8>, &str)>,
+    manifest: &mut Manifest,
+) -> Result<Published, Box<dyn Error>> {
+    let user = message.from.as_ref().map_or(0, |from| from.id);
+    if !config.allowed_users.contains(&user) {
+        return Err(format!("Telegram user {} may not publish", user).into());
+    }
+
+    let Some((bytes, name)) = file else {
+        let text = message.text.as_deref().unwrap_or_default();
+        if text.trim().is_empty() {
+            return Err("Send some text or a file".into());
+        }
+        return site.publish_markdown(text, "Note", None, manifest).await;
+    };
+    // Only the name, wherever the sender's client had the file.
+    let name = Path::new(name)
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .filter(|name| !name.starts_with('.'))
+        .unwrap_or_else(|| "file".to_string());
+    let dir = std::env::temp_dir()
+        .join("klistra-telegram")
+        .join(uuid::Uuid::new_v4().to_string());
+    fs::create_dir_all(&dir).await?;
+    let path = dir.join(name);
+    fs::write(&path, bytes).await?;
+    let published = site.publish_raw(&[path], false, manifest).await;
+    let _ = fs::remove_dir_all(&dir).await;
+    published
+}
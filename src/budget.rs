@@ -0,0 +1,116 @@
+use crate::config::UploadConfig;
+use crate::raw::format_size;
+use std::{error::Error, fmt, str::FromStr};
+
+/// A number of bytes, written like `2GB`, `500KB` or `1048576`. Units are
+/// powers of 1024.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, serde::Deserialize)]
+#[serde(try_from = "String")]
+pub struct Size(pub u64);
+
+impl FromStr for Size {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let trimmed = s.trim();
+        let split = trimmed
+            .find(|c: char| !(c.is_ascii_digit() || c == '.'))
+            .unwrap_or(trimmed.len());
+        let (number, unit) = trimmed.split_at(split);
+        let multiplier: u64 = match unit.trim().to_ascii_uppercase().as_str() {
+            "" | "B" => 1,
+            "K" | "KB" => 1024,
+            "M" | "MB" => 1024 * 1024,
+            "G" | "GB" => 1024 * 1024 * 1024,
+            "T" | "TB" => 1024 * 1024 * 1024 * 1024,
+            _ => return Err(format!("Unknown unit in size '{}'", s)),
+        };
+        let number: f64 = number
+            .parse()
+            .map_err(|_| format!("Invalid size '{}', expected e.g. 100MB", s))?;
+        Ok(Size((number * multiplier as f64) as u64))
+    }
+}
+
+impl TryFrom<String> for Size {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+impl fmt::Display for Size {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&format_size(self.0))
+    }
+}
+
+/// What publishing a post is about to upload.
+pub struct Upload<'a> {
+    /// The post's title, for messages.
+    pub post: &'a str,
+    /// Bytes of pages and other generated files.
+    pub pages: u64,
+    /// Files the post uploads from disk, by name, with their sizes.
+    pub assets: Vec<(String, u64)>,
+}
+
+impl Upload<'_> {
+    pub fn bytes(&self) -> u64 {
+        self.pages + self.assets.iter().map(|(_, size)| size).sum::<u64>()
+    }
+}
+
+/// Checks `upload` against the `[upload]` limits before anything goes up.
+/// `site` is what the other posts in the manifest take up. Going over a
+/// limit is an error, or only a warning with `--force`.
+pub fn check(config: &UploadConfig, upload: &Upload, site: u64) -> Result<(), Box<dyn Error>> {
+    let mut over = Vec::new();
+    if let Some(limit) = config.max_asset_bytes {
+        for (name, size) in &upload.assets {
+            if *size > limit.0 {
+                over.push(format!(
+                    "{} is {}, over max_asset_bytes ({})",
+                    name,
+                    format_size(*size),
+                    limit
+                ));
+            }
+        }
+    }
+    let bytes = upload.bytes();
+    if let Some(limit) = config.max_post_bytes {
+        if bytes > limit.0 {
+            over.push(format!(
+                "the post is {}, over max_post_bytes ({})",
+                format_size(bytes),
+                limit
+            ));
+        }
+    }
+    if let Some(limit) = config.max_total_bytes {
+        if site + bytes > limit.0 {
+            over.push(format!(
+                "the site would take up {}, over max_total_bytes ({})",
+                format_size(site + bytes),
+                limit
+            ));
+        }
+    }
+    if over.is_empty() {
+        return Ok(());
+    }
+    if config.force {
+        for problem in &over {
+            eprintln!("Warning: {}: {}", upload.post, problem);
+        }
+        return Ok(());
+    }
+    Err(format!(
+        "Not publishing {}: {}; raise the [upload] limit or use --force",
+        upload.post,
+        over.join("; ")
+    )
+    .into())
+}
@@ -0,0 +1,180 @@
+use crate::render::{escape_html, markdown_options};
+use pulldown_cmark::{CodeBlockKind, Event, Parser as MarkdownParser, Tag, TagEnd};
+use std::{borrow::Cow, error::Error, ops::RangeInclusive};
+
+/// What the `{...}` after a fence's language asks for.
+#[derive(Debug, Default, PartialEq, Eq)]
+struct CodeOptions {
+    linenos: bool,
+    /// Number of the first line, if not 1.
+    linenostart: Option<usize>,
+    hl_lines: Vec<RangeInclusive<usize>>,
+}
+
+impl CodeOptions {
+    /// Parses `linenos, linenostart=10, hl_lines=[3, 5-7]`. Line lists may
+    /// also be quoted or space-separated, as in `hl_lines="3 5-7"`.
+    fn parse(options: &str) -> Result<Self, Box<dyn Error>> {
+        let mut parsed = Self::default();
+        for option in split_options(options) {
+            let (key, value) = match option.split_once('=') {
+                Some((key, value)) => (key.trim(), Some(value.trim())),
+                None => (option, None),
+            };
+            match (key, value) {
+                ("linenos", None) => parsed.linenos = true,
+                ("linenostart", Some(value)) => {
+                    let start = value
+                        .parse()
+                        .map_err(|_| format!("Invalid linenostart '{}' in code block", value))?;
+                    parsed.linenostart = Some(start);
+                    parsed.linenos = true;
+                }
+                ("hl_lines", Some(value)) => parsed.hl_lines = parse_lines(value)?,
+                _ => {
+                    return Err(format!(
+                    "Unknown code block option '{}' (expected linenos, linenostart or hl_lines)",
+                    option
+                )
+                    .into())
+                }
+            }
+        }
+        Ok(parsed)
+    }
+
+    fn highlighted(&self, line: usize) -> bool {
+        self.hl_lines.iter().any(|range| range.contains(&line))
+    }
+}
+
+/// `options` split at commas outside `[...]`, trimmed, empty parts dropped.
+fn split_options(options: &str) -> Vec<&str> {
+    let mut parts = Vec::new();
+    let mut depth = 0;
+    let mut start = 0;
+    for (i, c) in options.char_indices() {
+        match c {
+            '[' => depth += 1,
+            ']' => depth -= 1,
+            ',' if depth == 0 => {
+                parts.push(&options[start..i]);
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    parts.push(&options[start..]);
+    parts
+        .into_iter()
+        .map(str::trim)
+        .filter(|part| !part.is_empty())
+        .collect()
+}
+
+/// `[3, 5-7]`, `"3 5-7"` or `3` as line ranges, counted from 1 within the block.
+fn parse_lines(value: &str) -> Result<Vec<RangeInclusive<usize>>, Box<dyn Error>> {
+    let invalid = || format!("Invalid hl_lines '{}' in code block", value);
+    value
+        .trim_start_matches('[')
+        .trim_end_matches(']')
+        .split([',', ' '])
+        .map(|item| item.trim().trim_matches('"'))
+        .filter(|item| !item.is_empty())
+        .map(|item| {
+            let (first, last) = item.split_once('-').unwrap_or((item, item));
+            let first: usize = first.trim().parse().map_err(|_| invalid())?;
+            let last: usize = last.trim().parse().map_err(|_| invalid())?;
+            if first == 0 || last < first {
+                return Err(invalid().into());
+            }
+            Ok(first..=last)
+        })
+        .collect()
+}
+
+/// Replaces fenced code blocks whose info string has options, like
+/// ```` ```rust {linenos, hl_lines=[3,5-7]} ````, with a block that numbers
+/// and highlights its lines. Blocks nested in lists or quotes are left as
+/// they are, since raw HTML there would lose its indentation.
+pub fn expand_code_options(markdown: &str) -> Result<Cow<'_, str>, Box<dyn Error>> {
+    if !markdown.contains('{') {
+        return Ok(Cow::Borrowed(markdown));
+    }
+
+    let mut replacements = Vec::new();
+    let mut events = MarkdownParser::new_ext(markdown, markdown_options()).into_offset_iter();
+    while let Some((event, range)) = events.next() {
+        let Event::Start(Tag::CodeBlock(CodeBlockKind::Fenced(info))) = event else {
+            continue;
+        };
+        let mut code = String::new();
+        for (event, _) in events.by_ref() {
+            match event {
+                Event::End(TagEnd::CodeBlock) => break,
+                Event::Text(text) => code.push_str(&text),
+                _ => {}
+            }
+        }
+        let Some((lang, options)) = info.split_once('{') else {
+            continue;
+        };
+        let at_line_start = markdown[..range.start].ends_with('\n') || range.start == 0;
+        let Some(options) = options.trim_end().strip_suffix('}') else {
+            continue;
+        };
+        if !at_line_start {
+            continue;
+        }
+        let options = CodeOptions::parse(options)?;
+        replacements.push((range, code_html(lang.trim(), &code, &options)));
+    }
+    if replacements.is_empty() {
+        return Ok(Cow::Borrowed(markdown));
+    }
+
+    let mut expanded = String::with_capacity(markdown.len());
+    let mut end = 0;
+    for (range, html) in replacements {
+        expanded.push_str(&markdown[end..range.start]);
+        expanded.push_str(&html);
+        end = range.end;
+    }
+    expanded.push_str(&markdown[end..]);
+    Ok(Cow::Owned(expanded))
+}
+
+/// `code` as a `<pre class="code">` block with one `<span class="line">` per
+/// line, like highlighted snippets. Ends with a newline, closing the HTML
+/// block.
+fn code_html(lang: &str, code: &str, options: &CodeOptions) -> String {
+    let mut classes = String::from("code");
+    if !options.linenos {
+        classes.push_str(" no-linenos");
+    }
+    let counter = match options.linenostart {
+        Some(start) if start != 1 => {
+            format!(" style=\"counter-reset: line {}\"", start.saturating_sub(1))
+        }
+        _ => String::new(),
+    };
+    let lang_class = match lang {
+        "" => String::new(),
+        lang => format!(" class=\"language-{}\"", escape_html(lang)),
+    };
+    let mut html = format!("<pre class=\"{}\"{}><code{}>", classes, counter, lang_class);
+    for (index, line) in code.lines().enumerate() {
+        let class = if options.highlighted(index + 1) {
+            "line hl"
+        } else {
+            "line"
+        };
+        html.push_str(&format!(
+            "<span class=\"{}\">{}</span>\n",
+            class,
+            escape_html(line)
+        ));
+    }
+    html.push_str("</code></pre>\n");
+    html
+}
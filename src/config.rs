@@ -0,0 +1,1091 @@
+use crate::budget::Size;
+use crate::frontmatter::{slugify, validate_labels, Labels};
+use crate::throttle::Rate;
+use crate::webhook::Event;
+use serde::Deserialize;
+use std::{
+    collections::BTreeMap,
+    error::Error,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+#[derive(Debug, Deserialize)]
+pub struct S3Config {
+    pub domain: String,
+    pub bucket: String,
+    /// B2 region like `eu-central-003`. Optional for MinIO.
+    #[serde(default)]
+    pub region: String,
+    /// Which kind of S3 server the bucket lives on.
+    #[serde(default)]
+    pub provider: Provider,
+    /// S3 API URL, e.g. `http://nas.local:9000`. Required for MinIO; for B2
+    /// it's derived from the region.
+    pub endpoint: Option<String>,
+    pub prefix: String,
+    /// Not needed with `backend = "delegated"`.
+    #[serde(default)]
+    pub access_key_id: String,
+    #[serde(default)]
+    pub secret_access_key: String,
+    /// Session token for temporary credentials, e.g. from STS.
+    pub session_token: Option<String>,
+    /// With B2, use the key above only to create a key limited to the
+    /// bucket and prefix, and upload with that one. Needs `writeKeys`.
+    #[serde(default)]
+    pub scoped_key: bool,
+    /// How long a scoped key lasts, in seconds.
+    #[serde(default = "default_scoped_key_ttl")]
+    pub scoped_key_ttl: u64,
+    /// B2's native API, where scoped keys are created.
+    #[serde(default = "default_b2_api")]
+    pub b2_api_url: String,
+    /// How often a request is tried before giving up, counting the first.
+    /// Throttled requests (429 and 503) wait as long as the bucket asks.
+    #[serde(default = "default_max_attempts")]
+    pub max_attempts: u32,
+}
+
+fn default_max_attempts() -> u32 {
+    8
+}
+
+fn default_scoped_key_ttl() -> u64 {
+    3600
+}
+
+fn default_b2_api() -> String {
+    "https://api.backblazeb2.com".to_string()
+}
+
+/// S3-compatible services with a preset for their quirks.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Provider {
+    #[default]
+    Backblaze,
+    /// MinIO or another self-hosted server: path-style addressing on any
+    /// endpoint and port.
+    Minio,
+}
+
+/// Site-wide identity shown on every page, so it lives in one place instead
+/// of in each post.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct SiteConfig {
+    /// Shown above every page and appended to page titles.
+    pub title: Option<String>,
+    /// Default author for posts that don't name one: an `id` or name from
+    /// `authors`, or just a name.
+    pub author: Option<String>,
+    /// Home page the site title links to.
+    pub base_url: Option<String>,
+    /// Plain text shown at the bottom of every page.
+    pub footer: Option<String>,
+    /// Profile links in the footer, marked `rel="me"` for verification.
+    pub links: Vec<SocialLink>,
+    /// People who publish here, referred to by `id` in front matter.
+    pub authors: Vec<Author>,
+}
+
+impl SiteConfig {
+    /// The configured author whose `id` or name is `key`, or an author with
+    /// just that name.
+    pub fn author(&self, key: &str) -> Author {
+        self.authors
+            .iter()
+            .find(|author| author.id.as_deref() == Some(key) || author.name == key)
+            .cloned()
+            .unwrap_or_else(|| Author {
+                id: None,
+                name: key.to_string(),
+                url: None,
+                avatar: None,
+            })
+    }
+}
+
+/// Someone who writes posts, shown in the byline.
+#[derive(Debug, Clone, Deserialize)]
+pub struct Author {
+    /// Short name to use in front matter, e.g. `jane`.
+    pub id: Option<String>,
+    pub name: String,
+    /// The byline links here.
+    pub url: Option<String>,
+    /// Small picture shown next to the name.
+    pub avatar: Option<String>,
+}
+
+/// A link to a profile elsewhere, e.g. Mastodon or GitHub.
+#[derive(Debug, Deserialize)]
+pub struct SocialLink {
+    pub name: String,
+    pub url: String,
+}
+
+/// Settings that control how the rendered page looks to the reader.
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct PageConfig {
+    /// Value of the `lang` attribute on `<html>`.
+    pub lang: String,
+    /// Locale used for month and weekday names, e.g. `sv_SE`. English if unset.
+    pub locale: Option<String>,
+    /// strftime-style format for the date shown above the post.
+    pub date_format: String,
+    /// Remove the first `# Heading` from the body when it's used as the title,
+    /// showing it as the page heading instead.
+    pub strip_title: bool,
+    /// Which markdown implementation renders post bodies.
+    pub parser: MarkdownBackend,
+    /// Where footnotes go: the bottom of the post, or the margin.
+    pub footnotes: FootnoteStyle,
+    /// Give new posts a URL from their title (`p/my-post/`) instead of a
+    /// random id, unless the front matter sets a `slug`.
+    pub slug_from_title: bool,
+    /// What to do when a new post's slug is already taken by another post.
+    pub slug_collision: SlugCollision,
+    /// How titles and front matter slugs become URLs.
+    pub slug_style: SlugStyle,
+    /// Let readers sort CSV/TSV tables by clicking a column header.
+    pub sortable_tables: bool,
+    /// Let readers tick task list checkboxes, remembered in their browser.
+    pub interactive_task_lists: bool,
+    /// Date line of an updated post; `{published}` and `{updated}` are
+    /// replaced by the formatted dates.
+    pub updated_format: String,
+    /// Heading above a post's front matter `changelog`.
+    pub changelog_heading: String,
+    /// How many related posts (by shared labels and similar titles) to link
+    /// at the end of each post; 0 turns the section off.
+    pub related_posts: usize,
+    /// Heading above the related posts.
+    pub related_heading: String,
+    /// Heading of the 404 page.
+    pub not_found_title: String,
+    /// Text of the 404 page.
+    pub not_found_text: String,
+    /// Leave out everything that differs between two runs over the same
+    /// input: dates are only shown when set in front matter, and new post ids
+    /// come from the content instead of being random.
+    pub reproducible: bool,
+    /// Directory of page templates that front matter `template` picks from.
+    /// Relative to the config file.
+    pub templates_dir: Option<PathBuf>,
+    /// Template for posts that don't name one. The built-in layout if unset.
+    pub template: Option<String>,
+    /// Put the `<body>` of `.html` posts into the page template instead of
+    /// publishing them as they are.
+    pub wrap_html: bool,
+}
+
+/// What happens when a new post's slug is already in use.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SlugCollision {
+    /// Use the first free one of `slug-2`, `slug-3`, ...
+    #[default]
+    Suffix,
+    /// Don't publish, so the author can pick another slug.
+    Refuse,
+}
+
+/// How text becomes a slug. Emoji are dropped in every style.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SlugStyle {
+    /// ASCII only: accents come off (`Smörgåsbord` is `smorgasbord`) and
+    /// other scripts are romanized character by character. Han characters
+    /// always get their Mandarin reading, so Japanese kanji come out as
+    /// pinyin, not romaji; only kana become romaji.
+    #[default]
+    Translit,
+    /// Letters and digits of any script, as they are.
+    Unicode,
+    /// A short hash of the title, for titles that don't romanize well.
+    /// Front matter slugs are transliterated.
+    Hash,
+}
+
+/// Where a post's footnotes are shown.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FootnoteStyle {
+    /// At the bottom of the post, as the markdown backend renders them.
+    #[default]
+    Bottom,
+    /// In the margin next to the text on wide screens, and opened in place by
+    /// tapping the note's number on narrow ones.
+    Sidenotes,
+}
+
+/// Markdown implementations a post body can be rendered with.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MarkdownBackend {
+    /// CommonMark plus tables, built in.
+    #[default]
+    Pulldown,
+    /// GitHub-flavored markdown with footnotes, description lists and more, built in.
+    Comrak,
+    /// Pandoc's markdown (fenced divs, citations, ...); needs `pandoc` on the `PATH`.
+    Pandoc,
+}
+
+impl Default for PageConfig {
+    fn default() -> Self {
+        Self {
+            lang: "en".to_string(),
+            locale: None,
+            date_format: "%B %d, %Y".to_string(),
+            strip_title: false,
+            parser: MarkdownBackend::default(),
+            footnotes: FootnoteStyle::default(),
+            slug_from_title: false,
+            slug_collision: SlugCollision::default(),
+            slug_style: SlugStyle::default(),
+            sortable_tables: false,
+            interactive_task_lists: false,
+            updated_format: "{published}, updated {updated}".to_string(),
+            changelog_heading: "Changelog".to_string(),
+            related_posts: 0,
+            related_heading: "Related posts".to_string(),
+            not_found_title: "Page not found".to_string(),
+            not_found_text: "There's nothing here. It may have been moved or deleted.".to_string(),
+            reproducible: false,
+            templates_dir: None,
+            template: None,
+            wrap_html: false,
+        }
+    }
+}
+
+/// Generated Open Graph preview images.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct OgImageConfig {
+    pub enabled: bool,
+    /// TTF/OTF font used for the text. A common system font is used if unset.
+    pub font: Option<PathBuf>,
+    /// Name shown at the bottom of the image. Defaults to the domain.
+    pub site_name: Option<String>,
+}
+
+/// Where to announce new posts after they've been published.
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct AnnounceConfig {
+    /// Message template; `{title}` and `{url}` are filled in.
+    pub message: String,
+    pub mastodon: Option<MastodonConfig>,
+    pub bluesky: Option<BlueskyConfig>,
+}
+
+impl Default for AnnounceConfig {
+    fn default() -> Self {
+        Self {
+            message: "New post: {title} {url}".to_string(),
+            mastodon: None,
+            bluesky: None,
+        }
+    }
+}
+
+impl AnnounceConfig {
+    pub fn is_enabled(&self) -> bool {
+        self.mastodon.is_some() || self.bluesky.is_some()
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct MastodonConfig {
+    /// Instance base URL, e.g. `https://mastodon.social`.
+    pub instance: String,
+    /// Access token with the `write:statuses` scope.
+    pub access_token: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct BlueskyConfig {
+    pub handle: String,
+    /// An app password, not the account password.
+    pub app_password: String,
+    #[serde(default = "default_bluesky_service")]
+    pub service: String,
+}
+
+fn default_bluesky_service() -> String {
+    "https://bsky.social".to_string()
+}
+
+/// How to talk to the SMTP server.
+#[derive(Debug, Default, Clone, Copy, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SmtpSecurity {
+    /// Implicit TLS, usually port 465.
+    Tls,
+    /// Plain connection upgraded with STARTTLS, usually port 587.
+    #[default]
+    Starttls,
+    /// Unencrypted, for local relays only.
+    None,
+}
+
+/// SMTP settings for `--email`.
+#[derive(Debug, Deserialize)]
+pub struct EmailConfig {
+    /// Sender, e.g. `Klistra <me@example.com>`.
+    pub from: String,
+    pub smtp_host: String,
+    #[serde(default = "default_smtp_port")]
+    pub smtp_port: u16,
+    #[serde(default)]
+    pub security: SmtpSecurity,
+    pub username: Option<String>,
+    pub password: Option<String>,
+}
+
+fn default_smtp_port() -> u16 {
+    587
+}
+
+/// Sending webmentions to sites the post links to.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct WebmentionConfig {
+    pub enabled: bool,
+}
+
+/// Other sites worth reading, listed on `blogroll/` and in `blogroll.opml`.
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct BlogrollConfig {
+    pub title: String,
+    pub sites: Vec<BlogrollSite>,
+}
+
+impl Default for BlogrollConfig {
+    fn default() -> Self {
+        Self {
+            title: "Blogroll".to_string(),
+            sites: Vec::new(),
+        }
+    }
+}
+
+/// A site in the blogroll.
+#[derive(Debug, Clone, Deserialize)]
+pub struct BlogrollSite {
+    pub name: String,
+    pub url: String,
+    /// Its RSS, Atom or JSON feed, for feed readers importing the OPML.
+    #[serde(default)]
+    pub feed: Option<String>,
+    /// A line about it, shown under the link.
+    #[serde(default)]
+    pub description: Option<String>,
+}
+
+/// Static documents for following the site from the fediverse.
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct ActivityPubConfig {
+    pub enabled: bool,
+    /// The account name, as in `@blog@paste.example.com`.
+    pub username: String,
+    /// A short bio for the account.
+    pub summary: Option<String>,
+    /// URL of the account's avatar.
+    pub icon: Option<String>,
+}
+
+impl Default for ActivityPubConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            username: "blog".to_string(),
+            summary: None,
+            icon: None,
+        }
+    }
+}
+
+/// A tracking pixel on every post, counted by `klistra views`.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct ViewsConfig {
+    pub enabled: bool,
+}
+
+/// Where `klistra views` reads the site's traffic from: access logs
+/// delivered to a bucket, or Cloudflare's analytics API.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct LogsConfig {
+    /// The bucket the logs are delivered to, with the `[s3]` credentials.
+    /// Defaults to `[s3] bucket`.
+    pub bucket: Option<String>,
+    /// Where the log files are in that bucket, e.g. `logs/`.
+    pub prefix: Option<String>,
+    /// Zone id of the site's domain. When set, Cloudflare's GraphQL
+    /// analytics API is used instead of log files.
+    pub cloudflare_zone: Option<String>,
+    /// API token with the Analytics Read permission.
+    pub cloudflare_token: Option<String>,
+}
+
+/// Cleaning SVG files as they're uploaded.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct SvgConfig {
+    /// Strip scripts, event handlers and external references.
+    pub sanitize: bool,
+    /// Strip comments, metadata and editor data.
+    pub optimize: bool,
+}
+
+/// Publishing a signed `integrity.json` of everything uploaded, for
+/// `klistra verify`.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct IntegrityConfig {
+    pub enabled: bool,
+}
+
+/// Keeping a record of every delete, move and overwrite in the bucket, for
+/// tracing accidents when several people share it.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct AuditConfig {
+    pub enabled: bool,
+    /// Where the log is appended to. Defaults to `audit.jsonl` next to the
+    /// manifest.
+    pub path: Option<PathBuf>,
+    /// Also upload each entry under `audit/` in the bucket.
+    pub remote: bool,
+}
+
+/// Keeping each post's markdown, so `klistra rebuild` can render it again
+/// after its source file has moved or gone, and readers can get at it.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct SourcesConfig {
+    /// Keep a copy of each post's markdown, by id.
+    pub keep: bool,
+    /// Where the copies go. Defaults to `sources/` next to the manifest.
+    pub dir: Option<PathBuf>,
+    /// Also publish each post's markdown as `index.md` next to its page, with
+    /// a "View source" link in the footer.
+    pub upload: bool,
+}
+
+/// TLS settings for every HTTPS connection, e.g. behind a TLS-intercepting
+/// gateway or for a self-hosted S3 with a private CA.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct TlsConfig {
+    /// PEM file with extra CA certificates to trust.
+    pub ca_bundle: Option<PathBuf>,
+    /// PEM client certificate for mutual TLS. May also contain the key.
+    pub client_cert: Option<PathBuf>,
+    /// PEM private key for `client_cert`, if it's in a separate file.
+    pub client_key: Option<PathBuf>,
+}
+
+impl TlsConfig {
+    /// Whether anything differs from the system defaults.
+    pub fn is_custom(&self) -> bool {
+        self.ca_bundle.is_some() || self.client_cert.is_some()
+    }
+}
+
+/// How uploads to the bucket behave.
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct UploadConfig {
+    /// Upload speed limit like `2MB/s`. Unlimited if unset.
+    pub limit_rate: Option<Rate>,
+    /// Object tags for every upload, e.g. `managed-by = "klistra"`. Front
+    /// matter labels are added to these and win on conflicts.
+    pub labels: Labels,
+    /// Where uploads go.
+    pub backend: Backend,
+    /// Take a lock object in the bucket while rebuilding the index and feed,
+    /// for buckets that several people publish to.
+    pub lock: bool,
+    /// How long to wait for someone else's lock, in seconds.
+    pub lock_wait: u64,
+    /// Largest post to publish, page and files together, e.g. `200MB`.
+    pub max_post_bytes: Option<Size>,
+    /// Largest single file (video, attachment, raw file, photo) to upload.
+    pub max_asset_bytes: Option<Size>,
+    /// Most the posts in the manifest may take up together.
+    pub max_total_bytes: Option<Size>,
+    /// Content types by file extension, e.g. `log = "text/plain"`, for
+    /// files the built-in detection gets wrong.
+    pub content_types: BTreeMap<String, String>,
+    /// Publish over the limits anyway, with a warning. Set by `--force`.
+    #[serde(skip)]
+    pub force: bool,
+    /// Upload photos with their camera metadata (EXIF, XMP, IPTC),
+    /// location included, instead of stripping it. Also `--keep-exif`.
+    pub keep_exif: bool,
+    /// Queue uploads for `klistra flush` when the bucket can't be reached,
+    /// instead of failing.
+    pub queue_offline: bool,
+    /// Queue every upload without trying the bucket. Set by `--queue`.
+    #[serde(skip)]
+    pub queue: bool,
+    /// Upload a post's page and files under `staging/` first and move them
+    /// into place once everything is up, so readers never see a page whose
+    /// files are still on the way.
+    pub staged: bool,
+}
+
+impl Default for UploadConfig {
+    fn default() -> Self {
+        Self {
+            limit_rate: None,
+            labels: Labels::default(),
+            backend: Backend::default(),
+            lock: false,
+            lock_wait: 60,
+            max_post_bytes: None,
+            max_asset_bytes: None,
+            max_total_bytes: None,
+            content_types: BTreeMap::new(),
+            force: false,
+            keep_exif: false,
+            queue_offline: false,
+            queue: false,
+            staged: false,
+        }
+    }
+}
+
+/// Storage behind [`crate::publisher::Publisher`].
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Backend {
+    /// The bucket in `[s3]`.
+    #[default]
+    S3,
+    /// Nowhere: uploads are kept in memory for the length of the command and
+    /// printed, and the manifest is a separate scratch file.
+    Memory,
+    /// The bucket in `[s3]`, through requests signed by the `klistra daemon`
+    /// in `[delegate]`, so no keys are needed here.
+    Delegated,
+}
+
+impl FromStr for Backend {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "s3" => Ok(Self::S3),
+            "memory" => Ok(Self::Memory),
+            "delegated" => Ok(Self::Delegated),
+            _ => Err(format!(
+                "Unknown backend '{}', expected s3, memory or delegated",
+                s
+            )),
+        }
+    }
+}
+
+/// Limits for `klistra raw`.
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct RawConfig {
+    /// Largest file that will be uploaded, in megabytes.
+    pub max_size_mb: u64,
+}
+
+impl Default for RawConfig {
+    fn default() -> Self {
+        Self { max_size_mb: 25 }
+    }
+}
+
+/// Where `klistra quick` looks for notes.
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct QuickConfig {
+    /// Directories searched recursively. A leading `~/` means the home directory.
+    pub dirs: Vec<PathBuf>,
+    /// How many of the most recently modified files to offer.
+    pub limit: usize,
+}
+
+impl Default for QuickConfig {
+    fn default() -> Self {
+        Self {
+            dirs: Vec::new(),
+            limit: 100,
+        }
+    }
+}
+
+/// The site's front page, built by `klistra index`.
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct IndexConfig {
+    /// Rebuild the index after every publish, update and delete.
+    pub enabled: bool,
+    /// Heading of the index. Defaults to the site title.
+    pub title: Option<String>,
+    /// Always build the archive page, as with `klistra index --archive`.
+    pub archive: bool,
+    /// Heading of the archive page.
+    pub archive_title: String,
+    /// List links to the sections of posts with at least this many words
+    /// under them in the index and feed; 0 turns them off.
+    pub section_links: usize,
+}
+
+impl Default for IndexConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            title: None,
+            archive: false,
+            archive_title: "Archive".to_string(),
+            section_links: 1500,
+        }
+    }
+}
+
+/// Publishing as one member of a team sharing the bucket.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct TeamConfig {
+    /// This person's name in paths: their posts go under
+    /// `authors/<author>/p/<id>/`. Lowercase letters, digits and dashes.
+    pub author: Option<String>,
+}
+
+/// Where the manifest is kept besides the local file.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct ManifestConfig {
+    /// Keep a copy in the bucket, merged with the local one on every save
+    /// and by `klistra sync`.
+    pub remote: bool,
+}
+
+/// Where `redirects.toml` is and how its redirects are uploaded.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct RedirectsConfig {
+    /// Defaults to `redirects.toml` next to the config file.
+    pub file: Option<PathBuf>,
+    pub style: RedirectStyle,
+}
+
+/// How an old slug sends readers to the new one.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RedirectStyle {
+    /// An `index.html` with a meta refresh, which works on any host.
+    #[default]
+    Page,
+    /// An empty object with `x-amz-website-redirect-location`, for S3 website
+    /// endpoints that answer it with a real HTTP redirect.
+    Object,
+}
+
+/// What every post's front matter must have, checked before it's published.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct FrontMatterSchema {
+    /// Keys that must be set and not empty. `labels.<key>` requires a label.
+    pub required: Vec<String>,
+    /// The type each key must have when it's set.
+    pub types: BTreeMap<String, ValueType>,
+    /// The values allowed for each label.
+    pub labels: BTreeMap<String, Vec<String>>,
+}
+
+/// Types a front matter value can be required to have.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ValueType {
+    String,
+    Number,
+    Bool,
+    /// `2025-03-01` or an RFC 3339 time, like the `date` key.
+    Date,
+    List,
+    Map,
+}
+
+/// Settings for `klistra check` and `--check`, which run posts through
+/// hunspell.
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct CheckConfig {
+    /// The hunspell program, if it isn't `hunspell` on the `PATH`.
+    pub hunspell: String,
+    /// Hunspell dictionary, like `en_US`. Hunspell's default (from `LANG`)
+    /// if unset.
+    pub dictionary: Option<String>,
+    /// Words to accept on top of the dictionary, like names and jargon.
+    /// Case doesn't matter.
+    pub words: Vec<String>,
+    /// Whether `--check` stops the publish when it finds misspellings, rather
+    /// than warning and publishing anyway.
+    pub fail: bool,
+    /// Whether posts are scanned for keys, tokens, passwords and email
+    /// addresses before they're published, and refused if any turn up.
+    pub secrets: bool,
+    /// Strings the secret scan lets through, like your own address.
+    pub allow: Vec<String>,
+    /// Publish what the scan finds anyway, with a warning. Set by
+    /// `--allow-secrets`.
+    #[serde(skip)]
+    pub allow_secrets: bool,
+}
+
+impl Default for CheckConfig {
+    fn default() -> Self {
+        Self {
+            hunspell: "hunspell".to_string(),
+            dictionary: None,
+            words: Vec::new(),
+            fail: true,
+            secrets: true,
+            allow: Vec::new(),
+            allow_secrets: false,
+        }
+    }
+}
+
+/// Settings for `klistra micropub-serve`. Requests are authorized with the
+/// static `token`, or by verifying IndieAuth tokens with `token_endpoint`.
+#[derive(Debug, Deserialize)]
+pub struct MicropubConfig {
+    pub token: Option<String>,
+    pub token_endpoint: Option<String>,
+    /// Your site URL; IndieAuth tokens issued for other sites are rejected.
+    pub me: Option<String>,
+}
+
+/// Settings for `klistra mail-fetch`, which publishes the emails in an IMAP
+/// mailbox. Replies go out through [email].
+#[derive(Debug, Deserialize)]
+pub struct MailConfig {
+    pub imap_host: String,
+    #[serde(default = "default_imap_port")]
+    pub imap_port: u16,
+    pub username: String,
+    pub password: String,
+    #[serde(default = "default_mailbox")]
+    pub mailbox: String,
+    /// Addresses whose emails are published. Everything else is skipped.
+    pub allowed_senders: Vec<String>,
+    /// Only publish emails your mail server marked `dmarc=pass`, so the
+    /// sender can't simply be forged.
+    #[serde(default = "default_true")]
+    pub require_dmarc: bool,
+    /// Seconds between checks with `--watch`.
+    #[serde(default = "default_mail_interval")]
+    pub interval: u64,
+}
+
+fn default_imap_port() -> u16 {
+    993
+}
+
+fn default_true() -> bool {
+    true
+}
+
+fn default_mailbox() -> String {
+    "INBOX".to_string()
+}
+
+fn default_mail_interval() -> u64 {
+    60
+}
+
+/// Settings for `klistra bot telegram`.
+#[derive(Debug, Deserialize)]
+pub struct TelegramConfig {
+    /// The bot's token from @BotFather.
+    pub token: String,
+    /// Telegram user IDs whose messages are published. Anyone else is told
+    /// their ID and nothing more.
+    pub allowed_users: Vec<i64>,
+    /// The Bot API server; a self-hosted one lifts the 20 MB limit on files.
+    #[serde(default = "default_telegram_api")]
+    pub api_url: String,
+}
+
+fn default_telegram_api() -> String {
+    "https://api.telegram.org".to_string()
+}
+
+/// Logging for the long-running modes: `daemon`, `micropub-serve`,
+/// `mail-fetch` and `bot`.
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct LoggingConfig {
+    /// Directory for log files, one per mode, e.g. `klistra-daemon.log`.
+    /// Logs go to stderr if unset.
+    pub dir: Option<PathBuf>,
+    /// Level, optionally per module: `info` or `warn,klistra::mail=debug`.
+    /// `RUST_LOG` overrides it.
+    pub level: String,
+    /// JSON lines or plain text. Defaults to JSON in files and text on
+    /// stderr.
+    pub format: Option<LogFormat>,
+    /// A log file is rotated once it reaches this size.
+    pub max_size_mb: u64,
+    /// How many rotated files are kept, as `.1` (newest) to `.<keep>`.
+    pub keep: usize,
+}
+
+impl Default for LoggingConfig {
+    fn default() -> Self {
+        Self {
+            dir: None,
+            level: "info".to_string(),
+            format: None,
+            max_size_mb: 10,
+            keep: 5,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+    Json,
+    Text,
+}
+
+/// Settings for `klistra daemon`.
+#[derive(Debug, Deserialize)]
+pub struct DaemonConfig {
+    /// Bearer token every API request must carry.
+    pub token: String,
+}
+
+/// The `klistra daemon` that signs uploads for `backend = "delegated"`.
+#[derive(Debug, Deserialize)]
+pub struct DelegateConfig {
+    /// Where the daemon listens, e.g. `https://signer.example.com`.
+    pub url: String,
+    /// Its `[daemon] token`.
+    pub token: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct WebhookConfig {
+    pub url: String,
+    /// Only notify for these events; all events if unset.
+    pub events: Option<Vec<Event>>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct AppConfig {
+    pub s3: S3Config,
+    #[serde(default)]
+    pub site: SiteConfig,
+    #[serde(default)]
+    pub page: PageConfig,
+    #[serde(default)]
+    pub og_image: OgImageConfig,
+    #[serde(default)]
+    pub announce: AnnounceConfig,
+    #[serde(default)]
+    pub webhooks: Vec<WebhookConfig>,
+    pub email: Option<EmailConfig>,
+    #[serde(default)]
+    pub webmention: WebmentionConfig,
+    pub micropub: Option<MicropubConfig>,
+    pub mail: Option<MailConfig>,
+    pub telegram: Option<TelegramConfig>,
+    pub daemon: Option<DaemonConfig>,
+    pub delegate: Option<DelegateConfig>,
+    #[serde(default)]
+    pub raw: RawConfig,
+    #[serde(default)]
+    pub quick: QuickConfig,
+    #[serde(default)]
+    pub index: IndexConfig,
+    #[serde(default)]
+    pub redirects: RedirectsConfig,
+    #[serde(default)]
+    pub manifest: ManifestConfig,
+    #[serde(default)]
+    pub team: TeamConfig,
+    #[serde(default)]
+    pub upload: UploadConfig,
+    #[serde(default)]
+    pub svg: SvgConfig,
+    #[serde(default)]
+    pub tls: TlsConfig,
+    #[serde(default)]
+    pub integrity: IntegrityConfig,
+    #[serde(default)]
+    pub audit: AuditConfig,
+    #[serde(default)]
+    pub sources: SourcesConfig,
+    #[serde(default)]
+    pub activitypub: ActivityPubConfig,
+    #[serde(default)]
+    pub blogroll: BlogrollConfig,
+    #[serde(default)]
+    pub views: ViewsConfig,
+    #[serde(default)]
+    pub logs: LogsConfig,
+    #[serde(default)]
+    pub logging: LoggingConfig,
+    #[serde(default)]
+    pub check: CheckConfig,
+    #[serde(default)]
+    pub front_matter: FrontMatterSchema,
+}
+
+impl AppConfig {
+    pub fn load(config_path: &Path) -> Result<Self, Box<dyn Error>> {
+        // Check if the config file exists
+        if !config_path.exists() {
+            return Err(format!("Config file not found at {}", config_path.display()).into());
+        }
+
+        // Environment variables like KLISTRA__S3__BUCKET override the file.
+        let settings = config::Config::builder()
+            .add_source(config::File::from(config_path).format(config_format(config_path)?))
+            .add_source(
+                config::Environment::with_prefix("KLISTRA")
+                    .prefix_separator("__")
+                    .separator("__")
+                    .try_parsing(true),
+            )
+            .build()?;
+        let mut config: Self = settings.try_deserialize()?;
+        // Caught here rather than on the first upload.
+        validate_labels(&config.upload.labels).map_err(|e| format!("[upload] labels: {}", e))?;
+        config
+            .redirects
+            .file
+            .get_or_insert_with(|| config_path.with_file_name("redirects.toml"));
+        if let Some(author) = &config.team.author {
+            if author.is_empty() || slugify(author) != *author {
+                return Err(format!(
+                    "[team] author '{}' must be lowercase letters, digits and dashes, like '{}'",
+                    author,
+                    slugify(author)
+                )
+                .into());
+            }
+            // Team members see each other's posts through the shared
+            // manifest, and take turns rebuilding the index.
+            config.manifest.remote = true;
+            config.upload.lock = true;
+        }
+        if let Some(dir) = &mut config.page.templates_dir {
+            if let Some(config_dir) = config_path.parent() {
+                *dir = config_dir.join(&*dir);
+            }
+        }
+        Ok(config)
+    }
+}
+
+/// The format of a config file, by extension: TOML (also without one),
+/// YAML or JSON.
+fn config_format(path: &Path) -> Result<config::FileFormat, Box<dyn Error>> {
+    match path.extension().and_then(|ext| ext.to_str()) {
+        None | Some("toml") => Ok(config::FileFormat::Toml),
+        Some("yaml" | "yml") => Ok(config::FileFormat::Yaml),
+        Some("json") => Ok(config::FileFormat::Json),
+        Some(ext) => Err(format!(
+            "Unsupported config format '.{}' ({}), expected .toml, .yaml or .json",
+            ext,
+            path.display()
+        )
+        .into()),
+    }
+}
+
+/// Names the config file can have in the config directory, in the order
+/// they're looked for.
+const CONFIG_NAMES: [&str; 4] = ["config.toml", "config.yaml", "config.yml", "config.json"];
+
+/// Files that live next to the config and move with it.
+const CONFIG_FILES: [&str; 2] = ["config.toml", "redirects.toml"];
+
+/// Finds the config file, first match wins:
+///
+/// 1. `--config`
+/// 2. `$KLISTRA_CONFIG`
+/// 3. `klistra/config.toml` (or `.yaml`, `.yml`, `.json`) in the platform's
+///    config directory:
+///    `$XDG_CONFIG_HOME` (or `~/.config`) on Linux, `~/Library/Application
+///    Support` on macOS, `%APPDATA%` on Windows
+/// 4. `~/.config/klistra/config.toml`, where older versions looked on every
+///    platform
+///
+/// A config found only in the legacy location is moved to the new one
+/// (along with `redirects.toml`) the first time.
+pub fn get_config_path(cli_config_path: Option<PathBuf>) -> Result<PathBuf, Box<dyn Error>> {
+    if let Some(path) = cli_config_path {
+        return Ok(path);
+    }
+    if let Some(path) = std::env::var_os("KLISTRA_CONFIG").filter(|path| !path.is_empty()) {
+        return Ok(PathBuf::from(path));
+    }
+
+    let legacy = dirs::home_dir().map(|home| home.join(".config").join("klistra"));
+    let Some(dir) = dirs::config_dir().map(|dir| dir.join("klistra")) else {
+        return legacy
+            .map(|dir| dir.join("config.toml"))
+            .ok_or_else(|| "Could not determine config file path".into());
+    };
+    if let Some(path) = CONFIG_NAMES
+        .iter()
+        .map(|name| dir.join(name))
+        .find(|path| path.exists())
+    {
+        return Ok(path);
+    }
+    let path = dir.join("config.toml");
+    if let Some(legacy) = legacy.filter(|legacy| *legacy != dir) {
+        if !path.exists() && legacy.join("config.toml").exists() {
+            migrate(&legacy, &dir)?;
+        }
+    }
+    Ok(path)
+}
+
+/// Moves the config files from `from` to `to`.
+fn migrate(from: &Path, to: &Path) -> Result<(), Box<dyn Error>> {
+    std::fs::create_dir_all(to)?;
+    for name in CONFIG_FILES {
+        let (old, new) = (from.join(name), to.join(name));
+        if !old.exists() || new.exists() {
+            continue;
+        }
+        // A rename can't cross filesystems; copy instead.
+        if std::fs::rename(&old, &new).is_err() {
+            std::fs::copy(&old, &new).map_err(|e| {
+                format!(
+                    "Could not move {} to {}: {}",
+                    old.display(),
+                    new.display(),
+                    e
+                )
+            })?;
+            std::fs::remove_file(&old)?;
+        }
+        eprintln!("Moved {} to {}", old.display(), new.display());
+    }
+    Ok(())
+}
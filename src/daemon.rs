@@ -0,0 +1,233 @@
+use crate::config::{AppConfig, Backend, DaemonConfig};
+use crate::delegate::{self, BucketRequest, SignRequest};
+use crate::metrics::{Metered, Metrics};
+use crate::publisher;
+use crate::server::{bearer_token, leak_config, token_matches, SharedSite};
+use crate::shutdown;
+use crate::site::Announce;
+use crate::upload::Bucket;
+use axum::{
+    extract::{Path, Query, Request, State},
+    http::{header, StatusCode},
+    middleware::{self, Next},
+    response::{IntoResponse, Response},
+    routing::{delete, get, post},
+    Json, Router,
+};
+use serde::Deserialize;
+use serde_json::json;
+use std::{error::Error, sync::Arc, time::Instant};
+use tracing::{error, info};
+
+struct ServerState {
+    shared: SharedSite,
+    config: &'static DaemonConfig,
+    metrics: Arc<Metrics>,
+    /// Signs requests for `backend = "delegated"` clients; only with S3.
+    signer: Option<Bucket>,
+}
+
+/// Runs the HTTP API: `POST /publish`, `GET /posts`, `DELETE /posts/{id}`,
+/// `GET /metrics`, and `POST /sign` and `POST /bucket` for delegated clients.
+pub async fn serve(app_config: AppConfig, listen: &str) -> Result<(), Box<dyn Error>> {
+    shutdown::listen();
+    let app_config = leak_config(app_config);
+    let config = app_config
+        .daemon
+        .as_ref()
+        .ok_or("daemon requires a [daemon] section with a token in the config")?;
+    if config.token.is_empty() {
+        return Err("[daemon] token must not be empty".into());
+    }
+
+    let signer = match app_config.upload.backend {
+        Backend::S3 => Some(
+            Bucket::new(&app_config.s3, &app_config.tls)?
+                .with_labels(app_config.upload.labels.clone()),
+        ),
+        _ => None,
+    };
+    let metrics = Arc::new(Metrics::default());
+    let bucket = Metered::new(publisher::from_config(app_config)?, metrics.clone());
+    let state = Arc::new(ServerState {
+        shared: SharedSite::with_publisher(app_config, Box::new(bucket)).await?,
+        config,
+        metrics,
+        signer,
+    });
+
+    let app = Router::new()
+        .route("/publish", post(publish))
+        .route("/posts", get(list_posts))
+        .route("/posts/{id}", delete(delete_post))
+        .route("/metrics", get(show_metrics))
+        .route("/sign", post(sign_request))
+        .route("/bucket", post(bucket_request))
+        .route_layer(middleware::from_fn_with_state(state.clone(), authorize))
+        .with_state(state);
+    let listener = tokio::net::TcpListener::bind(listen).await?;
+    info!("API listening on http://{}", listen);
+    axum::serve(listener, app)
+        .with_graceful_shutdown(shutdown::wait())
+        .await?;
+    info!("API stopped");
+    Ok(())
+}
+
+fn error(status: StatusCode, message: &str) -> Response {
+    (status, Json(json!({ "error": message }))).into_response()
+}
+
+async fn authorize(
+    State(state): State<Arc<ServerState>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    match bearer_token(request.headers()) {
+        Some(token) if token_matches(token, &state.config.token) => next.run(request).await,
+        Some(_) => error(StatusCode::FORBIDDEN, "Invalid token"),
+        None => error(StatusCode::UNAUTHORIZED, "Missing bearer token"),
+    }
+}
+
+#[derive(Deserialize)]
+struct PublishParams {
+    /// Title to use when the markdown has neither front matter nor a heading.
+    title: Option<String>,
+    #[serde(default)]
+    no_announce: bool,
+}
+
+async fn publish(
+    State(state): State<Arc<ServerState>>,
+    Query(params): Query<PublishParams>,
+    markdown: String,
+) -> Response {
+    if markdown.trim().is_empty() {
+        return error(StatusCode::BAD_REQUEST, "Empty body");
+    }
+    let announce = if params.no_announce {
+        Announce::No
+    } else {
+        Announce::Yes
+    };
+
+    let shared = &state.shared;
+    let mut manifest = shared.manifest.lock().await;
+    let start = Instant::now();
+    let result = async {
+        let published = shared
+            .site
+            .publish_markdown(
+                &markdown,
+                params.title.as_deref().unwrap_or("Document"),
+                None,
+                &mut manifest,
+            )
+            .await?;
+        shared
+            .site
+            .finish_publish(&published, &mut manifest, &shared.manifest_path, announce)
+            .await?;
+        Ok::<_, Box<dyn Error>>(published.post)
+    }
+    .await;
+    state.metrics.publish_done(start, result.is_ok());
+
+    match result {
+        Ok(post) => {
+            info!(url = %post.url, "Published via API");
+            (StatusCode::CREATED, Json(post)).into_response()
+        }
+        Err(e) => {
+            error!(error = %e, "API publish failed");
+            error(StatusCode::INTERNAL_SERVER_ERROR, &e.to_string())
+        }
+    }
+}
+
+async fn list_posts(State(state): State<Arc<ServerState>>) -> Response {
+    let manifest = state.shared.manifest.lock().await;
+    Json(&manifest.posts).into_response()
+}
+
+async fn delete_post(State(state): State<Arc<ServerState>>, Path(id): Path<String>) -> Response {
+    let shared = &state.shared;
+    let mut manifest = shared.manifest.lock().await;
+    let result = async {
+        let Some(post) = shared.site.delete(&id, &mut manifest).await? else {
+            return Ok(None);
+        };
+        shared
+            .site
+            .finish_delete(&post, &mut manifest, &shared.manifest_path)
+            .await?;
+        Ok::<_, Box<dyn Error>>(Some(post))
+    }
+    .await;
+    if !matches!(result, Ok(None)) {
+        state.metrics.delete_done(result.is_ok());
+    }
+
+    match result {
+        Ok(Some(post)) => {
+            info!(url = %post.url, "Deleted via API");
+            StatusCode::NO_CONTENT.into_response()
+        }
+        Ok(None) => error(StatusCode::NOT_FOUND, "No such post"),
+        Err(e) => {
+            error!(error = %e, "API delete failed");
+            error(StatusCode::INTERNAL_SERVER_ERROR, &e.to_string())
+        }
+    }
+}
+
+async fn show_metrics(State(state): State<Arc<ServerState>>) -> Response {
+    (
+        [(header::CONTENT_TYPE, "text/plain; version=0.0.4")],
+        state.metrics.render(),
+    )
+        .into_response()
+}
+
+fn no_signer() -> Response {
+    error(
+        StatusCode::NOT_IMPLEMENTED,
+        "Signing needs the daemon to use backend = \"s3\"",
+    )
+}
+
+async fn sign_request(
+    State(state): State<Arc<ServerState>>,
+    Json(request): Json<SignRequest>,
+) -> Response {
+    let Some(signer) = &state.signer else {
+        return no_signer();
+    };
+    match signer.sign(&request).await {
+        Ok(signed) => Json(signed).into_response(),
+        Err(e) => {
+            error!(error = %e, "Signing failed");
+            error(StatusCode::BAD_REQUEST, &e.to_string())
+        }
+    }
+}
+
+async fn bucket_request(
+    State(state): State<Arc<ServerState>>,
+    Json(request): Json<BucketRequest>,
+) -> Response {
+    let Some(signer) = &state.signer else {
+        return no_signer();
+    };
+    let result = delegate::perform(signer, &request)
+        .await
+        .map_err(|e| e.to_string());
+    match result {
+        Ok(answer) => Json(answer).into_response(),
+        Err(e) => {
+            error!(error = %e, "Delegated bucket request failed");
+            error(StatusCode::BAD_GATEWAY, &e)
+        }
+    }
+}
@@ -0,0 +1,471 @@
+use crate::config::{AppConfig, DelegateConfig};
+use crate::frontmatter::Labels;
+use crate::http;
+use crate::publisher::{Precondition, Publisher};
+use async_trait::async_trait;
+use base64::Engine;
+use md5::{Digest, Md5};
+use reqwest::{Method, StatusCode};
+use serde::{Deserialize, Serialize};
+use std::{collections::BTreeMap, error::Error, time::Duration};
+
+/// A request `klistra daemon` signs at `POST /sign` for a client without
+/// credentials. Paths are relative to the prefix, as in [`Publisher`].
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "snake_case")]
+pub enum SignRequest {
+    Put {
+        path: String,
+        content_type: String,
+        /// Base64 MD5 of the body, which the bucket checks it against.
+        content_md5: String,
+        /// Makes browsers save the object under this name.
+        #[serde(default)]
+        file_name: Option<String>,
+        #[serde(default)]
+        labels: Labels,
+        /// For a redirect object.
+        #[serde(default)]
+        location: Option<String>,
+        /// `*` to write only if nothing is there yet.
+        #[serde(default)]
+        if_none_match: Option<String>,
+        #[serde(default)]
+        if_match: Option<String>,
+    },
+    Get {
+        path: String,
+        /// How long the URL works, in seconds. Defaults to a few minutes.
+        #[serde(default)]
+        expires: Option<u64>,
+    },
+    Delete {
+        path: String,
+    },
+    UploadPart {
+        path: String,
+        upload_id: String,
+        number: i32,
+        content_md5: String,
+    },
+}
+
+/// What `klistra daemon` does in the bucket itself at `POST /bucket`, for
+/// requests that can't be presigned.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "snake_case")]
+pub enum BucketRequest {
+    List {
+        path: String,
+    },
+    DeletePrefix {
+        path: String,
+    },
+    MovePrefix {
+        from: String,
+        to: String,
+    },
+    CreateMultipart {
+        path: String,
+        content_type: String,
+        #[serde(default)]
+        labels: Labels,
+    },
+    CompleteMultipart {
+        path: String,
+        upload_id: String,
+        parts: Vec<(i32, String)>,
+    },
+    AbortMultipart {
+        path: String,
+        upload_id: String,
+    },
+}
+
+/// Carries out `request` on `bucket` for a delegated client, answering with
+/// what the [`Publisher`] method returned.
+pub async fn perform(
+    bucket: &dyn Publisher,
+    request: &BucketRequest,
+) -> Result<serde_json::Value, Box<dyn Error>> {
+    Ok(match request {
+        BucketRequest::List { path } => serde_json::to_value(bucket.list(path).await?)?,
+        BucketRequest::DeletePrefix { path } => bucket.delete_prefix(path).await?.into(),
+        BucketRequest::MovePrefix { from, to } => bucket.move_prefix(from, to).await?.into(),
+        BucketRequest::CreateMultipart {
+            path,
+            content_type,
+            labels,
+        } => bucket
+            .create_multipart(path, content_type, labels)
+            .await?
+            .into(),
+        BucketRequest::CompleteMultipart {
+            path,
+            upload_id,
+            parts,
+        } => {
+            bucket.complete_multipart(path, upload_id, parts).await?;
+            serde_json::Value::Null
+        }
+        BucketRequest::AbortMultipart { path, upload_id } => {
+            bucket.abort_multipart(path, upload_id).await?;
+            serde_json::Value::Null
+        }
+    })
+}
+
+/// A presigned request: send it as is, with these headers, before it expires.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Signed {
+    pub method: String,
+    pub url: String,
+    #[serde(default)]
+    pub headers: BTreeMap<String, String>,
+}
+
+/// How long what [`SignRequest`] asks for stays valid, unless it says.
+pub const SIGNED_FOR: Duration = Duration::from_secs(15 * 60);
+
+/// A [`Publisher`] holding no bucket credentials: every request is signed by
+/// the `klistra daemon` in `[delegate]` and then sent straight to the bucket.
+pub struct Delegated {
+    http: reqwest::Client,
+    url: String,
+    token: String,
+    domain: String,
+}
+
+impl Delegated {
+    pub fn new(config: &AppConfig) -> Result<Self, Box<dyn Error>> {
+        let DelegateConfig { url, token } = config
+            .delegate
+            .as_ref()
+            .ok_or("backend = \"delegated\" requires a [delegate] section with the signer's url")?;
+        Ok(Self {
+            http: http::client(&config.tls)?,
+            url: url.trim_end_matches('/').to_string(),
+            token: token.clone(),
+            domain: config.s3.domain.trim_end_matches('/').to_string(),
+        })
+    }
+
+    /// Posts `request` to the signer's `endpoint` and returns its answer.
+    async fn call<T: serde::de::DeserializeOwned>(
+        &self,
+        endpoint: &str,
+        request: &impl Serialize,
+    ) -> Result<T, Box<dyn Error>> {
+        let response = self
+            .http
+            .post(format!("{}/{}", self.url, endpoint))
+            .bearer_auth(&self.token)
+            .json(request)
+            .send()
+            .await
+            .map_err(|e| format!("Could not reach the signer at {}: {}", self.url, e))?;
+        let status = response.status();
+        if !status.is_success() {
+            let body = response.text().await.unwrap_or_default();
+            return Err(format!("The signer refused ({}): {}", status, body.trim()).into());
+        }
+        Ok(response.json().await?)
+    }
+
+    /// Has the signer sign `request`.
+    async fn sign(&self, request: &SignRequest) -> Result<Signed, Box<dyn Error>> {
+        self.call("sign", request).await
+    }
+
+    /// Signs `request` and sends it with `body`.
+    async fn send(
+        &self,
+        request: &SignRequest,
+        body: Vec<u8>,
+    ) -> Result<reqwest::Response, Box<dyn Error>> {
+        let signed = self.sign(request).await?;
+        let method = Method::from_bytes(signed.method.as_bytes())?;
+        let mut builder = self.http.request(method, &signed.url);
+        for (name, value) in &signed.headers {
+            builder = builder.header(name, value);
+        }
+        Ok(builder.body(body).send().await?)
+    }
+
+    async fn put_object(
+        &self,
+        path: &str,
+        body: Vec<u8>,
+        content_type: &str,
+        file_name: Option<&str>,
+        labels: &Labels,
+    ) -> Result<(), Box<dyn Error>> {
+        let digest = Md5::digest(&body);
+        let request = SignRequest::Put {
+            path: path.to_string(),
+            content_type: content_type.to_string(),
+            content_md5: base64::engine::general_purpose::STANDARD.encode(digest),
+            file_name: file_name.map(str::to_string),
+            labels: labels.clone(),
+            location: None,
+            if_none_match: None,
+            if_match: None,
+        };
+        let response = self.send(&request, body).await?;
+        let response = checked(path, response).await?;
+        verify(path, &response, &hex::encode(digest))
+    }
+}
+
+/// `response`, or its status and body as an error if it failed.
+async fn checked(
+    path: &str,
+    response: reqwest::Response,
+) -> Result<reqwest::Response, Box<dyn Error>> {
+    let status = response.status();
+    if status.is_success() {
+        return Ok(response);
+    }
+    let body = response.text().await.unwrap_or_default();
+    Err(format!(
+        "The bucket answered {} for {}: {}",
+        status,
+        path,
+        body.trim()
+    )
+    .into())
+}
+
+/// Compares the ETag of an upload with the `md5` of what was sent, where the
+/// ETag is a plain MD5.
+fn verify(path: &str, response: &reqwest::Response, md5: &str) -> Result<(), Box<dyn Error>> {
+    let etag = etag(response);
+    let etag = etag.trim_matches('"');
+    if etag.len() == 32
+        && etag.bytes().all(|b| b.is_ascii_hexdigit())
+        && !etag.eq_ignore_ascii_case(md5)
+    {
+        return Err(format!(
+            "Checksum mismatch after uploading {}: sent MD5 {}, the bucket has {}. Publish again to replace it.",
+            path, md5, etag
+        )
+        .into());
+    }
+    Ok(())
+}
+
+fn etag(response: &reqwest::Response) -> String {
+    response
+        .headers()
+        .get(reqwest::header::ETAG)
+        .and_then(|value| value.to_str().ok())
+        .unwrap_or_default()
+        .to_string()
+}
+
+#[async_trait]
+impl Publisher for Delegated {
+    fn host(&self) -> &str {
+        self.domain
+            .split_once("://")
+            .map_or(self.domain.as_str(), |(_, host)| host)
+    }
+
+    fn public_url(&self, path: &str) -> String {
+        format!("{}/{}", self.domain, path)
+    }
+
+    async fn put(
+        &self,
+        path: &str,
+        body: Vec<u8>,
+        content_type: &str,
+        labels: &Labels,
+    ) -> Result<(), Box<dyn Error>> {
+        self.put_object(path, body, content_type, None, labels)
+            .await
+    }
+
+    async fn put_download(
+        &self,
+        path: &str,
+        body: Vec<u8>,
+        content_type: &str,
+        file_name: &str,
+        labels: &Labels,
+    ) -> Result<(), Box<dyn Error>> {
+        self.put_object(path, body, content_type, Some(file_name), labels)
+            .await
+    }
+
+    async fn put_redirect(&self, path: &str, location: &str) -> Result<(), Box<dyn Error>> {
+        let request = SignRequest::Put {
+            path: path.to_string(),
+            content_type: "text/html".to_string(),
+            content_md5: base64::engine::general_purpose::STANDARD.encode(Md5::digest(b"")),
+            file_name: None,
+            labels: Labels::new(),
+            location: Some(location.to_string()),
+            if_none_match: None,
+            if_match: None,
+        };
+        let response = self.send(&request, Vec::new()).await?;
+        checked(path, response).await?;
+        Ok(())
+    }
+
+    async fn delete(&self, path: &str) -> Result<(), Box<dyn Error>> {
+        let request = SignRequest::Delete {
+            path: path.to_string(),
+        };
+        let response = self.send(&request, Vec::new()).await?;
+        checked(path, response).await?;
+        Ok(())
+    }
+
+    async fn list(&self, path: &str) -> Result<Vec<String>, Box<dyn Error>> {
+        let request = BucketRequest::List {
+            path: path.to_string(),
+        };
+        self.call("bucket", &request).await
+    }
+
+    async fn fetch(&self, path: &str) -> Result<Option<Vec<u8>>, Box<dyn Error>> {
+        Ok(self.fetch_tagged(path).await?.map(|(body, _)| body))
+    }
+
+    async fn fetch_tagged(&self, path: &str) -> Result<Option<(Vec<u8>, String)>, Box<dyn Error>> {
+        let request = SignRequest::Get {
+            path: path.to_string(),
+            expires: None,
+        };
+        let response = self.send(&request, Vec::new()).await?;
+        if response.status() == StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+        let response = checked(path, response).await?;
+        let etag = etag(&response);
+        Ok(Some((response.bytes().await?.to_vec(), etag)))
+    }
+
+    async fn put_if(
+        &self,
+        path: &str,
+        body: Vec<u8>,
+        content_type: &str,
+        precondition: &Precondition,
+    ) -> Result<bool, Box<dyn Error>> {
+        let digest = Md5::digest(&body);
+        let (if_none_match, if_match) = match precondition {
+            Precondition::Absent => (Some("*".to_string()), None),
+            Precondition::Matches(etag) => (None, Some(etag.clone())),
+        };
+        let request = SignRequest::Put {
+            path: path.to_string(),
+            content_type: content_type.to_string(),
+            content_md5: base64::engine::general_purpose::STANDARD.encode(digest),
+            file_name: None,
+            labels: Labels::new(),
+            location: None,
+            if_none_match,
+            if_match,
+        };
+        let response = self.send(&request, body).await?;
+        // 412 when the precondition fails, 409 when another conditional
+        // write to the key is in flight.
+        if matches!(response.status().as_u16(), 409 | 412) {
+            return Ok(false);
+        }
+        let response = checked(path, response).await?;
+        verify(path, &response, &hex::encode(digest))?;
+        Ok(true)
+    }
+
+    async fn presign(&self, path: &str, expires: Duration) -> Result<String, Box<dyn Error>> {
+        let request = SignRequest::Get {
+            path: path.to_string(),
+            expires: Some(expires.as_secs()),
+        };
+        Ok(self.sign(&request).await?.url)
+    }
+
+    async fn delete_prefix(&self, path: &str) -> Result<usize, Box<dyn Error>> {
+        let request = BucketRequest::DeletePrefix {
+            path: path.to_string(),
+        };
+        self.call("bucket", &request).await
+    }
+
+    async fn move_prefix(&self, from: &str, to: &str) -> Result<usize, Box<dyn Error>> {
+        let request = BucketRequest::MovePrefix {
+            from: from.to_string(),
+            to: to.to_string(),
+        };
+        self.call("bucket", &request).await
+    }
+
+    async fn create_multipart(
+        &self,
+        path: &str,
+        content_type: &str,
+        labels: &Labels,
+    ) -> Result<String, Box<dyn Error>> {
+        let request = BucketRequest::CreateMultipart {
+            path: path.to_string(),
+            content_type: content_type.to_string(),
+            labels: labels.clone(),
+        };
+        self.call("bucket", &request).await
+    }
+
+    async fn upload_part(
+        &self,
+        path: &str,
+        upload_id: &str,
+        number: i32,
+        body: Vec<u8>,
+    ) -> Result<String, Box<dyn Error>> {
+        let digest = Md5::digest(&body);
+        let request = SignRequest::UploadPart {
+            path: path.to_string(),
+            upload_id: upload_id.to_string(),
+            number,
+            content_md5: base64::engine::general_purpose::STANDARD.encode(digest),
+        };
+        let response = self.send(&request, body).await?;
+        let response = checked(path, response).await?;
+        verify(
+            &format!("{} (part {})", path, number),
+            &response,
+            &hex::encode(digest),
+        )?;
+        match etag(&response) {
+            etag if etag.is_empty() => Err("No ETag in response".into()),
+            etag => Ok(etag),
+        }
+    }
+
+    async fn abort_multipart(&self, path: &str, upload_id: &str) -> Result<(), Box<dyn Error>> {
+        let request = BucketRequest::AbortMultipart {
+            path: path.to_string(),
+            upload_id: upload_id.to_string(),
+        };
+        self.call::<serde_json::Value>("bucket", &request).await?;
+        Ok(())
+    }
+
+    async fn complete_multipart(
+        &self,
+        path: &str,
+        upload_id: &str,
+        parts: &[(i32, String)],
+    ) -> Result<(), Box<dyn Error>> {
+        let request = BucketRequest::CompleteMultipart {
+            path: path.to_string(),
+            upload_id: upload_id.to_string(),
+            parts: parts.to_vec(),
+        };
+        self.call::<serde_json::Value>("bucket", &request).await?;
+        Ok(())
+    }
+}
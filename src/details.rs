@@ -0,0 +1,75 @@
+use crate::render::escape_html;
+use std::{borrow::Cow, error::Error};
+
+/// Summary of a section opened with a bare `:::details`.
+const DEFAULT_SUMMARY: &str = "Details";
+
+/// Replaces `:::details Title` ... `:::` containers with collapsible
+/// `<details>` sections, titled with the text after `:::details`. Sections
+/// can be nested; lines inside fenced code blocks are left alone.
+pub fn expand_details(markdown: &str) -> Result<Cow<'_, str>, Box<dyn Error>> {
+    if !markdown.contains(":::") {
+        return Ok(Cow::Borrowed(markdown));
+    }
+
+    let mut expanded = String::with_capacity(markdown.len());
+    let mut fence: Option<&str> = None;
+    // Summaries of the sections still open.
+    let mut open = Vec::new();
+    for line in markdown.lines() {
+        let trimmed = line.trim();
+        if let Some(marker) = fence {
+            // Closed by a fence at least as long, with nothing after it.
+            let fence_char = &marker[..1];
+            if trimmed.starts_with(marker) && trimmed.trim_start_matches(fence_char).is_empty() {
+                fence = None;
+            }
+        } else if let Some(marker) = fence_marker(trimmed) {
+            fence = Some(marker);
+        } else if let Some(summary) = details_opener(trimmed) {
+            let summary = match summary {
+                "" => DEFAULT_SUMMARY,
+                summary => summary,
+            };
+            // Blank lines keep the HTML separate from the markdown inside.
+            expanded.push_str(&format!(
+                "\n<details>\n<summary>{}</summary>\n\n",
+                escape_html(summary)
+            ));
+            open.push(summary);
+            continue;
+        } else if trimmed == ":::" && open.pop().is_some() {
+            expanded.push_str("\n</details>\n\n");
+            continue;
+        }
+        expanded.push_str(line);
+        expanded.push('\n');
+    }
+    if let Some(summary) = open.pop() {
+        return Err(format!(
+            "The :::details section '{}' is never closed with :::",
+            summary
+        )
+        .into());
+    }
+    Ok(Cow::Owned(expanded))
+}
+
+/// The summary after `:::details` (or `::: details`), if `line` opens a section.
+fn details_opener(line: &str) -> Option<&str> {
+    let rest = line
+        .strip_prefix(":::")?
+        .trim_start()
+        .strip_prefix("details")?;
+    if !rest.is_empty() && !rest.starts_with(char::is_whitespace) {
+        return None;
+    }
+    Some(rest.trim())
+}
+
+/// The backticks or tildes that open a fenced code block on `line`.
+fn fence_marker(line: &str) -> Option<&str> {
+    let fence_char = line.chars().next().filter(|c| *c == '`' || *c == '~')?;
+    let length = line.len() - line.trim_start_matches(fence_char).len();
+    (length >= 3).then(|| &line[..length])
+}
@@ -0,0 +1,649 @@
+use crate::media::Media;
+use crate::render::escape_html;
+use base64::Engine;
+use quick_xml::escape::resolve_predefined_entity;
+use quick_xml::events::{BytesRef, BytesStart, Event};
+use quick_xml::{Reader, XmlVersion};
+use sha2::{Digest, Sha256};
+use std::{
+    collections::{BTreeMap, HashMap},
+    error::Error,
+    io::{Cursor, Read},
+};
+use zip::ZipArchive;
+
+/// Images browsers show, by the extension Word saves them with. Word's own
+/// EMF and WMF drawings are left out.
+const IMAGE_TYPES: &[(&str, &str)] = &[
+    ("png", "image/png"),
+    ("jpg", "image/jpeg"),
+    ("jpeg", "image/jpeg"),
+    ("gif", "image/gif"),
+    ("webp", "image/webp"),
+    ("svg", "image/svg+xml"),
+];
+
+/// Converts a Word document to a source klistra renders: front matter with
+/// its title and author, then its body as HTML. Headings, lists, tables,
+/// links, quotes, bold and italic text and images are kept; Word's fonts,
+/// colours and spacing aren't. Images are inlined as `data:` URLs until
+/// [`extract_images`] uploads them with the post.
+///
+/// The title is the paragraph styled Title, or the document's title
+/// property.
+pub fn convert(bytes: &[u8]) -> Result<String, Box<dyn Error>> {
+    let mut archive =
+        ZipArchive::new(Cursor::new(bytes)).map_err(|e| format!("Not a .docx: {}", e))?;
+    let document = part(&mut archive, "word/document.xml")?
+        .ok_or("Not a .docx: word/document.xml is missing")?;
+    let styles = part(&mut archive, "word/styles.xml")?
+        .map(|xml| style_names(&xml))
+        .transpose()?
+        .unwrap_or_default();
+    let numbering = part(&mut archive, "word/numbering.xml")?
+        .map(|xml| Numbering::read(&xml))
+        .transpose()?
+        .unwrap_or_default();
+    let relationships = part(&mut archive, "word/_rels/document.xml.rels")?
+        .map(|xml| relationships(&xml))
+        .transpose()?
+        .unwrap_or_default();
+    let properties = part(&mut archive, "docProps/core.xml")?
+        .map(|xml| core_properties(&xml))
+        .transpose()?
+        .unwrap_or_default();
+
+    let mut converter = Converter {
+        styles: &styles,
+        numbering: &numbering,
+        relationships: &relationships,
+        archive: &mut archive,
+        html: String::new(),
+        title: None,
+        lists: Vec::new(),
+    };
+    converter.body(&document)?;
+    let html = converter.html;
+
+    let mut front_matter = BTreeMap::new();
+    if let Some(title) = converter.title.or(properties.get("title").cloned()) {
+        front_matter.insert("title", title);
+    }
+    if let Some(author) = properties.get("creator") {
+        front_matter.insert("author", author.clone());
+    }
+    if front_matter.is_empty() {
+        return Ok(html);
+    }
+    Ok(format!(
+        "---\n{}---\n\n{}",
+        serde_yaml::to_string(&front_matter)?,
+        html
+    ))
+}
+
+/// Moves the `data:` images [`convert`] inlined out of `html` into files
+/// that go up next to the page as `images/<hash>.<ext>`, adding them to
+/// `media`.
+pub fn extract_images(html: &str, media: &mut Vec<Media>) -> Result<String, Box<dyn Error>> {
+    const START: &str = "src=\"data:";
+    let mut out = String::with_capacity(html.len());
+    let mut rest = html;
+    while let Some(start) = rest.find(START) {
+        out.push_str(&rest[..start + 5]);
+        let data = &rest[start + START.len()..];
+        let end = data.find('"').unwrap_or(data.len());
+        rest = &data[end..];
+        let Some((content_type, encoded)) = data[..end].split_once(";base64,") else {
+            out.push_str("data:");
+            out.push_str(&data[..end]);
+            continue;
+        };
+        let extension = IMAGE_TYPES
+            .iter()
+            .find(|(_, known)| *known == content_type)
+            .map_or("bin", |(extension, _)| *extension);
+        let bytes = base64::engine::general_purpose::STANDARD.decode(encoded)?;
+        let hash = hex::encode(Sha256::digest(&bytes));
+        let path = format!("images/{}.{}", &hash[..16], extension);
+        let dir = std::env::temp_dir().join("klistra-docx");
+        std::fs::create_dir_all(&dir)?;
+        let source = dir.join(format!("{}.{}", hash, extension));
+        if !source.exists() {
+            std::fs::write(&source, &bytes)?;
+        }
+        out.push_str(&path);
+        if !media.iter().any(|item| item.path == path) {
+            media.push(Media {
+                path,
+                source,
+                poster: None,
+                download: false,
+            });
+        }
+    }
+    out.push_str(rest);
+    Ok(out)
+}
+
+/// A file in the document's zip, if it's there.
+fn part(
+    archive: &mut ZipArchive<Cursor<&[u8]>>,
+    name: &str,
+) -> Result<Option<String>, Box<dyn Error>> {
+    let mut file = match archive.by_name(name) {
+        Ok(file) => file,
+        Err(zip::result::ZipError::FileNotFound) => return Ok(None),
+        Err(e) => return Err(e.into()),
+    };
+    let mut text = String::new();
+    file.read_to_string(&mut text)?;
+    Ok(Some(text))
+}
+
+fn bytes_of(archive: &mut ZipArchive<Cursor<&[u8]>>, name: &str) -> Option<Vec<u8>> {
+    let mut file = archive.by_name(name).ok()?;
+    let mut bytes = Vec::new();
+    file.read_to_end(&mut bytes).ok()?;
+    Some(bytes)
+}
+
+/// The value of the attribute called `name`, whatever its namespace.
+fn attribute(element: &BytesStart, name: &str) -> Option<String> {
+    element.attributes().flatten().find_map(|attribute| {
+        (attribute.key.local_name().as_ref() == name)
+            .then(|| attribute.normalized_value(XmlVersion::Implicit1_0).ok())
+            .flatten()
+            .map(|value| value.into_owned())
+    })
+}
+
+fn local_name(element: &BytesStart) -> String {
+    element.local_name().as_ref().to_string()
+}
+
+/// Paragraph style names (`heading 1`, `Title`) by their IDs, which are
+/// translated in documents written in other languages while the names
+/// aren't.
+fn style_names(xml: &str) -> Result<HashMap<String, String>, Box<dyn Error>> {
+    let mut reader = Reader::from_str(xml);
+    let mut names = HashMap::new();
+    let mut current = None;
+    loop {
+        match reader.read_event()? {
+            Event::Start(element) | Event::Empty(element) => match local_name(&element).as_str() {
+                "style" => current = attribute(&element, "styleId"),
+                "name" => {
+                    if let (Some(id), Some(name)) = (&current, attribute(&element, "val")) {
+                        names.insert(id.clone(), name.to_lowercase());
+                    }
+                }
+                _ => {}
+            },
+            Event::Eof => break,
+            _ => {}
+        }
+    }
+    Ok(names)
+}
+
+/// Which lists are numbered rather than bulleted.
+#[derive(Default)]
+struct Numbering {
+    /// List (`numId`) to its definition (`abstractNumId`).
+    lists: HashMap<String, String>,
+    /// Definition and level to whether that level is numbered.
+    ordered: HashMap<(String, String), bool>,
+}
+
+impl Numbering {
+    fn read(xml: &str) -> Result<Self, Box<dyn Error>> {
+        let mut reader = Reader::from_str(xml);
+        let mut numbering = Self::default();
+        let (mut definition, mut level, mut list) = (None, None, None);
+        loop {
+            match reader.read_event()? {
+                Event::Start(element) | Event::Empty(element) => {
+                    match local_name(&element).as_str() {
+                        "abstractNum" => definition = attribute(&element, "abstractNumId"),
+                        "lvl" => level = attribute(&element, "ilvl"),
+                        "numFmt" => {
+                            if let (Some(definition), Some(level), Some(format)) =
+                                (&definition, &level, attribute(&element, "val"))
+                            {
+                                numbering.ordered.insert(
+                                    (definition.clone(), level.clone()),
+                                    format != "bullet",
+                                );
+                            }
+                        }
+                        "num" => list = attribute(&element, "numId"),
+                        "abstractNumId" => {
+                            if let (Some(list), Some(definition)) =
+                                (&list, attribute(&element, "val"))
+                            {
+                                numbering.lists.insert(list.clone(), definition);
+                            }
+                        }
+                        _ => {}
+                    }
+                }
+                Event::Eof => break,
+                _ => {}
+            }
+        }
+        Ok(numbering)
+    }
+
+    fn is_ordered(&self, list: &str, level: usize) -> bool {
+        self.lists
+            .get(list)
+            .and_then(|definition| {
+                self.ordered
+                    .get(&(definition.clone(), level.to_string()))
+                    .copied()
+            })
+            .unwrap_or(false)
+    }
+}
+
+/// Where the document's links and images point, by relationship ID, with
+/// images as paths in the zip.
+fn relationships(xml: &str) -> Result<HashMap<String, String>, Box<dyn Error>> {
+    let mut reader = Reader::from_str(xml);
+    let mut targets = HashMap::new();
+    loop {
+        match reader.read_event()? {
+            Event::Start(element) | Event::Empty(element)
+                if local_name(&element) == "Relationship" =>
+            {
+                let (Some(id), Some(target)) =
+                    (attribute(&element, "Id"), attribute(&element, "Target"))
+                else {
+                    continue;
+                };
+                let external = attribute(&element, "TargetMode").as_deref() == Some("External");
+                let target = match target.strip_prefix('/') {
+                    Some(absolute) => absolute.to_string(),
+                    None if external => target,
+                    None => format!("word/{}", target),
+                };
+                targets.insert(id, target);
+            }
+            Event::Eof => break,
+            _ => {}
+        }
+    }
+    Ok(targets)
+}
+
+/// `dc:title`, `dc:creator` and the rest of the document's properties,
+/// by local name.
+fn core_properties(xml: &str) -> Result<HashMap<String, String>, Box<dyn Error>> {
+    let mut reader = Reader::from_str(xml);
+    let mut properties = HashMap::new();
+    let mut current: Option<(String, String)> = None;
+    loop {
+        match reader.read_event()? {
+            Event::Start(element) => current = Some((local_name(&element), String::new())),
+            Event::Text(text) => {
+                if let Some((_, value)) = &mut current {
+                    value.push_str(&text.xml10_content());
+                }
+            }
+            Event::GeneralRef(reference) => {
+                if let Some((_, value)) = &mut current {
+                    value.push_str(&reference_text(&reference)?);
+                }
+            }
+            Event::End(_) => {
+                if let Some((name, value)) = current.take() {
+                    if !value.trim().is_empty() {
+                        properties.insert(name, value.trim().to_string());
+                    }
+                }
+            }
+            Event::Eof => break,
+            _ => {}
+        }
+    }
+    Ok(properties)
+}
+
+/// The character an entity or character reference stands for.
+fn reference_text(reference: &BytesRef) -> Result<String, Box<dyn Error>> {
+    Ok(match reference.resolve_char_ref()? {
+        Some(c) => c.to_string(),
+        None => resolve_predefined_entity(reference)
+            .unwrap_or_default()
+            .to_string(),
+    })
+}
+
+/// What a paragraph's style makes it.
+#[derive(Clone, Copy, PartialEq)]
+enum Block {
+    Paragraph,
+    Heading(u8),
+    Title,
+    Quote,
+    Code,
+}
+
+/// A paragraph being read.
+struct Paragraph {
+    block: Block,
+    /// List ID and level, for list items.
+    list: Option<(String, usize)>,
+    html: String,
+    /// Its text without formatting, for titles and code.
+    text: String,
+}
+
+/// How a run of text is formatted.
+#[derive(Default, Clone, Copy)]
+struct Format {
+    bold: bool,
+    italic: bool,
+    strike: bool,
+    superscript: bool,
+    subscript: bool,
+}
+
+impl Format {
+    fn tags(&self) -> Vec<&'static str> {
+        [
+            (self.bold, "strong"),
+            (self.italic, "em"),
+            (self.strike, "s"),
+            (self.superscript, "sup"),
+            (self.subscript, "sub"),
+        ]
+        .iter()
+        .filter(|(on, _)| *on)
+        .map(|(_, tag)| *tag)
+        .collect()
+    }
+}
+
+struct Converter<'a, 'b> {
+    styles: &'a HashMap<String, String>,
+    numbering: &'a Numbering,
+    relationships: &'a HashMap<String, String>,
+    archive: &'a mut ZipArchive<Cursor<&'b [u8]>>,
+    html: String,
+    title: Option<String>,
+    /// The lists open around the current paragraph, innermost last, and
+    /// whether each is numbered.
+    lists: Vec<bool>,
+}
+
+impl Converter<'_, '_> {
+    fn body(&mut self, xml: &str) -> Result<(), Box<dyn Error>> {
+        let mut reader = Reader::from_str(xml);
+        // Local names of the elements we're in.
+        let mut open: Vec<String> = Vec::new();
+        let mut paragraph: Option<Paragraph> = None;
+        let mut format = Format::default();
+        let mut tables = 0usize;
+        // Deleted text in tracked changes, and field codes, aren't shown.
+        let mut hidden = 0usize;
+        let mut links: Vec<bool> = Vec::new();
+        let mut alt = None;
+        loop {
+            let event = reader.read_event()?;
+            let (element, empty) = match &event {
+                Event::Start(element) => (Some(element.clone()), false),
+                Event::Empty(element) => (Some(element.clone()), true),
+                _ => (None, false),
+            };
+            if let Some(element) = element {
+                let name = local_name(&element);
+                let in_run_properties = open.last().is_some_and(|parent| parent == "rPr")
+                    && open.iter().rev().nth(1).is_some_and(|parent| parent == "r");
+                match name.as_str() {
+                    "del" | "instrText" | "delText" if !empty => hidden += 1,
+                    "tbl" if !empty => {
+                        self.close_lists();
+                        tables += 1;
+                        self.html.push_str("<table>\n");
+                    }
+                    "tr" if !empty => self.html.push_str("<tr>"),
+                    "tc" if !empty => self.html.push_str("<td>"),
+                    "p" => {
+                        paragraph = Some(Paragraph {
+                            block: Block::Paragraph,
+                            list: None,
+                            html: String::new(),
+                            text: String::new(),
+                        });
+                        if empty {
+                            paragraph = None;
+                        }
+                    }
+                    "pStyle" => {
+                        if let (Some(paragraph), Some(style)) =
+                            (&mut paragraph, attribute(&element, "val"))
+                        {
+                            paragraph.block = self.block(&style);
+                        }
+                    }
+                    "ilvl" | "numId" => {
+                        if let (Some(paragraph), Some(value)) =
+                            (&mut paragraph, attribute(&element, "val"))
+                        {
+                            let (list, level) = paragraph.list.get_or_insert((String::new(), 0));
+                            if name == "ilvl" {
+                                *level = value.parse().unwrap_or(0);
+                            } else {
+                                *list = value;
+                            }
+                        }
+                    }
+                    "r" if !empty => format = Format::default(),
+                    "b" | "i" | "strike" | "dstrike" | "vertAlign" if in_run_properties => {
+                        let value = attribute(&element, "val");
+                        let on = !matches!(value.as_deref(), Some("0" | "false" | "none"));
+                        match name.as_str() {
+                            "b" => format.bold = on,
+                            "i" => format.italic = on,
+                            "vertAlign" => {
+                                format.superscript = value.as_deref() == Some("superscript");
+                                format.subscript = value.as_deref() == Some("subscript");
+                            }
+                            _ => format.strike = on,
+                        }
+                    }
+                    "tab" if hidden == 0 && open.last().is_some_and(|parent| parent == "r") => {
+                        self.text(&mut paragraph, format, "\t")
+                    }
+                    "br" | "cr" if hidden == 0 => {
+                        if let Some(paragraph) = &mut paragraph {
+                            paragraph.html.push_str("<br>");
+                        }
+                    }
+                    "hyperlink" if !empty => {
+                        let href = attribute(&element, "id")
+                            .and_then(|id| self.relationships.get(&id).cloned())
+                            .or_else(|| {
+                                attribute(&element, "anchor").map(|anchor| format!("#{}", anchor))
+                            });
+                        if let (Some(paragraph), Some(href)) = (&mut paragraph, &href) {
+                            paragraph
+                                .html
+                                .push_str(&format!("<a href=\"{}\">", escape_html(href)));
+                        }
+                        links.push(href.is_some() && paragraph.is_some());
+                    }
+                    // The alt text comes before the image itself.
+                    "docPr" => alt = attribute(&element, "descr"),
+                    "blip" => {
+                        let image = self.image(&element, alt.take().unwrap_or_default());
+                        if let (Some(paragraph), Some(image)) = (&mut paragraph, image) {
+                            paragraph.html.push_str(&image);
+                        }
+                    }
+                    _ => {}
+                }
+                if !empty {
+                    open.push(name);
+                }
+                continue;
+            }
+            match event {
+                Event::Text(text) if hidden == 0 && open.last().is_some_and(|name| name == "t") => {
+                    let text = text.xml10_content();
+                    self.text(&mut paragraph, format, &text);
+                }
+                Event::GeneralRef(reference)
+                    if hidden == 0 && open.last().is_some_and(|name| name == "t") =>
+                {
+                    let text = reference_text(&reference)?;
+                    self.text(&mut paragraph, format, &text);
+                }
+                Event::End(_) => {
+                    let Some(name) = open.pop() else {
+                        continue;
+                    };
+                    match name.as_str() {
+                        "del" | "instrText" | "delText" => hidden = hidden.saturating_sub(1),
+                        "p" => {
+                            if let Some(paragraph) = paragraph.take() {
+                                self.paragraph(paragraph, tables > 0);
+                            }
+                        }
+                        "hyperlink" if links.pop() == Some(true) => {
+                            if let Some(paragraph) = &mut paragraph {
+                                paragraph.html.push_str("</a>");
+                            }
+                        }
+                        "tc" => self.html.push_str("</td>"),
+                        "tr" => self.html.push_str("</tr>\n"),
+                        "tbl" => {
+                            tables = tables.saturating_sub(1);
+                            self.html.push_str("</table>\n");
+                        }
+                        _ => {}
+                    }
+                }
+                Event::Eof => break,
+                _ => {}
+            }
+        }
+        self.close_lists();
+        Ok(())
+    }
+
+    fn block(&self, style: &str) -> Block {
+        let name = self
+            .styles
+            .get(style)
+            .cloned()
+            .unwrap_or_else(|| style.to_lowercase());
+        let compact: String = name.chars().filter(|c| !c.is_whitespace()).collect();
+        if let Some(level) = compact.strip_prefix("heading") {
+            if let Ok(level @ 1..=6) = level.parse::<u8>() {
+                return Block::Heading(level);
+            }
+        }
+        match compact.as_str() {
+            "title" => Block::Title,
+            "quote" | "intensequote" | "blocktext" => Block::Quote,
+            name if name.contains("code") || name.contains("preformatted") => Block::Code,
+            _ => Block::Paragraph,
+        }
+    }
+
+    fn text(&self, paragraph: &mut Option<Paragraph>, format: Format, text: &str) {
+        let Some(paragraph) = paragraph else {
+            return;
+        };
+        let tags = format.tags();
+        for tag in &tags {
+            paragraph.html.push_str(&format!("<{}>", tag));
+        }
+        paragraph.html.push_str(&escape_html(text));
+        paragraph.text.push_str(text);
+        for tag in tags.iter().rev() {
+            paragraph.html.push_str(&format!("</{}>", tag));
+        }
+    }
+
+    /// An `<img>` for the picture `blip` embeds, inlined.
+    fn image(&mut self, blip: &BytesStart, alt: String) -> Option<String> {
+        let target = self.relationships.get(&attribute(blip, "embed")?)?.clone();
+        let extension = target.rsplit('.').next()?.to_ascii_lowercase();
+        let (_, content_type) = IMAGE_TYPES.iter().find(|(known, _)| *known == extension)?;
+        let bytes = bytes_of(self.archive, &target)?;
+        Some(format!(
+            "<img src=\"data:{};base64,{}\" alt=\"{}\">",
+            content_type,
+            base64::engine::general_purpose::STANDARD.encode(bytes),
+            escape_html(&alt)
+        ))
+    }
+
+    fn paragraph(&mut self, paragraph: Paragraph, in_table: bool) {
+        let html = paragraph.html;
+        if html.trim().is_empty() {
+            return;
+        }
+        if paragraph.block == Block::Title && self.title.is_none() && !in_table {
+            self.title = Some(paragraph.text.trim().to_string());
+            return;
+        }
+        match (&paragraph.list, in_table) {
+            (Some((list, level)), false) if !list.is_empty() && list != "0" => {
+                self.list_item(list, *level);
+                self.html.push_str(&html);
+                return;
+            }
+            _ if !in_table => self.close_lists(),
+            _ => {}
+        }
+        let html = match paragraph.block {
+            Block::Heading(level) => format!("<h{0}>{1}</h{0}>", level, html),
+            Block::Quote => format!("<blockquote><p>{}</p></blockquote>", html),
+            Block::Code => format!("<pre><code>{}</code></pre>", escape_html(&paragraph.text)),
+            Block::Paragraph | Block::Title => format!("<p>{}</p>", html),
+        };
+        self.html.push_str(&html);
+        if !in_table {
+            self.html.push('\n');
+        }
+    }
+
+    /// Opens or closes lists so the next item is at `level` of `list`, and
+    /// starts it.
+    fn list_item(&mut self, list: &str, level: usize) {
+        while self.lists.len() > level + 1 {
+            self.close_list();
+        }
+        if self.lists.len() == level + 1 {
+            self.html.push_str("</li>\n");
+        }
+        while self.lists.len() < level + 1 {
+            let ordered = self.numbering.is_ordered(list, self.lists.len());
+            self.html
+                .push_str(if ordered { "\n<ol>\n" } else { "\n<ul>\n" });
+            self.lists.push(ordered);
+        }
+        self.html.push_str("<li>");
+    }
+
+    fn close_list(&mut self) {
+        if let Some(ordered) = self.lists.pop() {
+            self.html.push_str(if ordered {
+                "</li>\n</ol>\n"
+            } else {
+                "</li>\n</ul>\n"
+            });
+        }
+    }
+
+    fn close_lists(&mut self) {
+        while !self.lists.is_empty() {
+            self.close_list();
+        }
+    }
+}
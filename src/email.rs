@@ -0,0 +1,86 @@
+use crate::config::{EmailConfig, SmtpSecurity};
+use crate::manifest::Post;
+use lettre::message::{header::ContentType, MultiPart};
+use lettre::transport::smtp::authentication::Credentials;
+use lettre::{AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor};
+use std::error::Error;
+
+/// Emails the link to a published post, optionally with the rendered page as
+/// the HTML body.
+pub async fn send_link(
+    config: &EmailConfig,
+    recipients: &[String],
+    post: &Post,
+    html: Option<&str>,
+) -> Result<(), Box<dyn Error>> {
+    let mut builder = Message::builder()
+        .from(config.from.parse()?)
+        .subject(&post.title);
+    for recipient in recipients {
+        builder = builder.to(recipient
+            .parse()
+            .map_err(|e| format!("Invalid email address '{}': {}", recipient, e))?);
+    }
+
+    let text = match &post.description {
+        Some(description) => format!("{}\n{}\n\n{}\n", post.title, description, post.url),
+        None => format!("{}\n{}\n", post.title, post.url),
+    };
+    let message = match html {
+        Some(html) => {
+            builder.multipart(MultiPart::alternative_plain_html(text, html.to_string()))?
+        }
+        None => builder.header(ContentType::TEXT_PLAIN).body(text)?,
+    };
+
+    transport(config)?.send(message).await?;
+    Ok(())
+}
+
+/// Answers the email `subject` from `to`, threaded under `in_reply_to`.
+pub async fn send_reply(
+    config: &EmailConfig,
+    to: &str,
+    subject: &str,
+    in_reply_to: Option<&str>,
+    text: &str,
+) -> Result<(), Box<dyn Error>> {
+    let subject = if subject.to_ascii_lowercase().starts_with("re:") {
+        subject.to_string()
+    } else {
+        format!("Re: {}", subject)
+    };
+    let mut builder = Message::builder()
+        .from(config.from.parse()?)
+        .to(to
+            .parse()
+            .map_err(|e| format!("Invalid email address '{}': {}", to, e))?)
+        .subject(subject);
+    if let Some(id) = in_reply_to {
+        let id = format!("<{}>", id.trim_matches(['<', '>']));
+        builder = builder.in_reply_to(id.clone()).references(id);
+    }
+    let message = builder
+        .header(ContentType::TEXT_PLAIN)
+        .body(text.to_string())?;
+    transport(config)?.send(message).await?;
+    Ok(())
+}
+
+fn transport(config: &EmailConfig) -> Result<AsyncSmtpTransport<Tokio1Executor>, Box<dyn Error>> {
+    let transport = match config.security {
+        SmtpSecurity::Tls => AsyncSmtpTransport::<Tokio1Executor>::relay(&config.smtp_host)?,
+        SmtpSecurity::Starttls => {
+            AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(&config.smtp_host)?
+        }
+        SmtpSecurity::None => {
+            AsyncSmtpTransport::<Tokio1Executor>::builder_dangerous(&config.smtp_host)
+        }
+    };
+    let mut transport = transport.port(config.smtp_port);
+    if let (Some(username), Some(password)) = (&config.username, &config.password) {
+        transport = transport.credentials(Credentials::new(username.clone(), password.clone()));
+    }
+
+    Ok(transport.build())
+}
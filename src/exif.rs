@@ -0,0 +1,300 @@
+/// XMP's name for a GPS position.
+const XMP_LATITUDE: &[u8] = b"GPSLatitude";
+
+/// EXIF tags this module reads.
+const ORIENTATION: u16 = 0x0112;
+const GPS_IFD: u16 = 0x8825;
+const GPS_LATITUDE: u16 = 0x0002;
+
+/// Whether the image in `bytes` says where it was taken.
+pub fn has_location(bytes: &[u8]) -> bool {
+    let tiff_location = match Format::of(bytes) {
+        Some(Format::Jpeg) => jpeg_segments(bytes)
+            .unwrap_or_default()
+            .iter()
+            .filter_map(|segment| exif_payload(segment))
+            .any(|tiff| Tiff(tiff).has_location()),
+        Some(Format::Png) => png_chunks(bytes)
+            .unwrap_or_default()
+            .iter()
+            .any(|chunk| chunk.kind == *b"eXIf" && Tiff(chunk.data).has_location()),
+        Some(Format::WebP) => riff_chunks(bytes).unwrap_or_default().iter().any(|chunk| {
+            chunk.kind == *b"EXIF" && Tiff(strip_exif_header(chunk.data)).has_location()
+        }),
+        None => return false,
+    };
+    tiff_location
+        || bytes
+            .windows(XMP_LATITUDE.len())
+            .any(|window| window == XMP_LATITUDE)
+}
+
+/// `bytes` without metadata. A JPEG keeps its orientation so it isn't
+/// shown sideways. Anything that isn't a well-formed JPEG, PNG or WebP
+/// comes back as it was.
+pub fn strip(bytes: Vec<u8>) -> Vec<u8> {
+    let stripped = match Format::of(&bytes) {
+        Some(Format::Jpeg) => strip_jpeg(&bytes),
+        Some(Format::Png) => strip_png(&bytes),
+        Some(Format::WebP) => strip_webp(&bytes),
+        None => None,
+    };
+    stripped.unwrap_or(bytes)
+}
+
+enum Format {
+    Jpeg,
+    Png,
+    WebP,
+}
+
+impl Format {
+    fn of(bytes: &[u8]) -> Option<Self> {
+        if bytes.starts_with(b"\xff\xd8\xff") {
+            Some(Self::Jpeg)
+        } else if bytes.starts_with(b"\x89PNG\r\n\x1a\n") {
+            Some(Self::Png)
+        } else if bytes.len() >= 12 && bytes.starts_with(b"RIFF") && &bytes[8..12] == b"WEBP" {
+            Some(Self::WebP)
+        } else {
+            None
+        }
+    }
+}
+
+/// A JPEG segment before the image data: marker and payload.
+struct Segment<'a> {
+    marker: u8,
+    /// The whole segment, marker and length included.
+    raw: &'a [u8],
+    payload: &'a [u8],
+}
+
+/// The segments of a JPEG up to the start of the image data, and the rest
+/// of the file from there.
+fn jpeg_segments(bytes: &[u8]) -> Option<Vec<Segment<'_>>> {
+    let mut segments = Vec::new();
+    let mut pos = 2;
+    loop {
+        if *bytes.get(pos)? != 0xff {
+            return None;
+        }
+        let marker = *bytes.get(pos + 1)?;
+        if marker == 0xff {
+            // Fill byte.
+            pos += 1;
+            continue;
+        }
+        if marker == 0xda {
+            segments.push(Segment {
+                marker,
+                raw: &bytes[pos..],
+                payload: &[],
+            });
+            return Some(segments);
+        }
+        let length = u16::from_be_bytes([*bytes.get(pos + 2)?, *bytes.get(pos + 3)?]) as usize;
+        let end = pos + 2 + length;
+        if length < 2 || end > bytes.len() {
+            return None;
+        }
+        segments.push(Segment {
+            marker,
+            raw: &bytes[pos..end],
+            payload: &bytes[pos + 4..end],
+        });
+        pos = end;
+    }
+}
+
+/// The TIFF data in an EXIF APP1 segment.
+fn exif_payload<'a>(segment: &Segment<'a>) -> Option<&'a [u8]> {
+    (segment.marker == 0xe1)
+        .then(|| segment.payload.strip_prefix(b"Exif\0\0"))
+        .flatten()
+}
+
+fn strip_jpeg(bytes: &[u8]) -> Option<Vec<u8>> {
+    let segments = jpeg_segments(bytes)?;
+    let orientation = segments
+        .iter()
+        .filter_map(exif_payload)
+        .find_map(|tiff| Tiff(tiff).orientation())
+        .filter(|orientation| *orientation != 1);
+
+    let mut out = Vec::with_capacity(bytes.len());
+    out.extend_from_slice(&bytes[..2]);
+    let mut orientation_written = orientation.is_none();
+    for segment in &segments {
+        // JFIF wants its APP0 first; the orientation goes right after.
+        if !orientation_written && segment.marker != 0xe0 {
+            out.extend_from_slice(&orientation_segment(orientation.unwrap_or(1)));
+            orientation_written = true;
+        }
+        // APP1 (EXIF, XMP), APP13 (IPTC) and comments.
+        if !matches!(segment.marker, 0xe1 | 0xed | 0xfe) {
+            out.extend_from_slice(segment.raw);
+        }
+    }
+    Some(out)
+}
+
+/// An EXIF APP1 segment with nothing but `orientation` in it.
+fn orientation_segment(orientation: u16) -> Vec<u8> {
+    let mut tiff = Vec::new();
+    tiff.extend_from_slice(b"MM\0\x2a");
+    tiff.extend_from_slice(&8u32.to_be_bytes());
+    tiff.extend_from_slice(&1u16.to_be_bytes());
+    tiff.extend_from_slice(&ORIENTATION.to_be_bytes());
+    // One SHORT, padded to four bytes.
+    tiff.extend_from_slice(&3u16.to_be_bytes());
+    tiff.extend_from_slice(&1u32.to_be_bytes());
+    tiff.extend_from_slice(&orientation.to_be_bytes());
+    tiff.extend_from_slice(&[0, 0]);
+    tiff.extend_from_slice(&0u32.to_be_bytes());
+
+    let mut segment = vec![0xff, 0xe1];
+    segment.extend_from_slice(&((2 + 6 + tiff.len()) as u16).to_be_bytes());
+    segment.extend_from_slice(b"Exif\0\0");
+    segment.extend_from_slice(&tiff);
+    segment
+}
+
+/// A PNG or RIFF chunk.
+struct Chunk<'a> {
+    kind: [u8; 4],
+    /// The whole chunk, header, padding and checksum included.
+    raw: &'a [u8],
+    data: &'a [u8],
+}
+
+fn png_chunks(bytes: &[u8]) -> Option<Vec<Chunk<'_>>> {
+    let mut chunks = Vec::new();
+    let mut pos = 8;
+    while pos < bytes.len() {
+        let length = u32::from_be_bytes(bytes.get(pos..pos + 4)?.try_into().ok()?) as usize;
+        let kind = bytes.get(pos + 4..pos + 8)?.try_into().ok()?;
+        let end = pos.checked_add(12 + length)?;
+        chunks.push(Chunk {
+            kind,
+            raw: bytes.get(pos..end)?,
+            data: &bytes[pos + 8..pos + 8 + length],
+        });
+        pos = end;
+    }
+    Some(chunks)
+}
+
+fn strip_png(bytes: &[u8]) -> Option<Vec<u8>> {
+    let mut out = bytes[..8].to_vec();
+    for chunk in png_chunks(bytes)? {
+        if !matches!(&chunk.kind, b"eXIf" | b"tEXt" | b"zTXt" | b"iTXt" | b"tIME") {
+            out.extend_from_slice(chunk.raw);
+        }
+    }
+    Some(out)
+}
+
+fn riff_chunks(bytes: &[u8]) -> Option<Vec<Chunk<'_>>> {
+    let mut chunks = Vec::new();
+    let mut pos = 12;
+    while pos < bytes.len() {
+        let kind = bytes.get(pos..pos + 4)?.try_into().ok()?;
+        let length = u32::from_le_bytes(bytes.get(pos + 4..pos + 8)?.try_into().ok()?) as usize;
+        let data = bytes.get(pos + 8..pos.checked_add(8 + length)?)?;
+        // Chunks are padded to an even length.
+        let end = (pos + 8 + length + length % 2).min(bytes.len());
+        chunks.push(Chunk {
+            kind,
+            raw: &bytes[pos..end],
+            data,
+        });
+        pos = end;
+    }
+    Some(chunks)
+}
+
+fn strip_webp(bytes: &[u8]) -> Option<Vec<u8>> {
+    let mut out = bytes[..12].to_vec();
+    for chunk in riff_chunks(bytes)? {
+        match &chunk.kind {
+            b"EXIF" | b"XMP " => {}
+            b"VP8X" => {
+                // Clear the flags saying there's EXIF and XMP.
+                let start = out.len();
+                out.extend_from_slice(chunk.raw);
+                if let Some(flags) = out.get_mut(start + 8) {
+                    *flags &= !0x0c;
+                }
+            }
+            _ => out.extend_from_slice(chunk.raw),
+        }
+    }
+    let size = (out.len() - 8) as u32;
+    out[4..8].copy_from_slice(&size.to_le_bytes());
+    Some(out)
+}
+
+/// Some WebP writers keep the JPEG-style header in front of the TIFF data.
+fn strip_exif_header(data: &[u8]) -> &[u8] {
+    data.strip_prefix(b"Exif\0\0").unwrap_or(data)
+}
+
+/// EXIF data: a TIFF header and its image file directories.
+struct Tiff<'a>(&'a [u8]);
+
+impl Tiff<'_> {
+    fn big_endian(&self) -> Option<bool> {
+        match self.0.get(..2)? {
+            b"MM" => Some(true),
+            b"II" => Some(false),
+            _ => None,
+        }
+    }
+
+    fn u16_at(&self, pos: usize) -> Option<u16> {
+        let bytes = self.0.get(pos..pos + 2)?.try_into().ok()?;
+        Some(if self.big_endian()? {
+            u16::from_be_bytes(bytes)
+        } else {
+            u16::from_le_bytes(bytes)
+        })
+    }
+
+    fn u32_at(&self, pos: usize) -> Option<u32> {
+        let bytes = self.0.get(pos..pos + 4)?.try_into().ok()?;
+        Some(if self.big_endian()? {
+            u32::from_be_bytes(bytes)
+        } else {
+            u32::from_le_bytes(bytes)
+        })
+    }
+
+    /// Where the value of `tag` is in the directory at `ifd`: the entry's
+    /// value field, which holds small values and offsets to big ones.
+    fn entry(&self, ifd: usize, tag: u16) -> Option<usize> {
+        let count = self.u16_at(ifd)? as usize;
+        (0..count)
+            .map(|index| ifd + 2 + index * 12)
+            .find(|entry| self.u16_at(*entry) == Some(tag))
+            .map(|entry| entry + 8)
+    }
+
+    fn first_ifd(&self) -> Option<usize> {
+        Some(self.u32_at(4)? as usize)
+    }
+
+    fn orientation(&self) -> Option<u16> {
+        let value = self.entry(self.first_ifd()?, ORIENTATION)?;
+        self.u16_at(value)
+            .filter(|orientation| (1..=8).contains(orientation))
+    }
+
+    fn has_location(&self) -> bool {
+        let latitude = || {
+            let gps = self.u32_at(self.entry(self.first_ifd()?, GPS_IFD)?)? as usize;
+            self.entry(gps, GPS_LATITUDE)
+        };
+        latitude().is_some()
+    }
+}
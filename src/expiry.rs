@@ -0,0 +1,50 @@
+use chrono::{DateTime, Duration, Utc};
+use std::str::FromStr;
+
+/// How long a post stays up, written like `30d`, `12h`, `2w` or `90m`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Ttl(Duration);
+
+impl Ttl {
+    /// When a post published at `published` expires.
+    pub fn expires(self, published: DateTime<Utc>) -> DateTime<Utc> {
+        published + self.0
+    }
+
+    /// How long this is.
+    pub fn duration(self) -> Duration {
+        self.0
+    }
+
+    /// The moment this long before `time`.
+    pub fn before(self, time: DateTime<Utc>) -> DateTime<Utc> {
+        time - self.0
+    }
+}
+
+impl FromStr for Ttl {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let trimmed = s.trim();
+        let split = trimmed
+            .find(|c: char| !c.is_ascii_digit())
+            .unwrap_or(trimmed.len());
+        let (number, unit) = trimmed.split_at(split);
+        let number: i64 = number
+            .parse()
+            .map_err(|_| format!("Invalid TTL '{}', expected e.g. 30d", s))?;
+        let duration = match unit.trim() {
+            "m" | "min" => Duration::try_minutes(number),
+            "h" => Duration::try_hours(number),
+            "d" | "" => Duration::try_days(number),
+            "w" => Duration::try_weeks(number),
+            _ => return Err(format!("Unknown unit in TTL '{}', use m, h, d or w", s)),
+        }
+        .ok_or_else(|| format!("TTL '{}' is too long", s))?;
+        if number == 0 {
+            return Err(format!("TTL '{}' must be above zero", s));
+        }
+        Ok(Ttl(duration))
+    }
+}
@@ -0,0 +1,238 @@
+use crate::config::AppConfig;
+use crate::frontmatter::Dir;
+use crate::manifest::Post;
+use crate::raw::format_size;
+use crate::render::{escape_html, format_date, today, Page};
+use crate::table::SORT_SCRIPT;
+use base64::{engine::general_purpose::STANDARD, Engine};
+use chrono::{DateTime, Local, Utc};
+use ring::{aead, pbkdf2, rand::SecureRandom};
+use serde::Serialize;
+use std::{error::Error, num::NonZeroU32, str::FromStr};
+
+/// PBKDF2-HMAC-SHA256 rounds for deriving the key of a protected report,
+/// as OWASP recommends.
+const PBKDF2_ITERATIONS: u32 = 310_000;
+
+/// What `klistra export` writes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExportFormat {
+    Html,
+    Csv,
+    Json,
+}
+
+impl FromStr for ExportFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "html" => Ok(ExportFormat::Html),
+            "csv" => Ok(ExportFormat::Csv),
+            "json" => Ok(ExportFormat::Json),
+            other => Err(format!(
+                "Unknown export format '{}', expected html, csv or json",
+                other
+            )),
+        }
+    }
+}
+
+/// One post in the report.
+#[derive(Serialize)]
+struct Row<'a> {
+    title: &'a str,
+    url: &'a str,
+    published: DateTime<Utc>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    updated: Option<DateTime<Utc>>,
+    #[serde(skip_serializing_if = "std::collections::BTreeMap::is_empty")]
+    tags: &'a std::collections::BTreeMap<String, String>,
+    /// Bytes uploaded, if the post was published since sizes were recorded.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    size: Option<u64>,
+}
+
+impl<'a> Row<'a> {
+    fn new(post: &'a Post) -> Self {
+        Self {
+            title: &post.title,
+            url: &post.url,
+            published: post.published,
+            updated: post.updated,
+            tags: &post.labels,
+            size: post.size,
+        }
+    }
+
+    /// Tags as `key=value`, or just `key` when the value is empty.
+    fn tags(&self) -> Vec<String> {
+        self.tags
+            .iter()
+            .map(|(key, value)| match value.as_str() {
+                "" => key.clone(),
+                value => format!("{}={}", key, value),
+            })
+            .collect()
+    }
+}
+
+/// A report of `posts`, newest first. With a `password`, the HTML report's
+/// table is encrypted and only shown once the password is entered in the
+/// browser; the other formats can't be protected.
+pub fn export(
+    posts: &[Post],
+    format: ExportFormat,
+    password: Option<&str>,
+    config: &AppConfig,
+) -> Result<String, Box<dyn Error>> {
+    if password.is_some() && format != ExportFormat::Html {
+        return Err("Only HTML exports can be password protected".into());
+    }
+    let mut rows: Vec<Row> = posts.iter().map(Row::new).collect();
+    rows.sort_by_key(|row| std::cmp::Reverse(row.published));
+    match format {
+        ExportFormat::Json => Ok(serde_json::to_string_pretty(&rows)? + "\n"),
+        ExportFormat::Csv => to_csv(&rows),
+        ExportFormat::Html => to_html(&rows, password, config),
+    }
+}
+
+fn to_csv(rows: &[Row]) -> Result<String, Box<dyn Error>> {
+    let mut writer = csv::Writer::from_writer(Vec::new());
+    writer.write_record(["title", "url", "published", "updated", "tags", "size"])?;
+    for row in rows {
+        writer.write_record([
+            row.title,
+            row.url,
+            &row.published.to_rfc3339(),
+            &row.updated
+                .map(|date| date.to_rfc3339())
+                .unwrap_or_default(),
+            &row.tags().join("; "),
+            &row.size.map(|size| size.to_string()).unwrap_or_default(),
+        ])?;
+    }
+    Ok(String::from_utf8(writer.into_inner()?)?)
+}
+
+fn to_html(
+    rows: &[Row],
+    password: Option<&str>,
+    config: &AppConfig,
+) -> Result<String, Box<dyn Error>> {
+    let date = |date: DateTime<Utc>| format_date(date.with_timezone(&Local), &config.page);
+    let mut body = String::new();
+    for row in rows {
+        body.push_str(&format!(
+            "<tr><td><a href=\"{url}\">{title}</a></td><td>{published}</td><td>{updated}</td><td>{tags}</td><td>{size}</td></tr>\n",
+            url = escape_html(row.url),
+            title = escape_html(row.title),
+            published = date(row.published)?,
+            updated = row.updated.map(date).transpose()?.unwrap_or_default(),
+            tags = escape_html(&row.tags().join(", ")),
+            size = row.size.map(format_size).unwrap_or_default(),
+        ));
+    }
+    let table = format!(
+        "<p>{count} posts</p>\n<div class=\"table-scroll\">\n<table class=\"data-table\">\n<thead>\n<tr><th>Title</th><th>Published</th><th>Updated</th><th>Tags</th><th>Size</th></tr>\n</thead>\n<tbody>\n{body}</tbody>\n</table>\n</div>",
+        count = rows.len(),
+    );
+    let body = match password {
+        Some(password) => locked(&table, password)?,
+        None => format!("{}\n{}", table, SORT_SCRIPT),
+    };
+
+    let title = match &config.site.title {
+        Some(site) => format!("Posts on {}", site),
+        None => "Posts".to_string(),
+    };
+    let date = today(&config.page)?;
+    Ok(Page {
+        title: &title,
+        heading: Some(&title),
+        description: None,
+        lang: &config.page.lang,
+        dir: Dir::from_lang(&config.page.lang),
+        date: &date,
+        body: &body,
+        alternates: &[],
+        image_url: None,
+        site: &config.site,
+        authors: &[],
+        source_url: None,
+    }
+    .render())
+}
+
+/// Decrypts the report in the browser with the Web Crypto API, using the
+/// same PBKDF2 and AES-GCM parameters [`locked`] encrypted it with.
+const UNLOCK_SCRIPT: &str = r##"<script>
+document.querySelector("#locked form").addEventListener("submit", async event => {
+    event.preventDefault();
+    const locked = document.getElementById("locked");
+    const data = locked.dataset;
+    const bytes = text => Uint8Array.from(atob(text), c => c.charCodeAt(0));
+    const password = new TextEncoder().encode(event.target.password.value);
+    try {
+        const material = await crypto.subtle.importKey("raw", password, "PBKDF2", false, ["deriveKey"]);
+        const key = await crypto.subtle.deriveKey(
+            { name: "PBKDF2", salt: bytes(data.salt), iterations: Number(data.iterations), hash: "SHA-256" },
+            material,
+            { name: "AES-GCM", length: 256 },
+            false,
+            ["decrypt"]
+        );
+        const plain = await crypto.subtle.decrypt({ name: "AES-GCM", iv: bytes(data.nonce) }, key, bytes(data.ciphertext));
+        locked.outerHTML = new TextDecoder().decode(plain);
+    } catch (e) {
+        locked.querySelector(".error").hidden = false;
+    }
+});
+</script>"##;
+
+/// `html` encrypted with AES-256-GCM under a key derived from `password`,
+/// wrapped in a form that asks for the password and swaps in the original.
+fn locked(html: &str, password: &str) -> Result<String, Box<dyn Error>> {
+    let random = ring::rand::SystemRandom::new();
+    let mut salt = [0u8; 16];
+    let mut nonce = [0u8; aead::NONCE_LEN];
+    random
+        .fill(&mut salt)
+        .and_then(|_| random.fill(&mut nonce))
+        .map_err(|_| "Could not generate random bytes")?;
+
+    let iterations = NonZeroU32::new(PBKDF2_ITERATIONS).ok_or("No PBKDF2 iterations")?;
+    let mut key = [0u8; 32];
+    pbkdf2::derive(
+        pbkdf2::PBKDF2_HMAC_SHA256,
+        iterations,
+        &salt,
+        password.as_bytes(),
+        &mut key,
+    );
+    let key = aead::UnboundKey::new(&aead::AES_256_GCM, &key)
+        .map(aead::LessSafeKey::new)
+        .map_err(|_| "Invalid encryption key")?;
+    let mut ciphertext = html.as_bytes().to_vec();
+    key.seal_in_place_append_tag(
+        aead::Nonce::assume_unique_for_key(nonce),
+        aead::Aad::empty(),
+        &mut ciphertext,
+    )
+    .map_err(|_| "Could not encrypt the report")?;
+
+    Ok(format!(
+        r#"<div id="locked" data-salt="{salt}" data-nonce="{nonce}" data-iterations="{iterations}" data-ciphertext="{ciphertext}">
+<form>
+<label>Password <input type="password" name="password" autocomplete="current-password" autofocus></label>
+<button type="submit">Show posts</button>
+</form>
+<p class="error" hidden>Wrong password.</p>
+</div>
+{UNLOCK_SCRIPT}"#,
+        salt = STANDARD.encode(salt),
+        nonce = STANDARD.encode(nonce),
+        ciphertext = STANDARD.encode(ciphertext),
+    ))
+}
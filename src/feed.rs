@@ -0,0 +1,148 @@
+use crate::config::AppConfig;
+use crate::index::{index_title, sections_list};
+use crate::manifest::Post;
+use crate::render::escape_html;
+use serde_json::{json, Value};
+use std::error::Error;
+
+/// Where the feed is uploaded, next to the index.
+pub const FEED_PATH: &str = "feed.xml";
+
+/// Where the JSON Feed version goes.
+pub const JSON_FEED_PATH: &str = "feed.json";
+
+/// An RSS 2.0 feed of `posts` (newest first), with each post's audio file as
+/// an enclosure so podcast apps can subscribe. `site_url` is the bucket's
+/// public URL, where the index is.
+pub fn rss(posts: &[&Post], config: &AppConfig, site_url: &str) -> String {
+    let title = index_title(config);
+    let mut items = String::new();
+    for post in posts {
+        items.push_str(&item(post));
+    }
+    format!(
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<rss version="2.0" xmlns:atom="http://www.w3.org/2005/Atom">
+<channel>
+<title>{title}</title>
+<link>{link}</link>
+<description>{title}</description>
+<language>{lang}</language>
+<atom:link href="{link}{FEED_PATH}" rel="self" type="application/rss+xml"/>
+{items}</channel>
+</rss>
+"#,
+        title = escape_html(title),
+        link = escape_html(site_url),
+        lang = escape_html(&config.page.lang),
+    )
+}
+
+fn item(post: &Post) -> String {
+    let description = match (post.description.as_deref(), post.sections.is_empty()) {
+        (None, true) => String::new(),
+        (Some(description), true) => escape_html(description),
+        // Readers show HTML descriptions, so long posts get their sections
+        // as links.
+        (description, false) => escape_html(&format!(
+            "{}{}",
+            description
+                .map(|description| format!("<p>{}</p>", escape_html(description)))
+                .unwrap_or_default(),
+            sections_list(&post.sections)
+        )),
+    };
+    let description = match description.as_str() {
+        "" => String::new(),
+        description => format!("<description>{}</description>\n", description),
+    };
+    let enclosure = post
+        .enclosure
+        .as_ref()
+        .map(|enclosure| {
+            format!(
+                "<enclosure url=\"{}\" length=\"{}\" type=\"{}\"/>\n",
+                escape_html(&enclosure.url),
+                enclosure.length,
+                escape_html(&enclosure.content_type)
+            )
+        })
+        .unwrap_or_default();
+    format!(
+        "<item>\n<title>{title}</title>\n<link>{url}</link>\n<guid isPermaLink=\"true\">{url}</guid>\n<pubDate>{date}</pubDate>\n{description}{enclosure}</item>\n",
+        title = escape_html(&post.title),
+        url = escape_html(&post.url),
+        date = post.published.to_rfc2822(),
+    )
+}
+
+/// The same feed as [`rss`] in JSON Feed 1.1, with each post's preview
+/// image and audio file.
+pub fn json_feed(
+    posts: &[&Post],
+    config: &AppConfig,
+    site_url: &str,
+) -> Result<String, Box<dyn Error>> {
+    let items: Vec<Value> = posts.iter().map(|post| json_item(post)).collect();
+    let feed = json!({
+        "version": "https://jsonfeed.org/version/1.1",
+        "title": index_title(config),
+        "home_page_url": site_url,
+        "feed_url": format!("{}{}", site_url, JSON_FEED_PATH),
+        "language": config.page.lang,
+        "items": items,
+    });
+    Ok(serde_json::to_string_pretty(&feed)?)
+}
+
+fn json_item(post: &Post) -> Value {
+    // Items need content; a post without a description gets a link to it.
+    let mut content = post
+        .description
+        .as_deref()
+        .map(|description| format!("<p>{}</p>", escape_html(description)))
+        .unwrap_or_else(|| {
+            format!(
+                "<p><a href=\"{}\">{}</a></p>",
+                escape_html(&post.url),
+                escape_html(&post.title)
+            )
+        });
+    content.push_str(&sections_list(&post.sections));
+    let mut item = json!({
+        "id": post.url,
+        "url": post.url,
+        "title": post.title,
+        "content_html": content,
+        "date_published": post.published.to_rfc3339(),
+    });
+    if let Some(description) = &post.description {
+        item["summary"] = Value::from(description.as_str());
+    }
+    if let Some(updated) = post.updated {
+        item["date_modified"] = Value::from(updated.to_rfc3339());
+    }
+    if !post.authors.is_empty() {
+        let authors: Vec<Value> = post
+            .authors
+            .iter()
+            .map(|name| json!({ "name": name }))
+            .collect();
+        item["authors"] = Value::from(authors);
+    }
+    if !post.labels.is_empty() {
+        let tags: Vec<&str> = post.labels.values().map(String::as_str).collect();
+        item["tags"] = json!(tags);
+    }
+    if let Some(image) = &post.image {
+        item["image"] = Value::from(image.as_str());
+    }
+    if let Some(enclosure) = &post.enclosure {
+        item["attachments"] = json!([{
+            "url": enclosure.url,
+            "mime_type": enclosure.content_type,
+            "size_in_bytes": enclosure.length,
+        }]);
+    }
+    item
+}
@@ -0,0 +1,238 @@
+use crate::docx;
+use crate::render::unescape_html;
+use crate::stream;
+use crate::table;
+use std::{
+    error::Error,
+    io::Write,
+    path::Path,
+    process::{Command, Stdio},
+};
+use tokio::fs;
+
+/// The markup a post is written in, decided by its file extension.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum InputFormat {
+    #[default]
+    Markdown,
+    /// `.adoc`/`.asciidoc`, converted with `asciidoctor`.
+    AsciiDoc,
+    /// `.rst`, converted with `pandoc`.
+    Rst,
+    /// `.org`, converted with `pandoc`.
+    Org,
+    /// `.csv`, rendered as a table.
+    Csv,
+    /// `.tsv`, rendered as a table.
+    Tsv,
+    /// `.docx`, converted to HTML when it's read ([`read_source`]).
+    Docx,
+    /// `.html`/`.htm`, published as it is, or with its `<body>` in the page
+    /// template if `[page] wrap_html` is on.
+    Html,
+}
+
+impl InputFormat {
+    pub fn from_path(path: &Path) -> Self {
+        match path.extension().and_then(|ext| ext.to_str()) {
+            Some("adoc" | "asciidoc") => InputFormat::AsciiDoc,
+            Some("rst") => InputFormat::Rst,
+            Some("org") => InputFormat::Org,
+            Some("csv") => InputFormat::Csv,
+            Some("tsv") => InputFormat::Tsv,
+            Some("docx") => InputFormat::Docx,
+            Some("html" | "htm") => InputFormat::Html,
+            _ => InputFormat::Markdown,
+        }
+    }
+}
+
+/// A non-markdown document converted to an HTML fragment.
+pub struct Converted {
+    /// The document title, which the converters leave out of `html`.
+    pub title: Option<String>,
+    pub html: String,
+}
+
+/// Converts an AsciiDoc, reStructuredText or Org document, or a CSV/TSV
+/// table, to HTML. The documents need an external tool: asciidoctor for
+/// AsciiDoc, pandoc for the others; there's no built-in parser for them.
+pub fn convert(format: InputFormat, text: &str) -> Result<Converted, Box<dyn Error>> {
+    match format {
+        InputFormat::Markdown => Err("Markdown is rendered by the markdown backends".into()),
+        InputFormat::AsciiDoc => Ok(Converted {
+            title: asciidoc_title(text),
+            html: run_converter("asciidoctor", &["--embedded", "--out-file", "-", "-"], text)
+                .map_err(|e| needs("AsciiDoc", "asciidoctor", e))?,
+        }),
+        // A lone top-level section becomes the document title, as in docutils.
+        InputFormat::Rst => Ok(Converted {
+            title: rst_title(text),
+            html: run_converter("pandoc", &["--from", "rst", "--to", "html5"], text)
+                .map_err(|e| needs("reStructuredText", "pandoc", e))?,
+        }),
+        // `#+TITLE:` goes into pandoc's metadata rather than the body.
+        InputFormat::Org => Ok(Converted {
+            title: org_title(text),
+            html: run_converter("pandoc", &["--from", "org", "--to", "html5"], text)
+                .map_err(|e| needs("Org", "pandoc", e))?,
+        }),
+        InputFormat::Csv => Ok(Converted {
+            title: None,
+            html: table::to_html(text, b',')?,
+        }),
+        InputFormat::Tsv => Ok(Converted {
+            title: None,
+            html: table::to_html(text, b'\t')?,
+        }),
+        InputFormat::Docx => Ok(Converted {
+            title: None,
+            html: text.to_string(),
+        }),
+        InputFormat::Html => Ok(Converted {
+            title: html_title(text),
+            html: html_body(text).to_string(),
+        }),
+    }
+}
+
+/// Reads the source at `path` as text. A Word document is converted to
+/// front matter and HTML on the way.
+pub async fn read_source(path: &Path) -> Result<String, Box<dyn Error>> {
+    if InputFormat::from_path(path) == InputFormat::Docx {
+        let bytes = fs::read(path).await?;
+        return docx::convert(&bytes).map_err(|e| format!("{}: {}", path.display(), e).into());
+    }
+    Ok(fs::read_to_string(path).await?)
+}
+
+/// Says which tool converting a `format` document failed in, since it isn't
+/// obvious that klistra doesn't read the format itself.
+fn needs(format: &str, tool: &str, error: Box<dyn Error>) -> Box<dyn Error> {
+    format!("{} files are converted with {}: {}", format, tool, error).into()
+}
+
+/// Pipes `input` through an external program and returns what it printed.
+/// Renders happen inside async publishes, so on a multi-threaded runtime
+/// the wait is handed off with [`stream::blocking`] rather than stalling
+/// the worker's other tasks.
+pub fn run_converter(program: &str, args: &[&str], input: &str) -> Result<String, Box<dyn Error>> {
+    let mut child = Command::new(program)
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| match e.kind() {
+            std::io::ErrorKind::NotFound => {
+                format!(
+                    "Could not run {}: it isn't installed or isn't on your PATH",
+                    program
+                )
+            }
+            _ => format!("Could not run {}: {}", program, e),
+        })?;
+    let mut stdin = child
+        .stdin
+        .take()
+        .ok_or_else(|| format!("{} stdin unavailable", program))?;
+    // Written from another thread: programs like `hunspell -a` answer as
+    // they read, and would block on a full stdout pipe while we block on a
+    // full stdin one.
+    let output = stream::blocking(|| {
+        std::thread::scope(|scope| {
+            let writer = scope.spawn(move || stdin.write_all(input.as_bytes()));
+            let output = child.wait_with_output();
+            (writer.join(), output)
+        })
+    });
+    match output.0 {
+        Ok(Ok(())) => {}
+        // The program may stop reading early, e.g. on bad input; its exit
+        // status says what went wrong.
+        Ok(Err(e)) if e.kind() == std::io::ErrorKind::BrokenPipe => {}
+        Ok(Err(e)) => return Err(e.into()),
+        Err(_) => return Err(format!("Writing to {} failed", program).into()),
+    }
+    let output = output.1?;
+    if !output.status.success() {
+        return Err(format!(
+            "{} failed: {}",
+            program,
+            String::from_utf8_lossy(&output.stderr).trim()
+        )
+        .into());
+    }
+    Ok(String::from_utf8(output.stdout)?)
+}
+
+/// The `= Title` line that opens an AsciiDoc document.
+fn asciidoc_title(text: &str) -> Option<String> {
+    let line = text
+        .lines()
+        .map(str::trim)
+        .find(|line| !line.is_empty() && !line.starts_with("//"))?;
+    let title = line.strip_prefix("= ")?.trim();
+    (!title.is_empty()).then(|| title.to_string())
+}
+
+/// The `<title>` of an HTML page.
+fn html_title(html: &str) -> Option<String> {
+    let lower = html.to_ascii_lowercase();
+    let start = lower.find("<title")?;
+    let start = start + lower[start..].find('>')? + 1;
+    let end = start + lower[start..].find("</title")?;
+    let title = unescape_html(html[start..end].trim());
+    (!title.is_empty()).then_some(title)
+}
+
+/// What's inside the `<body>` of an HTML page, or all of it if it has no
+/// body tag.
+fn html_body(html: &str) -> &str {
+    let lower = html.to_ascii_lowercase();
+    let Some(start) = lower.find("<body") else {
+        return html;
+    };
+    let Some(open_end) = lower[start..].find('>') else {
+        return html;
+    };
+    let start = start + open_end + 1;
+    let end = lower
+        .rfind("</body")
+        .filter(|end| *end >= start)
+        .unwrap_or(html.len());
+    &html[start..end]
+}
+
+/// The value of the `#+TITLE:` keyword in an Org document.
+fn org_title(text: &str) -> Option<String> {
+    text.lines().find_map(|line| {
+        let (keyword, value) = line.trim().split_once(':')?;
+        let value = value.trim();
+        (keyword.eq_ignore_ascii_case("#+title") && !value.is_empty()).then(|| value.to_string())
+    })
+}
+
+/// The first section title of an RST document: a line underlined (and
+/// optionally overlined) with a repeated punctuation character.
+fn rst_title(text: &str) -> Option<String> {
+    let is_adornment = |line: &str| {
+        let mut chars = line.chars();
+        chars.next().is_some_and(|first| {
+            first.is_ascii_punctuation() && chars.all(|c| c == first) && line.len() >= 2
+        })
+    };
+    let lines: Vec<&str> = text
+        .lines()
+        .map(str::trim_end)
+        .skip_while(|line| line.is_empty())
+        .take(3)
+        .collect();
+    let (title, underline) = match lines.as_slice() {
+        [over, title, under, ..] if is_adornment(over) => (title.trim(), *under),
+        [title, under, ..] => (title.trim(), *under),
+        _ => return None,
+    };
+    (!title.is_empty() && is_adornment(underline) && underline.len() >= title.chars().count())
+        .then(|| title.to_string())
+}
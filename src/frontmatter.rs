@@ -0,0 +1,224 @@
+use crate::config::SlugStyle;
+use crate::variants::is_lang_tag;
+use chrono::{DateTime, Local, NaiveDate, NaiveTime, Utc};
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use std::{collections::BTreeMap, error::Error};
+
+/// Key/value labels applied to a post's objects as S3 object tags.
+pub type Labels = BTreeMap<String, String>;
+
+/// S3 allows at most this many tags per object.
+pub const MAX_LABELS: usize = 10;
+
+/// Text direction of a post, rendered as the `dir` attribute on `<html>`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Dir {
+    Ltr,
+    Rtl,
+}
+
+impl Dir {
+    /// Guesses the direction from a BCP 47 language tag such as `ar` or `he-IL`.
+    pub fn from_lang(lang: &str) -> Self {
+        const RTL_LANGS: &[&str] = &[
+            "ar", "arc", "ckb", "dv", "fa", "he", "ps", "sd", "ug", "ur", "yi",
+        ];
+
+        let primary = lang.split(['-', '_']).next().unwrap_or("");
+        if RTL_LANGS.iter().any(|l| l.eq_ignore_ascii_case(primary)) {
+            Dir::Rtl
+        } else {
+            Dir::Ltr
+        }
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Dir::Ltr => "ltr",
+            Dir::Rtl => "rtl",
+        }
+    }
+}
+
+/// One entry of a post's changelog: `- date: 2025-03-01` with `note: ...`,
+/// or just the note.
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+pub enum ChangelogEntry {
+    Dated { date: String, note: String },
+    Note(String),
+}
+
+/// Optional YAML block at the top of a markdown file, delimited by `---` lines.
+#[derive(Debug, Default, Deserialize)]
+pub struct FrontMatter {
+    pub title: Option<String>,
+    /// Overrides the summary taken from the first paragraph or `<!--more-->`.
+    pub description: Option<String>,
+    pub lang: Option<String>,
+    pub dir: Option<Dir>,
+    /// When the post was written, `2025-03-01` or an RFC 3339 time. Shown
+    /// instead of the date it was published.
+    pub date: Option<String>,
+    /// Who wrote the post: an author `id` or name. Use `authors` for several.
+    pub author: Option<String>,
+    #[serde(default)]
+    pub authors: Vec<String>,
+    /// Notable changes, listed at the end of the post.
+    #[serde(default)]
+    pub changelog: Vec<ChangelogEntry>,
+    /// Object tags for everything uploaded for the post, e.g. `project: apollo`.
+    #[serde(default)]
+    pub labels: Labels,
+    /// Layout from `[page] templates_dir`, e.g. `photo-essay.html`.
+    pub template: Option<String>,
+    /// An audio file next to the post, e.g. `episode1.mp3`, played at the top
+    /// of the page and attached to the post in the feed.
+    pub audio: Option<String>,
+    /// Folder name under `p/` for a new post, e.g. `my-post`.
+    pub slug: Option<String>,
+}
+
+/// Splits `content` into the YAML between its `---` lines, if it starts with
+/// one, and the body after it.
+pub fn split(content: &str) -> Result<(Option<&str>, &str), Box<dyn Error>> {
+    let Some(rest) = content
+        .strip_prefix("---\n")
+        .or_else(|| content.strip_prefix("---\r\n"))
+    else {
+        return Ok((None, content));
+    };
+
+    let mut offset = 0;
+    for line in rest.split_inclusive('\n') {
+        if line.trim_end() == "---" {
+            return Ok((Some(&rest[..offset]), &rest[offset + line.len()..]));
+        }
+        offset += line.len();
+    }
+
+    Err("Front matter is missing its closing '---' line".into())
+}
+
+impl FrontMatter {
+    /// Splits `content` into its front matter and the remaining markdown body.
+    /// Files without a front matter block get the default (empty) front matter.
+    pub fn parse(content: &str) -> Result<(Self, &str), Box<dyn Error>> {
+        let (yaml, body) = split(content)?;
+        let front_matter: Self = match yaml {
+            Some(yaml) if !yaml.trim().is_empty() => {
+                serde_yaml::from_str(yaml).map_err(|e| format!("Invalid front matter: {}", e))?
+            }
+            _ => Self::default(),
+        };
+        validate_labels(&front_matter.labels)?;
+        if let Some(lang) = &front_matter.lang {
+            if !is_lang_tag(lang) {
+                return Err(format!(
+                    "Front matter lang '{}' isn't a language tag like 'sv' or 'pt-BR'",
+                    lang
+                )
+                .into());
+            }
+        }
+        Ok((front_matter, body))
+    }
+
+    /// The `date`, if set; a plain date means local midnight, so it shows as
+    /// that day.
+    pub fn date(&self) -> Result<Option<DateTime<Utc>>, Box<dyn Error>> {
+        let Some(date) = &self.date else {
+            return Ok(None);
+        };
+        if let Ok(time) = DateTime::parse_from_rfc3339(date) {
+            return Ok(Some(time.with_timezone(&Utc)));
+        }
+        let day = NaiveDate::parse_from_str(date, "%Y-%m-%d")
+            .map_err(|_| format!("Invalid front matter date '{}', expected YYYY-MM-DD", date))?;
+        let midnight = local_midnight(day)
+            .ok_or_else(|| format!("Front matter date '{}' doesn't exist here", date))?;
+        Ok(Some(midnight))
+    }
+}
+
+/// The start of `day` here.
+pub fn local_midnight(day: NaiveDate) -> Option<DateTime<Utc>> {
+    day.and_time(NaiveTime::MIN)
+        .and_local_timezone(Local)
+        .earliest()
+        .map(|midnight| midnight.with_timezone(&Utc))
+}
+
+/// The date a Jekyll-style file name like `2024-06-01-hello-world` starts
+/// with, and the name without it. A name that's only a date keeps it.
+pub fn split_file_date(name: &str) -> (Option<NaiveDate>, &str) {
+    let Some(date) = name
+        .get(..10)
+        .and_then(|date| NaiveDate::parse_from_str(date, "%Y-%m-%d").ok())
+    else {
+        return (None, name);
+    };
+    match name[10..].strip_prefix(['-', '_', ' ']) {
+        Some(rest) if !rest.is_empty() => (Some(date), rest),
+        _ if name.len() == 10 => (Some(date), name),
+        _ => (None, name),
+    }
+}
+
+/// Checks `labels` against S3's limits on object tags.
+pub fn validate_labels(labels: &Labels) -> Result<(), Box<dyn Error>> {
+    if labels.len() > MAX_LABELS {
+        return Err(format!("At most {} labels are allowed", MAX_LABELS).into());
+    }
+    for (key, value) in labels {
+        if key.is_empty() || key.chars().count() > 128 {
+            return Err(format!("Label key '{}' must be 1 to 128 characters", key).into());
+        }
+        if value.chars().count() > 256 {
+            return Err(format!("Label '{}' is longer than 256 characters", key).into());
+        }
+    }
+    Ok(())
+}
+
+/// `text` as a URL slug: lowercase letters and digits, with every run of
+/// anything else turned into one `-`.
+pub fn slugify(text: &str) -> String {
+    let mut slug = String::with_capacity(text.len());
+    for c in text.chars().flat_map(char::to_lowercase) {
+        if c.is_alphanumeric() {
+            slug.push(c);
+        } else if !slug.is_empty() && !slug.ends_with('-') {
+            slug.push('-');
+        }
+    }
+    slug.trim_end_matches('-').to_string()
+}
+
+/// `text` as a URL slug in `style`. Empty if nothing of it is left.
+pub fn slug(text: &str, style: SlugStyle) -> String {
+    let text: String = text.chars().filter(|c| !is_emoji(*c)).collect();
+    match style {
+        SlugStyle::Translit => slugify(&deunicode::deunicode(&text)),
+        SlugStyle::Unicode => slugify(&text),
+        SlugStyle::Hash if text.trim().is_empty() => String::new(),
+        SlugStyle::Hash => hex::encode(&Sha256::digest(text.trim().as_bytes())[..5]),
+    }
+}
+
+/// Emoji, their modifiers and the joiners between them, which would
+/// otherwise transliterate to their names.
+fn is_emoji(c: char) -> bool {
+    matches!(c as u32,
+        0x1f000..=0x1faff // pictographs, emoticons, flags, skin tones
+        | 0x2600..=0x27bf // symbols and dingbats
+        | 0x2b00..=0x2bff // arrows, stars
+        | 0x2300..=0x23ff // watch, hourglass, ...
+        | 0xfe00..=0xfe0f // variation selectors
+        | 0x200d // zero width joiner
+        | 0x20e3 // keycap
+        | 0xe0020..=0xe007f // tags
+    )
+}
@@ -0,0 +1,158 @@
+use crate::config::{PageConfig, SiteConfig};
+use crate::frontmatter::Dir;
+use crate::media;
+use crate::render::{escape_html, today, Page};
+use image::ImageFormat;
+use std::{error::Error, io::Cursor, path::Path};
+
+/// Longest side of a thumbnail, in pixels.
+const THUMBNAIL_SIZE: u32 = 480;
+
+/// Extensions of the images a gallery picks up from a folder.
+const IMAGE_EXTENSIONS: [&str; 6] = ["jpg", "jpeg", "png", "gif", "webp", "avif"];
+
+/// Whether `path` looks like an image a browser can show.
+pub fn is_image(path: &Path) -> bool {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| IMAGE_EXTENSIONS.contains(&ext.to_ascii_lowercase().as_str()))
+}
+
+/// A scaled-down copy of an image.
+pub struct Thumbnail {
+    pub body: Vec<u8>,
+    pub content_type: &'static str,
+    /// Extension for its file name.
+    pub extension: &'static str,
+}
+
+/// A thumbnail of the image in `bytes` (read from `path`), made with the
+/// built-in decoder if it handles the format and with `ffmpeg` otherwise.
+/// `None` if the image is already small or neither could, in which case the
+/// gallery shows the original.
+pub fn thumbnail(path: &Path, bytes: &[u8]) -> Option<Thumbnail> {
+    if let Ok(image) = image::load_from_memory(bytes) {
+        if image.width() <= THUMBNAIL_SIZE && image.height() <= THUMBNAIL_SIZE {
+            return None;
+        }
+        let mut body = Vec::new();
+        image
+            .thumbnail(THUMBNAIL_SIZE, THUMBNAIL_SIZE)
+            .write_to(&mut Cursor::new(&mut body), ImageFormat::Png)
+            .ok()?;
+        return Some(Thumbnail {
+            body,
+            content_type: "image/png",
+            extension: "png",
+        });
+    }
+    match media::ffmpeg_frame(path, None, THUMBNAIL_SIZE) {
+        Ok(body) => Some(Thumbnail {
+            body,
+            content_type: "image/jpeg",
+            extension: "jpg",
+        }),
+        Err(e) => {
+            eprintln!(
+                "No thumbnail for {} ({}); the gallery shows the full image",
+                path.display(),
+                e
+            );
+            None
+        }
+    }
+}
+
+/// An uploaded image, with paths relative to the gallery page.
+pub struct Photo {
+    pub name: String,
+    pub thumbnail: String,
+    /// Alt text, from the file name.
+    pub alt: String,
+}
+
+/// Alt text for an image: its file name without the extension, with `-` and
+/// `_` read as spaces.
+pub fn alt_text(path: &Path) -> String {
+    path.file_stem()
+        .map(|stem| stem.to_string_lossy().replace(['-', '_'], " "))
+        .unwrap_or_default()
+}
+
+/// Opens a photo full size in a dialog, with previous/next buttons and arrow
+/// keys. Without scripts the thumbnails are plain links to the photos.
+const LIGHTBOX_SCRIPT: &str = r#"<script>
+(() => {
+    const links = Array.from(document.querySelectorAll(".gallery a"));
+    const dialog = document.querySelector("dialog.lightbox");
+    const image = dialog.querySelector("img");
+    let current = 0;
+    const show = index => {
+        current = (index + links.length) % links.length;
+        image.src = links[current].href;
+        image.alt = links[current].querySelector("img").alt;
+    };
+    links.forEach((link, index) => link.addEventListener("click", event => {
+        event.preventDefault();
+        show(index);
+        dialog.showModal();
+    }));
+    dialog.querySelector(".previous").addEventListener("click", () => show(current - 1));
+    dialog.querySelector(".next").addEventListener("click", () => show(current + 1));
+    dialog.querySelector(".close").addEventListener("click", () => dialog.close());
+    dialog.addEventListener("click", event => { if (event.target === dialog) dialog.close(); });
+    dialog.addEventListener("keydown", event => {
+        if (event.key === "ArrowLeft") show(current - 1);
+        if (event.key === "ArrowRight") show(current + 1);
+    });
+})();
+</script>"#;
+
+/// A page showing `photos` as a grid of thumbnails that open in a lightbox.
+pub fn gallery_page(
+    title: &str,
+    photos: &[Photo],
+    page_config: &PageConfig,
+    site: &SiteConfig,
+) -> Result<String, Box<dyn Error>> {
+    let items: String = photos
+        .iter()
+        .map(|photo| {
+            format!(
+                "<a href=\"{name}\"><img src=\"{thumbnail}\" alt=\"{alt}\" loading=\"lazy\"></a>\n",
+                name = escape_html(&photo.name),
+                thumbnail = escape_html(&photo.thumbnail),
+                alt = escape_html(&photo.alt),
+            )
+        })
+        .collect();
+    let body = format!(
+        r#"<p>{count} photos</p>
+<div class="gallery">
+{items}</div>
+<dialog class="lightbox">
+    <img src="" alt="">
+    <button type="button" class="previous" aria-label="Previous">‹</button>
+    <button type="button" class="next" aria-label="Next">›</button>
+    <button type="button" class="close" aria-label="Close">×</button>
+</dialog>
+{LIGHTBOX_SCRIPT}"#,
+        count = photos.len(),
+    );
+    let date = today(page_config)?;
+    Ok(Page {
+        title,
+        heading: Some(title),
+        description: None,
+        lang: &page_config.lang,
+        dir: Dir::from_lang(&page_config.lang),
+        date: &date,
+        body: &body,
+        alternates: &[],
+        image_url: None,
+        site,
+        authors: &[],
+        source_url: None,
+    }
+    .render())
+}
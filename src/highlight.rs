@@ -0,0 +1,64 @@
+use std::{error::Error, path::Path};
+use syntect::{
+    easy::HighlightLines,
+    highlighting::{Color, Theme, ThemeSet},
+    html::{styled_line_to_highlighted_html, IncludeBackground},
+    parsing::SyntaxSet,
+    util::LinesWithEndings,
+};
+
+/// Syntax highlighting for source files, matching the dark page theme.
+pub struct Highlighter {
+    syntaxes: SyntaxSet,
+    theme: Theme,
+}
+
+impl Default for Highlighter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Highlighter {
+    pub fn new() -> Self {
+        let mut themes = ThemeSet::load_defaults();
+        Self {
+            syntaxes: SyntaxSet::load_defaults_newlines(),
+            theme: themes
+                .themes
+                .remove("base16-ocean.dark")
+                .expect("syntect ships base16-ocean.dark"),
+        }
+    }
+
+    /// Highlights `code` as a `<pre class="code">` block with one
+    /// `<span class="line">` per line (the page numbers them), picking the
+    /// language from the file name and falling back to plain text.
+    pub fn highlight(&self, code: &str, file_name: &Path) -> Result<String, Box<dyn Error>> {
+        let syntax = file_name
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .and_then(|ext| self.syntaxes.find_syntax_by_extension(ext))
+            .or_else(|| {
+                let first_line = code.lines().next().unwrap_or("");
+                self.syntaxes.find_syntax_by_first_line(first_line)
+            })
+            .unwrap_or_else(|| self.syntaxes.find_syntax_plain_text());
+
+        let Color { r, g, b, .. } = self.theme.settings.background.unwrap_or(Color::BLACK);
+        let mut html = format!(
+            "<pre class=\"code\" style=\"background-color:#{:02x}{:02x}{:02x};\"><code>",
+            r, g, b
+        );
+        let mut highlighter = HighlightLines::new(syntax, &self.theme);
+        for line in LinesWithEndings::from(code) {
+            let regions = highlighter.highlight_line(line, &self.syntaxes)?;
+            let line_html = styled_line_to_highlighted_html(&regions, IncludeBackground::No)?;
+            html.push_str("<span class=\"line\">");
+            html.push_str(line_html.trim_end_matches('\n'));
+            html.push_str("</span>\n");
+        }
+        html.push_str("</code></pre>");
+        Ok(html)
+    }
+}
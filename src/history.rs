@@ -0,0 +1,182 @@
+use crate::expiry::Ttl;
+use crate::manifest::Post;
+use crate::webhook::Event;
+use chrono::{DateTime, Local, NaiveDate, NaiveTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::{
+    error::Error,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+use tokio::{fs, io::AsyncWriteExt};
+
+/// One line of the history file: something that happened to a post.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Entry {
+    pub time: DateTime<Utc>,
+    pub event: Event,
+    pub id: String,
+    pub title: String,
+    pub url: String,
+}
+
+impl Entry {
+    pub fn new(event: Event, post: &Post) -> Self {
+        Self {
+            time: Utc::now(),
+            event,
+            id: post.id.clone(),
+            title: post.title.clone(),
+            url: post.url.clone(),
+        }
+    }
+}
+
+/// The history file kept next to the manifest at `manifest_path`:
+/// `history.jsonl` for `manifest.json`, `history-memory.jsonl` for the
+/// memory backend's scratch manifest.
+pub fn path_for(manifest_path: &Path) -> PathBuf {
+    let stem = manifest_path
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().replacen("manifest", "history", 1))
+        .unwrap_or_else(|| "history".to_string());
+    manifest_path.with_file_name(format!("{}.jsonl", stem))
+}
+
+/// Adds `entries` to the end of the history file, one JSON object per line.
+/// Earlier lines are never rewritten.
+pub async fn append(path: &Path, entries: &[Entry]) -> Result<(), Box<dyn Error>> {
+    if entries.is_empty() {
+        return Ok(());
+    }
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).await?;
+    }
+    let mut lines = String::new();
+    for entry in entries {
+        lines.push_str(&serde_json::to_string(entry)?);
+        lines.push('\n');
+    }
+    let mut file = fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .await?;
+    file.write_all(lines.as_bytes()).await?;
+    Ok(())
+}
+
+/// Every entry in the history file, oldest first. Lines that can't be read
+/// (say, cut short by a crash) are reported and skipped.
+pub async fn load(path: &Path) -> Result<Vec<Entry>, Box<dyn Error>> {
+    let content = match fs::read_to_string(path).await {
+        Ok(content) => content,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e.into()),
+    };
+    Ok(content
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .filter_map(|(number, line)| match serde_json::from_str(line) {
+            Ok(entry) => Some(entry),
+            Err(e) => {
+                eprintln!("Skipping line {} of {}: {}", number + 1, path.display(), e);
+                None
+            }
+        })
+        .collect())
+}
+
+/// A point in time for `--since`/`--until`: a date like `2025-03-01`, or
+/// how long ago, like `7d` or `12h`.
+#[derive(Debug, Clone, Copy)]
+pub enum Bound {
+    Date(NaiveDate),
+    Ago(Ttl),
+}
+
+impl Bound {
+    /// The first moment the bound covers: the start of the day for a date.
+    pub fn start(self) -> DateTime<Utc> {
+        match self {
+            Bound::Date(date) => local_midnight(date),
+            Bound::Ago(ago) => ago.before(Utc::now()),
+        }
+    }
+
+    /// The moment after the bound: the end of the day for a date.
+    pub fn end(self) -> DateTime<Utc> {
+        match self {
+            Bound::Date(date) => local_midnight(date.succ_opt().unwrap_or(date)),
+            Bound::Ago(ago) => ago.before(Utc::now()),
+        }
+    }
+}
+
+fn local_midnight(date: NaiveDate) -> DateTime<Utc> {
+    let midnight = date.and_time(NaiveTime::MIN);
+    midnight
+        .and_local_timezone(Local)
+        .earliest()
+        .map(|time| time.with_timezone(&Utc))
+        .unwrap_or_else(|| midnight.and_utc())
+}
+
+impl FromStr for Bound {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Ok(date) = NaiveDate::parse_from_str(s.trim(), "%Y-%m-%d") {
+            return Ok(Bound::Date(date));
+        }
+        s.parse()
+            .map(Bound::Ago)
+            .map_err(|_| format!("Invalid time '{}', expected e.g. 2025-03-01 or 7d", s))
+    }
+}
+
+/// Which entries `klistra history` shows.
+pub struct Filter {
+    /// Words that all have to appear in the title, URL or id.
+    pub query: Option<String>,
+    pub since: Option<Bound>,
+    pub until: Option<Bound>,
+    pub event: Option<Event>,
+}
+
+impl Filter {
+    pub fn matches(&self, entry: &Entry) -> bool {
+        if self.since.is_some_and(|since| entry.time < since.start())
+            || self.until.is_some_and(|until| entry.time >= until.end())
+            || self.event.is_some_and(|event| entry.event != event)
+        {
+            return false;
+        }
+        let Some(query) = &self.query else {
+            return true;
+        };
+        let haystack = format!("{} {} {}", entry.title, entry.url, entry.id).to_lowercase();
+        query
+            .to_lowercase()
+            .split_whitespace()
+            .all(|word| haystack.contains(word))
+    }
+}
+
+/// An entry as one line: local time, event, URL and title.
+pub fn format_entry(entry: &Entry) -> String {
+    let event = match entry.event {
+        Event::Published => "published",
+        Event::Updated => "updated",
+        Event::Deleted => "deleted",
+        Event::Restored => "restored",
+    };
+    format!(
+        "{}  {:<9}  {}  {}",
+        entry.time.with_timezone(&Local).format("%Y-%m-%d %H:%M"),
+        event,
+        entry.url,
+        entry.title
+    )
+}
@@ -0,0 +1,102 @@
+use crate::config::TlsConfig;
+use aws_sdk_s3::primitives::SdkBody;
+use aws_smithy_runtime_api::client::{
+    http::{
+        HttpClient, HttpConnector, HttpConnectorFuture, HttpConnectorSettings, SharedHttpClient,
+        SharedHttpConnector,
+    },
+    orchestrator::{HttpRequest, HttpResponse},
+    result::ConnectorError,
+    runtime_components::RuntimeComponents,
+};
+use reqwest::{redirect::Policy, Certificate, ClientBuilder, Identity};
+use std::error::Error;
+
+/// An HTTP client for everything but the bucket (announcements, webhooks,
+/// webmentions, ...), using the `[tls]` settings.
+pub fn client(tls: &TlsConfig) -> Result<reqwest::Client, Box<dyn Error>> {
+    Ok(builder(tls)?.build()?)
+}
+
+/// [`client`] before it's built, for adding more settings.
+pub fn builder(tls: &TlsConfig) -> Result<ClientBuilder, Box<dyn Error>> {
+    let mut builder = reqwest::Client::builder();
+    if let Some(path) = &tls.ca_bundle {
+        let pem = std::fs::read(path)
+            .map_err(|e| format!("Could not read CA bundle {}: {}", path.display(), e))?;
+        for certificate in Certificate::from_pem_bundle(&pem)? {
+            builder = builder.add_root_certificate(certificate);
+        }
+    }
+    if let Some(path) = &tls.client_cert {
+        let mut pem = std::fs::read(path).map_err(|e| {
+            format!(
+                "Could not read client certificate {}: {}",
+                path.display(),
+                e
+            )
+        })?;
+        if let Some(key) = &tls.client_key {
+            pem.extend(
+                std::fs::read(key)
+                    .map_err(|e| format!("Could not read client key {}: {}", key.display(), e))?,
+            );
+        }
+        builder = builder.identity(Identity::from_pem(&pem)?);
+    }
+    Ok(builder)
+}
+
+/// An HTTP client for the S3 SDK that honors the `[tls]` settings, which the
+/// SDK's built-in client can't do for client certificates.
+pub fn s3_client(tls: &TlsConfig) -> Result<SharedHttpClient, Box<dyn Error>> {
+    // The SDK handles redirects itself.
+    let client = builder(tls)?.redirect(Policy::none()).build()?;
+    Ok(SharedHttpClient::new(ReqwestConnector { client }))
+}
+
+#[derive(Debug, Clone)]
+struct ReqwestConnector {
+    client: reqwest::Client,
+}
+
+impl HttpConnector for ReqwestConnector {
+    fn call(&self, request: HttpRequest) -> HttpConnectorFuture {
+        let client = self.client.clone();
+        HttpConnectorFuture::new(async move {
+            let request = request
+                .try_into_http1x()
+                .map_err(|e| ConnectorError::other(e.into(), None))?;
+            let (parts, body) = request.into_parts();
+            let request = reqwest::Request::try_from(http::Request::from_parts(
+                parts,
+                reqwest::Body::wrap(body),
+            ))
+            .map_err(|e| ConnectorError::other(e.into(), None))?;
+
+            let response = client.execute(request).await.map_err(|e| {
+                if e.is_timeout() {
+                    ConnectorError::timeout(e.into())
+                } else {
+                    ConnectorError::io(e.into())
+                }
+            })?;
+            let (parts, body) = http::Response::from(response).into_parts();
+            HttpResponse::try_from(http::Response::from_parts(
+                parts,
+                SdkBody::from_body_1_x(body),
+            ))
+            .map_err(|e| ConnectorError::other(e.into(), None))
+        })
+    }
+}
+
+impl HttpClient for ReqwestConnector {
+    fn http_connector(
+        &self,
+        _settings: &HttpConnectorSettings,
+        _components: &RuntimeComponents,
+    ) -> SharedHttpConnector {
+        SharedHttpConnector::new(self.clone())
+    }
+}
@@ -0,0 +1,166 @@
+use std::{error::Error, sync::Arc};
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+use tokio::net::TcpStream;
+use tokio_rustls::{
+    client::TlsStream,
+    rustls::{self, pki_types::ServerName, ClientConfig, RootCertStore},
+    TlsConnector,
+};
+
+/// Emails bigger than this are refused rather than read into memory.
+const MAX_LITERAL: usize = 64 * 1024 * 1024;
+
+/// The few IMAP commands `mail-fetch` needs, over implicit TLS.
+pub struct Imap {
+    stream: BufReader<TlsStream<TcpStream>>,
+    tag: u32,
+}
+
+/// An untagged response: its text, with `{n}` literals cut out and kept in
+/// order in `literals`.
+struct Untagged {
+    text: String,
+    literals: Vec<Vec<u8>>,
+}
+
+impl Imap {
+    /// Connects to `host` and reads the server's greeting.
+    pub async fn connect(host: &str, port: u16) -> Result<Self, Box<dyn Error>> {
+        let roots = RootCertStore {
+            roots: webpki_roots::TLS_SERVER_ROOTS.to_vec(),
+        };
+        let config =
+            ClientConfig::builder_with_provider(Arc::new(rustls::crypto::ring::default_provider()))
+                .with_safe_default_protocol_versions()?
+                .with_root_certificates(roots)
+                .with_no_client_auth();
+        let tcp = TcpStream::connect((host, port))
+            .await
+            .map_err(|e| format!("Could not connect to {}:{}: {}", host, port, e))?;
+        let name = ServerName::try_from(host.to_string())?;
+        let tls = TlsConnector::from(Arc::new(config))
+            .connect(name, tcp)
+            .await?;
+        let mut imap = Imap {
+            stream: BufReader::new(tls),
+            tag: 0,
+        };
+        let greeting = imap.read_line().await?;
+        if !greeting.starts_with("* OK") && !greeting.starts_with("* PREAUTH") {
+            return Err(format!("Unexpected IMAP greeting: {}", greeting.trim_end()).into());
+        }
+        Ok(imap)
+    }
+
+    pub async fn login(&mut self, username: &str, password: &str) -> Result<(), Box<dyn Error>> {
+        self.command(&format!("LOGIN {} {}", quote(username), quote(password)))
+            .await
+            .map_err(|e| format!("IMAP login failed: {}", e))?;
+        Ok(())
+    }
+
+    pub async fn select(&mut self, mailbox: &str) -> Result<(), Box<dyn Error>> {
+        self.command(&format!("SELECT {}", quote(mailbox))).await?;
+        Ok(())
+    }
+
+    /// The UIDs of the emails not yet marked as seen.
+    pub async fn unseen(&mut self) -> Result<Vec<u32>, Box<dyn Error>> {
+        let responses = self.command("UID SEARCH UNSEEN").await?;
+        Ok(responses
+            .iter()
+            .filter_map(|response| response.text.strip_prefix("SEARCH"))
+            .flat_map(str::split_whitespace)
+            .filter_map(|uid| uid.parse().ok())
+            .collect())
+    }
+
+    /// The whole email with `uid`, without marking it as seen.
+    pub async fn fetch(&mut self, uid: u32) -> Result<Vec<u8>, Box<dyn Error>> {
+        let responses = self
+            .command(&format!("UID FETCH {} BODY.PEEK[]", uid))
+            .await?;
+        responses
+            .into_iter()
+            .filter(|response| response.text.contains("FETCH"))
+            .find_map(|response| response.literals.into_iter().next())
+            .ok_or_else(|| format!("The server sent no email for UID {}", uid).into())
+    }
+
+    pub async fn mark_seen(&mut self, uid: u32) -> Result<(), Box<dyn Error>> {
+        self.command(&format!("UID STORE {} +FLAGS.SILENT (\\Seen)", uid))
+            .await?;
+        Ok(())
+    }
+
+    pub async fn logout(mut self) -> Result<(), Box<dyn Error>> {
+        self.command("LOGOUT").await?;
+        Ok(())
+    }
+
+    /// Sends `command` and collects the untagged responses until its tagged
+    /// one, which must be `OK`.
+    async fn command(&mut self, command: &str) -> Result<Vec<Untagged>, Box<dyn Error>> {
+        self.tag += 1;
+        let tag = format!("a{}", self.tag);
+        let stream = self.stream.get_mut();
+        stream
+            .write_all(format!("{} {}\r\n", tag, command).as_bytes())
+            .await?;
+        stream.flush().await?;
+
+        let mut responses = Vec::new();
+        loop {
+            let line = self.read_line().await?;
+            if let Some(status) = line.strip_prefix(&format!("{} ", tag)) {
+                let status = status.trim_end();
+                if status.starts_with("OK") {
+                    return Ok(responses);
+                }
+                return Err(status.to_string().into());
+            }
+            let Some(text) = line.strip_prefix("* ") else {
+                // Continuation requests and anything else unexpected.
+                continue;
+            };
+            let mut response = Untagged {
+                text: String::new(),
+                literals: Vec::new(),
+            };
+            let mut text = text.to_string();
+            while let Some(length) = literal_length(&text) {
+                if length > MAX_LITERAL {
+                    return Err(format!("Refusing a {} byte response", length).into());
+                }
+                let mut literal = vec![0; length];
+                self.stream.read_exact(&mut literal).await?;
+                response.literals.push(literal);
+                let start = text.rfind('{').unwrap_or(text.len());
+                response.text.push_str(&text[..start]);
+                text = self.read_line().await?;
+            }
+            response.text.push_str(text.trim_end());
+            responses.push(response);
+        }
+    }
+
+    async fn read_line(&mut self) -> Result<String, Box<dyn Error>> {
+        let mut line = Vec::new();
+        if self.stream.read_until(b'\n', &mut line).await? == 0 {
+            return Err("The IMAP server closed the connection".into());
+        }
+        Ok(String::from_utf8_lossy(&line).into_owned())
+    }
+}
+
+/// The `n` of a line ending in `{n}`, announcing `n` bytes to follow.
+fn literal_length(line: &str) -> Option<usize> {
+    let line = line.trim_end().strip_suffix('}')?;
+    let start = line.rfind('{')?;
+    line[start + 1..].parse().ok()
+}
+
+/// `value` as an IMAP quoted string.
+fn quote(value: &str) -> String {
+    format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\""))
+}
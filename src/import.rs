@@ -0,0 +1,751 @@
+use crate::config::SlugStyle;
+use crate::frontmatter::{slug, split_file_date};
+use crate::notion;
+use chrono::{DateTime, Datelike, NaiveDate, NaiveDateTime};
+use serde_json::{Map, Value};
+use std::{
+    collections::HashSet,
+    error::Error,
+    fs,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+/// Front matter keys and values.
+type FrontMatter = Map<String, Value>;
+
+/// Extensions of the posts an import picks up.
+const MARKDOWN_EXTENSIONS: &[&str] = &["md", "markdown"];
+
+/// Config files Hugo reads, in the order it looks for them.
+const HUGO_CONFIGS: &[&str] = &[
+    "hugo.toml",
+    "hugo.yaml",
+    "hugo.yml",
+    "hugo.json",
+    "config.toml",
+    "config.yaml",
+    "config.yml",
+    "config.json",
+];
+
+/// Files at the top of a Jekyll site that are part of the build, not the
+/// site.
+const JEKYLL_IGNORED: &[&str] = &[
+    "Gemfile",
+    "Gemfile.lock",
+    "Rakefile",
+    "CNAME",
+    "node_modules",
+    "vendor",
+    "package.json",
+    "package-lock.json",
+];
+
+/// Where `klistra import` reads posts from: a static site generator's
+/// directory, or a Notion export.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Generator {
+    Hugo,
+    Jekyll,
+    /// A zip of Notion's Markdown or HTML export.
+    Notion,
+}
+
+impl FromStr for Generator {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "hugo" => Ok(Generator::Hugo),
+            "jekyll" => Ok(Generator::Jekyll),
+            "notion" => Ok(Generator::Notion),
+            other => Err(format!(
+                "Unknown source '{}', expected hugo, jekyll or notion",
+                other
+            )),
+        }
+    }
+}
+
+/// A site turned into klistra notes, ready to publish.
+#[derive(Debug, Default)]
+pub struct Conversion {
+    pub posts: Vec<ConvertedPost>,
+    /// Static files, with the key that keeps their old URL.
+    pub assets: Vec<(PathBuf, String)>,
+    /// Drafts, which are left out.
+    pub drafts: usize,
+}
+
+/// One post of the old site.
+#[derive(Debug)]
+pub struct ConvertedPost {
+    /// The note written for it.
+    pub note: PathBuf,
+    /// Where it was on the old site without the leading slash, like
+    /// `2024/06/hello-world`, followed by its aliases.
+    pub old_paths: Vec<String>,
+    /// Files from its page bundle, relative to the note, which go next to
+    /// the published page.
+    pub resources: Vec<String>,
+}
+
+/// A post to import, with its date and where the old site had it.
+struct Found {
+    post: SourcePost,
+    date: Option<NaiveDate>,
+    old_path: String,
+}
+
+/// A post as the old site has it.
+struct SourcePost {
+    path: PathBuf,
+    /// Keys lowercased, as Hugo matches them.
+    front_matter: FrontMatter,
+    body: String,
+    /// The directory of a page bundle, whose other files go with the post.
+    bundle: Option<PathBuf>,
+}
+
+impl SourcePost {
+    fn read(path: &Path) -> Result<Self, Box<dyn Error>> {
+        let text = fs::read_to_string(path)
+            .map_err(|e| format!("Could not read {}: {}", path.display(), e))?;
+        let (front_matter, body) =
+            parse(&text).map_err(|e| format!("{}: {}", path.display(), e))?;
+        let bundle = path
+            .file_stem()
+            .is_some_and(|stem| stem == "index")
+            .then(|| path.parent().map(Path::to_path_buf))
+            .flatten();
+        Ok(Self {
+            path: path.to_path_buf(),
+            front_matter,
+            body: body.to_string(),
+            bundle,
+        })
+    }
+
+    fn get(&self, key: &str) -> Option<&Value> {
+        self.front_matter.get(key).filter(|value| !value.is_null())
+    }
+
+    fn text(&self, key: &str) -> Option<&str> {
+        self.get(key)
+            .and_then(Value::as_str)
+            .map(str::trim)
+            .filter(|text| !text.is_empty())
+    }
+
+    /// Strings in a list, or a single string split on whitespace, like
+    /// Jekyll's `categories`.
+    fn list(&self, key: &str) -> Vec<String> {
+        match self.get(key) {
+            Some(Value::Array(items)) => items
+                .iter()
+                .filter_map(|item| match item {
+                    Value::String(text) => Some(text.clone()),
+                    Value::Number(number) => Some(number.to_string()),
+                    _ => None,
+                })
+                .collect(),
+            Some(Value::String(text)) => text.split_whitespace().map(str::to_string).collect(),
+            _ => Vec::new(),
+        }
+    }
+
+    fn is_draft(&self) -> bool {
+        self.get("draft").and_then(Value::as_bool) == Some(true)
+            || self.get("published").and_then(Value::as_bool) == Some(false)
+    }
+
+    /// The file name without extension, or the directory's name for a
+    /// bundle's `index.md`.
+    fn file_name(&self) -> String {
+        let path = match &self.bundle {
+            Some(bundle) => bundle.as_path(),
+            None => self.path.as_path(),
+        };
+        path.file_stem()
+            .map(|stem| stem.to_string_lossy().into_owned())
+            .unwrap_or_default()
+    }
+}
+
+/// Reads the Hugo or Jekyll site in `site` and writes each of its posts as
+/// a klistra note under `out`, with page bundles' files next to it. Drafts
+/// are skipped, and with `page` so is every post but the one with that
+/// title or file name. A Notion export is read by [`notion::convert`].
+pub fn convert(
+    generator: Generator,
+    site: &Path,
+    out: &Path,
+    page: Option<&str>,
+) -> Result<Conversion, Box<dyn Error>> {
+    if generator == Generator::Notion {
+        return notion::convert(site, out, page);
+    }
+    if !site.is_dir() {
+        return Err(format!("{} is not a directory", site.display()).into());
+    }
+    let mut conversion = Conversion::default();
+    let mut notes = HashSet::new();
+    let posts = match generator {
+        Generator::Hugo => hugo(site, &mut conversion)?,
+        Generator::Jekyll => jekyll(site, &mut conversion)?,
+        Generator::Notion => unreachable!("read from the zip above"),
+    };
+    let posts = match page {
+        Some(page) => {
+            let wanted = slug(page, SlugStyle::Translit);
+            let posts: Vec<Found> = posts
+                .into_iter()
+                .filter(|found| {
+                    let title = found.post.text("title").unwrap_or_default();
+                    [title, found.post.file_name().as_str()]
+                        .iter()
+                        .any(|name| slug(name, SlugStyle::Translit) == wanted)
+                })
+                .collect();
+            if posts.is_empty() {
+                return Err(format!("No post called '{}' in {}", page, site.display()).into());
+            }
+            posts
+        }
+        None => posts,
+    };
+    for Found {
+        post,
+        date,
+        old_path,
+    } in posts
+    {
+        let mut old_paths = vec![old_path];
+        for alias in post
+            .list("aliases")
+            .iter()
+            .chain(&post.list("redirect_from"))
+        {
+            old_paths.push(trim_path(alias));
+        }
+        let converted = write_note(&post, date, old_paths, out, &mut notes)?;
+        if post.body.contains("{{<") || post.body.contains("{{%") || post.body.contains("{%") {
+            eprintln!(
+                "{} uses shortcodes or Liquid tags, which are published as they are",
+                post.path.display()
+            );
+        }
+        conversion.posts.push(converted);
+    }
+    Ok(conversion)
+}
+
+/// The posts under Hugo's content directory with their dates and paths,
+/// and the files in its static directory.
+fn hugo(site: &Path, conversion: &mut Conversion) -> Result<Vec<Found>, Box<dyn Error>> {
+    let config = HUGO_CONFIGS
+        .iter()
+        .map(|name| site.join(name))
+        .find(|path| path.is_file())
+        .map(|path| read_config(&path))
+        .transpose()?
+        .unwrap_or(Value::Null);
+    let setting = |key: &str, default: &str| {
+        config
+            .get(key)
+            .or_else(|| config.get(key.to_ascii_lowercase()))
+            .and_then(Value::as_str)
+            .unwrap_or(default)
+            .to_string()
+    };
+    let content = site.join(setting("contentDir", "content"));
+    let permalinks = config.get("permalinks").cloned().unwrap_or(Value::Null);
+    // Newer Hugo nests them by kind.
+    let permalinks = permalinks.get("page").cloned().unwrap_or(permalinks);
+
+    let mut posts = Vec::new();
+    for path in files(&content)? {
+        let name = path.file_name().unwrap_or_default().to_string_lossy();
+        if !is_markdown(&path) || name.starts_with("_index.") {
+            continue;
+        }
+        let post = SourcePost::read(&path)?;
+        if post.is_draft() {
+            conversion.drafts += 1;
+            continue;
+        }
+        let relative = path.strip_prefix(&content).unwrap_or(&path);
+        let sections: Vec<String> = relative
+            .parent()
+            .into_iter()
+            .flat_map(Path::components)
+            .map(|part| part.as_os_str().to_string_lossy().into_owned())
+            .collect();
+        // A bundle's own directory isn't a section.
+        let sections = match post.bundle {
+            Some(_) => &sections[..sections.len().saturating_sub(1)],
+            None => &sections[..],
+        };
+        let section = sections.first().cloned().unwrap_or_default();
+        let date = post.get("date").and_then(date);
+
+        let title = post
+            .text("title")
+            .map(|title| slug(title, SlugStyle::Unicode));
+        let file_name = post.file_name();
+        let slug_or_title = post
+            .text("slug")
+            .map(str::to_string)
+            .or(title.clone())
+            .unwrap_or_else(|| file_name.clone());
+        let old_path = match post.text("url") {
+            Some(url) => url.to_string(),
+            None => match permalinks.get(&section).and_then(Value::as_str) {
+                Some(pattern) => expand(pattern, date, |token| match token {
+                    "title" => Some(title.clone().unwrap_or_else(|| file_name.clone())),
+                    "slug" => Some(slug_or_title.clone()),
+                    "filename" | "contentbasename" => Some(file_name.clone()),
+                    "slugorfilename" | "slugorcontentbasename" => {
+                        Some(post.text("slug").unwrap_or(&file_name).to_string())
+                    }
+                    "section" => Some(section.clone()),
+                    "sections" => Some(sections.join("/")),
+                    _ => None,
+                }),
+                None => {
+                    let name = post.text("slug").unwrap_or(&file_name);
+                    format!("/{}/{}/", sections.join("/"), name)
+                }
+            },
+        };
+        posts.push(Found {
+            post,
+            date,
+            old_path: trim_path(&old_path),
+        });
+    }
+
+    let static_dir = site.join(setting("staticDir", "static"));
+    if static_dir.is_dir() {
+        for path in files(&static_dir)? {
+            let key = key_of(&path, &static_dir);
+            conversion.assets.push((path, key));
+        }
+    }
+    Ok(posts)
+}
+
+/// The posts in Jekyll's `_posts` with their dates and paths, and the
+/// site's static files: everything outside `_` directories that isn't a
+/// page or part of the build.
+fn jekyll(site: &Path, conversion: &mut Conversion) -> Result<Vec<Found>, Box<dyn Error>> {
+    let config = ["_config.yml", "_config.yaml", "_config.toml"]
+        .iter()
+        .map(|name| site.join(name))
+        .find(|path| path.is_file())
+        .map(|path| read_config(&path))
+        .transpose()?
+        .unwrap_or(Value::Null);
+    let site_permalink = config
+        .get("permalink")
+        .and_then(Value::as_str)
+        .unwrap_or("date")
+        .to_string();
+
+    let mut posts = Vec::new();
+    let posts_dir = site.join("_posts");
+    let post_files = if posts_dir.is_dir() {
+        files(&posts_dir)?
+    } else {
+        Vec::new()
+    };
+    for path in post_files {
+        if !is_markdown(&path) {
+            continue;
+        }
+        let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+        // Jekyll only publishes posts whose name starts with their date.
+        let (Some(file_date), name) = split_file_date(&stem) else {
+            eprintln!("Skipping {}: not named YYYY-MM-DD-title", path.display());
+            continue;
+        };
+        let name = name.to_string();
+        let post = SourcePost::read(&path)?;
+        if post.is_draft() {
+            conversion.drafts += 1;
+            continue;
+        }
+        let date = post.get("date").and_then(date).or(Some(file_date));
+        let mut categories = post.list("categories");
+        categories.extend(post.list("category"));
+        let categories: Vec<String> = categories
+            .iter()
+            .map(|category| slug(category, SlugStyle::Unicode))
+            .collect();
+        let title = post.text("slug").map_or(name.clone(), str::to_string);
+
+        let pattern = post
+            .text("permalink")
+            .map_or(site_permalink.clone(), str::to_string);
+        let pattern = match pattern.as_str() {
+            "date" => "/:categories/:year/:month/:day/:title:output_ext",
+            "pretty" => "/:categories/:year/:month/:day/:title/",
+            "ordinal" => "/:categories/:year/:y_day/:title:output_ext",
+            "weekdate" => "/:categories/:year/W:week/:short_day/:title:output_ext",
+            "none" => "/:categories/:title:output_ext",
+            pattern => pattern,
+        };
+        let old_path = expand(pattern, date, |token| match token {
+            "title" | "slug" => Some(slug(&title, SlugStyle::Unicode)),
+            "categories" => Some(categories.join("/")),
+            "output_ext" => Some(".html".to_string()),
+            _ => None,
+        });
+        posts.push(Found {
+            post,
+            date,
+            old_path: trim_path(&old_path),
+        });
+    }
+
+    for path in files(site)? {
+        let relative = path.strip_prefix(site).unwrap_or(&path);
+        let skipped = relative.components().any(|part| {
+            let part = part.as_os_str().to_string_lossy();
+            part.starts_with('_')
+                || part.starts_with('.')
+                || JEKYLL_IGNORED.contains(&part.as_ref())
+        });
+        let extension = path
+            .extension()
+            .map(|ext| ext.to_string_lossy().to_ascii_lowercase())
+            .unwrap_or_default();
+        let page = [
+            "md", "markdown", "html", "htm", "yml", "yaml", "liquid", "gemspec",
+        ]
+        .contains(&extension.as_str());
+        if !skipped && !page {
+            conversion.assets.push((path.clone(), key_of(&path, site)));
+        }
+    }
+    Ok(posts)
+}
+
+/// Writes `post` as a note under `out`, named by its date so the index
+/// keeps the old order, with its slug from its old path so the new URL
+/// reads the same. A bundle's files are copied next to the note.
+fn write_note(
+    post: &SourcePost,
+    date: Option<NaiveDate>,
+    old_paths: Vec<String>,
+    out: &Path,
+    taken: &mut HashSet<PathBuf>,
+) -> Result<ConvertedPost, Box<dyn Error>> {
+    let last = old_paths[0].rsplit('/').next().unwrap_or_default();
+    let last = last
+        .strip_suffix(".html")
+        .or_else(|| last.strip_suffix(".htm"))
+        .unwrap_or(last);
+    let mut name = slug(last, SlugStyle::Translit);
+    if name.is_empty() {
+        name = slug(&post.file_name(), SlugStyle::Translit);
+    }
+
+    let mut front_matter = Map::new();
+    for key in ["title", "description", "lang"] {
+        if let Some(text) = post.text(key) {
+            front_matter.insert(key.to_string(), Value::String(text.to_string()));
+        }
+    }
+    if !front_matter.contains_key("description") {
+        // Hugo's `summary`, Jekyll's `excerpt`.
+        if let Some(text) = post.text("summary").or(post.text("excerpt")) {
+            front_matter.insert("description".to_string(), Value::String(text.to_string()));
+        }
+    }
+    if let Some(date) = post.get("date").and_then(date_string) {
+        front_matter.insert("date".to_string(), Value::String(date));
+    }
+    let authors = match post.list("authors").as_slice() {
+        [] => post.list("author"),
+        authors => authors.to_vec(),
+    };
+    match authors.as_slice() {
+        [] => {}
+        [author] => {
+            front_matter.insert("author".to_string(), Value::String(author.clone()));
+        }
+        authors => {
+            front_matter.insert("authors".to_string(), Value::from(authors.to_vec()));
+        }
+    }
+    if !name.is_empty() {
+        front_matter.insert("slug".to_string(), Value::String(name.clone()));
+    }
+
+    let stem = match date {
+        Some(date) => format!("{}-{}", date.format("%Y-%m-%d"), name),
+        None => name,
+    };
+    let mut stem_free = stem.clone();
+    for n in 2.. {
+        let candidate = out.join(&stem_free);
+        if !taken.contains(&candidate) {
+            taken.insert(candidate);
+            break;
+        }
+        stem_free = format!("{}-{}", stem, n);
+    }
+    let dir = match &post.bundle {
+        Some(_) => out.join(&stem_free),
+        None => out.to_path_buf(),
+    };
+    fs::create_dir_all(&dir).map_err(|e| format!("Could not create {}: {}", dir.display(), e))?;
+    let note = dir.join(format!("{}.md", stem_free));
+    let yaml = serde_yaml::to_string(&front_matter)?;
+    fs::write(
+        &note,
+        format!("---\n{}---\n\n{}", yaml, post.body.trim_start()),
+    )
+    .map_err(|e| format!("Could not write {}: {}", note.display(), e))?;
+
+    let mut resources = Vec::new();
+    if let Some(bundle) = &post.bundle {
+        for file in files(bundle)? {
+            if file == post.path || is_markdown(&file) {
+                continue;
+            }
+            let resource = key_of(&file, bundle);
+            let target = dir.join(&resource);
+            resources.push(resource);
+            if let Some(parent) = target.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            fs::copy(&file, &target)
+                .map_err(|e| format!("Could not copy {}: {}", file.display(), e))?;
+        }
+    }
+    Ok(ConvertedPost {
+        note,
+        old_paths,
+        resources,
+    })
+}
+
+/// Splits `text` into its front matter and body: YAML between `---`
+/// lines, TOML between `+++` lines, or a JSON object, as Hugo allows.
+fn parse(text: &str) -> Result<(FrontMatter, &str), Box<dyn Error>> {
+    let text = text.trim_start_matches('\u{feff}');
+    let (value, body) = if let Some((yaml, body)) = fenced(text, "---") {
+        let value: Value = match yaml.trim() {
+            "" => Value::Null,
+            yaml => serde_yaml::from_str(yaml)
+                .map_err(|e| format!("Invalid YAML front matter: {}", e))?,
+        };
+        (value, body)
+    } else if let Some((front_matter, body)) = fenced(text, "+++") {
+        let table: toml::Table = toml::from_str(front_matter)
+            .map_err(|e| format!("Invalid TOML front matter: {}", e))?;
+        (from_toml(toml::Value::Table(table)), body)
+    } else if text.starts_with('{') {
+        let mut values = serde_json::Deserializer::from_str(text).into_iter::<Value>();
+        let value = values
+            .next()
+            .transpose()
+            .map_err(|e| format!("Invalid JSON front matter: {}", e))?
+            .unwrap_or(Value::Null);
+        (value, &text[values.byte_offset()..])
+    } else {
+        (Value::Null, text)
+    };
+    let front_matter = match value {
+        Value::Object(map) => map
+            .into_iter()
+            .map(|(key, value)| (key.to_ascii_lowercase(), value))
+            .collect(),
+        _ => Map::new(),
+    };
+    Ok((front_matter, body))
+}
+
+/// What's between a first line of `fence` and the next, and what follows.
+fn fenced<'a>(text: &'a str, fence: &str) -> Option<(&'a str, &'a str)> {
+    let rest = text.strip_prefix(fence)?;
+    let rest = rest
+        .strip_prefix("\r\n")
+        .or_else(|| rest.strip_prefix('\n'))?;
+    let mut offset = 0;
+    for line in rest.split_inclusive('\n') {
+        if line.trim_end() == fence {
+            return Some((&rest[..offset], &rest[offset + line.len()..]));
+        }
+        offset += line.len();
+    }
+    None
+}
+
+/// A site config in TOML, YAML or JSON, by its extension.
+fn read_config(path: &Path) -> Result<Value, Box<dyn Error>> {
+    let text = fs::read_to_string(path)
+        .map_err(|e| format!("Could not read {}: {}", path.display(), e))?;
+    let invalid = |e: &dyn std::fmt::Display| format!("Invalid {}: {}", path.display(), e);
+    let value = match path.extension().and_then(|ext| ext.to_str()) {
+        Some("toml") => from_toml(toml::from_str(&text).map_err(|e| invalid(&e))?),
+        Some("json") => serde_json::from_str(&text).map_err(|e| invalid(&e))?,
+        _ => serde_yaml::from_str(&text).map_err(|e| invalid(&e))?,
+    };
+    Ok(value)
+}
+
+/// TOML as JSON, with dates and times as the strings they were written as.
+fn from_toml(value: toml::Value) -> Value {
+    match value {
+        toml::Value::String(text) => Value::String(text),
+        toml::Value::Integer(number) => Value::from(number),
+        toml::Value::Float(number) => Value::from(number),
+        toml::Value::Boolean(flag) => Value::Bool(flag),
+        toml::Value::Datetime(datetime) => Value::String(datetime.to_string()),
+        toml::Value::Array(items) => Value::Array(items.into_iter().map(from_toml).collect()),
+        toml::Value::Table(table) => Value::Object(
+            table
+                .into_iter()
+                .map(|(key, value)| (key, from_toml(value)))
+                .collect(),
+        ),
+    }
+}
+
+/// The day of a front matter date in any of the forms Hugo and Jekyll
+/// take.
+fn date(value: &Value) -> Option<NaiveDate> {
+    let text = value.as_str()?.trim();
+    if let Ok(time) = DateTime::parse_from_rfc3339(text) {
+        return Some(time.date_naive());
+    }
+    for format in [
+        "%Y-%m-%d %H:%M:%S %z",
+        "%Y-%m-%d %H:%M:%S%.f %z",
+        "%Y-%m-%d %H:%M %z",
+    ] {
+        if let Ok(time) = DateTime::parse_from_str(text, format) {
+            return Some(time.date_naive());
+        }
+    }
+    for format in ["%Y-%m-%d %H:%M:%S", "%Y-%m-%dT%H:%M:%S", "%Y-%m-%d %H:%M"] {
+        if let Ok(time) = NaiveDateTime::parse_from_str(text, format) {
+            return Some(time.date());
+        }
+    }
+    NaiveDate::parse_from_str(text.get(..10)?, "%Y-%m-%d").ok()
+}
+
+/// A front matter date as klistra reads it: RFC 3339 if it has a time and
+/// zone, otherwise the day.
+fn date_string(value: &Value) -> Option<String> {
+    let text = value.as_str()?.trim();
+    if let Ok(time) = DateTime::parse_from_rfc3339(text) {
+        return Some(time.to_rfc3339());
+    }
+    for format in [
+        "%Y-%m-%d %H:%M:%S %z",
+        "%Y-%m-%d %H:%M:%S%.f %z",
+        "%Y-%m-%d %H:%M %z",
+    ] {
+        if let Ok(time) = DateTime::parse_from_str(text, format) {
+            return Some(time.to_rfc3339());
+        }
+    }
+    date(value).map(|day| day.format("%Y-%m-%d").to_string())
+}
+
+/// `pattern` with its `:token`s filled in: dates from `date`, anything else
+/// from `token`. Tokens without a value are left out.
+fn expand(
+    pattern: &str,
+    date: Option<NaiveDate>,
+    token: impl Fn(&str) -> Option<String>,
+) -> String {
+    let mut out = String::new();
+    let mut rest = pattern;
+    while let Some(start) = rest.find(':') {
+        out.push_str(&rest[..start]);
+        let after = &rest[start + 1..];
+        let end = after
+            .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+            .unwrap_or(after.len());
+        let name = &after[..end];
+        let value = match (name, date) {
+            ("year", Some(date)) => Some(date.format("%Y").to_string()),
+            ("short_year", Some(date)) => Some(date.format("%y").to_string()),
+            ("month", Some(date)) => Some(date.format("%m").to_string()),
+            ("i_month", Some(date)) => Some(date.month().to_string()),
+            ("monthname", Some(date)) => Some(date.format("%B").to_string().to_lowercase()),
+            ("day", Some(date)) => Some(date.format("%d").to_string()),
+            ("i_day", Some(date)) => Some(date.day().to_string()),
+            ("y_day", Some(date)) => Some(format!("{:03}", date.ordinal())),
+            ("week", Some(date)) => Some(format!("{:02}", date.iso_week().week())),
+            ("short_day", Some(date)) => Some(date.format("%a").to_string()),
+            _ => token(name),
+        };
+        match value {
+            Some(value) => out.push_str(&value),
+            // Not a token, like the colon in a URL.
+            None if name.is_empty() => out.push(':'),
+            None => {}
+        }
+        rest = &after[end..];
+    }
+    out.push_str(rest);
+    out
+}
+
+/// A URL path as a redirect slug: no leading or trailing slashes, no
+/// `index.html`, and no runs of slashes left by empty tokens.
+fn trim_path(path: &str) -> String {
+    let path = path.strip_suffix("index.html").unwrap_or(path);
+    path.split('/')
+        .filter(|part| !part.is_empty())
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+/// Bucket key of the static file at `path` in `root`.
+fn key_of(path: &Path, root: &Path) -> String {
+    path.strip_prefix(root)
+        .unwrap_or(path)
+        .components()
+        .map(|part| part.as_os_str().to_string_lossy().into_owned())
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+fn is_markdown(path: &Path) -> bool {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| MARKDOWN_EXTENSIONS.contains(&ext.to_ascii_lowercase().as_str()))
+}
+
+/// Every file under `dir`, sorted, skipping hidden ones.
+fn files(dir: &Path) -> Result<Vec<PathBuf>, Box<dyn Error>> {
+    let mut found = Vec::new();
+    let mut pending = vec![dir.to_path_buf()];
+    while let Some(current) = pending.pop() {
+        let entries = fs::read_dir(&current)
+            .map_err(|e| format!("Could not read {}: {}", current.display(), e))?;
+        for entry in entries {
+            let entry = entry?;
+            if entry.file_name().to_string_lossy().starts_with('.') {
+                continue;
+            }
+            let path = entry.path();
+            if entry.file_type()?.is_dir() {
+                pending.push(path);
+            } else {
+                found.push(path);
+            }
+        }
+    }
+    found.sort();
+    Ok(found)
+}
@@ -0,0 +1,376 @@
+use crate::config::AppConfig;
+use crate::feed::FEED_PATH;
+use crate::formats::{self, InputFormat};
+use crate::frontmatter::Dir;
+use crate::manifest::{Enclosure, Manifest, Post, Section};
+use crate::render::{escape_html, format_date, format_date_as, plain_text, today, Page};
+use chrono::{DateTime, Datelike, Local, Utc};
+use serde::Serialize;
+use std::{collections::BTreeMap, error::Error};
+
+/// Filters the post list as the reader types, using `search.json`: every word
+/// has to appear somewhere in a post, and title matches rank first.
+const SEARCH_SCRIPT: &str = r#"<script>
+const search = document.getElementById("search");
+const posts = document.getElementById("posts");
+const results = document.getElementById("search-results");
+let searchIndex;
+search.addEventListener("input", async () => {
+    const words = search.value.toLowerCase().split(/\s+/).filter(Boolean);
+    if (!words.length) {
+        results.hidden = true;
+        posts.hidden = false;
+        return;
+    }
+    searchIndex ??= await fetch("search.json").then(response => response.json());
+    const hits = searchIndex.map(post => {
+        const title = post.title.toLowerCase();
+        const text = [post.description, ...post.labels, post.text].join(" ").toLowerCase();
+        let score = 0;
+        for (const word of words) {
+            if (title.includes(word)) score += 10;
+            else if (text.includes(word)) score += 1;
+            else return null;
+        }
+        return { post, score };
+    }).filter(Boolean).sort((a, b) => b.score - a.score);
+    results.replaceChildren(...hits.map(({ post }) => {
+        const link = document.createElement("a");
+        link.href = post.url;
+        link.textContent = post.title;
+        const item = document.createElement("li");
+        item.append(link);
+        return item;
+    }));
+    if (!hits.length) results.innerHTML = "<li>No posts found.</li>";
+    posts.hidden = true;
+    results.hidden = false;
+});
+</script>"#;
+
+/// A post as stored in `search.json`.
+#[derive(Serialize)]
+struct SearchEntry<'a> {
+    title: &'a str,
+    url: &'a str,
+    description: &'a str,
+    labels: Vec<&'a str>,
+    /// The whole post as plain text, if its source file is still around.
+    text: String,
+}
+
+/// Where the machine-readable list of posts goes.
+pub const POSTS_PATH: &str = "posts.json";
+
+/// Where the blogroll page goes, relative to the index.
+pub const BLOGROLL_DIR: &str = "blogroll/";
+
+/// Where the blogroll goes as OPML, for importing into a feed reader.
+pub const BLOGROLL_OPML_PATH: &str = "blogroll.opml";
+
+/// Version of the `posts.json` format, raised when fields change meaning or
+/// go away. New fields can appear without it changing.
+const POSTS_VERSION: u32 = 1;
+
+/// `posts.json`: the site and its posts, for other tools to read.
+#[derive(Serialize)]
+struct PostsDocument<'a> {
+    version: u32,
+    title: &'a str,
+    url: &'a str,
+    feed: String,
+    posts: Vec<PostsEntry<'a>>,
+}
+
+/// A post in `posts.json`.
+#[derive(Serialize)]
+struct PostsEntry<'a> {
+    id: &'a str,
+    title: &'a str,
+    url: &'a str,
+    published: DateTime<Utc>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    updated: Option<DateTime<Utc>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    summary: Option<&'a str>,
+    /// The front matter labels.
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    tags: &'a BTreeMap<String, String>,
+    #[serde(skip_serializing_if = "<[_]>::is_empty")]
+    authors: &'a [String],
+    #[serde(skip_serializing_if = "<[_]>::is_empty")]
+    languages: &'a [String],
+    #[serde(skip_serializing_if = "<[_]>::is_empty")]
+    sections: &'a [Section],
+    #[serde(skip_serializing_if = "Option::is_none")]
+    enclosure: Option<&'a Enclosure>,
+}
+
+/// Posts shown on the index: everything but raw uploads, newest first.
+pub fn listed(manifest: &Manifest) -> Vec<&Post> {
+    let mut posts: Vec<&Post> = manifest.posts.iter().filter(|post| !post.raw).collect();
+    posts.sort_by_key(|post| std::cmp::Reverse(post.published));
+    posts
+}
+
+/// The client-side search index: every post with its full text read from
+/// the source file. Posts whose source can't be read or converted are
+/// searchable by title, description and labels only.
+pub async fn search_index(posts: &[&Post]) -> Result<String, Box<dyn Error>> {
+    let mut entries = Vec::with_capacity(posts.len());
+    for post in posts {
+        let mut text = String::new();
+        if let Some(source) = &post.source {
+            if let Ok(content) = formats::read_source(source).await {
+                text = plain_text(&content, InputFormat::from_path(source)).unwrap_or_default();
+            }
+        }
+        entries.push(SearchEntry {
+            title: &post.title,
+            url: &post.url,
+            description: post.description.as_deref().unwrap_or_default(),
+            labels: post.labels.values().map(String::as_str).collect(),
+            text,
+        });
+    }
+    Ok(serde_json::to_string(&entries)?)
+}
+
+/// The listed posts as `posts.json`, newest first, for static frontends and
+/// other tools. `site_url` is the bucket's public URL, where the index is.
+pub fn posts_json(
+    posts: &[&Post],
+    config: &AppConfig,
+    site_url: &str,
+) -> Result<String, Box<dyn Error>> {
+    let document = PostsDocument {
+        version: POSTS_VERSION,
+        title: index_title(config),
+        url: site_url,
+        feed: format!("{}{}", site_url, FEED_PATH),
+        posts: posts
+            .iter()
+            .map(|post| PostsEntry {
+                id: &post.id,
+                title: &post.title,
+                url: &post.url,
+                published: post.published,
+                updated: post.updated,
+                summary: post.description.as_deref(),
+                tags: &post.labels,
+                authors: &post.authors,
+                languages: &post.languages,
+                sections: &post.sections,
+                enclosure: post.enclosure.as_ref(),
+            })
+            .collect(),
+    };
+    Ok(serde_json::to_string_pretty(&document)?)
+}
+
+/// A post in a list: its title, date and description.
+fn post_item(post: &Post, config: &AppConfig) -> Result<String, Box<dyn Error>> {
+    let date = format_date(post.published.with_timezone(&Local), &config.page)?;
+    let description = post
+        .description
+        .as_deref()
+        .map(|description| format!("<p>{}</p>", escape_html(description)))
+        .unwrap_or_default();
+    Ok(format!(
+        "<li><a href=\"{}\">{}</a> <time datetime=\"{}\">{}</time>{}{}</li>\n",
+        escape_html(&post.url),
+        escape_html(&post.title),
+        post.published.format("%Y-%m-%d"),
+        escape_html(&date),
+        description,
+        sections_list(&post.sections)
+    ))
+}
+
+/// Links straight to the sections of a long post, or nothing.
+pub fn sections_list(sections: &[Section]) -> String {
+    if sections.is_empty() {
+        return String::new();
+    }
+    let items: String = sections
+        .iter()
+        .map(|section| {
+            format!(
+                "<li><a href=\"{}\">{}</a></li>",
+                escape_html(&section.url),
+                escape_html(&section.title)
+            )
+        })
+        .collect();
+    format!("<ul class=\"sections\">{}</ul>", items)
+}
+
+/// Wraps a list page in the site's layout.
+fn list_page(title: &str, body: &str, config: &AppConfig) -> Result<String, Box<dyn Error>> {
+    let date = today(&config.page)?;
+    Ok(Page {
+        title,
+        heading: Some(title),
+        description: None,
+        lang: &config.page.lang,
+        dir: Dir::from_lang(&config.page.lang),
+        date: &date,
+        body,
+        alternates: &[],
+        image_url: None,
+        site: &config.site,
+        authors: &[],
+        source_url: None,
+    }
+    .render())
+}
+
+/// Heading of the index page.
+pub fn index_title(config: &AppConfig) -> &str {
+    config
+        .index
+        .title
+        .as_deref()
+        .or(config.site.title.as_deref())
+        .unwrap_or("Posts")
+}
+
+/// The site's front page: a search box and every post with its date and
+/// description, plus links to the archive and blogroll if there are any.
+pub fn index_page(
+    posts: &[&Post],
+    config: &AppConfig,
+    archive: bool,
+) -> Result<String, Box<dyn Error>> {
+    let mut items = String::new();
+    for post in posts {
+        items.push_str(&post_item(post, config)?);
+    }
+    let mut archive_link = if archive {
+        format!(
+            "<p class=\"archive-link\"><a href=\"archive/\">{}</a></p>\n",
+            escape_html(&config.index.archive_title)
+        )
+    } else {
+        String::new()
+    };
+    if !config.blogroll.sites.is_empty() {
+        archive_link.push_str(&format!(
+            "<p class=\"blogroll-link\"><a href=\"{}\">{}</a></p>\n",
+            BLOGROLL_DIR,
+            escape_html(&config.blogroll.title)
+        ));
+    }
+    let body = format!(
+        "<input type=\"search\" id=\"search\" placeholder=\"Search\" aria-label=\"Search posts\">\n<ul id=\"search-results\" class=\"posts\" hidden></ul>\n<ul id=\"posts\" class=\"posts\">\n{}</ul>\n{}<p class=\"feed-link\"><a href=\"{}\">RSS feed</a></p>\n{}",
+        items, archive_link, FEED_PATH, SEARCH_SCRIPT
+    );
+    list_page(index_title(config), &body, config)
+}
+
+/// Every post grouped by year and month, newest first, with the number of
+/// posts in each. `posts` must already be sorted newest first.
+pub fn archive_page(posts: &[&Post], config: &AppConfig) -> Result<String, Box<dyn Error>> {
+    let mut body = format!(
+        "<p><a href=\"../\">{}</a></p>\n",
+        escape_html(index_title(config))
+    );
+    let mut rest = posts;
+    while let Some(first) = rest.first() {
+        let year = first.published.with_timezone(&Local).year();
+        let in_year = rest
+            .iter()
+            .take_while(|post| post.published.with_timezone(&Local).year() == year)
+            .count();
+        let (year_posts, after) = rest.split_at(in_year);
+        body.push_str(&format!(
+            "<section class=\"archive-year\">\n<h2>{} <span class=\"count\">({})</span></h2>\n",
+            year, in_year
+        ));
+
+        let mut months = year_posts;
+        while let Some(first) = months.first() {
+            let published = first.published.with_timezone(&Local);
+            let in_month = months
+                .iter()
+                .take_while(|post| {
+                    post.published.with_timezone(&Local).month() == published.month()
+                })
+                .count();
+            let (month_posts, after) = months.split_at(in_month);
+            let mut items = String::new();
+            for post in month_posts {
+                items.push_str(&post_item(post, config)?);
+            }
+            body.push_str(&format!(
+                "<h3>{} <span class=\"count\">({})</span></h3>\n<ul class=\"posts\">\n{}</ul>\n",
+                escape_html(&format_date_as(published, "%B", &config.page)?),
+                in_month,
+                items
+            ));
+            months = after;
+        }
+
+        body.push_str("</section>\n");
+        rest = after;
+    }
+    list_page(&config.index.archive_title, &body, config)
+}
+
+/// The blogroll: every configured site with its description and a link to
+/// its feed.
+pub fn blogroll_page(config: &AppConfig) -> Result<String, Box<dyn Error>> {
+    let mut items = String::new();
+    for site in &config.blogroll.sites {
+        let feed = site
+            .feed
+            .as_deref()
+            .map(|feed| format!(" <a class=\"feed\" href=\"{}\">feed</a>", escape_html(feed)))
+            .unwrap_or_default();
+        let description = site
+            .description
+            .as_deref()
+            .map(|description| format!("<p>{}</p>", escape_html(description)))
+            .unwrap_or_default();
+        items.push_str(&format!(
+            "<li><a href=\"{}\">{}</a>{}{}</li>\n",
+            escape_html(&site.url),
+            escape_html(&site.name),
+            feed,
+            description
+        ));
+    }
+    let body = format!(
+        "<p><a href=\"../\">{}</a></p>\n<ul class=\"posts blogroll\">\n{}</ul>\n<p class=\"feed-link\"><a href=\"../{}\">OPML</a></p>\n",
+        escape_html(index_title(config)),
+        items,
+        BLOGROLL_OPML_PATH
+    );
+    list_page(&config.blogroll.title, &body, config)
+}
+
+/// The blogroll as OPML 2.0. Sites without a feed are listed as links.
+pub fn blogroll_opml(config: &AppConfig) -> String {
+    let mut outlines = String::new();
+    for site in &config.blogroll.sites {
+        let feed = match &site.feed {
+            Some(feed) => format!(" type=\"rss\" xmlUrl=\"{}\"", escape_html(feed)),
+            None => " type=\"link\"".to_string(),
+        };
+        let description = site
+            .description
+            .as_deref()
+            .map(|description| format!(" description=\"{}\"", escape_html(description)))
+            .unwrap_or_default();
+        outlines.push_str(&format!(
+            "<outline text=\"{name}\" title=\"{name}\"{feed} htmlUrl=\"{url}\"{description}/>\n",
+            name = escape_html(&site.name),
+            url = escape_html(&site.url),
+        ));
+    }
+    format!(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<opml version=\"2.0\">\n<head>\n<title>{}</title>\n</head>\n<body>\n{}</body>\n</opml>\n",
+        escape_html(&config.blogroll.title),
+        outlines
+    )
+}
@@ -0,0 +1,510 @@
+use crate::frontmatter::Labels;
+use crate::publisher::{Precondition, Publisher};
+use async_trait::async_trait;
+use base64::{engine::general_purpose::STANDARD, Engine};
+use chrono::{DateTime, Utc};
+use ring::signature::{self, Ed25519KeyPair, KeyPair};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::{
+    collections::{BTreeMap, HashMap},
+    error::Error,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex, MutexGuard},
+    time::Duration,
+};
+use tokio::fs;
+
+/// Where the signed list of objects is published.
+pub const REMOTE_PATH: &str = "integrity.json";
+
+/// What an object should contain.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FileDigest {
+    /// Hex SHA-256 of the body.
+    pub sha256: String,
+    pub size: u64,
+}
+
+impl FileDigest {
+    pub fn of(body: &[u8]) -> Self {
+        Self {
+            sha256: hex::encode(Sha256::digest(body)),
+            size: body.len() as u64,
+        }
+    }
+}
+
+/// The local record of every object uploaded, by path. It's the reference
+/// `klistra verify` checks the bucket against, so it never comes from there.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Integrity {
+    pub files: BTreeMap<String, FileDigest>,
+}
+
+impl Integrity {
+    pub async fn load(path: &Path) -> Result<Self, Box<dyn Error>> {
+        match fs::read_to_string(path).await {
+            Ok(content) => Ok(serde_json::from_str(&content)?),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    pub async fn save(&self, path: &Path) -> Result<(), Box<dyn Error>> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).await?;
+        }
+        fs::write(path, serde_json::to_string_pretty(self)?).await?;
+        Ok(())
+    }
+
+    fn apply(&mut self, change: Change) {
+        match change {
+            Change::Put(path, digest) => {
+                self.files.insert(path, digest);
+            }
+            Change::Delete(path) => {
+                self.files.remove(&path);
+            }
+            Change::DeletePrefix(prefix) => self.files.retain(|path, _| !path.starts_with(&prefix)),
+            Change::Move { from, to } => {
+                let moved: Vec<String> = self
+                    .files
+                    .keys()
+                    .filter(|path| path.starts_with(&from))
+                    .cloned()
+                    .collect();
+                for path in moved {
+                    if let Some(digest) = self.files.remove(&path) {
+                        self.files
+                            .insert(format!("{}{}", to, &path[from.len()..]), digest);
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// The local integrity record next to the manifest at `manifest_path`:
+/// `integrity.json` for `manifest.json`, `integrity-memory.json` for the
+/// memory backend's scratch manifest.
+pub fn path_for(manifest_path: &Path) -> PathBuf {
+    let stem = manifest_path
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().replacen("manifest", "integrity", 1))
+        .unwrap_or_else(|| "integrity".to_string());
+    manifest_path.with_file_name(format!("{}.json", stem))
+}
+
+/// The Ed25519 key `integrity.json` is signed with, kept next to the manifest.
+fn key_path(manifest_path: &Path) -> PathBuf {
+    manifest_path.with_file_name("integrity.key")
+}
+
+/// Loads the signing key, creating one the first time.
+async fn signing_key(manifest_path: &Path) -> Result<Ed25519KeyPair, Box<dyn Error>> {
+    let path = key_path(manifest_path);
+    let pkcs8 = match fs::read(&path).await {
+        Ok(pkcs8) => pkcs8,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+            let pkcs8 = Ed25519KeyPair::generate_pkcs8(&ring::rand::SystemRandom::new())
+                .map_err(|_| "Could not generate a signing key")?;
+            if let Some(parent) = path.parent() {
+                fs::create_dir_all(parent).await?;
+            }
+            fs::write(&path, pkcs8.as_ref()).await?;
+            #[cfg(unix)]
+            {
+                use std::os::unix::fs::PermissionsExt;
+                fs::set_permissions(&path, std::fs::Permissions::from_mode(0o600)).await?;
+            }
+            eprintln!("Created an integrity signing key at {}", path.display());
+            pkcs8.as_ref().to_vec()
+        }
+        Err(e) => return Err(e.into()),
+    };
+    Ok(Ed25519KeyPair::from_pkcs8(&pkcs8)
+        .map_err(|_| format!("{} isn't an Ed25519 key", path.display()))?)
+}
+
+/// `integrity.json` as published: the files, signed.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Signed {
+    pub generated: DateTime<Utc>,
+    /// Base64 Ed25519 public key the signature can be checked with.
+    pub public_key: String,
+    /// Base64 Ed25519 signature over `files` serialized as compact JSON.
+    pub signature: String,
+    pub files: BTreeMap<String, FileDigest>,
+}
+
+impl Signed {
+    fn new(
+        files: &BTreeMap<String, FileDigest>,
+        key: &Ed25519KeyPair,
+    ) -> Result<Self, Box<dyn Error>> {
+        let message = serde_json::to_vec(files)?;
+        Ok(Self {
+            generated: Utc::now(),
+            public_key: STANDARD.encode(key.public_key().as_ref()),
+            signature: STANDARD.encode(key.sign(&message).as_ref()),
+            files: files.clone(),
+        })
+    }
+
+    /// Whether the signature over `files` is valid for `public_key`.
+    fn is_signed_by(&self, public_key: &[u8]) -> bool {
+        let (Ok(message), Ok(signature)) = (
+            serde_json::to_vec(&self.files),
+            STANDARD.decode(&self.signature),
+        ) else {
+            return false;
+        };
+        signature::UnparsedPublicKey::new(&signature::ED25519, public_key)
+            .verify(&message, &signature)
+            .is_ok()
+    }
+}
+
+/// A change to what's in the bucket.
+#[derive(Debug)]
+pub enum Change {
+    Put(String, FileDigest),
+    Delete(String),
+    DeletePrefix(String),
+    Move { from: String, to: String },
+}
+
+/// Changes a [`Recorder`] saw that haven't been written to the integrity
+/// record yet. Clones share the same list.
+#[derive(Clone, Default)]
+pub struct Changes(Arc<Mutex<Vec<Change>>>);
+
+impl Changes {
+    fn lock(&self) -> MutexGuard<'_, Vec<Change>> {
+        self.0.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn push(&self, change: Change) {
+        self.lock().push(change);
+    }
+
+    /// Everything since the last call, oldest first.
+    pub fn take(&self) -> Vec<Change> {
+        std::mem::take(&mut *self.lock())
+    }
+}
+
+/// A multipart upload being hashed as its parts go by. `None` once a part
+/// arrives out of order, e.g. when resuming an earlier upload.
+type PartialHash = Option<(Sha256, i32, u64)>;
+
+/// A [`Publisher`] that notes the digest of everything uploaded through it,
+/// and what's removed or moved, in [`Changes`].
+pub struct Recorder {
+    inner: Box<dyn Publisher>,
+    changes: Changes,
+    uploads: Mutex<HashMap<String, PartialHash>>,
+}
+
+impl Recorder {
+    pub fn new(inner: Box<dyn Publisher>, changes: Changes) -> Self {
+        Self {
+            inner,
+            changes,
+            uploads: Mutex::new(HashMap::new()),
+        }
+    }
+
+    fn uploads(&self) -> MutexGuard<'_, HashMap<String, PartialHash>> {
+        self.uploads.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn put_file(&self, path: &str, digest: FileDigest) {
+        // The integrity file can't list itself.
+        if path != REMOTE_PATH {
+            self.changes.push(Change::Put(path.to_string(), digest));
+        }
+    }
+}
+
+#[async_trait]
+impl Publisher for Recorder {
+    fn host(&self) -> &str {
+        self.inner.host()
+    }
+
+    fn public_url(&self, path: &str) -> String {
+        self.inner.public_url(path)
+    }
+
+    async fn put(
+        &self,
+        path: &str,
+        body: Vec<u8>,
+        content_type: &str,
+        labels: &Labels,
+    ) -> Result<(), Box<dyn Error>> {
+        let digest = FileDigest::of(&body);
+        self.inner.put(path, body, content_type, labels).await?;
+        self.put_file(path, digest);
+        Ok(())
+    }
+
+    async fn put_download(
+        &self,
+        path: &str,
+        body: Vec<u8>,
+        content_type: &str,
+        file_name: &str,
+        labels: &Labels,
+    ) -> Result<(), Box<dyn Error>> {
+        let digest = FileDigest::of(&body);
+        self.inner
+            .put_download(path, body, content_type, file_name, labels)
+            .await?;
+        self.put_file(path, digest);
+        Ok(())
+    }
+
+    async fn put_redirect(&self, path: &str, location: &str) -> Result<(), Box<dyn Error>> {
+        self.inner.put_redirect(path, location).await?;
+        self.put_file(path, FileDigest::of(b""));
+        Ok(())
+    }
+
+    async fn delete(&self, path: &str) -> Result<(), Box<dyn Error>> {
+        self.inner.delete(path).await?;
+        self.changes.push(Change::Delete(path.to_string()));
+        Ok(())
+    }
+
+    async fn list(&self, path: &str) -> Result<Vec<String>, Box<dyn Error>> {
+        self.inner.list(path).await
+    }
+
+    async fn fetch(&self, path: &str) -> Result<Option<Vec<u8>>, Box<dyn Error>> {
+        self.inner.fetch(path).await
+    }
+
+    async fn fetch_tagged(&self, path: &str) -> Result<Option<(Vec<u8>, String)>, Box<dyn Error>> {
+        self.inner.fetch_tagged(path).await
+    }
+
+    async fn put_if(
+        &self,
+        path: &str,
+        body: Vec<u8>,
+        content_type: &str,
+        precondition: &Precondition,
+    ) -> Result<bool, Box<dyn Error>> {
+        let digest = FileDigest::of(&body);
+        let stored = self
+            .inner
+            .put_if(path, body, content_type, precondition)
+            .await?;
+        if stored {
+            self.put_file(path, digest);
+        }
+        Ok(stored)
+    }
+
+    async fn presign(&self, path: &str, expires: Duration) -> Result<String, Box<dyn Error>> {
+        self.inner.presign(path, expires).await
+    }
+
+    async fn delete_prefix(&self, path: &str) -> Result<usize, Box<dyn Error>> {
+        let removed = self.inner.delete_prefix(path).await?;
+        self.changes.push(Change::DeletePrefix(path.to_string()));
+        Ok(removed)
+    }
+
+    async fn move_prefix(&self, from: &str, to: &str) -> Result<usize, Box<dyn Error>> {
+        let moved = self.inner.move_prefix(from, to).await?;
+        self.changes.push(Change::Move {
+            from: from.to_string(),
+            to: to.to_string(),
+        });
+        Ok(moved)
+    }
+
+    async fn create_multipart(
+        &self,
+        path: &str,
+        content_type: &str,
+        labels: &Labels,
+    ) -> Result<String, Box<dyn Error>> {
+        let upload_id = self
+            .inner
+            .create_multipart(path, content_type, labels)
+            .await?;
+        self.uploads()
+            .insert(upload_id.clone(), Some((Sha256::new(), 1, 0)));
+        Ok(upload_id)
+    }
+
+    async fn upload_part(
+        &self,
+        path: &str,
+        upload_id: &str,
+        number: i32,
+        body: Vec<u8>,
+    ) -> Result<String, Box<dyn Error>> {
+        {
+            let mut uploads = self.uploads();
+            let hash = uploads.entry(upload_id.to_string()).or_insert(None);
+            match hash {
+                Some((hasher, next, size)) if *next == number => {
+                    hasher.update(&body);
+                    *next += 1;
+                    *size += body.len() as u64;
+                }
+                _ => *hash = None,
+            }
+        }
+        let etag = self.inner.upload_part(path, upload_id, number, body).await;
+        if etag.is_err() {
+            // The part was hashed already, so a retry would count it twice.
+            self.uploads().insert(upload_id.to_string(), None);
+        }
+        etag
+    }
+
+    async fn abort_multipart(&self, path: &str, upload_id: &str) -> Result<(), Box<dyn Error>> {
+        self.uploads().remove(upload_id);
+        self.inner.abort_multipart(path, upload_id).await
+    }
+
+    async fn complete_multipart(
+        &self,
+        path: &str,
+        upload_id: &str,
+        parts: &[(i32, String)],
+    ) -> Result<(), Box<dyn Error>> {
+        self.inner
+            .complete_multipart(path, upload_id, parts)
+            .await?;
+        let hash = self.uploads().remove(upload_id).flatten();
+        let digest = match hash {
+            Some((hasher, next, size)) if next as usize == parts.len() + 1 => FileDigest {
+                sha256: hex::encode(hasher.finalize()),
+                size,
+            },
+            // Some parts went up in an earlier run, so read back the result.
+            _ => {
+                let body = self
+                    .inner
+                    .fetch(path)
+                    .await?
+                    .ok_or_else(|| format!("{} is missing right after uploading it", path))?;
+                FileDigest::of(&body)
+            }
+        };
+        self.put_file(path, digest);
+        Ok(())
+    }
+}
+
+/// Writes `changes` into the local integrity record and publishes the signed
+/// `integrity.json`.
+pub async fn publish(
+    bucket: &dyn Publisher,
+    manifest_path: &Path,
+    changes: Vec<Change>,
+) -> Result<(), Box<dyn Error>> {
+    if changes.is_empty() {
+        return Ok(());
+    }
+    let path = path_for(manifest_path);
+    let mut integrity = Integrity::load(&path).await?;
+    for change in changes {
+        integrity.apply(change);
+    }
+    integrity.save(&path).await?;
+    let signed = Signed::new(&integrity.files, &signing_key(manifest_path).await?)?;
+    bucket
+        .put(
+            REMOTE_PATH,
+            serde_json::to_vec_pretty(&signed)?,
+            "application/json",
+            &Labels::new(),
+        )
+        .await
+}
+
+/// What `klistra verify` found.
+#[derive(Debug, Default)]
+pub struct Report {
+    /// How many objects were checked.
+    pub checked: usize,
+    /// Objects that are missing or differ from what was uploaded, and
+    /// problems with the published `integrity.json`.
+    pub problems: Vec<String>,
+    /// Objects in the bucket klistra has no record of, e.g. ones uploaded
+    /// before integrity records were kept.
+    pub untracked: Vec<String>,
+}
+
+/// Downloads every object in the local integrity record and compares it
+/// with what was uploaded, and checks that the published `integrity.json`
+/// is signed with the local key and lists the same files.
+pub async fn verify(
+    bucket: &dyn Publisher,
+    manifest_path: &Path,
+) -> Result<Report, Box<dyn Error>> {
+    let path = path_for(manifest_path);
+    if !path.exists() {
+        return Err(format!(
+            "No integrity record at {}. Set [integrity] enabled = true and publish first.",
+            path.display()
+        )
+        .into());
+    }
+    let integrity = Integrity::load(&path).await?;
+    let key = signing_key(manifest_path).await?;
+    let mut report = Report::default();
+
+    match bucket.fetch(REMOTE_PATH).await? {
+        None => report
+            .problems
+            .push(format!("{} is missing from the bucket", REMOTE_PATH)),
+        Some(body) => match serde_json::from_slice::<Signed>(&body) {
+            Err(e) => report
+                .problems
+                .push(format!("{} can't be read: {}", REMOTE_PATH, e)),
+            Ok(signed) if !signed.is_signed_by(key.public_key().as_ref()) => report
+                .problems
+                .push(format!("{} isn't signed with the local key", REMOTE_PATH)),
+            Ok(signed) if signed.files != integrity.files => report.problems.push(format!(
+                "{} doesn't list the same files as the local record",
+                REMOTE_PATH
+            )),
+            Ok(_) => {}
+        },
+    }
+
+    for (path, expected) in &integrity.files {
+        report.checked += 1;
+        match bucket.fetch(path).await? {
+            None => report.problems.push(format!("{} is missing", path)),
+            Some(body) => {
+                let actual = FileDigest::of(&body);
+                if actual != *expected {
+                    report.problems.push(format!(
+                        "{} differs: expected SHA-256 {} ({} bytes), found {} ({} bytes)",
+                        path, expected.sha256, expected.size, actual.sha256, actual.size
+                    ));
+                }
+            }
+        }
+    }
+
+    report.untracked = bucket
+        .list("")
+        .await?
+        .into_iter()
+        .filter(|path| path != REMOTE_PATH && !integrity.files.contains_key(path))
+        .collect();
+    Ok(report)
+}
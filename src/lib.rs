@@ -0,0 +1,77 @@
+//! Rendering and publishing behind the `klistra` command. Everything the
+//! binary does lives here; [`render_to_string`] renders a post without a
+//! config file, manifest or bucket, e.g. for snapshot tests.
+
+pub mod activitypub;
+pub mod announce;
+pub mod audit;
+pub mod b2;
+pub mod bench;
+pub mod bot;
+pub mod budget;
+pub mod codeblock;
+pub mod config;
+pub mod daemon;
+pub mod delegate;
+pub mod details;
+pub mod docx;
+pub mod email;
+pub mod exif;
+pub mod expiry;
+pub mod export;
+pub mod feed;
+pub mod formats;
+pub mod frontmatter;
+pub mod gallery;
+pub mod highlight;
+pub mod history;
+pub mod http;
+pub mod imap;
+pub mod import;
+pub mod index;
+pub mod integrity;
+pub mod lock;
+pub mod logging;
+pub mod logs;
+pub mod mail;
+pub mod manifest;
+pub mod media;
+pub mod metrics;
+pub mod micropub;
+pub mod mime;
+pub mod notion;
+pub mod ogimage;
+pub mod publisher;
+pub mod queue;
+pub mod quick;
+pub mod raw;
+pub mod redirects;
+pub mod related;
+pub mod render;
+pub mod resume;
+pub mod schema;
+pub mod secrets;
+pub mod selfupdate;
+pub mod server;
+pub mod service;
+pub mod setup;
+pub mod shutdown;
+pub mod sidenote;
+pub mod site;
+pub mod sources;
+pub mod spellcheck;
+pub mod stdio;
+pub mod stream;
+pub mod svg;
+pub mod table;
+pub mod template;
+pub mod throttle;
+pub mod traffic;
+pub mod tui;
+pub mod upload;
+pub mod variants;
+pub mod views;
+pub mod webhook;
+pub mod webmention;
+
+pub use render::render_to_string;
@@ -0,0 +1,89 @@
+use crate::publisher::{Precondition, Publisher};
+use chrono::{DateTime, TimeDelta, Utc};
+use serde::{Deserialize, Serialize};
+use std::{error::Error, time::Duration};
+
+/// Where the lock object lives, relative to the prefix.
+pub const LOCK_PATH: &str = "klistra.lock";
+
+/// How long a lock is good for. Rebuilding the index takes seconds, so a
+/// lock this old was left behind by a publish that died, and is taken over.
+const LOCK_TTL: TimeDelta = TimeDelta::minutes(10);
+
+/// How often to look again while someone else holds the lock.
+const RETRY: Duration = Duration::from_secs(2);
+
+/// What the lock object says.
+#[derive(Serialize, Deserialize)]
+struct Holder {
+    /// Tells this publish's lock from a later one that took it over.
+    token: String,
+    since: DateTime<Utc>,
+    expires: DateTime<Utc>,
+}
+
+/// The bucket's lock, held until [`Lock::release`].
+pub struct Lock<'a> {
+    bucket: &'a dyn Publisher,
+    token: String,
+}
+
+/// Takes the lock object in `bucket`, waiting up to `wait` for whoever holds
+/// it. Taking it is a conditional write, so of two publishes racing for a
+/// free (or expired) lock exactly one gets it.
+pub async fn acquire(bucket: &dyn Publisher, wait: Duration) -> Result<Lock<'_>, Box<dyn Error>> {
+    let token = uuid::Uuid::new_v4().to_string();
+    let deadline = tokio::time::Instant::now() + wait;
+    loop {
+        let current = bucket.fetch_tagged(LOCK_PATH).await?;
+        let precondition = match current {
+            None => Precondition::Absent,
+            Some((body, etag)) => match serde_json::from_slice::<Holder>(&body) {
+                Ok(holder) if holder.expires > Utc::now() => {
+                    if tokio::time::Instant::now() >= deadline {
+                        return Err(format!(
+                            "Another publish has held {} since {} (it expires at {}); try again later, or delete it if nothing is publishing",
+                            bucket.public_url(LOCK_PATH),
+                            holder.since.to_rfc3339(),
+                            holder.expires.to_rfc3339()
+                        )
+                        .into());
+                    }
+                    tokio::time::sleep(RETRY).await;
+                    continue;
+                }
+                // Expired or unreadable: replace exactly the version seen.
+                _ => Precondition::Matches(etag),
+            },
+        };
+        let now = Utc::now();
+        let holder = Holder {
+            token: token.clone(),
+            since: now,
+            expires: now + LOCK_TTL,
+        };
+        let body = serde_json::to_vec(&holder)?;
+        if bucket
+            .put_if(LOCK_PATH, body, "application/json", &precondition)
+            .await?
+        {
+            return Ok(Lock { bucket, token });
+        }
+    }
+}
+
+impl Lock<'_> {
+    /// Deletes the lock object, unless it expired and someone else has
+    /// taken it since.
+    pub async fn release(self) -> Result<(), Box<dyn Error>> {
+        let Some((body, _)) = self.bucket.fetch_tagged(LOCK_PATH).await? else {
+            return Ok(());
+        };
+        let ours =
+            serde_json::from_slice::<Holder>(&body).is_ok_and(|holder| holder.token == self.token);
+        if ours {
+            self.bucket.delete(LOCK_PATH).await?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,115 @@
+use crate::config::{LogFormat, LoggingConfig};
+use std::{
+    error::Error,
+    fs::{self, File, OpenOptions},
+    io::{self, Write},
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+use tracing_subscriber::EnvFilter;
+
+/// Sets up logging for the long-running `mode` (`daemon`, `telegram`, ...)
+/// as configured in [logging]: to `klistra-<mode>.log` in its directory,
+/// rotated by size, or to stderr.
+pub fn init(config: &LoggingConfig, mode: &str) -> Result<(), Box<dyn Error>> {
+    let filter = match std::env::var("RUST_LOG") {
+        Ok(filter) if !filter.is_empty() => EnvFilter::try_new(&filter)
+            .map_err(|e| format!("Invalid RUST_LOG '{}': {}", filter, e))?,
+        _ => EnvFilter::try_new(&config.level)
+            .map_err(|e| format!("Invalid [logging] level '{}': {}", config.level, e))?,
+    };
+    let builder = tracing_subscriber::fmt()
+        .with_env_filter(filter)
+        .with_target(true);
+
+    let initialized = match &config.dir {
+        Some(dir) => {
+            let path = dir.join(format!("klistra-{}.log", mode));
+            let file = RotatingFile::open(&path, config.max_size_mb * 1024 * 1024, config.keep)
+                .map_err(|e| format!("Could not open {}: {}", path.display(), e))?;
+            let builder = builder.with_ansi(false).with_writer(Mutex::new(file));
+            match config.format.unwrap_or(LogFormat::Json) {
+                LogFormat::Json => builder.json().try_init(),
+                LogFormat::Text => builder.try_init(),
+            }
+        }
+        None => {
+            let builder = builder.with_writer(io::stderr);
+            match config.format.unwrap_or(LogFormat::Text) {
+                LogFormat::Json => builder.json().try_init(),
+                LogFormat::Text => builder.try_init(),
+            }
+        }
+    };
+    initialized.map_err(|e| format!("Could not set up logging: {}", e).into())
+}
+
+/// A log file that's moved aside to `<name>.1` once it reaches `max_size`,
+/// shifting older ones up and dropping those past `keep`.
+pub struct RotatingFile {
+    path: PathBuf,
+    file: File,
+    size: u64,
+    max_size: u64,
+    keep: usize,
+}
+
+impl RotatingFile {
+    /// Opens `path` for appending, creating it and its directory if needed.
+    pub fn open(path: &Path, max_size: u64, keep: usize) -> io::Result<Self> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        let size = file.metadata()?.len();
+        Ok(RotatingFile {
+            path: path.to_path_buf(),
+            file,
+            size,
+            max_size,
+            keep,
+        })
+    }
+
+    fn rotated(&self, n: usize) -> PathBuf {
+        let mut name = self.path.clone().into_os_string();
+        name.push(format!(".{}", n));
+        PathBuf::from(name)
+    }
+
+    fn rotate(&mut self) -> io::Result<()> {
+        self.file.flush()?;
+        if self.keep == 0 {
+            fs::remove_file(&self.path)?;
+        } else {
+            for n in (1..self.keep).rev() {
+                let from = self.rotated(n);
+                if from.exists() {
+                    fs::rename(&from, self.rotated(n + 1))?;
+                }
+            }
+            fs::rename(&self.path, self.rotated(1))?;
+        }
+        self.file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?;
+        self.size = 0;
+        Ok(())
+    }
+}
+
+impl Write for RotatingFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.size > 0 && self.size + buf.len() as u64 > self.max_size {
+            self.rotate()?;
+        }
+        let written = self.file.write(buf)?;
+        self.size += written as u64;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()
+    }
+}
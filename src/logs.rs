@@ -0,0 +1,242 @@
+use crate::config::{AppConfig, Backend};
+use crate::publisher::Publisher;
+use crate::upload::Bucket;
+use chrono::{DateTime, NaiveDate, Utc};
+use flate2::read::MultiGzDecoder;
+use serde_json::{json, Value};
+use std::{error::Error, io::Read};
+
+const CLOUDFLARE_GRAPHQL: &str = "https://api.cloudflare.com/client/v4/graphql";
+
+/// Requests for one path, from an access log line or an analytics group.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Hit {
+    /// The requested path without its query, e.g. `/p/<id>/pixel.gif`. Logs
+    /// of path-style S3 requests have the bucket and prefix in front.
+    pub path: String,
+    pub time: DateTime<Utc>,
+    pub status: u16,
+    pub requests: u64,
+    /// Bytes sent in response.
+    pub bytes: u64,
+}
+
+impl Hit {
+    /// Whether the request got the object (or a cached copy of it).
+    pub fn served(&self) -> bool {
+        (200..300).contains(&self.status) || self.status == 304
+    }
+
+    /// Whether the request was for `dir` (e.g. `p/<id>`) or something in it.
+    pub fn is_in(&self, dir: &str) -> bool {
+        let dir = format!("/{}", dir.trim_matches('/'));
+        self.path.ends_with(&dir) || self.path.contains(&format!("{}/", dir))
+    }
+}
+
+/// Everything requested since `since`, from wherever `[logs]` points.
+pub async fn fetch(
+    config: &AppConfig,
+    http: &reqwest::Client,
+    since: DateTime<Utc>,
+) -> Result<Vec<Hit>, Box<dyn Error>> {
+    let logs = &config.logs;
+    if let Some(zone) = &logs.cloudflare_zone {
+        let token = logs
+            .cloudflare_token
+            .as_deref()
+            .ok_or("[logs] cloudflare_zone needs a cloudflare_token")?;
+        return cloudflare(http, zone, token, since).await;
+    }
+    let prefix = logs
+        .prefix
+        .as_deref()
+        .ok_or("Set [logs] prefix to where access logs are delivered, or cloudflare_zone")?;
+    let source: Box<dyn Publisher> = match config.upload.backend {
+        Backend::S3 => Box::new(
+            Bucket::new(&config.s3, &config.tls)?
+                .with_location(logs.bucket.as_deref().unwrap_or(&config.s3.bucket), prefix),
+        ),
+        // Nothing is ever served from memory, so there are no logs.
+        Backend::Memory => return Ok(Vec::new()),
+        Backend::Delegated => {
+            return Err("Reading access logs needs the bucket's keys, not a signer".into())
+        }
+    };
+    read_logs(source.as_ref(), since).await
+}
+
+/// Reads every log file in `source`, gzipped or not: S3 server access
+/// logs, common or combined logs from a web server or CDN, or Cloudflare
+/// Logpush's JSON lines. Lines that are none of these are skipped.
+pub async fn read_logs(
+    source: &dyn Publisher,
+    since: DateTime<Utc>,
+) -> Result<Vec<Hit>, Box<dyn Error>> {
+    let mut hits = Vec::new();
+    for path in source.list("").await? {
+        let Some(body) = source.fetch(&path).await? else {
+            continue;
+        };
+        let text = if path.ends_with(".gz") {
+            let mut text = String::new();
+            MultiGzDecoder::new(body.as_slice())
+                .read_to_string(&mut text)
+                .map_err(|e| format!("Could not unpack log {}: {}", path, e))?;
+            text
+        } else {
+            String::from_utf8_lossy(&body).into_owned()
+        };
+        hits.extend(
+            text.lines()
+                .filter_map(parse_line)
+                .filter(|hit| hit.time >= since),
+        );
+    }
+    Ok(hits)
+}
+
+/// One request from an access log line in any of the formats
+/// [`read_logs`] reads.
+pub fn parse_line(line: &str) -> Option<Hit> {
+    let line = line.trim();
+    if line.starts_with('{') {
+        return parse_logpush(line);
+    }
+
+    // `... [10/Oct/2025:13:55:36 +0000] ... "GET /path HTTP/1.1" 200 ...`
+    let (_, rest) = line.split_once('[')?;
+    let (time, rest) = rest.split_once(']')?;
+    let time = DateTime::parse_from_str(time, "%d/%b/%Y:%H:%M:%S %z").ok()?;
+    let (_, rest) = rest.split_once('"')?;
+    let (request, rest) = rest.split_once('"')?;
+    let mut request = request.split_whitespace();
+    let _method = request.next()?;
+    let target = request.next()?;
+    let mut fields = rest.split_whitespace();
+    let status = fields.next()?.parse().ok()?;
+    // Common log format has the bytes next; S3 logs have an error code
+    // (or `-`) in between.
+    let bytes = match fields.next()? {
+        "-" => fields.next().and_then(|bytes| bytes.parse().ok()),
+        field => field
+            .parse()
+            .ok()
+            .or_else(|| fields.next().and_then(|bytes| bytes.parse().ok())),
+    };
+    Some(Hit {
+        path: request_path(target),
+        time: time.with_timezone(&Utc),
+        status,
+        requests: 1,
+        bytes: bytes.unwrap_or(0),
+    })
+}
+
+/// A Cloudflare Logpush HTTP request record.
+fn parse_logpush(line: &str) -> Option<Hit> {
+    let record: Value = serde_json::from_str(line).ok()?;
+    let target = record
+        .get("ClientRequestPath")
+        .or_else(|| record.get("ClientRequestURI"))?
+        .as_str()?;
+    // RFC 3339, or Unix time in seconds or nanoseconds, depending on the
+    // job's timestamp format.
+    let time = match record.get("EdgeStartTimestamp")? {
+        Value::String(time) => DateTime::parse_from_rfc3339(time).ok()?.with_timezone(&Utc),
+        Value::Number(number) => {
+            let number = number.as_i64()?;
+            if number > 1_000_000_000_000 {
+                DateTime::from_timestamp_nanos(number)
+            } else {
+                DateTime::from_timestamp(number, 0)?
+            }
+        }
+        _ => return None,
+    };
+    Some(Hit {
+        path: request_path(target),
+        time,
+        status: record.get("EdgeResponseStatus")?.as_u64()? as u16,
+        requests: 1,
+        bytes: record
+            .get("EdgeResponseBytes")
+            .and_then(Value::as_u64)
+            .unwrap_or(0),
+    })
+}
+
+/// The decoded path of a request target, without the query.
+fn request_path(target: &str) -> String {
+    let path = target.split(['?', '#']).next().unwrap_or(target);
+    // Absolute-form targets, as proxies see them.
+    let path = match path.split_once("://") {
+        Some((_, rest)) => rest.find('/').map_or("/", |start| &rest[start..]),
+        None => path,
+    };
+    percent_encoding::percent_decode_str(path)
+        .decode_utf8_lossy()
+        .into_owned()
+}
+
+/// Requests per path and day since `since` from Cloudflare's GraphQL
+/// analytics API. How far back it goes depends on the plan.
+async fn cloudflare(
+    http: &reqwest::Client,
+    zone: &str,
+    token: &str,
+    since: DateTime<Utc>,
+) -> Result<Vec<Hit>, Box<dyn Error>> {
+    let query = "query ($zone: String!, $since: Time!, $until: Time!) {
+  viewer {
+    zones(filter: {zoneTag: $zone}) {
+      httpRequestsAdaptiveGroups(limit: 10000, filter: {datetime_geq: $since, datetime_lt: $until}) {
+        count
+        sum { edgeResponseBytes }
+        dimensions { clientRequestPath date edgeResponseStatus }
+      }
+    }
+  }
+}";
+    let body = json!({
+        "query": query,
+        "variables": {
+            "zone": zone,
+            "since": since.to_rfc3339(),
+            "until": Utc::now().to_rfc3339(),
+        },
+    });
+    let response: Value = http
+        .post(CLOUDFLARE_GRAPHQL)
+        .bearer_auth(token)
+        .json(&body)
+        .send()
+        .await?
+        .error_for_status()?
+        .json()
+        .await?;
+    if let Some(error) = response["errors"].get(0) {
+        return Err(format!(
+            "Cloudflare analytics: {}",
+            error["message"].as_str().unwrap_or("unknown error")
+        )
+        .into());
+    }
+    let groups = response["data"]["viewer"]["zones"][0]["httpRequestsAdaptiveGroups"]
+        .as_array()
+        .ok_or("Cloudflare analytics returned no data for the zone")?;
+    Ok(groups
+        .iter()
+        .filter_map(|group| {
+            let dimensions = &group["dimensions"];
+            let date: NaiveDate = dimensions["date"].as_str()?.parse().ok()?;
+            Some(Hit {
+                path: request_path(dimensions["clientRequestPath"].as_str()?),
+                time: date.and_hms_opt(0, 0, 0)?.and_utc(),
+                status: dimensions["edgeResponseStatus"].as_u64()? as u16,
+                requests: group["count"].as_u64()?,
+                bytes: group["sum"]["edgeResponseBytes"].as_u64().unwrap_or(0),
+            })
+        })
+        .collect())
+}
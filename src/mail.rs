@@ -0,0 +1,253 @@
+use crate::config::{AppConfig, MailConfig, SlugStyle};
+use crate::email;
+use crate::frontmatter::slug;
+use crate::imap::Imap;
+use crate::manifest::Manifest;
+use crate::shutdown;
+use crate::site::{Announce, Published, Site};
+use mail_parser::{Message, MessageParser, MimeHeaders};
+use std::{collections::HashSet, error::Error, path::Path, time::Duration};
+use tokio::fs;
+use tracing::{error, info, warn};
+
+/// Publishes the unseen emails in the [mail] mailbox from its allowed
+/// senders and replies to each with the post's URL. With `watch`, keeps
+/// checking every `interval` seconds.
+pub async fn fetch(app_config: &AppConfig, watch: bool) -> Result<(), Box<dyn Error>> {
+    let config = app_config
+        .mail
+        .as_ref()
+        .ok_or("mail-fetch requires a [mail] section in the config")?;
+    if config.allowed_senders.is_empty() {
+        return Err("[mail] allowed_senders is empty, so no email would be published".into());
+    }
+    let site = Site::new(app_config)?;
+    let manifest_path = Manifest::path_for(app_config)?;
+    shutdown::listen();
+
+    loop {
+        let checked = check(app_config, config, &site, &manifest_path).await;
+        if !watch {
+            let published = checked?;
+            println!("{} post(s) published by email", published);
+            return Ok(());
+        }
+        if let Err(e) = checked {
+            error!(host = %config.imap_host, error = %e, "Could not check the mailbox");
+        }
+        tokio::select! {
+            _ = tokio::time::sleep(Duration::from_secs(config.interval.max(1))) => {}
+            _ = shutdown::wait() => {}
+        }
+        if shutdown::requested() {
+            info!("Stopped checking for email");
+            return Ok(());
+        }
+    }
+}
+
+/// Goes through the unseen emails once, returning how many were published.
+async fn check(
+    app_config: &AppConfig,
+    config: &MailConfig,
+    site: &Site<'_>,
+    manifest_path: &Path,
+) -> Result<usize, Box<dyn Error>> {
+    let mut imap = Imap::connect(&config.imap_host, config.imap_port).await?;
+    imap.login(&config.username, &config.password).await?;
+    imap.select(&config.mailbox).await?;
+
+    let mut published = 0;
+    for uid in imap.unseen().await? {
+        // The rest stay unseen for next time.
+        if shutdown::requested() {
+            break;
+        }
+        let raw = imap.fetch(uid).await?;
+        // Marked first, so an email that fails halfway isn't posted twice;
+        // mark it unread to try again.
+        imap.mark_seen(uid).await?;
+        if handle(app_config, config, site, manifest_path, &raw).await {
+            published += 1;
+        }
+    }
+    imap.logout().await?;
+    Ok(published)
+}
+
+/// Publishes one email and replies to its sender with the URL or the error.
+async fn handle(
+    app_config: &AppConfig,
+    config: &MailConfig,
+    site: &Site<'_>,
+    manifest_path: &Path,
+    raw: &[u8],
+) -> bool {
+    let Some(message) = MessageParser::default().parse(raw) else {
+        warn!("Skipping an email that could not be parsed");
+        return false;
+    };
+    let sender = match sender(&message, config) {
+        Ok(sender) => sender,
+        Err(e) => {
+            warn!(reason = %e, "Skipping email");
+            return false;
+        }
+    };
+
+    let result = async {
+        let mut manifest = Manifest::load(manifest_path).await?;
+        let published = publish_message(site, &message, &mut manifest).await?;
+        site.finish_publish(&published, &mut manifest, manifest_path, Announce::Yes)
+            .await?;
+        Ok::<_, Box<dyn Error>>(published.post.url)
+    }
+    .await;
+    let reply = match &result {
+        Ok(url) => {
+            info!(url = %url, "Published by email");
+            format!("Published: {}\n", url)
+        }
+        Err(e) => {
+            error!(sender = %sender, error = %e, "Could not publish the email");
+            format!("Could not publish your post: {}\n", e)
+        }
+    };
+    if let Some(email_config) = &app_config.email {
+        let subject = message.subject().unwrap_or_default();
+        let sent =
+            email::send_reply(email_config, &sender, subject, message.message_id(), &reply).await;
+        if let Err(e) = sent {
+            warn!(sender = %sender, error = %e, "Could not reply");
+        }
+    }
+    result.is_ok()
+}
+
+/// Publishes the email `raw` as a post: the subject is the title unless the
+/// body sets one, the plain text body is the Markdown, and attachments are
+/// uploaded next to the page and linked at the end, images shown inline.
+pub async fn publish_mail(
+    site: &Site<'_>,
+    config: &MailConfig,
+    raw: &[u8],
+    manifest: &mut Manifest,
+) -> Result<Published, Box<dyn Error>> {
+    let message = MessageParser::default()
+        .parse(raw)
+        .ok_or("The email could not be parsed")?;
+    sender(&message, config)?;
+    publish_message(site, &message, manifest).await
+}
+
+/// The sender's address, if they may publish.
+fn sender(message: &Message, config: &MailConfig) -> Result<String, Box<dyn Error>> {
+    let address = message
+        .from()
+        .and_then(|from| from.first())
+        .and_then(|from| from.address())
+        .ok_or("The email has no sender")?;
+    if !config
+        .allowed_senders
+        .iter()
+        .any(|allowed| allowed.eq_ignore_ascii_case(address))
+    {
+        return Err(format!("{} is not an allowed sender", address).into());
+    }
+    // The topmost header is the one your own server added.
+    let passed = message
+        .header_raw("Authentication-Results")
+        .is_some_and(|results| results.to_ascii_lowercase().contains("dmarc=pass"));
+    if config.require_dmarc && !passed {
+        return Err(format!("The email from {} did not pass DMARC", address).into());
+    }
+    Ok(address.to_string())
+}
+
+async fn publish_message(
+    site: &Site<'_>,
+    message: &Message<'_>,
+    manifest: &mut Manifest,
+) -> Result<Published, Box<dyn Error>> {
+    let title = message.subject().map(str::trim).unwrap_or_default();
+    let title = if title.is_empty() { "Note" } else { title };
+    let body = message
+        .body_text(0)
+        .unwrap_or_default()
+        .replace("\r\n", "\n");
+    // Everything after the `-- ` line is the signature.
+    let body = match body.find("\n-- \n") {
+        Some(end) => &body[..end],
+        None => &body,
+    };
+
+    let dir = std::env::temp_dir()
+        .join("klistra-mail")
+        .join(uuid::Uuid::new_v4().to_string());
+    let mut markdown = body.trim_end().to_string();
+    let mut files = Vec::new();
+    let mut taken = HashSet::new();
+    for part in message.attachments() {
+        if part.is_message() {
+            continue;
+        }
+        let name = file_name(part.attachment_name(), &mut taken);
+        let is_image = part
+            .content_type()
+            .is_some_and(|content_type| content_type.ctype().eq_ignore_ascii_case("image"));
+        fs::create_dir_all(&dir).await?;
+        fs::write(dir.join(&name), part.contents()).await?;
+        markdown.push_str(&format!(
+            "\n\n{}[{}]({})",
+            if is_image { "!" } else { "" },
+            name,
+            name
+        ));
+        files.push(name);
+    }
+    markdown.push('\n');
+
+    let published = site
+        .publish_markdown(&markdown, title, None, manifest)
+        .await;
+    if let Ok(published) = &published {
+        let post_dir = published.post.dir();
+        for name in &files {
+            let file = dir.join(name);
+            let uploaded = site
+                .upload_static(&file, &format!("{}/{}", post_dir, name))
+                .await;
+            if let Err(e) = uploaded {
+                warn!(file = %name, error = %e, "Could not upload the attachment");
+            }
+        }
+    }
+    if !files.is_empty() {
+        let _ = fs::remove_dir_all(&dir).await;
+    }
+    published
+}
+
+/// A safe, unique file name for an attachment called `name`.
+fn file_name(name: Option<&str>, taken: &mut HashSet<String>) -> String {
+    let name = name
+        .and_then(|name| name.rsplit(['/', '\\']).next())
+        .unwrap_or_default();
+    let (stem, extension) = match name.rsplit_once('.') {
+        Some((stem, extension)) if !stem.is_empty() => {
+            (stem, format!(".{}", slug(extension, SlugStyle::Translit)))
+        }
+        _ => (name, String::new()),
+    };
+    let stem = match slug(stem, SlugStyle::Translit) {
+        stem if stem.is_empty() => "attachment".to_string(),
+        stem => stem,
+    };
+    let mut name = format!("{}{}", stem, extension);
+    let mut n = 2;
+    while !taken.insert(name.clone()) {
+        name = format!("{}-{}{}", stem, n, extension);
+        n += 1;
+    }
+    name
+}
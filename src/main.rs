@@ -1,233 +1,559 @@
-use aws_sdk_s3::config::{
-    BehaviorVersion, Credentials, Region, RequestChecksumCalculation, ResponseChecksumValidation,
+use chrono::Utc;
+use clap::{Parser, Subcommand};
+use klistra::config::{get_config_path, AppConfig, Backend, Provider};
+use klistra::expiry::Ttl;
+use klistra::export::{self, ExportFormat};
+use klistra::formats::{self, InputFormat};
+use klistra::history::{self, Bound};
+use klistra::import::{self, Generator};
+use klistra::integrity;
+use klistra::manifest::{Manifest, Post};
+use klistra::media::{self, Media};
+use klistra::render::{render_post, PostContext};
+use klistra::site::{file_title, Announce, Published, Site};
+use klistra::throttle::Rate;
+use klistra::webhook::Event;
+use klistra::{
+    bench, bot, daemon, email, http, logging, logs, mail, micropub, publisher, queue, quick,
+    selfupdate, service, setup, shutdown, spellcheck, stdio, traffic, tui, variants, views,
 };
-use aws_sdk_s3::primitives::ByteStream;
-use aws_sdk_s3::Client;
-use clap::Parser;
-use pulldown_cmark::{html::push_html, Options, Parser as MarkdownParser};
-use serde::Deserialize;
 use std::{error::Error, path::Path, path::PathBuf};
 use tokio::fs;
-use uuid::Uuid;
-use dirs;
-
-#[derive(Debug, Deserialize)]
-struct S3Config {
-    domain: String,
-    bucket: String,
-    region: String,
-    prefix: String,
-    access_key_id: String,
-    secret_access_key: String,
-}
-
-#[derive(Debug, Deserialize)]
-struct AppConfig {
-    s3: S3Config,
-}
 
 /// A simple markdown-to-HTML converter and uploader for Backblaze B2.
 #[derive(Parser, Debug)]
-#[command(name = "klistra", author, version, about)]
+#[command(
+    name = "klistra",
+    author,
+    version,
+    about,
+    subcommand_negates_reqs = true
+)]
 struct Cli {
-    /// The markdown file to convert.
-    file: String,
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    /// The markdown (or .adoc/.rst/.org) file to convert, or a directory to publish every note in.
+    #[arg(required = true)]
+    file: Option<String>,
 
     /// Output the HTML locally with the same base name as the input file (extension .html).
     /// If this flag is provided, the file will NOT be uploaded.
     #[arg(short = 'f', long = "file-output", alias = "fo")]
     file_output: bool,
 
-    /// Optional path to the config file. If not provided, will look in $HOME/.config/klistra/config.toml
-    #[arg(short = 'c', long = "config")]
+    /// Path to the config file. Defaults to $KLISTRA_CONFIG, then klistra/config.toml in the
+    /// platform's config directory (e.g. $XDG_CONFIG_HOME or ~/.config on Linux).
+    #[arg(short = 'c', long = "config", global = true)]
     config_path: Option<PathBuf>,
+
+    /// Where uploads go: s3, or memory to try things out without credentials or network
+    /// (uploads are printed, and a scratch manifest is used). Overrides [upload] backend.
+    #[arg(long, value_name = "BACKEND", global = true)]
+    backend: Option<Backend>,
+
+    /// Limit upload speed, e.g. 2MB/s. Overrides [upload] limit_rate.
+    #[arg(long, value_name = "RATE", global = true)]
+    limit_rate: Option<Rate>,
+
+    /// Publish even if it goes over the [upload] size limits.
+    #[arg(long, global = true)]
+    force: bool,
+
+    /// Upload photos with their EXIF data, GPS position included.
+    /// Overrides [upload] keep_exif.
+    #[arg(long, global = true)]
+    keep_exif: bool,
+
+    /// Publish even if the post looks like it contains keys, tokens or
+    /// passwords.
+    #[arg(long, global = true)]
+    allow_secrets: bool,
+
+    /// Mark the upload for deletion after this long, e.g. 30d, 12h or 2w.
+    /// Expired posts are removed by `klistra reap`.
+    #[arg(long, value_name = "DURATION", global = true)]
+    ttl: Option<Ttl>,
+
+    /// Don't announce the post on the services configured under [announce].
+    #[arg(long)]
+    no_announce: bool,
+
+    /// Print the announcement instead of posting it.
+    #[arg(long)]
+    announce_dry_run: bool,
+
+    /// Email the published URL to this address using the [email] SMTP settings. Repeatable.
+    #[arg(long = "email", value_name = "ADDRESS")]
+    email: Vec<String>,
+
+    /// With --email, send the rendered page as the message body.
+    #[arg(long, requires = "email")]
+    email_html: bool,
+
+    /// When publishing a directory, print how long each file took to render and upload.
+    #[arg(long)]
+    timings: bool,
+
+    /// Produce the same HTML on every run over the same input: no dates unless set in front
+    /// matter, and ids derived from content. Overrides [page] reproducible.
+    #[arg(long, global = true)]
+    reproducible: bool,
+
+    /// Don't upload now: keep everything in a local queue for `klistra flush`,
+    /// e.g. on a train. The post isn't announced.
+    #[arg(long, global = true)]
+    queue: bool,
+
+    /// Spellcheck with hunspell first, and don't publish if it finds misspellings (or only
+    /// warn, with [check] fail = false).
+    #[arg(long)]
+    check: bool,
 }
 
-fn get_config_path(cli_config_path: Option<PathBuf>) -> Option<PathBuf> {
-    // If config path is provided via CLI, use that
-    if let Some(path) = cli_config_path {
-        return Some(path);
-    }
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Upload files as-is (no markdown processing) and print the URL. Several
+    /// files, or a directory, get a listing page.
+    Raw {
+        /// The files or directories to upload.
+        #[arg(required = true)]
+        files: Vec<PathBuf>,
+
+        /// For text files, also publish a syntax-highlighted page linking to the raw file.
+        #[arg(long)]
+        wrap: bool,
+    },
+    /// Publish a source file as a page with line numbers, highlighting, a raw
+    /// download link and a copy button.
+    Snippet {
+        /// The source file to share.
+        file: PathBuf,
+    },
+    /// Publish a folder of photos as an album: a grid of thumbnails that
+    /// open full size in a lightbox.
+    Gallery {
+        /// The folder with the photos.
+        dir: PathBuf,
+
+        /// Album title. Defaults to the folder's name.
+        #[arg(long)]
+        title: Option<String>,
+    },
+    /// Upload a draft where only a presigned link reaches it, for showing it
+    /// to someone before publishing. `klistra reap` deletes it afterwards.
+    Preview {
+        /// The markdown file to preview.
+        file: PathBuf,
+
+        /// How long the link works, at most 7d.
+        #[arg(long, value_name = "DURATION", default_value = "48h")]
+        expires: Ttl,
+    },
+    /// Spellcheck posts with hunspell, printing the words it doesn't know.
+    /// Fails if there are any.
+    Check {
+        /// The files, or directories of notes, to check.
+        #[arg(required = true)]
+        files: Vec<PathBuf>,
+    },
+    /// Serve an authenticated HTTP API for publishing, listing and deleting posts.
+    Daemon {
+        /// Address to listen on.
+        #[arg(long, default_value = "127.0.0.1:7878")]
+        listen: String,
+    },
+    /// Read newline-delimited JSON commands (render, preview, publish) from stdin,
+    /// for editor plugins.
+    #[command(name = "stdio-json", alias = "lsp-ish")]
+    StdioJson,
+    /// Serve a Micropub endpoint that publishes incoming posts.
+    MicropubServe {
+        /// Address to listen on.
+        #[arg(long, default_value = "127.0.0.1:7879")]
+        listen: String,
+    },
+    /// Publish the unseen emails in the [mail] mailbox and reply with their URLs.
+    MailFetch {
+        /// Keep checking every [mail] interval instead of once.
+        #[arg(long)]
+        watch: bool,
+    },
+    /// Run a chat bot that publishes what you send it and replies with the URL:
+    /// text as a post, files as raw shares.
+    Bot {
+        /// telegram.
+        platform: bot::Platform,
+    },
+    /// Pick a recently modified note from the [quick] directories and publish it.
+    Quick,
+    /// Browse, search and manage published posts interactively.
+    Tui,
+    /// Publish a post again from its source file, keeping its URL and
+    /// showing when it was updated.
+    Update {
+        /// The post's id or URL.
+        post: String,
+    },
+    /// Move a post to the trash, where it can be restored from. On B2 its
+    /// files stay publicly readable there until purged.
+    Delete {
+        /// The post's id or URL.
+        post: String,
+
+        /// Remove the files permanently instead (also works for posts in the trash).
+        #[arg(long)]
+        purge: bool,
+    },
+    /// Restore a deleted post from the trash.
+    Undelete {
+        /// The post's id or URL.
+        post: String,
+    },
+    /// Download the markdown a post was published from ([sources] upload),
+    /// e.g. to edit it on another machine.
+    Pull {
+        /// The post's id or URL.
+        post: String,
+        /// Write it here instead of to stdout.
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+    /// Time parsing, rendering, templating and uploading a file or every note
+    /// in a directory, without publishing anything.
+    Bench {
+        /// A file or a directory of notes.
+        target: PathBuf,
+        /// How many times to process each file.
+        #[arg(long, default_value_t = 10, value_parser = clap::value_parser!(u32).range(1..))]
+        iterations: u32,
+        /// Really upload (to bench/, removed afterwards) instead of only timing
+        /// the upload body.
+        #[arg(long)]
+        upload: bool,
+    },
+    /// Replace this binary with the latest GitHub release, after checking its SHA-256.
+    SelfUpdate {
+        /// Only report whether a newer release exists.
+        #[arg(long)]
+        check: bool,
+    },
+    /// Show what was published, updated, deleted and restored, oldest first.
+    History {
+        /// Only entries whose title, URL or id contain all of these words.
+        query: Option<String>,
+        /// Only entries from this date (2025-03-01) or this long ago (7d, 12h) on.
+        #[arg(long, value_name = "TIME")]
+        since: Option<Bound>,
+        /// Only entries up to and including this date, or up to this long ago.
+        #[arg(long, value_name = "TIME")]
+        until: Option<Bound>,
+        /// Only published, updated, deleted or restored entries.
+        #[arg(long)]
+        event: Option<Event>,
+        /// Show at most this many of the most recent matches.
+        #[arg(long, default_value_t = 20)]
+        limit: usize,
+    },
+    /// Move a Hugo or Jekyll site or a Notion export over: write its posts
+    /// as notes, publish them, upload its static files, and redirect its old
+    /// URLs.
+    Import {
+        /// hugo, jekyll or notion.
+        generator: Generator,
+        /// The site's directory, with its config file, or the export's zip.
+        dir: PathBuf,
+        /// Write the converted notes here.
+        #[arg(long, default_value = "imported")]
+        into: PathBuf,
+        /// Only the post or page with this title.
+        #[arg(long)]
+        page: Option<String>,
+    },
+    /// Write a report of every post (title, URL, dates, tags and size) for
+    /// sharing an overview with collaborators.
+    Export {
+        /// html, csv or json.
+        #[arg(long, default_value = "json")]
+        format: ExportFormat,
+        /// Write the report here instead of to stdout.
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+        /// Encrypt the HTML report with a password, asked for on the terminal
+        /// (or read from the first line of stdin). Readers enter it in the browser.
+        #[arg(long)]
+        password: bool,
+    },
+    /// Count how often each post was viewed, from the hits on its [views]
+    /// pixel in the access logs ([logs]).
+    Views {
+        /// Only views from this date (2025-03-01) or this long ago (7d) on.
+        #[arg(long, value_name = "TIME", default_value = "30d")]
+        since: Bound,
+    },
+    /// Rank posts by requests and bytes sent, from the access logs ([logs]).
+    Traffic {
+        /// Only traffic from this date (2025-03-01) or this long ago (7d) on.
+        #[arg(long, value_name = "TIME", default_value = "30d")]
+        since: Bound,
+        /// Also write a dashboard page with a chart per day here.
+        #[arg(long, value_name = "FILE")]
+        html: Option<PathBuf>,
+    },
+    /// Download everything klistra uploaded and check it against the local
+    /// integrity record and the signed integrity.json.
+    Verify,
+    /// Build the front page listing every post, with a search box, and upload it.
+    Index {
+        /// Also build an archive page grouping posts by year and month.
+        #[arg(long)]
+        archive: bool,
+    },
+    /// Render every post again with the current templates and theme and upload
+    /// the pages that changed, along with the front page. Posts keep their
+    /// URLs and aren't marked as updated.
+    Rebuild,
+    /// Upload new redirects from redirects.toml and remove deleted ones.
+    /// Publishing does this too.
+    Redirects,
+    /// Merge the local manifest with the copy in the bucket ([manifest]
+    /// remote), so posts published from other machines are known here too.
+    Sync,
+    /// Upload what --queue, or [upload] queue_offline, kept for later.
+    Flush,
+    /// Delete posts whose --ttl has run out, and previews whose link has.
+    /// Run it from cron or a systemd timer.
+    Reap {
+        /// Only list the posts and previews that would be deleted.
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// Install a long-running mode as a user-level systemd unit (launchd agent
+    /// on macOS) that starts at boot, or remove it again.
+    Service {
+        #[command(subcommand)]
+        action: ServiceAction,
+    },
+    /// Create the bucket if it's missing and configure public access and CORS.
+    SetupBucket {
+        /// Also add a lifecycle rule deleting objects under `drafts/` after this many days.
+        #[arg(long, value_name = "DAYS", value_parser = clap::value_parser!(i32).range(1..))]
+        expire_drafts: Option<i32>,
+    },
+}
 
-    // Otherwise, look in the default location: $HOME/.config/klistra/config.toml
-    dirs::home_dir().map(|home| home.join(".config").join("klistra").join("config.toml"))
+#[derive(Subcommand, Debug)]
+enum ServiceAction {
+    /// Write the unit for a service and start it.
+    Install {
+        /// daemon, micropub, mail, telegram or reap (hourly).
+        service: service::Service,
+        /// Only print the unit files.
+        #[arg(long)]
+        print: bool,
+    },
+    /// Stop a service and remove its unit.
+    Uninstall {
+        /// daemon, micropub, mail, telegram or reap.
+        service: service::Service,
+    },
 }
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn Error>> {
     let cli = Cli::parse();
 
+    // Updating works without a config; its [tls] settings are used if there is one.
+    if let Some(Command::SelfUpdate { check }) = &cli.command {
+        let tls = get_config_path(cli.config_path)
+            .and_then(|path| AppConfig::load(&path))
+            .map(|config| config.tls)
+            .unwrap_or_default();
+        return selfupdate::run(&tls, *check).await;
+    }
+
     // Get the config path
-    let config_path = get_config_path(cli.config_path)
-        .ok_or_else(|| "Could not determine config file path")?;
+    let config_path = get_config_path(cli.config_path)?;
+    let mut app_config = AppConfig::load(&config_path)?;
+    app_config.upload.force = cli.force;
+    app_config.upload.keep_exif |= cli.keep_exif;
+    app_config.check.allow_secrets = cli.allow_secrets;
+    if cli.limit_rate.is_some() {
+        app_config.upload.limit_rate = cli.limit_rate;
+    }
+    if let Some(backend) = cli.backend {
+        app_config.upload.backend = backend;
+    }
+    if cli.reproducible {
+        app_config.page.reproducible = true;
+    }
+    app_config.upload.queue = cli.queue;
 
-    // Check if the config file exists
-    if !config_path.exists() {
-        return Err(format!(
-            "Config file not found at {}",
-            config_path.display()
-        )
-        .into());
+    let announce = match (cli.no_announce || cli.queue, cli.announce_dry_run) {
+        (true, _) => Announce::No,
+        (false, true) => Announce::DryRun,
+        (false, false) => Announce::Yes,
+    };
+
+    if let Some(command) = cli.command {
+        return match command {
+            Command::Raw { files, wrap } => upload_raw(&app_config, &files, wrap, cli.ttl).await,
+            Command::Snippet { file } => upload_raw(&app_config, &[file], true, cli.ttl).await,
+            Command::Gallery { dir, title } => {
+                publish_gallery(&app_config, &dir, title.as_deref(), cli.ttl).await
+            }
+            Command::Preview { file, expires } => preview(&app_config, &file, expires).await,
+            Command::Check { files } => match spellcheck(&app_config, &files).await? {
+                0 => {
+                    println!("No misspellings found");
+                    Ok(())
+                }
+                found => Err(format!(
+                    "{} possible misspelling(s); add words to [check] words to accept them",
+                    found
+                )
+                .into()),
+            },
+            Command::Tui => tui::run(&app_config).await,
+            Command::Quick => match quick::pick(&app_config.quick).await? {
+                Some(source) => publish_file(&app_config, &source, announce, cli.ttl)
+                    .await
+                    .map(|_| ()),
+                None => Ok(()),
+            },
+            Command::Update { post } => update(&app_config, &post).await,
+            Command::Delete { post, purge } => delete(&app_config, &post, purge).await,
+            Command::Undelete { post } => undelete(&app_config, &post).await,
+            Command::Pull { post, output } => pull(&app_config, &post, output.as_deref()).await,
+            Command::Reap { dry_run } => reap(&app_config, dry_run).await,
+            Command::Index { archive } => index(&app_config, archive).await,
+            Command::Rebuild => rebuild(&app_config).await,
+            Command::Redirects => redirects(&app_config).await,
+            Command::Sync => sync(&app_config).await,
+            Command::Flush => {
+                app_config.upload.queue = false;
+                app_config.upload.queue_offline = false;
+                flush(&app_config).await
+            }
+            Command::Verify => verify(&app_config).await,
+            Command::Views { since } => views(&app_config, since).await,
+            Command::Traffic { since, html } => traffic(&app_config, since, html.as_deref()).await,
+            Command::History {
+                query,
+                since,
+                until,
+                event,
+                limit,
+            } => {
+                let filter = history::Filter {
+                    query,
+                    since,
+                    until,
+                    event,
+                };
+                show_history(&app_config, &filter, limit).await
+            }
+            Command::Import {
+                generator,
+                dir,
+                into,
+                page,
+            } => import(&app_config, generator, &dir, &into, page.as_deref()).await,
+            Command::Export {
+                format,
+                output,
+                password,
+            } => export(&app_config, format, output.as_deref(), password).await,
+            Command::Bench {
+                target,
+                iterations,
+                upload,
+            } => bench::run(&app_config, &target, iterations, upload).await,
+            Command::SelfUpdate { .. } => unreachable!("handled before loading the config"),
+            Command::Daemon { listen } => {
+                logging::init(&app_config.logging, "daemon")?;
+                daemon::serve(app_config, &listen).await
+            }
+            Command::StdioJson => stdio::serve(app_config).await,
+            Command::MicropubServe { listen } => {
+                logging::init(&app_config.logging, "micropub")?;
+                micropub::serve(app_config, &listen).await
+            }
+            Command::MailFetch { watch } => {
+                logging::init(&app_config.logging, "mail")?;
+                mail::fetch(&app_config, watch).await
+            }
+            Command::Bot { platform } => {
+                logging::init(&app_config.logging, "bot")?;
+                bot::run(&app_config, platform).await
+            }
+            Command::Service { action } => match action {
+                ServiceAction::Install { service, print } => {
+                    service::install(service, &config_path, print).await
+                }
+                ServiceAction::Uninstall { service } => service::uninstall(service).await,
+            },
+            Command::SetupBucket { expire_drafts } => {
+                setup::setup_bucket(&app_config, expire_drafts).await
+            }
+        };
     }
 
-    let settings = config::Config::builder()
-        .add_source(config::File::with_name(
-            config_path
-                .to_str()
-                .ok_or_else(|| "Invalid config path")?
-        ))
-        .build()?;
-    let app_config: AppConfig = settings.try_deserialize()?;
-
-    let markdown_content = fs::read_to_string(&cli.file).await?;
-
-    let mut options = Options::empty();
-    options.insert(Options::ENABLE_TABLES);
-
-    let parser = MarkdownParser::new_ext(&markdown_content, options);
-    let mut html_output = String::new();
-    push_html(&mut html_output, parser);
-
-    let current_date = chrono::Local::now().format("%B %d, %Y").to_string();
-
-    let title = Path::new(&cli.file)
-        .file_stem()
-        .and_then(|s| s.to_str())
-        .unwrap_or("Document");
-
-    let full_html = format!(
-        r#"<!DOCTYPE html>
-<html lang="en">
-<head>
-    <meta charset="UTF-8">
-    <meta name="viewport" content="width=device-width, initial-scale=1.0">
-    <title>{}</title>
-    <style>
-        :root {{
-            --background: #121212;
-            --text: rgba(255, 255, 255, 0.87);
-            --text-secondary: rgba(255, 255, 255, 0.6);
-            --max-width: 800px;
-            --spacing: 2rem;
-        }}
-
-        * {{
-            margin: 0;
-            padding: 0;
-            box-sizing: border-box;
-        }}
-
-        body {{
-            font-family: -apple-system, BlinkMacSystemFont, "Segoe UI", Roboto, Oxygen-Sans, Ubuntu, Cantarell, "Helvetica Neue", sans-serif;
-            background: var(--background);
-            color: var(--text);
-            line-height: 1.6;
-            padding: var(--spacing);
-        }}
-
-        .container {{
-            max-width: var(--max-width);
-            margin: 0 auto;
-            padding: var(--spacing);
-        }}
-
-        .date {{
-            color: var(--text-secondary);
-            margin-bottom: 1rem;
-            font-size: 1rem;
-        }}
-
-        h1 {{
-            font-size: 2.5rem;
-            font-weight: 600;
-            margin-bottom: 0.5rem;
-            line-height: 1.2;
-        }}
-
-        h2 {{
-            font-size: 1.75rem;
-            color: var(--text);
-            margin: 2rem 0 1rem;
-        }}
-
-        p {{
-            margin-bottom: 1.5rem;
-            font-size: 1.1rem;
-        }}
-
-        a {{
-            color: #3B82F6;
-            text-decoration: none;
-        }}
-
-        a:hover {{
-            text-decoration: underline;
-        }}
-
-        code {{
-            font-family: "SF Mono", "Segoe UI Mono", "Roboto Mono", Menlo, Courier, monospace;
-            background: rgba(255, 255, 255, 0.1);
-            padding: 0.2em 0.4em;
-            border-radius: 3px;
-            font-size: 0.9em;
-        }}
-
-        pre {{
-            background: rgba(255, 255, 255, 0.1);
-            padding: 1rem;
-            border-radius: 4px;
-            overflow-x: auto;
-            margin: 1.5rem 0;
-        }}
-
-        pre code {{
-            background: none;
-            padding: 0;
-        }}
-
-        img {{
-            max-width: 100%;
-            height: auto;
-            border-radius: 8px;
-            margin: 1.5rem 0;
-        }}
-
-        .subtitle {{
-            color: var(--text-secondary);
-            font-size: 1.25rem;
-            margin-bottom: 2rem;
-        }}
-
-        table {{
-            width: 100%;
-            border-collapse: collapse;
-            margin-bottom: 1.5rem;
-        }}
-
-        th, td {{
-            border: 1px solid rgba(255, 255, 255, 0.2);
-            padding: 0.75rem;
-            text-align: left;
-        }}
-
-        thead {{
-            background-color: rgba(255, 255, 255, 0.1);
-        }}
-    </style>
-</head>
-<body>
-    <div class="container">
-        <div class="date">{}</div>
-        {}
-    </div>
-</body>
-</html>"#,
-        title, current_date, html_output
-    );
+    let file = cli.file.ok_or("No input file given")?;
+    let source = Path::new(&file);
+
+    if cli.check {
+        let found = spellcheck(&app_config, &[source.to_path_buf()]).await?;
+        match (found, app_config.check.fail) {
+            (0, _) => {}
+            (found, true) => {
+                return Err(format!(
+                    "Not publishing: {} possible misspelling(s). Fix them, add them to [check] \
+                     words, or set [check] fail = false to publish anyway",
+                    found
+                )
+                .into())
+            }
+            (found, false) => eprintln!("Publishing with {} possible misspelling(s)", found),
+        }
+    }
+
+    if fs::metadata(source).await.is_ok_and(|meta| meta.is_dir()) {
+        if cli.file_output || !cli.email.is_empty() {
+            return Err("-f and --email only work on single files".into());
+        }
+        return publish_dir(&app_config, source, announce, cli.ttl, cli.timings).await;
+    }
 
     if cli.file_output {
-        let input_path = Path::new(&cli.file);
-        let output_path: PathBuf = input_path.with_extension("html");
+        let markdown_content = formats::read_source(source).await?;
+        let file_starts = media::read_starts(&markdown_content, source.parent()).await;
+        let (title, lang) = match variants::split_lang(source) {
+            Some((base, lang)) => (base, lang),
+            None => (file_title(source).to_string(), app_config.page.lang.clone()),
+        };
+        let post = render_post(
+            &markdown_content,
+            &app_config.page,
+            &PostContext {
+                fallback_title: &title,
+                lang: &lang,
+                alternates: &[],
+                image_url: None,
+                format: InputFormat::from_path(source),
+                base_dir: source.parent(),
+                site: &app_config.site,
+                published: None,
+                updated: None,
+                related: &[],
+                view_pixel: None,
+                source_url: None,
+                content_types: &app_config.upload.content_types,
+                file_starts: &file_starts,
+            },
+        )?;
+
+        let output_path: PathBuf = source.with_extension("html");
 
         if fs::metadata(&output_path).await.is_ok() {
             println!(
@@ -235,53 +561,629 @@ async fn main() -> Result<(), Box<dyn Error>> {
                 output_path.display()
             );
         } else {
-            fs::write(&output_path, &full_html).await?;
+            fs::write(&output_path, &post.html).await?;
             println!("Local HTML file created: {}", output_path.display());
         }
+        write_posters(&post.media).await;
         return Ok(());
     }
 
-    let folder_name = Uuid::new_v4().to_string();
-
-    let s3_conf = app_config.s3;
-    let endpoint = format!("https://s3.{}.backblazeb2.com", s3_conf.region);
-    let aws_config = aws_sdk_s3::Config::builder()
-        .region(Region::new(s3_conf.region.clone()))
-        .endpoint_url(endpoint)
-        .force_path_style(true)
-        .behavior_version(BehaviorVersion::latest())
-        .use_fips(false)
-        .use_dual_stack(false)
-        .request_checksum_calculation(RequestChecksumCalculation::WhenRequired)
-        .response_checksum_validation(ResponseChecksumValidation::WhenRequired)
-        .credentials_provider(Credentials::new(
-            s3_conf.access_key_id,
-            s3_conf.secret_access_key,
-            None,
-            None,
-            "backblaze-credentials",
-        ))
-        .build();
-    let client = Client::from_conf(aws_config);
-
-    let key = format!(
-        "{}/p/{}/index.html",
-        s3_conf.prefix.trim_end_matches('/'),
-        folder_name
+    let email_config = match (&app_config.email, cli.email.is_empty()) {
+        (_, true) => None,
+        (Some(email_config), false) => Some(email_config),
+        (None, false) => return Err("--email requires an [email] section in the config".into()),
+    };
+
+    let published = publish_file(&app_config, source, announce, cli.ttl).await?;
+
+    let post = &published.post;
+    if let Some(email_config) = email_config {
+        let html = cli.email_html.then_some(published.html.as_str());
+        email::send_link(email_config, &cli.email, post, html).await?;
+        println!("Emailed link to {}", cli.email.join(", "));
+    }
+
+    Ok(())
+}
+
+/// Spellchecks `paths` (files, or every note under a directory), printing
+/// each misspelling with its file and line. Returns how many there were.
+async fn spellcheck(app_config: &AppConfig, paths: &[PathBuf]) -> Result<usize, Box<dyn Error>> {
+    let mut files = Vec::new();
+    for path in paths {
+        if fs::metadata(path).await.is_ok_and(|meta| meta.is_dir()) {
+            let mut notes: Vec<PathBuf> = quick::note_files(path)
+                .await?
+                .into_iter()
+                .map(|(note, _)| note)
+                .collect();
+            notes.sort();
+            files.extend(notes);
+        } else {
+            files.push(path.clone());
+        }
+    }
+
+    let mut found = 0;
+    for file in files {
+        let source = formats::read_source(&file)
+            .await
+            .map_err(|e| format!("Could not read {}: {}", file.display(), e))?;
+        let misspellings =
+            spellcheck::check(&source, InputFormat::from_path(&file), &app_config.check)
+                .map_err(|e| format!("Could not check {}: {}", file.display(), e))?;
+        for misspelling in &misspellings {
+            match misspelling.line {
+                Some(line) => println!("{}:{}: {}", file.display(), line, misspelling),
+                None => println!("{}: {}", file.display(), misspelling),
+            }
+        }
+        found += misspellings.len();
+    }
+    Ok(found)
+}
+
+/// Publishes every new or changed note under `dir`, then runs everything that
+/// follows for them at once.
+async fn publish_dir(
+    app_config: &AppConfig,
+    dir: &Path,
+    announce: Announce,
+    ttl: Option<Ttl>,
+    timings: bool,
+) -> Result<(), Box<dyn Error>> {
+    let site = Site::new(app_config)?;
+    let manifest_path = Manifest::path_for(app_config)?;
+    let mut manifest = Manifest::load(&manifest_path).await?;
+
+    shutdown::listen();
+    let (published, failed) = site.publish_dir(dir, &mut manifest, timings).await?;
+    for published in &published {
+        set_expiry(&mut manifest, &published.post, ttl);
+    }
+    site.finish_publish_all(&published, &mut manifest, &manifest_path, announce)
+        .await?;
+    println!("{} post(s) published or updated", published.len());
+    if failed > 0 {
+        return Err(format!("{} file(s) could not be published", failed).into());
+    }
+    Ok(())
+}
+
+/// Converts the Hugo or Jekyll site or Notion export in `dir` to notes in
+/// `into` and publishes them. Nothing is announced: the posts aren't new.
+async fn import(
+    app_config: &AppConfig,
+    generator: Generator,
+    dir: &Path,
+    into: &Path,
+    page: Option<&str>,
+) -> Result<(), Box<dyn Error>> {
+    let conversion = import::convert(generator, dir, into, page)?;
+    let site = Site::new(app_config)?;
+    let manifest_path = Manifest::path_for(app_config)?;
+    let mut manifest = Manifest::load(&manifest_path).await?;
+
+    shutdown::listen();
+
+    let (published, failed) = site.import(&conversion, into, &mut manifest).await?;
+    site.finish_publish_all(&published, &mut manifest, &manifest_path, Announce::No)
+        .await?;
+    println!(
+        "{} post(s) imported into {}, {} static file(s) uploaded, {} draft(s) left out",
+        published.len(),
+        into.display(),
+        conversion.assets.len(),
+        conversion.drafts
     );
+    if failed > 0 {
+        return Err(format!("{} file(s) could not be published", failed).into());
+    }
+    Ok(())
+}
+
+/// Publishes `source` and runs everything that follows (webmentions, webhooks,
+/// announcements).
+async fn publish_file(
+    app_config: &AppConfig,
+    source: &Path,
+    announce: Announce,
+    ttl: Option<Ttl>,
+) -> Result<Published, Box<dyn Error>> {
+    let site = Site::new(app_config)?;
+    let manifest_path = Manifest::path_for(app_config)?;
+    let mut manifest = Manifest::load(&manifest_path).await?;
 
-    client
-        .put_object()
-        .bucket(s3_conf.bucket.clone())
-        .key(key.clone())
-        .body(ByteStream::from(full_html.into_bytes()))
-        .content_type("text/html")
-        .send()
+    let published = site.publish(source, &mut manifest).await?;
+    println!("File uploaded successfully: {}", published.post.url);
+    set_expiry(&mut manifest, &published.post, ttl);
+    site.finish_publish(&published, &mut manifest, &manifest_path, announce)
         .await?;
+    Ok(published)
+}
+
+async fn upload_raw(
+    app_config: &AppConfig,
+    files: &[PathBuf],
+    wrap: bool,
+    ttl: Option<Ttl>,
+) -> Result<(), Box<dyn Error>> {
+    let site = Site::new(app_config)?;
+    let manifest_path = Manifest::path_for(app_config)?;
+    let mut manifest = Manifest::load(&manifest_path).await?;
+
+    let published = site.publish_raw(files, wrap, &mut manifest).await?;
+    println!("File uploaded successfully: {}", published.post.url);
+    set_expiry(&mut manifest, &published.post, ttl);
+    // Raw uploads are shares, not posts, so they aren't announced.
+    site.finish_publish(&published, &mut manifest, &manifest_path, Announce::No)
+        .await
+}
+
+/// Writes the poster frames a page made with `-f` refers to next to their
+/// videos, leaving existing files alone.
+async fn write_posters(media: &[Media]) {
+    for item in media {
+        let Some(poster) = &item.poster else {
+            continue;
+        };
+        let Some(dir) = item.source.parent() else {
+            continue;
+        };
+        let file_name = poster.rsplit('/').next().unwrap_or(poster);
+        let path = dir.join(file_name);
+        if fs::metadata(&path).await.is_ok() {
+            continue;
+        }
+        match media::poster_frame(&item.source) {
+            Ok(jpeg) => match fs::write(&path, jpeg).await {
+                Ok(()) => println!("Poster frame created: {}", path.display()),
+                Err(e) => eprintln!("Could not write {}: {}", path.display(), e),
+            },
+            Err(e) => eprintln!("No poster for {}: {}", item.source.display(), e),
+        }
+    }
+}
+
+async fn publish_gallery(
+    app_config: &AppConfig,
+    dir: &Path,
+    title: Option<&str>,
+    ttl: Option<Ttl>,
+) -> Result<(), Box<dyn Error>> {
+    let site = Site::new(app_config)?;
+    let manifest_path = Manifest::path_for(app_config)?;
+    let mut manifest = Manifest::load(&manifest_path).await?;
+
+    let published = site.publish_gallery(dir, title, &mut manifest).await?;
+    println!("Album published successfully: {}", published.post.url);
+    set_expiry(&mut manifest, &published.post, ttl);
+    // Like raw uploads, albums are shares and aren't announced.
+    site.finish_publish(&published, &mut manifest, &manifest_path, Announce::No)
+        .await
+}
+
+/// Records when `post` expires, counting from now. Without a TTL, any earlier
+/// expiry of an updated post is kept.
+fn set_expiry(manifest: &mut Manifest, post: &Post, ttl: Option<Ttl>) {
+    if let (Some(ttl), Some(post)) = (ttl, manifest.post_mut(&post.id)) {
+        let expires = ttl.expires(Utc::now());
+        post.expires = Some(expires);
+        eprintln!("Expires {}", expires.format("%Y-%m-%d %H:%M UTC"));
+    }
+}
+
+async fn preview(
+    app_config: &AppConfig,
+    source: &Path,
+    expires: Ttl,
+) -> Result<(), Box<dyn Error>> {
+    let site = Site::new(app_config)?;
+    let manifest_path = Manifest::path_for(app_config)?;
+    let mut manifest = Manifest::load(&manifest_path).await?;
+
+    let preview = site.preview(source, expires, &mut manifest).await?;
+    site.save_manifest(&mut manifest, &manifest_path).await?;
+    println!("{}", preview.url);
+    eprintln!(
+        "Preview of {} works until {}",
+        preview.title,
+        preview.expires.format("%Y-%m-%d %H:%M UTC")
+    );
+    Ok(())
+}
+
+async fn reap(app_config: &AppConfig, dry_run: bool) -> Result<(), Box<dyn Error>> {
+    let manifest_path = Manifest::path_for(app_config)?;
+    let mut manifest = Manifest::load(&manifest_path).await?;
+    let now = Utc::now();
+    let expired = manifest.expired(now);
+    let previews = manifest.expired_previews(now);
+    if expired.is_empty() && previews.is_empty() {
+        eprintln!("No expired posts");
+        return Ok(());
+    }
+    if dry_run {
+        for post in &expired {
+            println!("Would delete {} ({})", post.url, post.title);
+        }
+        for preview in &previews {
+            println!("Would delete the preview of {}", preview.title);
+        }
+        return Ok(());
+    }
+
+    let site = Site::new(app_config)?;
+    for post in expired {
+        // Expired shares are gone for good rather than kept in the trash.
+        if let Some(post) = site.purge(&post.id, &mut manifest).await? {
+            site.finish_delete(&post, &mut manifest, &manifest_path)
+                .await?;
+            println!("Deleted {} ({})", post.url, post.title);
+        }
+    }
+    if !previews.is_empty() {
+        for preview in &previews {
+            site.delete_preview(preview).await?;
+            manifest.previews.retain(|kept| kept.id != preview.id);
+            println!("Deleted the preview of {}", preview.title);
+        }
+        site.save_manifest(&mut manifest, &manifest_path).await?;
+    }
+    Ok(())
+}
+
+/// The post id in `p/<id>` URLs, or `post` itself if it isn't a URL.
+fn post_id(post: &str) -> &str {
+    let post = post.trim_end_matches('/');
+    match post.rsplit_once("/p/") {
+        Some((_, rest)) => rest.split('/').next().unwrap_or(rest),
+        None => post,
+    }
+}
+
+async fn delete(app_config: &AppConfig, post: &str, purge: bool) -> Result<(), Box<dyn Error>> {
+    let site = Site::new(app_config)?;
+    let manifest_path = Manifest::path_for(app_config)?;
+    let mut manifest = Manifest::load(&manifest_path).await?;
+    let id = post_id(post);
+
+    let deleted = if purge {
+        site.purge(id, &mut manifest).await?
+    } else {
+        site.delete(id, &mut manifest).await?
+    };
+    let post = deleted.ok_or_else(|| format!("No post with id {}", id))?;
+    if purge && post.deleted.is_some() {
+        // Already announced as deleted when it went to the trash.
+        site.save_manifest(&mut manifest, &manifest_path).await?;
+    } else {
+        site.finish_delete(&post, &mut manifest, &manifest_path)
+            .await?;
+    }
+
+    if purge {
+        println!("Permanently deleted {} ({})", post.url, post.title);
+    } else {
+        println!("Moved {} ({}) to the trash", post.url, post.title);
+        eprintln!("Restore it with: klistra undelete {}", post.id);
+        if app_config.upload.backend != Backend::Memory
+            && app_config.s3.provider == Provider::Backblaze
+        {
+            eprintln!(
+                "Its files can still be downloaded from trash/{}/ (B2 can't keep part of a public bucket private); remove them with: klistra delete --purge {}",
+                post.dir(),
+                post.id
+            );
+        }
+    }
+    Ok(())
+}
 
-    let public_url = format!("{}/p/{}", s3_conf.domain, folder_name);
+async fn update(app_config: &AppConfig, post: &str) -> Result<(), Box<dyn Error>> {
+    let site = Site::new(app_config)?;
+    let manifest_path = Manifest::path_for(app_config)?;
+    let mut manifest = Manifest::load(&manifest_path).await?;
 
-    println!("File uploaded successfully: {}", public_url);
+    let published = site.update(post_id(post), &mut manifest).await?;
+    println!("Updated {}", published.post.url);
+    // Updates aren't announced again.
+    site.finish_publish(&published, &mut manifest, &manifest_path, Announce::No)
+        .await
+}
 
+async fn index(app_config: &AppConfig, archive: bool) -> Result<(), Box<dyn Error>> {
+    let site = Site::new(app_config)?;
+    let manifest_path = Manifest::path_for(app_config)?;
+    let manifest = Manifest::load(&manifest_path).await?;
+    println!("{}", site.publish_index(&manifest, archive).await?);
+    site.update_integrity(&manifest_path).await;
+    Ok(())
+}
+
+async fn rebuild(app_config: &AppConfig) -> Result<(), Box<dyn Error>> {
+    let site = Site::new(app_config)?;
+    let manifest_path = Manifest::path_for(app_config)?;
+    let mut manifest = Manifest::load(&manifest_path).await?;
+
+    shutdown::listen();
+    let result = site.rebuild(&mut manifest).await;
+    // Keep what was rebuilt before a failure too.
+    site.save_manifest(&mut manifest, &manifest_path).await?;
+    let (changed, failed) = result?;
+    for published in &changed {
+        println!("Rebuilt {}", published.post.url);
+    }
+    println!("{}", site.publish_index(&manifest, false).await?);
+    site.update_integrity(&manifest_path).await;
+    println!(
+        "{} of {} post(s) changed",
+        changed.len(),
+        manifest.posts.iter().filter(|post| !post.raw).count()
+    );
+    if failed > 0 {
+        return Err(format!("{} post(s) could not be rebuilt", failed).into());
+    }
+    Ok(())
+}
+
+async fn redirects(app_config: &AppConfig) -> Result<(), Box<dyn Error>> {
+    let site = Site::new(app_config)?;
+    let manifest_path = Manifest::path_for(app_config)?;
+    let mut manifest = Manifest::load(&manifest_path).await?;
+    let result = site.sync_redirects(&mut manifest).await;
+    // Keep track of what was uploaded before a failure too.
+    site.save_manifest(&mut manifest, &manifest_path).await?;
+    site.update_integrity(&manifest_path).await;
+    let (uploaded, removed) = result?;
+    println!("{} redirect(s) uploaded, {} removed", uploaded, removed);
+    Ok(())
+}
+
+async fn sync(app_config: &AppConfig) -> Result<(), Box<dyn Error>> {
+    if !app_config.manifest.remote {
+        return Err(
+            "Set remote = true in [manifest] to keep a copy of the manifest in the bucket".into(),
+        );
+    }
+    let site = Site::new(app_config)?;
+    let manifest_path = Manifest::path_for(app_config)?;
+    let mut manifest = Manifest::load(&manifest_path).await?;
+    let taken = site.save_manifest(&mut manifest, &manifest_path).await?;
+    site.update_integrity(&manifest_path).await;
+    println!(
+        "{} post(s) taken from the bucket, {} published and {} in the trash",
+        taken,
+        manifest.posts.len(),
+        manifest.trash.len()
+    );
+    Ok(())
+}
+
+async fn flush(app_config: &AppConfig) -> Result<(), Box<dyn Error>> {
+    let dir = queue::dir(app_config)?;
+    if queue::pending(&dir).await? == 0 {
+        println!("Nothing queued");
+        return Ok(());
+    }
+    let bucket = publisher::from_config(app_config)?;
+    let sent = queue::flush(bucket.as_ref(), &dir).await?;
+    println!("Sent {} queued change(s)", sent);
+    // Queued publishes left the shared manifest alone; merge it now.
+    if app_config.manifest.remote {
+        let site = Site::with_publisher(app_config, bucket)?;
+        let manifest_path = Manifest::path_for(app_config)?;
+        let mut manifest = Manifest::load(&manifest_path).await?;
+        site.save_manifest(&mut manifest, &manifest_path).await?;
+    }
+    Ok(())
+}
+
+async fn verify(app_config: &AppConfig) -> Result<(), Box<dyn Error>> {
+    let bucket = publisher::from_config(app_config)?;
+    let manifest_path = Manifest::path_for(app_config)?;
+    let report = integrity::verify(bucket.as_ref(), &manifest_path).await?;
+    if !report.untracked.is_empty() {
+        eprintln!(
+            "{} object(s) in the bucket have no integrity record (uploaded before it was enabled?):",
+            report.untracked.len()
+        );
+        for path in &report.untracked {
+            eprintln!("  {}", path);
+        }
+    }
+    for problem in &report.problems {
+        eprintln!("{}", problem);
+    }
+    if !report.problems.is_empty() {
+        return Err(format!(
+            "{} problem(s) found checking {} object(s)",
+            report.problems.len(),
+            report.checked
+        )
+        .into());
+    }
+    println!("All {} object(s) match what was published", report.checked);
+    Ok(())
+}
+
+async fn views(app_config: &AppConfig, since: Bound) -> Result<(), Box<dyn Error>> {
+    if !app_config.views.enabled {
+        eprintln!("[views] isn't enabled, so posts have no pixel to count");
+    }
+    let manifest = Manifest::load(&Manifest::path_for(app_config)?).await?;
+    let http = http::client(&app_config.tls)?;
+    let hits = logs::fetch(app_config, &http, since.start()).await?;
+    let counts = views::count(&manifest.posts, &hits);
+    if counts.is_empty() {
+        eprintln!("No views found in {} log entries", hits.len());
+    }
+    for (post, views) in counts {
+        println!("{:>8}  {}  {}", views, post.title, post.url);
+    }
+    Ok(())
+}
+
+async fn traffic(
+    app_config: &AppConfig,
+    since: Bound,
+    html: Option<&Path>,
+) -> Result<(), Box<dyn Error>> {
+    let manifest = Manifest::load(&Manifest::path_for(app_config)?).await?;
+    let http = http::client(&app_config.tls)?;
+    let since = since.start();
+    let hits = logs::fetch(app_config, &http, since).await?;
+    // Deleted posts still get requests for a while.
+    let posts: Vec<Post> = manifest.posts.into_iter().chain(manifest.trash).collect();
+    let report = traffic::report(&posts, &hits);
+    print!("{}", traffic::text(&report));
+    if let Some(path) = html {
+        fs::write(path, traffic::html(&report, since, app_config)?).await?;
+        eprintln!("Wrote {}", path.display());
+    }
+    Ok(())
+}
+
+async fn show_history(
+    app_config: &AppConfig,
+    filter: &history::Filter,
+    limit: usize,
+) -> Result<(), Box<dyn Error>> {
+    let path = history::path_for(&Manifest::path_for(app_config)?);
+    let entries: Vec<history::Entry> = history::load(&path)
+        .await?
+        .into_iter()
+        .filter(|entry| filter.matches(entry))
+        .collect();
+    if entries.is_empty() {
+        eprintln!("Nothing in the history matches");
+    }
+    for entry in &entries[entries.len().saturating_sub(limit)..] {
+        println!("{}", history::format_entry(entry));
+    }
+    Ok(())
+}
+
+async fn export(
+    app_config: &AppConfig,
+    format: ExportFormat,
+    output: Option<&Path>,
+    password: bool,
+) -> Result<(), Box<dyn Error>> {
+    if password && format != ExportFormat::Html {
+        return Err("--password only works with --format html".into());
+    }
+    let manifest = Manifest::load(&Manifest::path_for(app_config)?).await?;
+    let password = if password {
+        Some(read_password()?)
+    } else {
+        None
+    };
+    let report = export::export(&manifest.posts, format, password.as_deref(), app_config)?;
+    match output {
+        Some(path) => {
+            fs::write(path, report).await?;
+            eprintln!(
+                "Exported {} posts to {}",
+                manifest.posts.len(),
+                path.display()
+            );
+        }
+        None => print!("{}", report),
+    }
+    Ok(())
+}
+
+/// Asks for a password without echoing it, or reads the first line of stdin
+/// when it isn't a terminal.
+fn read_password() -> Result<String, Box<dyn Error>> {
+    use ratatui::crossterm::{
+        event::{self, Event, KeyCode, KeyEventKind, KeyModifiers},
+        terminal,
+    };
+    use std::io::{IsTerminal, Write};
+
+    let password = if std::io::stdin().is_terminal() {
+        eprint!("Password: ");
+        std::io::stderr().flush()?;
+        terminal::enable_raw_mode()?;
+        let mut password = String::new();
+        let read = loop {
+            let key = match event::read() {
+                Ok(Event::Key(key)) if key.kind != KeyEventKind::Release => key,
+                Ok(_) => continue,
+                Err(e) => break Err(e),
+            };
+            match key.code {
+                KeyCode::Enter => break Ok(()),
+                KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                    break Err(std::io::ErrorKind::Interrupted.into())
+                }
+                KeyCode::Char(c) => password.push(c),
+                KeyCode::Backspace => {
+                    password.pop();
+                }
+                _ => {}
+            }
+        };
+        terminal::disable_raw_mode()?;
+        eprintln!();
+        read?;
+        password
+    } else {
+        let mut line = String::new();
+        std::io::stdin().read_line(&mut line)?;
+        line.trim_end_matches(['\r', '\n']).to_string()
+    };
+    if password.is_empty() {
+        return Err("The password can't be empty".into());
+    }
+    Ok(password)
+}
+
+async fn pull(
+    app_config: &AppConfig,
+    post: &str,
+    output: Option<&Path>,
+) -> Result<(), Box<dyn Error>> {
+    let site = Site::new(app_config)?;
+    let manifest_path = Manifest::path_for(app_config)?;
+    let manifest = Manifest::load(&manifest_path).await?;
+    let id = post_id(post);
+
+    let post = manifest
+        .posts
+        .iter()
+        .find(|post| post.id == id)
+        .ok_or_else(|| format!("No post with id {}", id))?;
+    let markdown = site.fetch_source(post).await?.ok_or_else(|| {
+        format!(
+            "{} has no published source; it was published without [sources] upload",
+            post.url
+        )
+    })?;
+    match output {
+        Some(path) => {
+            tokio::fs::write(path, markdown).await?;
+            eprintln!("Wrote {}", path.display());
+        }
+        None => print!("{}", markdown),
+    }
+    Ok(())
+}
+
+async fn undelete(app_config: &AppConfig, post: &str) -> Result<(), Box<dyn Error>> {
+    let site = Site::new(app_config)?;
+    let manifest_path = Manifest::path_for(app_config)?;
+    let mut manifest = Manifest::load(&manifest_path).await?;
+    let id = post_id(post);
+
+    let post = site
+        .undelete(id, &mut manifest)
+        .await?
+        .ok_or_else(|| format!("No deleted post with id {}", id))?;
+    site.finish_undelete(&post, &mut manifest, &manifest_path)
+        .await?;
+    println!("Restored {}", post.url);
     Ok(())
 }
@@ -0,0 +1,339 @@
+use crate::config::{AppConfig, Backend};
+use crate::frontmatter::Labels;
+use crate::setup::DRAFTS_PREFIX;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::{
+    collections::BTreeMap,
+    error::Error,
+    path::{Path, PathBuf},
+};
+use tokio::fs;
+
+/// A published post as remembered by the manifest.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Post {
+    /// Folder name under `p/`.
+    pub id: String,
+    /// Source markdown file, if the post came from one. For translated posts
+    /// this is the path without the language suffix, e.g. `notes/post.md` for
+    /// `notes/post.sv.md`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub source: Option<PathBuf>,
+    pub title: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    pub url: String,
+    pub published: DateTime<Utc>,
+    /// When the post was last published again in place.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub updated: Option<DateTime<Utc>>,
+    /// Languages published under `p/<id>/<lang>/`. Empty for single-language posts.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub languages: Vec<String>,
+    /// Link targets we've already sent webmentions to.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub webmentions: Vec<String>,
+    /// When `klistra reap` should delete the post, if it was published with `--ttl`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expires: Option<DateTime<Utc>>,
+    /// Author names from the byline, for feeds and listings.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub authors: Vec<String>,
+    /// Labels from the front matter, also applied as object tags.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub labels: Labels,
+    /// Whether the post is a `klistra raw` upload rather than a rendered page.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub raw: bool,
+    /// When the post was moved to the trash.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub deleted: Option<DateTime<Utc>>,
+    /// The team member (`[team] author`) who published the post, whose posts
+    /// live under `authors/<member>/`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub member: Option<String>,
+    /// When the post was last taken back out of the trash.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub restored: Option<DateTime<Utc>>,
+    /// [`content_hash`] of the source(s) last published, so unchanged files
+    /// can be skipped when publishing a directory.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub source_hash: Option<String>,
+    /// [`content_hash`] of the page last uploaded, so identical pages aren't
+    /// uploaded again.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub output_hash: Option<String>,
+    /// Bytes uploaded for the post the last time it was published.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub size: Option<u64>,
+    /// URL of the post's preview image, if one was generated.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub image: Option<String>,
+    /// The audio file from the front matter, listed in the feed.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub enclosure: Option<Enclosure>,
+    /// Links to the sections of a long post, listed under it in the index
+    /// and feed.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub sections: Vec<Section>,
+}
+
+impl Post {
+    /// Where the post's objects are in the bucket, e.g. `p/<id>`.
+    pub fn dir(&self) -> String {
+        post_dir(self.member.as_deref(), &self.id)
+    }
+
+    /// When anything last happened to the post: publishing, updating,
+    /// deleting or restoring it.
+    pub fn changed(&self) -> DateTime<Utc> {
+        [self.updated, self.deleted, self.restored]
+            .into_iter()
+            .flatten()
+            .fold(self.published, DateTime::max)
+    }
+}
+
+/// A file attached to a post in the feed, like a podcast episode.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Enclosure {
+    pub url: String,
+    /// Size in bytes.
+    pub length: u64,
+    pub content_type: String,
+}
+
+/// A heading in a post and the link straight to it.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Section {
+    pub title: String,
+    pub url: String,
+}
+
+/// The directory of post `id`: `p/<id>`, or `authors/<member>/p/<id>` for a
+/// team member's post.
+pub fn post_dir(member: Option<&str>, id: &str) -> String {
+    match member {
+        Some(member) => format!("authors/{}/p/{}", member, id),
+        None => format!("p/{}", id),
+    }
+}
+
+/// A draft uploaded with `klistra preview`, readable through its presigned
+/// URL until `klistra reap` deletes it.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Preview {
+    /// Random folder name under `drafts/`, so the objects can't be guessed.
+    pub id: String,
+    pub source: PathBuf,
+    pub title: String,
+    /// The presigned URL of the page.
+    pub url: String,
+    pub expires: DateTime<Utc>,
+}
+
+impl Preview {
+    /// Where the draft's objects are in the bucket, e.g. `drafts/<id>`.
+    pub fn dir(&self) -> String {
+        format!("{}{}", DRAFTS_PREFIX, self.id)
+    }
+}
+
+/// Hex SHA-256 of `parts` taken together.
+pub fn content_hash<'a>(parts: impl IntoIterator<Item = &'a [u8]>) -> String {
+    let mut hasher = Sha256::new();
+    for part in parts {
+        hasher.update(part);
+    }
+    hex::encode(hasher.finalize())
+}
+
+/// Where `[manifest] remote` keeps a copy of the manifest, relative to the
+/// prefix.
+pub const REMOTE_PATH: &str = "manifest.json";
+
+/// Local record of everything klistra has published, stored as JSON.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Manifest {
+    #[serde(default)]
+    pub posts: Vec<Post>,
+    /// Deleted posts whose objects are kept under `trash/` until purged.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub trash: Vec<Post>,
+    /// Redirects from `redirects.toml` that are in the bucket, old slug to target.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub redirects: BTreeMap<String, String>,
+    /// Ids of purged posts and when they were purged, so merging in an older
+    /// copy of the manifest doesn't bring them back.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub purged: BTreeMap<String, DateTime<Utc>>,
+    /// Drafts uploaded with `klistra preview` that haven't been reaped yet.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub previews: Vec<Preview>,
+}
+
+impl Manifest {
+    /// Default location: `<data dir>/klistra/manifest.json`.
+    pub fn default_path() -> Option<PathBuf> {
+        dirs::data_dir().map(|dir| dir.join("klistra").join("manifest.json"))
+    }
+
+    /// Where the manifest for `config`'s backend lives: the default path, or
+    /// `manifest-memory.json` next to it for the memory backend, so trying
+    /// things out never touches the real list of posts.
+    pub fn path_for(config: &AppConfig) -> Result<PathBuf, Box<dyn Error>> {
+        let path = Self::default_path().ok_or("Could not determine manifest path")?;
+        Ok(match config.upload.backend {
+            Backend::S3 | Backend::Delegated => path,
+            Backend::Memory => path.with_file_name("manifest-memory.json"),
+        })
+    }
+
+    /// Loads the manifest, or an empty one if the file doesn't exist yet.
+    pub async fn load(path: &Path) -> Result<Self, Box<dyn Error>> {
+        match fs::read_to_string(path).await {
+            Ok(content) => serde_json::from_str(&content)
+                .map_err(|e| format!("Invalid manifest {}: {}", path.display(), e).into()),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    pub async fn save(&self, path: &Path) -> Result<(), Box<dyn Error>> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).await?;
+        }
+        fs::write(path, serde_json::to_string_pretty(self)?).await?;
+        Ok(())
+    }
+
+    /// The copy `[manifest] remote` uploads: everything but the previews,
+    /// since `manifest.json` is as public as the posts and a preview's
+    /// presigned link is meant only for whoever it was sent to.
+    pub fn remote_copy(&self) -> Result<Vec<u8>, Box<dyn Error>> {
+        let mut value = serde_json::to_value(self)?;
+        if let Some(object) = value.as_object_mut() {
+            object.remove("previews");
+        }
+        Ok(serde_json::to_vec_pretty(&value)?)
+    }
+
+    /// Folds in `other`, another machine's copy of the manifest: posts only
+    /// it knows are added, and of a post both know the copy changed last
+    /// wins, moving it in or out of the trash. Posts purged on either side
+    /// stay purged. Returns how many posts were taken from `other`.
+    pub fn merge(&mut self, other: Manifest) -> usize {
+        for (id, purged) in other.purged {
+            let time = self.purged.entry(id).or_insert(purged);
+            *time = (*time).max(purged);
+        }
+        for (slug, target) in other.redirects {
+            self.redirects.entry(slug).or_insert(target);
+        }
+        // Copies uploaded before previews were left out can still have some,
+        // which are reaped from here then. An expired preview missing here
+        // was reaped here already.
+        let now = Utc::now();
+        for preview in other.previews {
+            if preview.expires > now && self.previews.iter().all(|ours| ours.id != preview.id) {
+                self.previews.push(preview);
+            }
+        }
+
+        let mut taken = 0;
+        for mut theirs in other.posts.into_iter().chain(other.trash) {
+            let ours = self
+                .posts
+                .iter()
+                .chain(&self.trash)
+                .find(|post| post.id == theirs.id);
+            match ours {
+                Some(ours) if ours.changed() >= theirs.changed() => {
+                    let webmentions = theirs.webmentions;
+                    if let Some(ours) = self.post_or_trash_mut(&theirs.id) {
+                        for target in webmentions {
+                            if !ours.webmentions.contains(&target) {
+                                ours.webmentions.push(target);
+                            }
+                        }
+                    }
+                    continue;
+                }
+                Some(ours) => {
+                    for target in &ours.webmentions {
+                        if !theirs.webmentions.contains(target) {
+                            theirs.webmentions.push(target.clone());
+                        }
+                    }
+                    self.posts.retain(|post| post.id != theirs.id);
+                    self.trash.retain(|post| post.id != theirs.id);
+                }
+                None => {}
+            }
+            if self.is_purged(&theirs) {
+                continue;
+            }
+            if theirs.deleted.is_some() {
+                self.trash.push(theirs);
+            } else {
+                self.posts.push(theirs);
+            }
+            taken += 1;
+        }
+
+        let purged = &self.purged;
+        let kept = |post: &Post| {
+            purged
+                .get(&post.id)
+                .is_none_or(|time| *time < post.changed())
+        };
+        self.posts.retain(kept);
+        self.trash.retain(kept);
+        taken
+    }
+
+    /// Whether `post` was purged after it last changed.
+    fn is_purged(&self, post: &Post) -> bool {
+        self.purged
+            .get(&post.id)
+            .is_some_and(|time| *time >= post.changed())
+    }
+
+    fn post_or_trash_mut(&mut self, id: &str) -> Option<&mut Post> {
+        self.posts
+            .iter_mut()
+            .chain(&mut self.trash)
+            .find(|post| post.id == id)
+    }
+
+    /// Posts whose expiry time has passed.
+    pub fn expired(&self, now: DateTime<Utc>) -> Vec<Post> {
+        self.posts
+            .iter()
+            .filter(|post| post.expires.is_some_and(|expires| expires <= now))
+            .cloned()
+            .collect()
+    }
+
+    /// Previews whose presigned URLs have run out.
+    pub fn expired_previews(&self, now: DateTime<Utc>) -> Vec<Preview> {
+        self.previews
+            .iter()
+            .filter(|preview| preview.expires <= now)
+            .cloned()
+            .collect()
+    }
+
+    pub fn post_mut(&mut self, id: &str) -> Option<&mut Post> {
+        self.posts.iter_mut().find(|post| post.id == id)
+    }
+
+    /// The translated post previously published from `source`, if any.
+    pub fn translated_post_mut(&mut self, source: &Path) -> Option<&mut Post> {
+        self.posts
+            .iter_mut()
+            .find(|post| post.source.as_deref() == Some(source) && !post.languages.is_empty())
+    }
+}
@@ -0,0 +1,419 @@
+use crate::frontmatter::FrontMatter;
+use crate::mime;
+use crate::raw;
+use crate::render::{escape_html, markdown_options, parse_attributes, unescape_html};
+use pulldown_cmark::{Event, Parser as MarkdownParser, Tag, TagEnd};
+use std::{
+    borrow::Cow,
+    collections::BTreeMap,
+    error::Error,
+    path::{Component, Path, PathBuf},
+    process::{Command, Stdio},
+    sync::OnceLock,
+};
+
+/// Extensions of the videos embedded with a player instead of an image tag.
+const VIDEO_EXTENSIONS: [&str; 2] = ["mp4", "webm"];
+
+/// Longest side of a video poster, in pixels.
+const POSTER_SIZE: u32 = 1280;
+
+/// A local file a post links to, uploaded next to its page.
+pub struct Media {
+    /// Where it goes, relative to the page; also how the page refers to it.
+    pub path: String,
+    /// The file on disk.
+    pub source: PathBuf,
+    /// Where the poster frame of a video goes, relative to the page.
+    pub poster: Option<String>,
+    /// Whether browsers should download the file rather than show it.
+    pub download: bool,
+}
+
+/// Replaces images like `![Demo](demo.mp4)` that point at a local video
+/// with a `<video>` player, collecting the videos into `media`. Paths are
+/// relative to `base_dir`, the directory of the post, and are kept as
+/// written, so the page finds the video whether it's published or opened
+/// next to the source. References that leave the directory or don't exist
+/// are left alone.
+pub fn embed_videos<'a>(
+    markdown: &'a str,
+    base_dir: Option<&Path>,
+    media: &mut Vec<Media>,
+) -> Cow<'a, str> {
+    let Some(base_dir) = base_dir else {
+        return Cow::Borrowed(markdown);
+    };
+    let mentions_video = VIDEO_EXTENSIONS.iter().any(|ext| {
+        markdown
+            .as_bytes()
+            .windows(ext.len() + 1)
+            .any(|window| window[0] == b'.' && window[1..].eq_ignore_ascii_case(ext.as_bytes()))
+    });
+    if !mentions_video {
+        return Cow::Borrowed(markdown);
+    }
+
+    let mut replacements = Vec::new();
+    let mut events = MarkdownParser::new_ext(markdown, markdown_options()).into_offset_iter();
+    while let Some((event, range)) = events.next() {
+        let Event::Start(Tag::Image {
+            dest_url, title, ..
+        }) = event
+        else {
+            continue;
+        };
+        let mut alt = String::new();
+        for (event, _) in events.by_ref() {
+            match event {
+                Event::End(TagEnd::Image) => break,
+                Event::Text(text) | Event::Code(text) | Event::InlineMath(text) => {
+                    alt.push_str(&text)
+                }
+                _ => {}
+            }
+        }
+        if !is_video(&dest_url) {
+            continue;
+        }
+        let Some((path, source)) = local_file(&dest_url, base_dir) else {
+            continue;
+        };
+        let poster = has_ffmpeg().then(|| poster_path(&path));
+        replacements.push((range, video_html(&path, poster.as_deref(), &alt, &title)));
+        media.push(Media {
+            path,
+            source,
+            poster,
+            download: false,
+        });
+    }
+    if replacements.is_empty() {
+        return Cow::Borrowed(markdown);
+    }
+
+    let mut embedded = String::with_capacity(markdown.len());
+    let mut end = 0;
+    for (range, html) in replacements {
+        embedded.push_str(&markdown[end..range.start]);
+        embedded.push_str(&html);
+        end = range.end;
+    }
+    embedded.push_str(&markdown[end..]);
+    Cow::Owned(embedded)
+}
+
+/// The first bytes of the attachments and audio a post names, by path, so
+/// rendering can type them without reading files.
+pub type FileStarts = BTreeMap<PathBuf, Vec<u8>>;
+
+/// Reads the start of every file `markdown` attaches or names as its
+/// `audio`, relative to `base_dir`. Files that can't be read are left out;
+/// rendering reports them.
+pub async fn read_starts(markdown: &str, base_dir: Option<&Path>) -> FileStarts {
+    let base_dir = base_dir.unwrap_or(Path::new("."));
+    let audio = FrontMatter::parse(markdown)
+        .ok()
+        .and_then(|(front_matter, _)| front_matter.audio);
+    let references = markdown
+        .lines()
+        .filter_map(attach_shortcode)
+        .chain(audio.as_deref());
+    let mut starts = FileStarts::new();
+    for reference in references {
+        if let Some((_, source)) = local_file(reference, base_dir) {
+            if let Ok(start) = mime::read_start(&source).await {
+                starts.insert(source, start);
+            }
+        }
+    }
+    starts
+}
+
+/// The type `source` is uploaded with, from its start in `starts` if it was read.
+fn content_type(
+    source: &Path,
+    content_types: &BTreeMap<String, String>,
+    starts: &FileStarts,
+) -> String {
+    let start = starts.get(source).map_or(&[][..], Vec::as_slice);
+    mime::content_type(source, start, content_types)
+}
+
+/// The audio file a post's front matter names.
+pub struct Audio {
+    /// Where it goes, relative to the page.
+    pub path: String,
+    /// Size in bytes.
+    pub length: u64,
+    pub content_type: String,
+}
+
+/// Looks up the front matter's `audio` file relative to `base_dir` and adds
+/// it to `media`, typed like uploads with `content_types` and its start in
+/// `starts`. Unlike videos in the text, a missing file is an error.
+pub fn audio(
+    reference: &str,
+    base_dir: Option<&Path>,
+    content_types: &BTreeMap<String, String>,
+    starts: &FileStarts,
+    media: &mut Vec<Media>,
+) -> Result<Audio, Box<dyn Error>> {
+    let base_dir = base_dir.unwrap_or(Path::new("."));
+    let (path, source) = local_file(reference, base_dir).ok_or_else(|| {
+        format!(
+            "Audio file '{}' not found next to the post (it must be inside {})",
+            reference,
+            base_dir.display()
+        )
+    })?;
+    let audio = Audio {
+        length: std::fs::metadata(&source)?.len(),
+        content_type: content_type(&source, content_types, starts),
+        path: path.clone(),
+    };
+    media.push(Media {
+        path,
+        source,
+        poster: None,
+        download: false,
+    });
+    Ok(audio)
+}
+
+/// A player for the post's audio, with a download link.
+pub fn audio_html(audio: &Audio) -> String {
+    let path = escape_html(&audio.path);
+    format!(
+        "<figure class=\"audio\">\n<audio controls preload=\"metadata\" src=\"{path}\"><a href=\"{path}\">{path}</a></audio>\n<figcaption><a href=\"{path}\" download>Download</a> ({type}, {size})</figcaption>\n</figure>\n",
+        type = escape_html(&audio.content_type),
+        size = raw::format_size(audio.length),
+    )
+}
+
+/// Replaces lines like `{{< attach report.pdf >}}` with a download card
+/// for the file, collecting the files into `media`. Paths are relative to
+/// `base_dir`, the directory of the post, and must stay inside it. The card
+/// shows the type the file is uploaded with, `content_types` included.
+pub fn embed_attachments<'a>(
+    markdown: &'a str,
+    base_dir: Option<&Path>,
+    content_types: &BTreeMap<String, String>,
+    starts: &FileStarts,
+    media: &mut Vec<Media>,
+) -> Result<Cow<'a, str>, Box<dyn Error>> {
+    if !markdown.contains("{{<") {
+        return Ok(Cow::Borrowed(markdown));
+    }
+
+    let base_dir = base_dir.unwrap_or(Path::new("."));
+    let mut embedded = String::with_capacity(markdown.len());
+    let mut found = false;
+    for line in markdown.lines() {
+        let Some(reference) = attach_shortcode(line) else {
+            embedded.push_str(line);
+            embedded.push('\n');
+            continue;
+        };
+        let (path, source) = local_file(reference, base_dir).ok_or_else(|| {
+            format!(
+                "Attachment '{}' not found next to the post (it must be inside {})",
+                reference,
+                base_dir.display()
+            )
+        })?;
+        let size = std::fs::metadata(&source)?.len();
+        let content_type = content_type(&source, content_types, starts);
+        // Blank lines keep the HTML block separate from surrounding markdown.
+        embedded.push('\n');
+        embedded.push_str(&attachment_html(&path, size, &content_type));
+        embedded.push('\n');
+        media.push(Media {
+            path,
+            source,
+            poster: None,
+            download: true,
+        });
+        found = true;
+    }
+    Ok(if found {
+        Cow::Owned(embedded)
+    } else {
+        Cow::Borrowed(markdown)
+    })
+}
+
+/// The file named by an `{{< attach file >}}` line, quoted or not.
+fn attach_shortcode(line: &str) -> Option<&str> {
+    let inner = line
+        .trim()
+        .strip_prefix("{{<")?
+        .strip_suffix(">}}")?
+        .trim()
+        .strip_prefix("attach")?;
+    if !inner.starts_with(char::is_whitespace) {
+        return None;
+    }
+    let inner = inner.trim();
+    let file = inner
+        .strip_prefix('"')
+        .and_then(|quoted| quoted.strip_suffix('"'))
+        .unwrap_or(inner);
+    (!file.is_empty()).then_some(file)
+}
+
+/// A card linking to the file at `path`, with a badge showing its type.
+fn attachment_html(path: &str, size: u64, content_type: &str) -> String {
+    let name = path.rsplit('/').next().unwrap_or(path);
+    let kind = name
+        .rsplit_once('.')
+        .map(|(_, ext)| ext.to_ascii_uppercase())
+        .filter(|ext| ext.len() <= 4)
+        .unwrap_or_else(|| "FILE".to_string());
+    format!(
+        "<div class=\"attachment\">\n<span class=\"attachment-type\" title=\"{content_type}\" aria-hidden=\"true\">{kind}</span>\n<a href=\"{path}\" download>{name}</a>\n<span class=\"attachment-size\">{size}</span>\n</div>",
+        content_type = escape_html(content_type),
+        kind = escape_html(&kind),
+        path = escape_html(path),
+        name = escape_html(name),
+        size = raw::format_size(size),
+    )
+}
+
+/// A player for the video at `path`, falling back to a link to it.
+fn video_html(path: &str, poster: Option<&str>, alt: &str, title: &str) -> String {
+    let path = escape_html(path);
+    let poster = poster
+        .map(|poster| format!(" poster=\"{}\"", escape_html(poster)))
+        .unwrap_or_default();
+    let title = match title {
+        "" => String::new(),
+        title => format!(" title=\"{}\"", escape_html(title)),
+    };
+    let label = match alt {
+        "" => String::new(),
+        alt => format!(" aria-label=\"{}\"", escape_html(alt)),
+    };
+    let link_text = match alt {
+        "" => path.clone(),
+        alt => escape_html(alt),
+    };
+    format!(
+        "<video controls preload=\"metadata\" src=\"{path}\"{poster}{title}{label}><a href=\"{path}\">{link_text}</a></video>"
+    )
+}
+
+fn is_video(reference: &str) -> bool {
+    reference.rsplit_once('.').is_some_and(|(_, ext)| {
+        VIDEO_EXTENSIONS
+            .iter()
+            .any(|video| ext.eq_ignore_ascii_case(video))
+    })
+}
+
+/// Collects the local files an HTML post loads or links to (`src`, `href`
+/// and `poster`) into `media`, so they go up next to it at the same paths.
+pub fn html_files(html: &str, base_dir: Option<&Path>, media: &mut Vec<Media>) {
+    let base_dir = base_dir.unwrap_or(Path::new("."));
+    for tag in html.split('<').skip(1) {
+        let tag = tag.split('>').next().unwrap_or_default();
+        for (name, value) in parse_attributes(tag) {
+            if !matches!(name.as_str(), "src" | "href" | "poster") {
+                continue;
+            }
+            let Some((path, source)) = local_file(&unescape_html(&value), base_dir) else {
+                continue;
+            };
+            if !media.iter().any(|item| item.path == path) {
+                media.push(Media {
+                    path,
+                    source,
+                    poster: None,
+                    download: false,
+                });
+            }
+        }
+    }
+}
+
+/// The file a relative reference in a post points at, if it stays inside
+/// `base_dir` and exists, with the reference tidied up (`./clip.mp4` is
+/// `clip.mp4`). URLs, absolute paths and references with a query or
+/// fragment aren't local files.
+fn local_file(reference: &str, base_dir: &Path) -> Option<(String, PathBuf)> {
+    if reference.contains([':', '?', '#', '\\']) {
+        return None;
+    }
+    let mut parts = Vec::new();
+    for component in Path::new(reference).components() {
+        match component {
+            Component::Normal(part) => parts.push(part.to_str()?),
+            Component::CurDir => {}
+            _ => return None,
+        }
+    }
+    let path = parts.join("/");
+    let source = base_dir.join(&path);
+    source.is_file().then_some((path, source))
+}
+
+/// `clip.poster.jpg` for `clip.mp4`, in the same directory.
+fn poster_path(path: &str) -> String {
+    let stem = path.rsplit_once('.').map_or(path, |(stem, _)| stem);
+    format!("{}.poster.jpg", stem)
+}
+
+/// Whether `ffmpeg` is on the PATH. Checked once.
+pub fn has_ffmpeg() -> bool {
+    static FOUND: OnceLock<bool> = OnceLock::new();
+    *FOUND.get_or_init(|| {
+        Command::new("ffmpeg")
+            .arg("-version")
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .status()
+            .is_ok_and(|status| status.success())
+    })
+}
+
+/// A JPEG of a frame from early in the video at `path`, for its poster.
+pub fn poster_frame(path: &Path) -> Result<Vec<u8>, Box<dyn Error>> {
+    // A second in skips the black or title frame many videos open with;
+    // shorter videos get their first frame.
+    ffmpeg_frame(path, Some(1), POSTER_SIZE).or_else(|_| ffmpeg_frame(path, None, POSTER_SIZE))
+}
+
+/// A JPEG of the image, or the frame `seek` seconds into the video, at
+/// `path`, scaled by `ffmpeg` to fit in `max_size` pixels square.
+pub fn ffmpeg_frame(
+    path: &Path,
+    seek: Option<u32>,
+    max_size: u32,
+) -> Result<Vec<u8>, Box<dyn Error>> {
+    let mut command = Command::new("ffmpeg");
+    command.args(["-v", "error"]);
+    if let Some(seek) = seek {
+        command.args(["-ss", &seek.to_string()]);
+    }
+    // Only ever scaled down.
+    let scale = format!(
+        "scale='min(iw,{size})':'min(ih,{size})':force_original_aspect_ratio=decrease",
+        size = max_size
+    );
+    let output = command
+        .arg("-i")
+        .arg(path)
+        .args(["-vf", &scale, "-frames:v", "1", "-q:v", "4"])
+        .args(["-f", "image2pipe", "-c:v", "mjpeg", "-"])
+        .stdin(Stdio::null())
+        .output()
+        .map_err(|e| format!("could not run ffmpeg: {}", e))?;
+    if !output.status.success() || output.stdout.is_empty() {
+        return Err(format!(
+            "ffmpeg failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        )
+        .into());
+    }
+    Ok(output.stdout)
+}
@@ -0,0 +1,284 @@
+use crate::frontmatter::Labels;
+use crate::publisher::{Precondition, Publisher};
+use async_trait::async_trait;
+use std::{
+    error::Error,
+    fmt::Write,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+
+/// Upper bounds in seconds of the latency histograms' buckets.
+const BUCKETS: [f64; 11] = [0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0, 60.0, 300.0];
+
+/// What `klistra daemon` serves at `/metrics`, in Prometheus' text format.
+#[derive(Default)]
+pub struct Metrics {
+    publishes: Outcomes,
+    deletes: Outcomes,
+    uploads: Outcomes,
+    upload_bytes: AtomicU64,
+    publish_seconds: Histogram,
+    upload_seconds: Histogram,
+}
+
+/// A counter split by `result="ok"` and `result="error"`.
+#[derive(Default)]
+struct Outcomes {
+    ok: AtomicU64,
+    error: AtomicU64,
+}
+
+impl Outcomes {
+    fn count(&self, ok: bool) {
+        let counter = if ok { &self.ok } else { &self.error };
+        counter.fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn render(&self, out: &mut String, name: &str, help: &str) {
+        let _ = writeln!(out, "# HELP {} {}", name, help);
+        let _ = writeln!(out, "# TYPE {} counter", name);
+        for (result, counter) in [("ok", &self.ok), ("error", &self.error)] {
+            let _ = writeln!(
+                out,
+                "{}{{result=\"{}\"}} {}",
+                name,
+                result,
+                counter.load(Ordering::Relaxed)
+            );
+        }
+    }
+}
+
+#[derive(Default)]
+struct Histogram {
+    buckets: [AtomicU64; BUCKETS.len()],
+    count: AtomicU64,
+    sum_micros: AtomicU64,
+}
+
+impl Histogram {
+    fn observe(&self, elapsed: Duration) {
+        let seconds = elapsed.as_secs_f64();
+        for (bound, bucket) in BUCKETS.iter().zip(&self.buckets) {
+            if seconds <= *bound {
+                bucket.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+        self.count.fetch_add(1, Ordering::Relaxed);
+        self.sum_micros
+            .fetch_add(elapsed.as_micros() as u64, Ordering::Relaxed);
+    }
+
+    fn render(&self, out: &mut String, name: &str, help: &str) {
+        let _ = writeln!(out, "# HELP {} {}", name, help);
+        let _ = writeln!(out, "# TYPE {} histogram", name);
+        for (bound, bucket) in BUCKETS.iter().zip(&self.buckets) {
+            let _ = writeln!(
+                out,
+                "{}_bucket{{le=\"{}\"}} {}",
+                name,
+                bound,
+                bucket.load(Ordering::Relaxed)
+            );
+        }
+        let count = self.count.load(Ordering::Relaxed);
+        let _ = writeln!(out, "{}_bucket{{le=\"+Inf\"}} {}", name, count);
+        let sum = self.sum_micros.load(Ordering::Relaxed) as f64 / 1e6;
+        let _ = writeln!(out, "{}_sum {}", name, sum);
+        let _ = writeln!(out, "{}_count {}", name, count);
+    }
+}
+
+impl Metrics {
+    /// Counts a publish through the API that started at `start`.
+    pub fn publish_done(&self, start: Instant, ok: bool) {
+        self.publishes.count(ok);
+        self.publish_seconds.observe(start.elapsed());
+    }
+
+    pub fn delete_done(&self, ok: bool) {
+        self.deletes.count(ok);
+    }
+
+    fn upload_done(&self, start: Instant, bytes: usize, ok: bool) {
+        self.uploads.count(ok);
+        if ok {
+            self.upload_bytes.fetch_add(bytes as u64, Ordering::Relaxed);
+        }
+        self.upload_seconds.observe(start.elapsed());
+    }
+
+    /// Everything counted so far, in the Prometheus text exposition format.
+    pub fn render(&self) -> String {
+        let mut out = String::new();
+        self.publishes
+            .render(&mut out, "klistra_publishes_total", "Posts published.");
+        self.deletes
+            .render(&mut out, "klistra_deletes_total", "Posts deleted.");
+        self.uploads.render(
+            &mut out,
+            "klistra_uploads_total",
+            "Objects and multipart parts uploaded to the bucket.",
+        );
+        let _ = writeln!(
+            out,
+            "# HELP klistra_upload_bytes_total Bytes uploaded to the bucket.\n# TYPE klistra_upload_bytes_total counter\nklistra_upload_bytes_total {}",
+            self.upload_bytes.load(Ordering::Relaxed)
+        );
+        self.publish_seconds.render(
+            &mut out,
+            "klistra_publish_duration_seconds",
+            "How long publishing a post took, uploads and announcements included.",
+        );
+        self.upload_seconds.render(
+            &mut out,
+            "klistra_upload_duration_seconds",
+            "How long each upload to the bucket took.",
+        );
+        out
+    }
+}
+
+/// A [`Publisher`] that counts what's uploaded through it in [`Metrics`].
+pub struct Metered {
+    inner: Box<dyn Publisher>,
+    metrics: Arc<Metrics>,
+}
+
+impl Metered {
+    pub fn new(inner: Box<dyn Publisher>, metrics: Arc<Metrics>) -> Self {
+        Self { inner, metrics }
+    }
+}
+
+#[async_trait]
+impl Publisher for Metered {
+    fn host(&self) -> &str {
+        self.inner.host()
+    }
+
+    fn public_url(&self, path: &str) -> String {
+        self.inner.public_url(path)
+    }
+
+    async fn put(
+        &self,
+        path: &str,
+        body: Vec<u8>,
+        content_type: &str,
+        labels: &Labels,
+    ) -> Result<(), Box<dyn Error>> {
+        let (start, bytes) = (Instant::now(), body.len());
+        let result = self.inner.put(path, body, content_type, labels).await;
+        self.metrics.upload_done(start, bytes, result.is_ok());
+        result
+    }
+
+    async fn put_download(
+        &self,
+        path: &str,
+        body: Vec<u8>,
+        content_type: &str,
+        file_name: &str,
+        labels: &Labels,
+    ) -> Result<(), Box<dyn Error>> {
+        let (start, bytes) = (Instant::now(), body.len());
+        let result = self
+            .inner
+            .put_download(path, body, content_type, file_name, labels)
+            .await;
+        self.metrics.upload_done(start, bytes, result.is_ok());
+        result
+    }
+
+    async fn put_redirect(&self, path: &str, location: &str) -> Result<(), Box<dyn Error>> {
+        self.inner.put_redirect(path, location).await
+    }
+
+    async fn delete(&self, path: &str) -> Result<(), Box<dyn Error>> {
+        self.inner.delete(path).await
+    }
+
+    async fn list(&self, path: &str) -> Result<Vec<String>, Box<dyn Error>> {
+        self.inner.list(path).await
+    }
+
+    async fn fetch(&self, path: &str) -> Result<Option<Vec<u8>>, Box<dyn Error>> {
+        self.inner.fetch(path).await
+    }
+
+    async fn fetch_tagged(&self, path: &str) -> Result<Option<(Vec<u8>, String)>, Box<dyn Error>> {
+        self.inner.fetch_tagged(path).await
+    }
+
+    async fn put_if(
+        &self,
+        path: &str,
+        body: Vec<u8>,
+        content_type: &str,
+        precondition: &Precondition,
+    ) -> Result<bool, Box<dyn Error>> {
+        let (start, bytes) = (Instant::now(), body.len());
+        let result = self
+            .inner
+            .put_if(path, body, content_type, precondition)
+            .await;
+        // A failed precondition uploads nothing but isn't an error either.
+        let bytes = if matches!(result, Ok(true)) { bytes } else { 0 };
+        self.metrics.upload_done(start, bytes, result.is_ok());
+        result
+    }
+
+    async fn presign(&self, path: &str, expires: Duration) -> Result<String, Box<dyn Error>> {
+        self.inner.presign(path, expires).await
+    }
+
+    async fn delete_prefix(&self, path: &str) -> Result<usize, Box<dyn Error>> {
+        self.inner.delete_prefix(path).await
+    }
+
+    async fn move_prefix(&self, from: &str, to: &str) -> Result<usize, Box<dyn Error>> {
+        self.inner.move_prefix(from, to).await
+    }
+
+    async fn create_multipart(
+        &self,
+        path: &str,
+        content_type: &str,
+        labels: &Labels,
+    ) -> Result<String, Box<dyn Error>> {
+        self.inner
+            .create_multipart(path, content_type, labels)
+            .await
+    }
+
+    async fn upload_part(
+        &self,
+        path: &str,
+        upload_id: &str,
+        number: i32,
+        body: Vec<u8>,
+    ) -> Result<String, Box<dyn Error>> {
+        let (start, bytes) = (Instant::now(), body.len());
+        let result = self.inner.upload_part(path, upload_id, number, body).await;
+        self.metrics.upload_done(start, bytes, result.is_ok());
+        result
+    }
+
+    async fn abort_multipart(&self, path: &str, upload_id: &str) -> Result<(), Box<dyn Error>> {
+        self.inner.abort_multipart(path, upload_id).await
+    }
+
+    async fn complete_multipart(
+        &self,
+        path: &str,
+        upload_id: &str,
+        parts: &[(i32, String)],
+    ) -> Result<(), Box<dyn Error>> {
+        self.inner.complete_multipart(path, upload_id, parts).await
+    }
+}
@@ -0,0 +1,323 @@
+use crate::config::{AppConfig, MicropubConfig};
+use crate::server::{bearer_token, leak_config, token_matches, SharedSite};
+use crate::shutdown;
+use crate::site::Announce;
+use axum::{
+    body::Bytes,
+    extract::{Query, State},
+    http::{header, HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+    routing::get,
+    Json, Router,
+};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::{collections::HashMap, error::Error, sync::Arc};
+use tracing::{error, info};
+
+struct ServerState {
+    shared: SharedSite,
+    config: &'static MicropubConfig,
+}
+
+/// Runs a minimal Micropub endpoint at `/micropub` that publishes `h-entry`
+/// posts through the normal pipeline.
+pub async fn serve(app_config: AppConfig, listen: &str) -> Result<(), Box<dyn Error>> {
+    shutdown::listen();
+    let app_config = leak_config(app_config);
+    let config = app_config
+        .micropub
+        .as_ref()
+        .ok_or("micropub-serve requires a [micropub] section in the config")?;
+    if config.token.is_none() && config.token_endpoint.is_none() {
+        return Err("[micropub] needs either a token or a token_endpoint".into());
+    }
+
+    let state = Arc::new(ServerState {
+        shared: SharedSite::new(app_config).await?,
+        config,
+    });
+
+    let app = Router::new()
+        .route("/micropub", get(query).post(create))
+        .with_state(state);
+    let listener = tokio::net::TcpListener::bind(listen).await?;
+    info!("Micropub endpoint listening on http://{}/micropub", listen);
+    axum::serve(listener, app)
+        .with_graceful_shutdown(shutdown::wait())
+        .await?;
+    info!("Micropub endpoint stopped");
+    Ok(())
+}
+
+fn error(status: StatusCode, error: &str, description: &str) -> Response {
+    (
+        status,
+        Json(json!({ "error": error, "error_description": description })),
+    )
+        .into_response()
+}
+
+async fn query(
+    State(state): State<Arc<ServerState>>,
+    headers: HeaderMap,
+    Query(params): Query<HashMap<String, String>>,
+) -> Response {
+    if let Err(response) = authorize(
+        state.config,
+        state.shared.site.http(),
+        &headers,
+        params.get("access_token"),
+    )
+    .await
+    {
+        return response;
+    }
+    match params.get("q").map(String::as_str) {
+        Some("config") | Some("syndicate-to") => {
+            Json(json!({ "syndicate-to": [] })).into_response()
+        }
+        _ => error(
+            StatusCode::BAD_REQUEST,
+            "invalid_request",
+            "Unsupported query",
+        ),
+    }
+}
+
+/// A new post as sent by the client, in either form or JSON encoding.
+#[derive(Debug, Default)]
+struct Entry {
+    name: Option<String>,
+    content: Option<String>,
+    access_token: Option<String>,
+}
+
+impl Entry {
+    fn from_form(body: &[u8]) -> Result<Self, String> {
+        let pairs: Vec<(String, String)> =
+            serde_urlencoded::from_bytes(body).map_err(|e| e.to_string())?;
+        let mut entry = Entry::default();
+        for (key, value) in pairs {
+            match key.as_str() {
+                "h" if value != "entry" => return Err(format!("Unsupported type h-{}", value)),
+                "action" => return Err(format!("Unsupported action '{}'", value)),
+                "name" => entry.name = Some(value),
+                "content" => entry.content = Some(value),
+                "access_token" => entry.access_token = Some(value),
+                _ => {}
+            }
+        }
+        Ok(entry)
+    }
+
+    fn from_json(body: &[u8]) -> Result<Self, String> {
+        #[derive(Deserialize)]
+        struct JsonEntry {
+            #[serde(rename = "type", default)]
+            kind: Vec<String>,
+            action: Option<String>,
+            #[serde(default)]
+            properties: HashMap<String, Vec<Value>>,
+        }
+
+        let json: JsonEntry = serde_json::from_slice(body).map_err(|e| e.to_string())?;
+        if let Some(action) = json.action {
+            return Err(format!("Unsupported action '{}'", action));
+        }
+        if json.kind.iter().any(|kind| kind != "h-entry") {
+            return Err(format!("Unsupported type {}", json.kind.join(", ")));
+        }
+
+        let first = |name: &str| json.properties.get(name).and_then(|values| values.first());
+        let content = first("content").and_then(|value| match value {
+            Value::String(text) => Some(text.clone()),
+            // Markdown passes HTML through untouched.
+            Value::Object(object) => object
+                .get("html")
+                .or_else(|| object.get("text"))
+                .and_then(Value::as_str)
+                .map(str::to_string),
+            _ => None,
+        });
+        Ok(Entry {
+            name: first("name").and_then(Value::as_str).map(str::to_string),
+            content,
+            access_token: None,
+        })
+    }
+
+    /// The entry as markdown; a name becomes the post's `# Heading`.
+    fn to_markdown(&self) -> String {
+        let content = self.content.as_deref().unwrap_or("");
+        match &self.name {
+            Some(name) => format!("# {}\n\n{}\n", name, content),
+            None => format!("{}\n", content),
+        }
+    }
+}
+
+async fn create(
+    State(state): State<Arc<ServerState>>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Response {
+    let content_type = headers
+        .get(header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or("");
+    let entry = if content_type.starts_with("application/json") {
+        Entry::from_json(&body)
+    } else if content_type.starts_with("application/x-www-form-urlencoded") {
+        Entry::from_form(&body)
+    } else {
+        return error(
+            StatusCode::UNSUPPORTED_MEDIA_TYPE,
+            "invalid_request",
+            "Only form-encoded and JSON requests are supported",
+        );
+    };
+    let entry = match entry {
+        Ok(entry) => entry,
+        Err(e) => return error(StatusCode::BAD_REQUEST, "invalid_request", &e),
+    };
+
+    if let Err(response) = authorize(
+        state.config,
+        state.shared.site.http(),
+        &headers,
+        entry.access_token.as_ref(),
+    )
+    .await
+    {
+        return response;
+    }
+    if entry.content.is_none() && entry.name.is_none() {
+        return error(
+            StatusCode::BAD_REQUEST,
+            "invalid_request",
+            "Missing content",
+        );
+    }
+
+    let shared = &state.shared;
+    let mut manifest = shared.manifest.lock().await;
+    let result = async {
+        let published = shared
+            .site
+            .publish_markdown(&entry.to_markdown(), "Note", None, &mut manifest)
+            .await?;
+        shared
+            .site
+            .finish_publish(
+                &published,
+                &mut manifest,
+                &shared.manifest_path,
+                Announce::Yes,
+            )
+            .await?;
+        Ok::<_, Box<dyn Error>>(published.post.url)
+    }
+    .await;
+
+    match result {
+        Ok(url) => {
+            info!(url = %url, "Published via Micropub");
+            (StatusCode::CREATED, [(header::LOCATION, url)]).into_response()
+        }
+        Err(e) => {
+            error!(error = %e, "Micropub publish failed");
+            error(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "server_error",
+                &e.to_string(),
+            )
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct TokenInfo {
+    me: String,
+    #[serde(default)]
+    scope: String,
+}
+
+/// Checks the bearer token (header or `access_token` parameter) against the
+/// static token, or verifies it with the IndieAuth token endpoint.
+async fn authorize(
+    config: &MicropubConfig,
+    client: &reqwest::Client,
+    headers: &HeaderMap,
+    body_token: Option<&String>,
+) -> Result<(), Response> {
+    let Some(token) = bearer_token(headers).or(body_token.map(String::as_str)) else {
+        return Err(error(
+            StatusCode::UNAUTHORIZED,
+            "unauthorized",
+            "Missing access token",
+        ));
+    };
+
+    if let Some(expected) = &config.token {
+        return if token_matches(token, expected) {
+            Ok(())
+        } else {
+            Err(error(
+                StatusCode::FORBIDDEN,
+                "forbidden",
+                "Invalid access token",
+            ))
+        };
+    }
+
+    let Some(endpoint) = &config.token_endpoint else {
+        return Err(error(
+            StatusCode::FORBIDDEN,
+            "forbidden",
+            "No way to verify tokens",
+        ));
+    };
+    let info: TokenInfo = match verify_with_endpoint(client, endpoint, token).await {
+        Ok(info) => info,
+        Err(e) => return Err(error(StatusCode::FORBIDDEN, "forbidden", &e.to_string())),
+    };
+    let same_site = |a: &str, b: &str| a.trim_end_matches('/') == b.trim_end_matches('/');
+    if let Some(me) = &config.me {
+        if !same_site(&info.me, me) {
+            return Err(error(
+                StatusCode::FORBIDDEN,
+                "forbidden",
+                "Token is for another site",
+            ));
+        }
+    }
+    if !info
+        .scope
+        .split_whitespace()
+        .any(|s| s == "create" || s == "post")
+    {
+        return Err(error(
+            StatusCode::FORBIDDEN,
+            "insufficient_scope",
+            "Token lacks the create scope",
+        ));
+    }
+    Ok(())
+}
+
+async fn verify_with_endpoint(
+    client: &reqwest::Client,
+    endpoint: &str,
+    token: &str,
+) -> Result<TokenInfo, reqwest::Error> {
+    client
+        .get(endpoint)
+        .bearer_auth(token)
+        .header(reqwest::header::ACCEPT, "application/json")
+        .send()
+        .await?
+        .error_for_status()?
+        .json()
+        .await
+}
@@ -0,0 +1,73 @@
+use std::{collections::BTreeMap, path::Path};
+use tokio::io::AsyncReadExt;
+
+/// How much of a file is read to tell what it is.
+const SNIFF_LEN: usize = 8192;
+
+/// Content type to upload the file at `path` with, given its first bytes:
+/// the `[upload] content_types` entry for its extension, else what the
+/// extension means, else what the bytes look like. Text types get an
+/// explicit charset, and text that is nothing more specific is served as
+/// plain text.
+pub fn content_type(path: &Path, start: &[u8], overrides: &BTreeMap<String, String>) -> String {
+    let extension = path
+        .extension()
+        .map(|ext| ext.to_string_lossy().to_lowercase());
+    let configured = extension.as_deref().and_then(|ext| {
+        overrides
+            .iter()
+            .find(|(key, _)| key.trim_start_matches('.').eq_ignore_ascii_case(ext))
+            .map(|(_, content_type)| content_type.clone())
+    });
+    if let Some(content_type) = configured {
+        return content_type;
+    }
+
+    let text = is_text(start);
+    let essence = mime_guess::from_path(path)
+        .first()
+        .map(|mime| mime.essence_str().to_string())
+        .or_else(|| sniff(start).map(str::to_string));
+    match essence {
+        Some(essence) if text && (essence.starts_with("text/") || essence == "image/svg+xml") => {
+            format!("{}; charset=utf-8", essence)
+        }
+        Some(essence) => essence,
+        None if text => "text/plain; charset=utf-8".to_string(),
+        None => "application/octet-stream".to_string(),
+    }
+}
+
+/// The first bytes of the file at `path`, for [`content_type`].
+pub async fn read_start(path: &Path) -> std::io::Result<Vec<u8>> {
+    let mut start = Vec::with_capacity(SNIFF_LEN);
+    tokio::fs::File::open(path)
+        .await?
+        .take(SNIFF_LEN as u64)
+        .read_to_end(&mut start)
+        .await?;
+    Ok(start)
+}
+
+/// Whether the file (or its beginning) looks like text: valid UTF-8 without
+/// NUL bytes. A character cut off at the end is fine.
+pub fn is_text(bytes: &[u8]) -> bool {
+    !bytes.contains(&0)
+        && match std::str::from_utf8(bytes) {
+            Ok(_) => true,
+            Err(e) => e.error_len().is_none(),
+        }
+}
+
+/// The content type a file's first bytes give away, for files without a
+/// telling extension: SVG, which is text to `infer`, or whatever `infer`
+/// recognizes.
+pub fn sniff(start: &[u8]) -> Option<&'static str> {
+    let text = String::from_utf8_lossy(&start[..start.len().min(1024)]);
+    let text = text.trim_start_matches('\u{feff}').trim_start();
+    let lower = text.to_ascii_lowercase();
+    if lower.starts_with("<svg") || (lower.starts_with("<?xml") && lower.contains("<svg")) {
+        return Some("image/svg+xml");
+    }
+    infer::get(start).map(|kind| kind.mime_type())
+}
@@ -0,0 +1,329 @@
+use crate::config::SlugStyle;
+use crate::formats;
+use crate::frontmatter::slug;
+use crate::import::{Conversion, ConvertedPost};
+use percent_encoding::percent_decode_str;
+use std::{
+    collections::{HashMap, HashSet},
+    error::Error,
+    fs,
+    io::{Cursor, Read, Seek},
+    path::Path,
+};
+use zip::ZipArchive;
+
+/// Notion's page IDs: 32 hex digits after a space, at the end of every
+/// page's file and directory name.
+const ID_LENGTH: usize = 32;
+
+/// Unpacks the Notion export `zip` (Markdown or HTML) into `out` as notes,
+/// one per page with its images and files next to it under clean names,
+/// and its links pointed at them. With `page`, only the pages with that
+/// title are written. HTML pages are converted with `pandoc`.
+pub fn convert(zip: &Path, out: &Path, page: Option<&str>) -> Result<Conversion, Box<dyn Error>> {
+    let file =
+        fs::File::open(zip).map_err(|e| format!("Could not open {}: {}", zip.display(), e))?;
+    let mut entries = Vec::new();
+    read_zip(file, &mut entries).map_err(|e| format!("{}: {}", zip.display(), e))?;
+
+    // Each page, by its path without extension, which is also the
+    // directory its images and subpages are in.
+    let pages: HashSet<String> = entries
+        .iter()
+        .filter(|(path, _)| is_page(path))
+        .map(|(path, _)| without_extension(path).to_string())
+        .collect();
+    let wanted: Option<HashSet<&str>> = match page {
+        Some(title) => {
+            let slugged = slug(title, SlugStyle::Translit);
+            let found: HashSet<&str> = pages
+                .iter()
+                .filter(|key| slug(&page_title(key), SlugStyle::Translit) == slugged)
+                .map(String::as_str)
+                .collect();
+            if found.is_empty() {
+                return Err(format!("No page called '{}' in {}", title, zip.display()).into());
+            }
+            Some(found)
+        }
+        None => None,
+    };
+
+    let mut names = Names::default();
+    let mut renamed = HashMap::new();
+    let mut notes = HashMap::new();
+    for (path, _) in &entries {
+        let new = if is_page(path) {
+            let note = format!("{}.md", names.node(without_extension(path)));
+            notes.insert(without_extension(path), note.clone());
+            note
+        } else {
+            names.file(path)
+        };
+        renamed.insert(path.clone(), new);
+    }
+
+    let mut conversion = Conversion::default();
+    let mut resources: HashMap<&str, Vec<String>> = HashMap::new();
+    for (path, bytes) in &entries {
+        let new = &renamed[path];
+        if is_page(path) {
+            if wanted
+                .as_ref()
+                .is_some_and(|wanted| !wanted.contains(without_extension(path)))
+            {
+                continue;
+            }
+            let text = String::from_utf8_lossy(bytes);
+            let markdown = if path.ends_with(".html") {
+                formats::run_converter("pandoc", &["--from", "html", "--to", "gfm"], &text)?
+            } else {
+                text.into_owned()
+            };
+            let markdown = rewrite_links(&markdown, path, new, &renamed);
+            write(&out.join(new), markdown.as_bytes())?;
+            conversion.posts.push(ConvertedPost {
+                note: out.join(new),
+                old_paths: Vec::new(),
+                resources: Vec::new(),
+            });
+        } else if path.ends_with(".csv") {
+            // Databases come as their pages plus a CSV of their properties.
+            continue;
+        } else {
+            let Some(owner) = owner(path, &pages) else {
+                // Loose files at the top have no page to go with.
+                continue;
+            };
+            if wanted
+                .as_ref()
+                .is_some_and(|wanted| !wanted.contains(owner))
+            {
+                continue;
+            }
+            write(&out.join(new), bytes)?;
+            let owner_note = &notes[owner];
+            resources
+                .entry(owner_note)
+                .or_default()
+                .push(relative(owner_note, new));
+        }
+    }
+    for post in &mut conversion.posts {
+        let new = post.note.strip_prefix(out).unwrap_or(&post.note);
+        let new = new.to_string_lossy().replace('\\', "/");
+        if let Some(files) = resources.remove(new.as_str()) {
+            post.resources = files;
+        }
+    }
+    Ok(conversion)
+}
+
+/// Every file in the zip, with the zips Notion splits big exports into
+/// read in turn.
+fn read_zip<R: Read + Seek>(
+    reader: R,
+    entries: &mut Vec<(String, Vec<u8>)>,
+) -> Result<(), Box<dyn Error>> {
+    let mut archive = ZipArchive::new(reader)?;
+    for index in 0..archive.len() {
+        let mut file = archive.by_index(index)?;
+        if file.is_dir() {
+            continue;
+        }
+        // Only paths that stay inside the output directory.
+        let Some(path) = file.enclosed_name() else {
+            continue;
+        };
+        let path = path
+            .components()
+            .map(|part| part.as_os_str().to_string_lossy().into_owned())
+            .collect::<Vec<_>>()
+            .join("/");
+        let mut bytes = Vec::with_capacity(file.size() as usize);
+        file.read_to_end(&mut bytes)?;
+        if path.ends_with(".zip") {
+            read_zip(Cursor::new(bytes), entries)?;
+        } else {
+            entries.push((path, bytes));
+        }
+    }
+    Ok(())
+}
+
+fn is_page(path: &str) -> bool {
+    path.ends_with(".md") || path.ends_with(".html")
+}
+
+fn without_extension(path: &str) -> &str {
+    path.rsplit_once('.')
+        .filter(|(_, ext)| !ext.contains('/'))
+        .map_or(path, |(stem, _)| stem)
+}
+
+/// The page whose directory `path` is in, the nearest if subpages nest.
+fn owner<'a>(path: &str, pages: &'a HashSet<String>) -> Option<&'a str> {
+    pages
+        .iter()
+        .filter(|key| path.starts_with(&format!("{}/", key)))
+        .max_by_key(|key| key.len())
+        .map(String::as_str)
+}
+
+/// The title in a page's file name, without its ID.
+fn page_title(key: &str) -> String {
+    strip_id(key.rsplit('/').next().unwrap_or(key)).to_string()
+}
+
+/// `name` without the page ID Notion appends to it.
+fn strip_id(name: &str) -> &str {
+    match name.char_indices().rev().nth(ID_LENGTH - 1) {
+        Some((start, _)) if start > 0 => {
+            let (title, id) = name.split_at(start);
+            match title.strip_suffix(' ') {
+                Some(title) if id.chars().all(|c| c.is_ascii_hexdigit()) => title,
+                _ => name,
+            }
+        }
+        _ => name,
+    }
+}
+
+/// Clean names for the export's pages, directories and files, unique
+/// among their siblings.
+#[derive(Default)]
+struct Names {
+    renamed: HashMap<String, String>,
+    taken: HashSet<String>,
+}
+
+impl Names {
+    /// The new path of a page or directory, which share one: `My Page
+    /// 1a2b….md` and `My Page 1a2b…/` become `my-page.md` and `my-page/`.
+    fn node(&mut self, path: &str) -> String {
+        if let Some(new) = self.renamed.get(path) {
+            return new.clone();
+        }
+        let (parent, name) = self.split(path);
+        let name = match slug(strip_id(name), SlugStyle::Translit) {
+            name if name.is_empty() => "untitled".to_string(),
+            name => name,
+        };
+        let new = self.unique(&parent, &name, "");
+        self.renamed.insert(path.to_string(), new.clone());
+        new
+    }
+
+    /// The new path of an image or other file, keeping its extension.
+    fn file(&mut self, path: &str) -> String {
+        let (parent, name) = self.split(path);
+        let (stem, extension) = match name.rsplit_once('.') {
+            Some((stem, extension)) if !stem.is_empty() => {
+                (stem, format!(".{}", extension.to_ascii_lowercase()))
+            }
+            _ => (name, String::new()),
+        };
+        let stem = match slug(strip_id(stem), SlugStyle::Translit) {
+            stem if stem.is_empty() => "file".to_string(),
+            stem => stem,
+        };
+        self.unique(&parent, &stem, &extension)
+    }
+
+    /// The new path of `path`'s directory, and its own name.
+    fn split<'a>(&mut self, path: &'a str) -> (String, &'a str) {
+        match path.rsplit_once('/') {
+            Some((parent, name)) => (format!("{}/", self.node(parent)), name),
+            None => (String::new(), path),
+        }
+    }
+
+    fn unique(&mut self, parent: &str, name: &str, extension: &str) -> String {
+        let mut new = format!("{}{}{}", parent, name, extension);
+        let mut n = 2;
+        while !self.taken.insert(new.clone()) {
+            new = format!("{}{}-{}{}", parent, name, n, extension);
+            n += 1;
+        }
+        new
+    }
+}
+
+/// `markdown` with its links to other files in the export pointed at their
+/// new names. The page was at `old` in the export and is now at `new`.
+fn rewrite_links(
+    markdown: &str,
+    old: &str,
+    new: &str,
+    renamed: &HashMap<String, String>,
+) -> String {
+    let mut replacements = Vec::new();
+    for (open, close) in [("](", ')'), ("src=\"", '"'), ("href=\"", '"')] {
+        for (start, _) in markdown.match_indices(open) {
+            let start = start + open.len();
+            let Some(length) = markdown[start..].find([close, '\n']) else {
+                continue;
+            };
+            let target = &markdown[start..start + length];
+            // Other sites, anchors and `mailto:`.
+            if target.contains(':') || target.starts_with(['#', '/']) {
+                continue;
+            }
+            let decoded = percent_decode_str(target).decode_utf8_lossy();
+            let Some(resolved) = resolve(old, &decoded) else {
+                continue;
+            };
+            if let Some(target_new) = renamed.get(&resolved) {
+                replacements.push((start..start + length, relative(new, target_new)));
+            }
+        }
+    }
+    replacements.sort_by_key(|(range, _)| range.start);
+    let mut rewritten = String::with_capacity(markdown.len());
+    let mut end = 0;
+    for (range, target) in replacements {
+        if range.start < end {
+            continue;
+        }
+        rewritten.push_str(&markdown[end..range.start]);
+        rewritten.push_str(&target);
+        end = range.end;
+    }
+    rewritten.push_str(&markdown[end..]);
+    rewritten
+}
+
+/// The export path `link` points at from the page at `from`.
+fn resolve(from: &str, link: &str) -> Option<String> {
+    let mut parts: Vec<&str> = from.split('/').collect();
+    parts.pop();
+    for part in link.split('/') {
+        match part {
+            "" | "." => {}
+            ".." => {
+                parts.pop()?;
+            }
+            part => parts.push(part),
+        }
+    }
+    Some(parts.join("/"))
+}
+
+/// A link from the file at `from` to the one at `to`, both relative to the
+/// same root.
+fn relative(from: &str, to: &str) -> String {
+    let from: Vec<&str> = from.split('/').collect();
+    let from = &from[..from.len() - 1];
+    let to: Vec<&str> = to.split('/').collect();
+    let common = from.iter().zip(&to).take_while(|(a, b)| a == b).count();
+    let mut parts = vec![".."; from.len() - common];
+    parts.extend(&to[common..]);
+    parts.join("/")
+}
+
+fn write(path: &Path, bytes: &[u8]) -> Result<(), Box<dyn Error>> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(path, bytes).map_err(|e| format!("Could not write {}: {}", path.display(), e).into())
+}
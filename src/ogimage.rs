@@ -0,0 +1,169 @@
+use crate::config::OgImageConfig;
+use ab_glyph::{point, Font, FontVec, PxScale, ScaleFont};
+use image::{ImageFormat, Rgba, RgbaImage};
+use std::{error::Error, io::Cursor, path::Path};
+
+const WIDTH: u32 = 1200;
+const HEIGHT: u32 = 630;
+const MARGIN: f32 = 80.0;
+
+const BACKGROUND: Rgba<u8> = Rgba([0x12, 0x12, 0x12, 0xff]);
+const TEXT: [u8; 3] = [0xde, 0xde, 0xde];
+const TEXT_SECONDARY: [u8; 3] = [0x99, 0x99, 0x99];
+
+/// Fonts tried, in order, when the config doesn't name one.
+const FALLBACK_FONTS: &[&str] = &[
+    "/usr/share/fonts/truetype/dejavu/DejaVuSans-Bold.ttf",
+    "/usr/share/fonts/TTF/DejaVuSans-Bold.ttf",
+    "/usr/share/fonts/dejavu/DejaVuSans-Bold.ttf",
+    "/System/Library/Fonts/Supplemental/Arial Bold.ttf",
+    "/Library/Fonts/Arial Bold.ttf",
+    "C:\\Windows\\Fonts\\arialbd.ttf",
+];
+
+/// Renders Open Graph preview images (1200×630 PNG) for posts.
+pub struct OgImageRenderer {
+    font: FontVec,
+}
+
+impl OgImageRenderer {
+    pub fn new(config: &OgImageConfig) -> Result<Self, Box<dyn Error>> {
+        let font_path = match &config.font {
+            Some(path) => path.clone(),
+            None => FALLBACK_FONTS
+                .iter()
+                .map(Path::new)
+                .find(|path| path.exists())
+                .ok_or("No font found for preview images; set [og_image].font")?
+                .to_path_buf(),
+        };
+        let data = std::fs::read(&font_path)
+            .map_err(|e| format!("Could not read font {}: {}", font_path.display(), e))?;
+        let font = FontVec::try_from_vec(data)
+            .map_err(|_| format!("Invalid font file {}", font_path.display()))?;
+        Ok(Self { font })
+    }
+
+    /// Draws the title (wrapped, up to four lines), date and site name as PNG bytes.
+    pub fn render(
+        &self,
+        title: &str,
+        date: &str,
+        site_name: &str,
+    ) -> Result<Vec<u8>, Box<dyn Error>> {
+        let mut canvas = RgbaImage::from_pixel(WIDTH, HEIGHT, BACKGROUND);
+        let max_width = WIDTH as f32 - 2.0 * MARGIN;
+
+        let date_scale = PxScale::from(36.0);
+        self.draw_line(
+            &mut canvas,
+            date,
+            date_scale,
+            MARGIN,
+            MARGIN,
+            TEXT_SECONDARY,
+        );
+
+        let title_scale = PxScale::from(72.0);
+        let line_height = self.font.as_scaled(title_scale).height() * 1.15;
+        let mut y = MARGIN + 90.0;
+        for line in self.wrap(title, title_scale, max_width).iter().take(4) {
+            self.draw_line(&mut canvas, line, title_scale, MARGIN, y, TEXT);
+            y += line_height;
+        }
+
+        let site_scale = PxScale::from(32.0);
+        let site_y = HEIGHT as f32 - MARGIN - self.font.as_scaled(site_scale).height();
+        self.draw_line(
+            &mut canvas,
+            site_name,
+            site_scale,
+            MARGIN,
+            site_y,
+            TEXT_SECONDARY,
+        );
+
+        let mut png = Vec::new();
+        canvas.write_to(&mut Cursor::new(&mut png), ImageFormat::Png)?;
+        Ok(png)
+    }
+
+    fn line_width(&self, text: &str, scale: PxScale) -> f32 {
+        let font = self.font.as_scaled(scale);
+        let mut width = 0.0;
+        let mut previous = None;
+        for c in text.chars() {
+            let id = font.glyph_id(c);
+            if let Some(prev) = previous {
+                width += font.kern(prev, id);
+            }
+            width += font.h_advance(id);
+            previous = Some(id);
+        }
+        width
+    }
+
+    /// Greedy word wrap; words wider than a full line are left to overflow.
+    fn wrap(&self, text: &str, scale: PxScale, max_width: f32) -> Vec<String> {
+        let mut lines = Vec::new();
+        let mut current = String::new();
+        for word in text.split_whitespace() {
+            let candidate = if current.is_empty() {
+                word.to_string()
+            } else {
+                format!("{} {}", current, word)
+            };
+            if self.line_width(&candidate, scale) > max_width && !current.is_empty() {
+                lines.push(std::mem::replace(&mut current, word.to_string()));
+            } else {
+                current = candidate;
+            }
+        }
+        if !current.is_empty() {
+            lines.push(current);
+        }
+        lines
+    }
+
+    /// Draws one line of text with its top edge at `y`.
+    fn draw_line(
+        &self,
+        canvas: &mut RgbaImage,
+        text: &str,
+        scale: PxScale,
+        x: f32,
+        y: f32,
+        color: [u8; 3],
+    ) {
+        let font = self.font.as_scaled(scale);
+        let baseline = y + font.ascent();
+        let mut caret = x;
+        let mut previous = None;
+        for c in text.chars() {
+            let id = font.glyph_id(c);
+            if let Some(prev) = previous {
+                caret += font.kern(prev, id);
+            }
+            let glyph = id.with_scale_and_position(scale, point(caret, baseline));
+            caret += font.h_advance(id);
+            previous = Some(id);
+
+            let Some(outlined) = self.font.outline_glyph(glyph) else {
+                continue;
+            };
+            let bounds = outlined.px_bounds();
+            outlined.draw(|gx, gy, coverage| {
+                let px = bounds.min.x as i32 + gx as i32;
+                let py = bounds.min.y as i32 + gy as i32;
+                if px < 0 || py < 0 || px >= WIDTH as i32 || py >= HEIGHT as i32 {
+                    return;
+                }
+                let pixel = canvas.get_pixel_mut(px as u32, py as u32);
+                for i in 0..3 {
+                    let blended = pixel[i] as f32 * (1.0 - coverage) + color[i] as f32 * coverage;
+                    pixel[i] = blended.round() as u8;
+                }
+            });
+        }
+    }
+}
@@ -0,0 +1,544 @@
+use crate::config::{AppConfig, Backend};
+use crate::delegate::Delegated;
+use crate::frontmatter::{validate_labels, Labels};
+use crate::queue::{self, Queue};
+use crate::upload::Bucket;
+use async_trait::async_trait;
+use md5::{Digest, Md5};
+use std::{
+    collections::BTreeMap,
+    error::Error,
+    sync::{Arc, Mutex, MutexGuard},
+    time::Duration,
+};
+
+/// Where published files go. [`Bucket`] uploads them to S3, and
+/// [`MemoryPublisher`] keeps them in memory for tests and `--backend memory`.
+/// Paths are relative to the configured prefix, e.g. `p/<id>/index.html`.
+#[async_trait]
+pub trait Publisher: Send + Sync {
+    /// The domain without its scheme, e.g. `paste.example.com`.
+    fn host(&self) -> &str;
+
+    /// Public URL of `path`, e.g. `p/<id>` becomes `https://domain/p/<id>`.
+    fn public_url(&self, path: &str) -> String;
+
+    async fn put(
+        &self,
+        path: &str,
+        body: Vec<u8>,
+        content_type: &str,
+        labels: &Labels,
+    ) -> Result<(), Box<dyn Error>>;
+
+    /// Like [`Publisher::put`], but browsers save the object as `file_name`
+    /// (`Content-Disposition: attachment`) instead of showing it.
+    async fn put_download(
+        &self,
+        path: &str,
+        body: Vec<u8>,
+        content_type: &str,
+        file_name: &str,
+        labels: &Labels,
+    ) -> Result<(), Box<dyn Error>>;
+
+    async fn put_html(
+        &self,
+        path: &str,
+        html: String,
+        labels: &Labels,
+    ) -> Result<(), Box<dyn Error>> {
+        self.put(path, html.into_bytes(), "text/html", labels).await
+    }
+
+    /// Stores an empty object at `path` that S3 website endpoints answer with
+    /// a redirect to `location`.
+    async fn put_redirect(&self, path: &str, location: &str) -> Result<(), Box<dyn Error>>;
+
+    /// Deletes the object at `path`.
+    async fn delete(&self, path: &str) -> Result<(), Box<dyn Error>>;
+
+    /// Paths of every object under `path`.
+    async fn list(&self, path: &str) -> Result<Vec<String>, Box<dyn Error>>;
+
+    /// The body of the object at `path`, or `None` if there's nothing there.
+    async fn fetch(&self, path: &str) -> Result<Option<Vec<u8>>, Box<dyn Error>>;
+
+    /// Like [`Publisher::fetch`], along with the object's ETag for
+    /// [`Precondition::Matches`].
+    async fn fetch_tagged(&self, path: &str) -> Result<Option<(Vec<u8>, String)>, Box<dyn Error>>;
+
+    /// Like [`Publisher::put`], but only if `precondition` holds when the
+    /// object is written (`If-None-Match`/`If-Match`), so two writers can't
+    /// both replace the same version. Returns whether it was stored.
+    async fn put_if(
+        &self,
+        path: &str,
+        body: Vec<u8>,
+        content_type: &str,
+        precondition: &Precondition,
+    ) -> Result<bool, Box<dyn Error>>;
+
+    /// A URL that reads the object at `path` without credentials for
+    /// `expires`, even where the bucket isn't public.
+    async fn presign(&self, path: &str, expires: Duration) -> Result<String, Box<dyn Error>>;
+
+    /// Deletes every object under `path`. Returns how many objects were
+    /// removed.
+    async fn delete_prefix(&self, path: &str) -> Result<usize, Box<dyn Error>>;
+
+    /// Moves every object under `from` to the same name under `to`, keeping
+    /// content types and tags. Returns how many objects were moved.
+    async fn move_prefix(&self, from: &str, to: &str) -> Result<usize, Box<dyn Error>>;
+
+    /// Starts a multipart upload to `path` and returns its upload id.
+    async fn create_multipart(
+        &self,
+        path: &str,
+        content_type: &str,
+        labels: &Labels,
+    ) -> Result<String, Box<dyn Error>>;
+
+    /// Uploads one part (numbered from 1) and returns its ETag.
+    async fn upload_part(
+        &self,
+        path: &str,
+        upload_id: &str,
+        number: i32,
+        body: Vec<u8>,
+    ) -> Result<String, Box<dyn Error>>;
+
+    /// Gives up on a multipart upload, discarding the parts uploaded so far.
+    async fn abort_multipart(&self, path: &str, upload_id: &str) -> Result<(), Box<dyn Error>>;
+
+    /// Finishes a multipart upload from `(part number, ETag)` pairs.
+    async fn complete_multipart(
+        &self,
+        path: &str,
+        upload_id: &str,
+        parts: &[(i32, String)],
+    ) -> Result<(), Box<dyn Error>>;
+}
+
+/// What [`Publisher::put_if`] requires of the object it replaces.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Precondition {
+    /// Nothing is stored at the path yet.
+    Absent,
+    /// The stored object still has this ETag.
+    Matches(String),
+}
+
+/// The publisher `[upload] backend` (or `--backend`) asks for, with the
+/// configured rate limit and labels, queueing uploads if asked to.
+pub fn from_config(config: &AppConfig) -> Result<Box<dyn Publisher>, Box<dyn Error>> {
+    Ok(match config.upload.backend {
+        Backend::S3 => {
+            let bucket: Box<dyn Publisher> = Box::new(
+                Bucket::new(&config.s3, &config.tls)?
+                    .with_limit_rate(config.upload.limit_rate)
+                    .with_labels(config.upload.labels.clone()),
+            );
+            if config.upload.queue || config.upload.queue_offline {
+                Box::new(Queue::new(bucket, queue::dir(config)?, config.upload.queue))
+            } else {
+                bucket
+            }
+        }
+        Backend::Delegated => Box::new(Delegated::new(config)?),
+        Backend::Memory => Box::new(
+            MemoryPublisher::new(&config.s3.domain)
+                .with_labels(config.upload.labels.clone())
+                .logging(),
+        ),
+    })
+}
+
+/// The `Content-Disposition` that makes browsers save a file as `file_name`:
+/// a plain ASCII name for old clients and the exact name per RFC 6266.
+pub fn attachment_disposition(file_name: &str) -> String {
+    let ascii: String = file_name
+        .chars()
+        .map(|c| match c {
+            ' '..='~' if c != '"' && c != '\\' => c,
+            _ => '_',
+        })
+        .collect();
+    let encoded: String = file_name
+        .bytes()
+        .map(|byte| match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'.' | b'-' | b'_' => {
+                (byte as char).to_string()
+            }
+            _ => format!("%{:02X}", byte),
+        })
+        .collect();
+    format!(
+        "attachment; filename=\"{}\"; filename*=UTF-8''{}",
+        ascii, encoded
+    )
+}
+
+/// An object stored by a [`MemoryPublisher`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StoredObject {
+    pub body: Vec<u8>,
+    pub content_type: String,
+    /// Object tags: the publisher's labels plus the upload's.
+    pub labels: Labels,
+    /// Where an S3 website endpoint would redirect to, for redirect objects.
+    pub redirect: Option<String>,
+    /// Set for objects uploaded with [`Publisher::put_download`].
+    pub content_disposition: Option<String>,
+}
+
+impl StoredObject {
+    /// The body as text, for HTML and JSON.
+    pub fn text(&self) -> &str {
+        std::str::from_utf8(&self.body).unwrap_or_default()
+    }
+}
+
+struct PendingUpload {
+    path: String,
+    content_type: String,
+    labels: Labels,
+    parts: BTreeMap<i32, Vec<u8>>,
+}
+
+#[derive(Default)]
+struct Store {
+    objects: BTreeMap<String, StoredObject>,
+    uploads: BTreeMap<String, PendingUpload>,
+    next_upload: usize,
+}
+
+/// A [`Publisher`] that keeps everything in memory. Clones share the same
+/// objects, so a test can hold on to one and look at what was published
+/// through another.
+#[derive(Clone, Default)]
+pub struct MemoryPublisher {
+    domain: String,
+    labels: Labels,
+    /// Print every change to stderr.
+    log: bool,
+    store: Arc<Mutex<Store>>,
+}
+
+impl MemoryPublisher {
+    /// An empty store whose public URLs are under `domain`, e.g.
+    /// `https://paste.example.com`.
+    pub fn new(domain: &str) -> Self {
+        Self {
+            domain: domain.trim_end_matches('/').to_string(),
+            ..Self::default()
+        }
+    }
+
+    /// Tags every object with `labels`, in addition to per-upload labels.
+    pub fn with_labels(mut self, labels: Labels) -> Self {
+        self.labels = labels;
+        self
+    }
+
+    /// Prints every change to stderr.
+    pub fn logging(mut self) -> Self {
+        self.log = true;
+        self
+    }
+
+    /// The object at `path`, if there is one.
+    pub fn get(&self, path: &str) -> Option<StoredObject> {
+        self.store().objects.get(path).cloned()
+    }
+
+    /// Paths of every stored object, sorted.
+    pub fn paths(&self) -> Vec<String> {
+        self.store().objects.keys().cloned().collect()
+    }
+
+    fn store(&self) -> MutexGuard<'_, Store> {
+        // A panic elsewhere can't leave the maps half-updated.
+        self.store.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn labels(&self, labels: &Labels) -> Result<Labels, Box<dyn Error>> {
+        let mut all = self.labels.clone();
+        all.extend(labels.iter().map(|(k, v)| (k.clone(), v.clone())));
+        validate_labels(&all)?;
+        Ok(all)
+    }
+
+    fn insert(&self, path: &str, object: StoredObject) {
+        if self.log {
+            match &object.redirect {
+                Some(location) => eprintln!("[memory] redirect {} -> {}", path, location),
+                None => eprintln!(
+                    "[memory] put {} ({}, {} bytes)",
+                    path,
+                    object.content_type,
+                    object.body.len()
+                ),
+            }
+        }
+        self.store().objects.insert(path.to_string(), object);
+    }
+
+    fn upload_mut<'s>(
+        store: &'s mut Store,
+        path: &str,
+        upload_id: &str,
+    ) -> Result<&'s mut PendingUpload, Box<dyn Error>> {
+        store
+            .uploads
+            .get_mut(upload_id)
+            .filter(|upload| upload.path == path)
+            .ok_or_else(|| format!("No multipart upload {} to {}", upload_id, path).into())
+    }
+}
+
+#[async_trait]
+impl Publisher for MemoryPublisher {
+    fn host(&self) -> &str {
+        self.domain
+            .split_once("://")
+            .map_or(self.domain.as_str(), |(_, host)| host)
+    }
+
+    fn public_url(&self, path: &str) -> String {
+        format!("{}/{}", self.domain, path)
+    }
+
+    async fn put(
+        &self,
+        path: &str,
+        body: Vec<u8>,
+        content_type: &str,
+        labels: &Labels,
+    ) -> Result<(), Box<dyn Error>> {
+        let labels = self.labels(labels)?;
+        self.insert(
+            path,
+            StoredObject {
+                body,
+                content_type: content_type.to_string(),
+                labels,
+                redirect: None,
+                content_disposition: None,
+            },
+        );
+        Ok(())
+    }
+
+    async fn put_download(
+        &self,
+        path: &str,
+        body: Vec<u8>,
+        content_type: &str,
+        file_name: &str,
+        labels: &Labels,
+    ) -> Result<(), Box<dyn Error>> {
+        let labels = self.labels(labels)?;
+        self.insert(
+            path,
+            StoredObject {
+                body,
+                content_type: content_type.to_string(),
+                labels,
+                redirect: None,
+                content_disposition: Some(attachment_disposition(file_name)),
+            },
+        );
+        Ok(())
+    }
+
+    async fn put_redirect(&self, path: &str, location: &str) -> Result<(), Box<dyn Error>> {
+        let labels = self.labels(&Labels::new())?;
+        self.insert(
+            path,
+            StoredObject {
+                body: Vec::new(),
+                content_type: "text/html".to_string(),
+                labels,
+                redirect: Some(location.to_string()),
+                content_disposition: None,
+            },
+        );
+        Ok(())
+    }
+
+    async fn delete(&self, path: &str) -> Result<(), Box<dyn Error>> {
+        if self.store().objects.remove(path).is_some() && self.log {
+            eprintln!("[memory] delete {}", path);
+        }
+        Ok(())
+    }
+
+    async fn list(&self, path: &str) -> Result<Vec<String>, Box<dyn Error>> {
+        Ok(self
+            .store()
+            .objects
+            .keys()
+            .filter(|key| key.starts_with(path))
+            .cloned()
+            .collect())
+    }
+
+    async fn fetch(&self, path: &str) -> Result<Option<Vec<u8>>, Box<dyn Error>> {
+        Ok(self.get(path).map(|object| object.body))
+    }
+
+    async fn fetch_tagged(&self, path: &str) -> Result<Option<(Vec<u8>, String)>, Box<dyn Error>> {
+        Ok(self.get(path).map(|object| {
+            let etag = etag(&object.body);
+            (object.body, etag)
+        }))
+    }
+
+    async fn put_if(
+        &self,
+        path: &str,
+        body: Vec<u8>,
+        content_type: &str,
+        precondition: &Precondition,
+    ) -> Result<bool, Box<dyn Error>> {
+        let labels = self.labels(&Labels::new())?;
+        // Checked and written under one lock, as S3 does.
+        let mut store = self.store();
+        let current = store.objects.get(path).map(|object| etag(&object.body));
+        let holds = match precondition {
+            Precondition::Absent => current.is_none(),
+            Precondition::Matches(expected) => current.as_ref() == Some(expected),
+        };
+        if !holds {
+            return Ok(false);
+        }
+        if self.log {
+            eprintln!(
+                "[memory] put {} ({}, {} bytes)",
+                path,
+                content_type,
+                body.len()
+            );
+        }
+        store.objects.insert(
+            path.to_string(),
+            StoredObject {
+                body,
+                content_type: content_type.to_string(),
+                labels,
+                redirect: None,
+                content_disposition: None,
+            },
+        );
+        Ok(true)
+    }
+
+    async fn presign(&self, path: &str, expires: Duration) -> Result<String, Box<dyn Error>> {
+        Ok(format!(
+            "{}?X-Amz-Expires={}",
+            self.public_url(path),
+            expires.as_secs()
+        ))
+    }
+
+    async fn delete_prefix(&self, path: &str) -> Result<usize, Box<dyn Error>> {
+        let paths = self.list(path).await?;
+        for path in &paths {
+            self.delete(path).await?;
+        }
+        Ok(paths.len())
+    }
+
+    async fn move_prefix(&self, from: &str, to: &str) -> Result<usize, Box<dyn Error>> {
+        let paths = self.list(from).await?;
+        let mut store = self.store();
+        for path in &paths {
+            let destination = format!("{}{}", to, &path[from.len()..]);
+            if self.log {
+                eprintln!("[memory] move {} -> {}", path, destination);
+            }
+            if let Some(object) = store.objects.remove(path) {
+                store.objects.insert(destination, object);
+            }
+        }
+        Ok(paths.len())
+    }
+
+    async fn create_multipart(
+        &self,
+        path: &str,
+        content_type: &str,
+        labels: &Labels,
+    ) -> Result<String, Box<dyn Error>> {
+        let labels = self.labels(labels)?;
+        let mut store = self.store();
+        store.next_upload += 1;
+        let upload_id = format!("memory-{}", store.next_upload);
+        store.uploads.insert(
+            upload_id.clone(),
+            PendingUpload {
+                path: path.to_string(),
+                content_type: content_type.to_string(),
+                labels,
+                parts: BTreeMap::new(),
+            },
+        );
+        Ok(upload_id)
+    }
+
+    async fn upload_part(
+        &self,
+        path: &str,
+        upload_id: &str,
+        number: i32,
+        body: Vec<u8>,
+    ) -> Result<String, Box<dyn Error>> {
+        let mut store = self.store();
+        let upload = Self::upload_mut(&mut store, path, upload_id)?;
+        upload.parts.insert(number, body);
+        Ok(format!("\"{}-{}\"", upload_id, number))
+    }
+
+    async fn abort_multipart(&self, path: &str, upload_id: &str) -> Result<(), Box<dyn Error>> {
+        let mut store = self.store();
+        Self::upload_mut(&mut store, path, upload_id)?;
+        store.uploads.remove(upload_id);
+        Ok(())
+    }
+
+    async fn complete_multipart(
+        &self,
+        path: &str,
+        upload_id: &str,
+        parts: &[(i32, String)],
+    ) -> Result<(), Box<dyn Error>> {
+        let object = {
+            let mut store = self.store();
+            let upload = Self::upload_mut(&mut store, path, upload_id)?;
+            let mut body = Vec::new();
+            for (number, _) in parts {
+                let part = upload
+                    .parts
+                    .get(number)
+                    .ok_or_else(|| format!("Part {} of {} was never uploaded", number, path))?;
+                body.extend_from_slice(part);
+            }
+            let upload = store.uploads.remove(upload_id).expect("looked up above");
+            StoredObject {
+                body,
+                content_type: upload.content_type,
+                labels: upload.labels,
+                redirect: None,
+                content_disposition: None,
+            }
+        };
+        self.insert(path, object);
+        Ok(())
+    }
+}
+
+/// The quoted MD5 of `body`, the ETag S3 gives a single-part upload.
+fn etag(body: &[u8]) -> String {
+    format!("\"{}\"", hex::encode(Md5::digest(body)))
+}
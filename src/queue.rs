@@ -0,0 +1,542 @@
+use crate::config::AppConfig;
+use crate::frontmatter::Labels;
+use crate::manifest::{self, Manifest};
+use crate::publisher::{Precondition, Publisher};
+use async_trait::async_trait;
+use aws_smithy_runtime_api::client::result::ConnectorError;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    error::Error,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Mutex,
+    },
+    time::Duration,
+};
+use tokio::{fs, io::AsyncWriteExt};
+
+/// One change to the bucket waiting in the queue, as a line of `queue.jsonl`.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "snake_case")]
+enum Op {
+    /// The body is the file `object` in the queue's `objects` directory.
+    Put {
+        path: String,
+        object: String,
+        content_type: String,
+        /// Set for [`Publisher::put_download`].
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        file_name: Option<String>,
+        #[serde(default, skip_serializing_if = "Labels::is_empty")]
+        labels: Labels,
+    },
+    Redirect {
+        path: String,
+        location: String,
+    },
+    Delete {
+        path: String,
+    },
+    DeletePrefix {
+        path: String,
+    },
+    MovePrefix {
+        from: String,
+        to: String,
+    },
+}
+
+/// Where queued uploads wait: `queue/` next to the manifest.
+pub fn dir(config: &AppConfig) -> Result<PathBuf, Box<dyn Error>> {
+    Ok(Manifest::path_for(config)?.with_file_name("queue"))
+}
+
+/// A [`Publisher`] that keeps uploads on disk in `dir` for [`flush`] instead
+/// of sending them: always, or once the bucket turns out to be unreachable.
+/// Reads are answered from the queue while queueing.
+pub struct Queue {
+    inner: Box<dyn Publisher>,
+    dir: PathBuf,
+    /// Set from the start with `--queue`, or on the first connection error.
+    queueing: AtomicBool,
+    /// Whether the user was told that uploads are being queued.
+    told: AtomicBool,
+    /// Multipart uploads started in the queue: path, content type and labels
+    /// by upload id.
+    multipart: Mutex<HashMap<String, (String, String, Labels)>>,
+    /// Serializes appends to the journal.
+    journal: tokio::sync::Mutex<()>,
+}
+
+impl Queue {
+    /// Queues everything for `inner` if `always`, otherwise only once it
+    /// can't be reached.
+    pub fn new(inner: Box<dyn Publisher>, dir: PathBuf, always: bool) -> Self {
+        Self {
+            inner,
+            dir,
+            queueing: AtomicBool::new(always),
+            told: AtomicBool::new(false),
+            multipart: Mutex::new(HashMap::new()),
+            journal: tokio::sync::Mutex::new(()),
+        }
+    }
+
+    fn queueing(&self) -> bool {
+        self.queueing.load(Ordering::SeqCst)
+    }
+
+    /// Whether `error` means the bucket can't be reached, switching to
+    /// queueing if so.
+    fn went_offline(&self, error: &(dyn Error + 'static)) -> bool {
+        let mut source = Some(error);
+        while let Some(error) = source {
+            if let Some(connector) = error.downcast_ref::<ConnectorError>() {
+                if connector.is_io() || connector.is_timeout() {
+                    eprintln!("Can't reach the bucket: {}", connector);
+                    self.queueing.store(true, Ordering::SeqCst);
+                    return true;
+                }
+            }
+            source = error.source();
+        }
+        false
+    }
+
+    async fn append(&self, op: &Op) -> Result<(), Box<dyn Error>> {
+        if !self.told.swap(true, Ordering::SeqCst) {
+            eprintln!(
+                "Queueing uploads in {}; run `klistra flush` once you're online",
+                self.dir.display()
+            );
+        }
+        let mut line = serde_json::to_string(op)?;
+        line.push('\n');
+        let _guard = self.journal.lock().await;
+        fs::create_dir_all(&self.dir).await?;
+        let mut file = fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(journal_path(&self.dir))
+            .await?;
+        file.write_all(line.as_bytes()).await?;
+        Ok(())
+    }
+
+    /// Stores `body` in the queue and returns its object name.
+    async fn store(&self, body: &[u8]) -> Result<String, Box<dyn Error>> {
+        let object = uuid::Uuid::new_v4().to_string();
+        let dir = objects_dir(&self.dir);
+        fs::create_dir_all(&dir).await?;
+        fs::write(dir.join(&object), body).await?;
+        Ok(object)
+    }
+
+    async fn queue_put(
+        &self,
+        path: &str,
+        body: &[u8],
+        content_type: &str,
+        file_name: Option<&str>,
+        labels: &Labels,
+    ) -> Result<(), Box<dyn Error>> {
+        let object = self.store(body).await?;
+        self.append(&Op::Put {
+            path: path.to_string(),
+            object,
+            content_type: content_type.to_string(),
+            file_name: file_name.map(str::to_string),
+            labels: labels.clone(),
+        })
+        .await
+    }
+
+    /// What the queue holds for `path` right now, as far as it knows.
+    async fn queued(&self, path: &str) -> Result<Option<Vec<u8>>, Box<dyn Error>> {
+        let mut object = None;
+        for op in read(&self.dir).await? {
+            match op {
+                Op::Put {
+                    path: put,
+                    object: o,
+                    ..
+                } if put == path => object = Some(o),
+                Op::Delete { path: deleted } if deleted == path => object = None,
+                Op::DeletePrefix { path: prefix } if path.starts_with(&prefix) => object = None,
+                _ => {}
+            }
+        }
+        match object {
+            Some(object) => Ok(Some(fs::read(objects_dir(&self.dir).join(object)).await?)),
+            None => Ok(None),
+        }
+    }
+}
+
+#[async_trait]
+impl Publisher for Queue {
+    fn host(&self) -> &str {
+        self.inner.host()
+    }
+
+    fn public_url(&self, path: &str) -> String {
+        self.inner.public_url(path)
+    }
+
+    async fn put(
+        &self,
+        path: &str,
+        body: Vec<u8>,
+        content_type: &str,
+        labels: &Labels,
+    ) -> Result<(), Box<dyn Error>> {
+        if !self.queueing() {
+            match self
+                .inner
+                .put(path, body.clone(), content_type, labels)
+                .await
+            {
+                Err(e) if self.went_offline(e.as_ref()) => {}
+                result => return result,
+            }
+        }
+        self.queue_put(path, &body, content_type, None, labels)
+            .await
+    }
+
+    async fn put_download(
+        &self,
+        path: &str,
+        body: Vec<u8>,
+        content_type: &str,
+        file_name: &str,
+        labels: &Labels,
+    ) -> Result<(), Box<dyn Error>> {
+        if !self.queueing() {
+            match self
+                .inner
+                .put_download(path, body.clone(), content_type, file_name, labels)
+                .await
+            {
+                Err(e) if self.went_offline(e.as_ref()) => {}
+                result => return result,
+            }
+        }
+        self.queue_put(path, &body, content_type, Some(file_name), labels)
+            .await
+    }
+
+    async fn put_redirect(&self, path: &str, location: &str) -> Result<(), Box<dyn Error>> {
+        if !self.queueing() {
+            match self.inner.put_redirect(path, location).await {
+                Err(e) if self.went_offline(e.as_ref()) => {}
+                result => return result,
+            }
+        }
+        self.append(&Op::Redirect {
+            path: path.to_string(),
+            location: location.to_string(),
+        })
+        .await
+    }
+
+    async fn delete(&self, path: &str) -> Result<(), Box<dyn Error>> {
+        if !self.queueing() {
+            match self.inner.delete(path).await {
+                Err(e) if self.went_offline(e.as_ref()) => {}
+                result => return result,
+            }
+        }
+        self.append(&Op::Delete {
+            path: path.to_string(),
+        })
+        .await
+    }
+
+    async fn list(&self, path: &str) -> Result<Vec<String>, Box<dyn Error>> {
+        if !self.queueing() {
+            match self.inner.list(path).await {
+                Err(e) if self.went_offline(e.as_ref()) => {}
+                result => return result,
+            }
+        }
+        let mut paths = Vec::new();
+        for op in read(&self.dir).await? {
+            match op {
+                Op::Put { path: put, .. } if put.starts_with(path) && !paths.contains(&put) => {
+                    paths.push(put)
+                }
+                Op::Delete { path: deleted } => paths.retain(|p| *p != deleted),
+                Op::DeletePrefix { path: prefix } => paths.retain(|p| !p.starts_with(&prefix)),
+                _ => {}
+            }
+        }
+        Ok(paths)
+    }
+
+    async fn fetch(&self, path: &str) -> Result<Option<Vec<u8>>, Box<dyn Error>> {
+        if !self.queueing() {
+            match self.inner.fetch(path).await {
+                Err(e) if self.went_offline(e.as_ref()) => {}
+                result => return result,
+            }
+        }
+        self.queued(path).await
+    }
+
+    async fn fetch_tagged(&self, path: &str) -> Result<Option<(Vec<u8>, String)>, Box<dyn Error>> {
+        if !self.queueing() {
+            match self.inner.fetch_tagged(path).await {
+                Err(e) if self.went_offline(e.as_ref()) => {}
+                result => return result,
+            }
+        }
+        Ok(self
+            .queued(path)
+            .await?
+            .map(|body| (body, "queued".to_string())))
+    }
+
+    async fn put_if(
+        &self,
+        path: &str,
+        body: Vec<u8>,
+        content_type: &str,
+        precondition: &Precondition,
+    ) -> Result<bool, Box<dyn Error>> {
+        if !self.queueing() {
+            match self
+                .inner
+                .put_if(path, body.clone(), content_type, precondition)
+                .await
+            {
+                Err(e) if self.went_offline(e.as_ref()) => {}
+                result => return result,
+            }
+        }
+        // The shared manifest is merged and saved by `flush` itself, since
+        // the copy it would replace may change before then.
+        if path != manifest::REMOTE_PATH {
+            self.queue_put(path, &body, content_type, None, &Labels::new())
+                .await?;
+        }
+        Ok(true)
+    }
+
+    async fn presign(&self, path: &str, expires: Duration) -> Result<String, Box<dyn Error>> {
+        if self.queueing() {
+            return Err("Presigned links need the bucket, which can't be reached".into());
+        }
+        self.inner.presign(path, expires).await
+    }
+
+    async fn delete_prefix(&self, path: &str) -> Result<usize, Box<dyn Error>> {
+        if !self.queueing() {
+            match self.inner.delete_prefix(path).await {
+                Err(e) if self.went_offline(e.as_ref()) => {}
+                result => return result,
+            }
+        }
+        self.append(&Op::DeletePrefix {
+            path: path.to_string(),
+        })
+        .await?;
+        Ok(0)
+    }
+
+    async fn move_prefix(&self, from: &str, to: &str) -> Result<usize, Box<dyn Error>> {
+        if !self.queueing() {
+            match self.inner.move_prefix(from, to).await {
+                Err(e) if self.went_offline(e.as_ref()) => {}
+                result => return result,
+            }
+        }
+        self.append(&Op::MovePrefix {
+            from: from.to_string(),
+            to: to.to_string(),
+        })
+        .await?;
+        Ok(0)
+    }
+
+    async fn create_multipart(
+        &self,
+        path: &str,
+        content_type: &str,
+        labels: &Labels,
+    ) -> Result<String, Box<dyn Error>> {
+        if !self.queueing() {
+            match self
+                .inner
+                .create_multipart(path, content_type, labels)
+                .await
+            {
+                Err(e) if self.went_offline(e.as_ref()) => {}
+                result => return result,
+            }
+        }
+        let upload_id = format!("queued-{}", uuid::Uuid::new_v4());
+        self.multipart.lock().unwrap().insert(
+            upload_id.clone(),
+            (path.to_string(), content_type.to_string(), labels.clone()),
+        );
+        Ok(upload_id)
+    }
+
+    async fn upload_part(
+        &self,
+        path: &str,
+        upload_id: &str,
+        number: i32,
+        body: Vec<u8>,
+    ) -> Result<String, Box<dyn Error>> {
+        // Uploads the bucket started have to finish there.
+        if !self.multipart.lock().unwrap().contains_key(upload_id) {
+            return self.inner.upload_part(path, upload_id, number, body).await;
+        }
+        let dir = objects_dir(&self.dir);
+        fs::create_dir_all(&dir).await?;
+        let part = format!("{}.{}", upload_id, number);
+        fs::write(dir.join(&part), body).await?;
+        Ok(part)
+    }
+
+    async fn abort_multipart(&self, path: &str, upload_id: &str) -> Result<(), Box<dyn Error>> {
+        if self.multipart.lock().unwrap().remove(upload_id).is_none() {
+            return self.inner.abort_multipart(path, upload_id).await;
+        }
+        let prefix = format!("{}.", upload_id);
+        let mut entries = fs::read_dir(objects_dir(&self.dir)).await?;
+        while let Some(entry) = entries.next_entry().await? {
+            if entry.file_name().to_string_lossy().starts_with(&prefix) {
+                fs::remove_file(entry.path()).await?;
+            }
+        }
+        Ok(())
+    }
+
+    async fn complete_multipart(
+        &self,
+        path: &str,
+        upload_id: &str,
+        parts: &[(i32, String)],
+    ) -> Result<(), Box<dyn Error>> {
+        let started = self.multipart.lock().unwrap().remove(upload_id);
+        let Some((path, content_type, labels)) = started else {
+            return self.inner.complete_multipart(path, upload_id, parts).await;
+        };
+        let dir = objects_dir(&self.dir);
+        let object = uuid::Uuid::new_v4().to_string();
+        let mut file = fs::File::create(dir.join(&object)).await?;
+        for (_, part) in parts {
+            file.write_all(&fs::read(dir.join(part)).await?).await?;
+        }
+        file.flush().await?;
+        for (_, part) in parts {
+            fs::remove_file(dir.join(part)).await?;
+        }
+        self.append(&Op::Put {
+            path,
+            object,
+            content_type,
+            file_name: None,
+            labels,
+        })
+        .await
+    }
+}
+
+fn journal_path(dir: &Path) -> PathBuf {
+    dir.join("queue.jsonl")
+}
+
+fn objects_dir(dir: &Path) -> PathBuf {
+    dir.join("objects")
+}
+
+async fn read(dir: &Path) -> Result<Vec<Op>, Box<dyn Error>> {
+    let path = journal_path(dir);
+    let content = match fs::read_to_string(&path).await {
+        Ok(content) => content,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e.into()),
+    };
+    content
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| {
+            serde_json::from_str(line)
+                .map_err(|e| format!("Invalid entry in {}: {}", path.display(), e).into())
+        })
+        .collect()
+}
+
+/// How many changes are waiting in the queue at `dir`.
+pub async fn pending(dir: &Path) -> Result<usize, Box<dyn Error>> {
+    Ok(read(dir).await?.len())
+}
+
+/// Sends everything queued in `dir` to `bucket`, oldest first, removing each
+/// change from the queue once it went through. Stops at the first failure
+/// with the rest still queued. Returns how many changes were sent.
+pub async fn flush(bucket: &dyn Publisher, dir: &Path) -> Result<usize, Box<dyn Error>> {
+    let ops = read(dir).await?;
+    let objects = objects_dir(dir);
+    for (done, op) in ops.iter().enumerate() {
+        if let Err(e) = send(bucket, &objects, op).await {
+            let rest: String = ops[done..]
+                .iter()
+                .map(|op| serde_json::to_string(op).map(|line| line + "\n"))
+                .collect::<Result<_, _>>()?;
+            fs::write(journal_path(dir), rest).await?;
+            return Err(format!(
+                "Sent {} of {} queued change(s), the rest stay queued: {}",
+                done,
+                ops.len(),
+                e
+            )
+            .into());
+        }
+    }
+    match fs::remove_file(journal_path(dir)).await {
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => return Err(e.into()),
+        _ => {}
+    }
+    Ok(ops.len())
+}
+
+async fn send(bucket: &dyn Publisher, objects: &Path, op: &Op) -> Result<(), Box<dyn Error>> {
+    match op {
+        Op::Put {
+            path,
+            object,
+            content_type,
+            file_name,
+            labels,
+        } => {
+            let body = fs::read(objects.join(object))
+                .await
+                .map_err(|e| format!("Queued upload of {} is missing: {}", path, e))?;
+            match file_name {
+                Some(file_name) => {
+                    bucket
+                        .put_download(path, body, content_type, file_name, labels)
+                        .await?
+                }
+                None => bucket.put(path, body, content_type, labels).await?,
+            }
+            fs::remove_file(objects.join(object)).await?;
+        }
+        Op::Redirect { path, location } => bucket.put_redirect(path, location).await?,
+        Op::Delete { path } => bucket.delete(path).await?,
+        Op::DeletePrefix { path } => {
+            bucket.delete_prefix(path).await?;
+        }
+        Op::MovePrefix { from, to } => {
+            bucket.move_prefix(from, to).await?;
+        }
+    }
+    Ok(())
+}
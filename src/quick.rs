@@ -0,0 +1,200 @@
+use crate::config::QuickConfig;
+use chrono::{DateTime, Local};
+use fuzzy_matcher::{skim::SkimMatcherV2, FuzzyMatcher};
+use ratatui::{
+    crossterm::event::{self, Event, KeyCode, KeyEventKind, KeyModifiers},
+    layout::{Constraint, Layout},
+    style::{Style, Stylize},
+    text::{Line, Span},
+    widgets::{Block, List, ListItem, ListState, Paragraph},
+    DefaultTerminal,
+};
+use std::{
+    error::Error,
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+use tokio::fs;
+
+/// Extensions of files that can be published as posts.
+const NOTE_EXTENSIONS: &[&str] = &["md", "markdown", "adoc", "asciidoc", "rst", "org", "docx"];
+
+/// A note found in one of the configured directories.
+struct Note {
+    path: PathBuf,
+    /// The path as shown in the picker, relative to its directory.
+    display: String,
+    modified: SystemTime,
+}
+
+/// Lets the user pick one of the most recently modified notes. Returns `None`
+/// if the picker was closed without choosing.
+pub async fn pick(config: &QuickConfig) -> Result<Option<PathBuf>, Box<dyn Error>> {
+    if config.dirs.is_empty() {
+        return Err("Add the directories to search to [quick] dirs in the config".into());
+    }
+    let notes = find_notes(config).await?;
+    if notes.is_empty() {
+        return Err("No notes found in the [quick] directories".into());
+    }
+
+    let mut terminal = ratatui::init();
+    let result = run_picker(&mut terminal, &notes);
+    ratatui::restore();
+    Ok(result?.map(|index| notes[index].path.clone()))
+}
+
+/// `~/notes` becomes `$HOME/notes`.
+fn expand_home(dir: &Path) -> PathBuf {
+    match (dir.strip_prefix("~"), dirs::home_dir()) {
+        (Ok(rest), Some(home)) => home.join(rest),
+        _ => dir.to_path_buf(),
+    }
+}
+
+/// Notes under the configured directories (hidden files and directories
+/// skipped), most recently modified first, at most `config.limit` of them.
+async fn find_notes(config: &QuickConfig) -> Result<Vec<Note>, Box<dyn Error>> {
+    let mut notes = Vec::new();
+    for dir in &config.dirs {
+        let root = expand_home(dir);
+        for (path, modified) in note_files(&root).await? {
+            let display = path
+                .strip_prefix(&root)
+                .unwrap_or(&path)
+                .display()
+                .to_string();
+            notes.push(Note {
+                path,
+                display,
+                modified,
+            });
+        }
+    }
+    notes.sort_by_key(|note| std::cmp::Reverse(note.modified));
+    notes.truncate(config.limit);
+    Ok(notes)
+}
+
+/// Every file under `root` that can be published as a post, with its
+/// modification time, skipping hidden files and directories.
+pub async fn note_files(root: &Path) -> Result<Vec<(PathBuf, SystemTime)>, Box<dyn Error>> {
+    let mut files = Vec::new();
+    let mut pending = vec![root.to_path_buf()];
+    while let Some(current) = pending.pop() {
+        let mut entries = fs::read_dir(&current)
+            .await
+            .map_err(|e| format!("Could not read {}: {}", current.display(), e))?;
+        while let Some(entry) = entries.next_entry().await? {
+            if entry.file_name().to_string_lossy().starts_with('.') {
+                continue;
+            }
+            let path = entry.path();
+            let file_type = entry.file_type().await?;
+            if file_type.is_dir() {
+                pending.push(path);
+                continue;
+            }
+            let is_note = path
+                .extension()
+                .and_then(|ext| ext.to_str())
+                .is_some_and(|ext| NOTE_EXTENSIONS.contains(&ext));
+            if file_type.is_file() && is_note {
+                files.push((path, entry.metadata().await?.modified()?));
+            }
+        }
+    }
+    Ok(files)
+}
+
+/// How long ago `time` was, like `5m`, `3h` or `12d`.
+fn age(time: SystemTime) -> String {
+    let minutes = (Local::now() - DateTime::<Local>::from(time))
+        .num_minutes()
+        .max(0);
+    match minutes {
+        0..60 => format!("{}m", minutes),
+        60..1440 => format!("{}h", minutes / 60),
+        _ => format!("{}d", minutes / 1440),
+    }
+}
+
+/// A skim-style picker: type to fuzzy-filter, arrows to move, Enter to choose.
+/// Returns the index into `notes` of the chosen one.
+fn run_picker(
+    terminal: &mut DefaultTerminal,
+    notes: &[Note],
+) -> Result<Option<usize>, Box<dyn Error>> {
+    let matcher = SkimMatcherV2::default();
+    let mut query = String::new();
+    let mut visible: Vec<usize> = (0..notes.len()).collect();
+    let mut list = ListState::default().with_selected(Some(0));
+
+    loop {
+        terminal.draw(|frame| {
+            let [input_area, list_area] =
+                Layout::vertical([Constraint::Length(3), Constraint::Min(0)]).areas(frame.area());
+            frame.render_widget(
+                Paragraph::new(query.as_str())
+                    .block(Block::bordered().title("Publish which note?")),
+                input_area,
+            );
+            let items: Vec<ListItem> = visible
+                .iter()
+                .map(|&i| {
+                    ListItem::new(Line::from(vec![
+                        Span::raw(format!("{:>4}  ", age(notes[i].modified))).dark_gray(),
+                        Span::raw(notes[i].display.as_str()),
+                    ]))
+                })
+                .collect();
+            frame.render_stateful_widget(
+                List::new(items)
+                    .block(Block::bordered().title(format!("{}/{}", visible.len(), notes.len())))
+                    .highlight_style(Style::new().reversed())
+                    .highlight_symbol("> "),
+                list_area,
+                &mut list,
+            );
+        })?;
+
+        let Event::Key(key) = event::read()? else {
+            continue;
+        };
+        if key.kind != KeyEventKind::Press {
+            continue;
+        }
+        match key.code {
+            KeyCode::Esc => return Ok(None),
+            KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => return Ok(None),
+            KeyCode::Enter => {
+                return Ok(list.selected().and_then(|i| visible.get(i).copied()));
+            }
+            KeyCode::Up => {
+                list.select_previous();
+                continue;
+            }
+            KeyCode::Down => {
+                list.select_next();
+                continue;
+            }
+            KeyCode::Backspace => {
+                query.pop();
+            }
+            KeyCode::Char(c) => query.push(c),
+            _ => continue,
+        }
+
+        // Matches of equal score keep their recency order.
+        let mut scored: Vec<(i64, usize)> = (0..notes.len())
+            .filter_map(|i| {
+                matcher
+                    .fuzzy_match(&notes[i].display, &query)
+                    .map(|score| (score, i))
+            })
+            .collect();
+        scored.sort_by_key(|&(score, _)| std::cmp::Reverse(score));
+        visible = scored.into_iter().map(|(_, i)| i).collect();
+        list.select((!visible.is_empty()).then_some(0));
+    }
+}
@@ -0,0 +1,204 @@
+use crate::config::{PageConfig, SiteConfig};
+use crate::frontmatter::Dir;
+use crate::highlight::Highlighter;
+use crate::render::{escape_html, today, Page};
+use std::{
+    error::Error,
+    path::{Path, PathBuf},
+};
+use tokio::fs;
+
+/// An uploaded file, as shown on the listing page.
+pub struct Entry {
+    /// Name in the bucket, relative to the post's folder.
+    pub name: String,
+    pub size: u64,
+    pub content_type: String,
+    /// The highlighted page for this file, if one was made.
+    pub page: Option<String>,
+}
+
+/// The files to upload: each file as given, and the files directly inside
+/// each directory (sorted by name, hidden files skipped).
+pub async fn collect_files(sources: &[PathBuf]) -> Result<Vec<PathBuf>, Box<dyn Error>> {
+    let mut files = Vec::new();
+    for source in sources {
+        if !fs::metadata(source).await?.is_dir() {
+            files.push(source.clone());
+            continue;
+        }
+        let mut in_dir = Vec::new();
+        let mut entries = fs::read_dir(source).await?;
+        while let Some(entry) = entries.next_entry().await? {
+            let hidden = entry.file_name().to_string_lossy().starts_with('.');
+            if !hidden && entry.file_type().await?.is_file() {
+                in_dir.push(entry.path());
+            }
+        }
+        in_dir.sort();
+        files.extend(in_dir);
+    }
+    if files.is_empty() {
+        return Err("No files to upload".into());
+    }
+    Ok(files)
+}
+
+/// `name`, or `name-2`, `name-3`, ... (before the extension) if it's among
+/// the `taken` names: earlier files, generated pages and `index.html`.
+pub fn unique_name(name: &str, taken: &[String]) -> String {
+    let is_taken = |candidate: &str| taken.iter().any(|taken| taken == candidate);
+    if !is_taken(name) {
+        return name.to_string();
+    }
+    let (stem, extension) = match name.rsplit_once('.') {
+        Some((stem, extension)) if !stem.is_empty() => (stem, format!(".{}", extension)),
+        _ => (name, String::new()),
+    };
+    (2..)
+        .map(|n| format!("{}-{}{}", stem, n, extension))
+        .find(|candidate| !is_taken(candidate))
+        .expect("some suffix is free")
+}
+
+/// Title for a multi-file upload: the directory's name if a single directory
+/// was given, otherwise the number of files.
+pub fn listing_title(sources: &[PathBuf], count: usize) -> String {
+    match sources {
+        [source] => std::path::absolute(source)
+            .ok()
+            .and_then(|path| {
+                path.file_name()
+                    .map(|name| name.to_string_lossy().into_owned())
+            })
+            .unwrap_or_else(|| format!("{} files", count)),
+        _ => format!("{} files", count),
+    }
+}
+
+/// Sizes like `512 B`, `1.5 KB`, `20.0 MB`.
+pub fn format_size(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["KB", "MB", "GB", "TB"];
+    if bytes < 1024 {
+        return format!("{} B", bytes);
+    }
+    let mut size = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    format!("{:.1} {}", size, UNITS[unit])
+}
+
+/// A page listing uploaded files with their sizes, types and download links.
+pub fn listing_page(
+    title: &str,
+    entries: &[Entry],
+    page_config: &PageConfig,
+    site: &SiteConfig,
+) -> Result<String, Box<dyn Error>> {
+    let mut rows = String::new();
+    for entry in entries {
+        let name = escape_html(&entry.name);
+        let view = entry
+            .page
+            .as_deref()
+            .map(|page| format!(" (<a href=\"{}\">view</a>)", escape_html(page)))
+            .unwrap_or_default();
+        rows.push_str(&format!(
+            "<tr><td><a href=\"{name}\">{name}</a>{view}</td><td>{size}</td><td>{content_type}</td><td><a href=\"{name}\" download>Download</a></td></tr>\n",
+            size = format_size(entry.size),
+            content_type = escape_html(&entry.content_type),
+        ));
+    }
+    let total: u64 = entries.iter().map(|entry| entry.size).sum();
+    let body = format!(
+        "<p>{count} files, {total}</p>\n<div class=\"table-scroll\">\n<table>\n<thead>\n<tr><th>Name</th><th>Size</th><th>Type</th><th></th></tr>\n</thead>\n<tbody>\n{rows}</tbody>\n</table>\n</div>",
+        count = entries.len(),
+        total = format_size(total),
+    );
+    let date = today(page_config)?;
+    Ok(Page {
+        title,
+        heading: Some(title),
+        description: None,
+        lang: &page_config.lang,
+        dir: Dir::from_lang(&page_config.lang),
+        date: &date,
+        body: &body,
+        alternates: &[],
+        image_url: None,
+        site,
+        authors: &[],
+        source_url: None,
+    }
+    .render())
+}
+
+/// The file name as it will appear in the URL: anything but letters, digits,
+/// `.`, `-` and `_` becomes `-`.
+pub fn safe_file_name(path: &Path) -> String {
+    let name = path
+        .file_name()
+        .and_then(|name| name.to_str())
+        .unwrap_or("file");
+    name.chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || matches!(c, '.' | '-' | '_') {
+                c
+            } else {
+                '-'
+            }
+        })
+        .collect()
+}
+
+/// Copies the snippet's text (without the CSS line numbers) to the clipboard.
+const COPY_SCRIPT: &str = r#"<script>
+document.querySelector(".snippet-toolbar .copy").addEventListener("click", event => {
+    const code = document.querySelector("pre.code code").innerText;
+    navigator.clipboard.writeText(code).then(() => {
+        event.target.textContent = "Copied";
+        setTimeout(() => event.target.textContent = "Copy", 1500);
+    });
+});
+</script>"#;
+
+/// A page showing a text file with line numbers, syntax highlighting, links
+/// to the raw file and a copy button.
+pub fn wrapper_page(
+    file_name: &str,
+    code: &str,
+    highlighter: &Highlighter,
+    page_config: &PageConfig,
+    site: &SiteConfig,
+) -> Result<String, Box<dyn Error>> {
+    let body = format!(
+        r#"<div class="snippet-toolbar">
+    <a href="{name}">Raw</a>
+    <a href="{name}" download>Download</a>
+    <button type="button" class="copy">Copy</button>
+</div>
+{code}
+{COPY_SCRIPT}"#,
+        name = escape_html(file_name),
+        code = highlighter.highlight(code, Path::new(file_name))?,
+    );
+    let date = today(page_config)?;
+    Ok(Page {
+        title: file_name,
+        heading: Some(file_name),
+        description: None,
+        lang: &page_config.lang,
+        dir: Dir::from_lang(&page_config.lang),
+        date: &date,
+        body: &body,
+        alternates: &[],
+        image_url: None,
+        site,
+        authors: &[],
+        source_url: None,
+    }
+    .render())
+}
@@ -0,0 +1,74 @@
+use std::{collections::BTreeMap, error::Error, path::Path};
+use tokio::fs;
+
+/// Reads `redirects.toml`: a table of old slugs and where they now point,
+/// either another slug on the site or a full URL.
+///
+/// ```toml
+/// "p/0c9e4f6a-…" = "p/7f3a1b2c-…"
+/// "notes/rust" = "https://example.com/rust"
+/// ```
+///
+/// A missing file means there are no redirects. Slugs lose their leading and
+/// trailing slashes.
+pub async fn load(path: &Path) -> Result<BTreeMap<String, String>, Box<dyn Error>> {
+    let content = match fs::read_to_string(path).await {
+        Ok(content) => content,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(BTreeMap::new()),
+        Err(e) => return Err(e.into()),
+    };
+    let redirects: BTreeMap<String, String> = toml::from_str(&content)
+        .map_err(|e| format!("Invalid redirects file {}: {}", path.display(), e))?;
+    Ok(redirects
+        .into_iter()
+        .map(|(slug, target)| (slug.trim_matches('/').to_string(), target))
+        .filter(|(slug, _)| !slug.is_empty())
+        .collect())
+}
+
+/// Whether `target` is a full URL rather than a slug on this site.
+pub fn is_absolute(target: &str) -> bool {
+    target.starts_with("https://") || target.starts_with("http://")
+}
+
+/// Adds `redirects` to the file at `path`, keeping what's already there.
+/// Slugs that already redirect somewhere are left alone. Returns how many
+/// were added.
+pub async fn add(path: &Path, redirects: &[(String, String)]) -> Result<usize, Box<dyn Error>> {
+    let existing = load(path).await?;
+    let mut content = match fs::read_to_string(path).await {
+        Ok(content) => content,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => String::new(),
+        Err(e) => return Err(e.into()),
+    };
+    let mut added = 0;
+    for (slug, target) in redirects {
+        let slug = slug.trim_matches('/');
+        if slug.is_empty() || existing.contains_key(slug) {
+            continue;
+        }
+        if !content.is_empty() && !content.ends_with('\n') {
+            content.push('\n');
+        }
+        content.push_str(&format!(
+            "{} = {}\n",
+            toml::Value::String(slug.to_string()),
+            toml::Value::String(target.clone())
+        ));
+        added += 1;
+    }
+    if added > 0 {
+        fs::write(path, content).await?;
+    }
+    Ok(added)
+}
+
+/// The key a redirect from `slug` is stored under: the slug itself for an
+/// old `.html` page, otherwise its `index.html`.
+pub fn object_key(slug: &str) -> String {
+    if slug.ends_with(".html") || slug.ends_with(".htm") {
+        slug.to_string()
+    } else {
+        format!("{}/index.html", slug)
+    }
+}
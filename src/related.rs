@@ -0,0 +1,68 @@
+use crate::frontmatter::Labels;
+use crate::manifest::{Manifest, Post};
+use std::{cmp::Ordering, collections::HashSet};
+
+/// Posts that can be linked as related to the post `id`: everything else in
+/// the manifest except raw uploads.
+pub fn candidates(manifest: &Manifest, id: Option<&str>) -> Vec<Post> {
+    manifest
+        .posts
+        .iter()
+        .filter(|post| !post.raw && Some(post.id.as_str()) != id)
+        .cloned()
+        .collect()
+}
+
+/// Lowercased words of three or more letters, so "a", "of" and the like
+/// don't make titles look similar.
+fn words(title: &str) -> HashSet<String> {
+    title
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|word| word.chars().count() >= 3)
+        .map(str::to_lowercase)
+        .collect()
+}
+
+/// How related two posts are: one point per shared label (same key and
+/// value) plus up to two for overlapping title words.
+fn score(title_words: &HashSet<String>, labels: &Labels, other: &Post) -> f64 {
+    let shared_labels = labels
+        .iter()
+        .filter(|(key, value)| other.labels.get(*key) == Some(*value))
+        .count();
+    let other_words = words(&other.title);
+    let union = title_words.union(&other_words).count();
+    let title_similarity = if union == 0 {
+        0.0
+    } else {
+        title_words.intersection(&other_words).count() as f64 / union as f64
+    };
+    shared_labels as f64 + 2.0 * title_similarity
+}
+
+/// Up to `count` of `candidates` most related to a post with `title` and
+/// `labels`, best first and newest first among equals. Unrelated posts are
+/// left out.
+pub fn find<'a>(
+    title: &str,
+    labels: &Labels,
+    candidates: impl IntoIterator<Item = &'a Post>,
+    count: usize,
+) -> Vec<&'a Post> {
+    let title_words = words(title);
+    let mut scored: Vec<(f64, &Post)> = candidates
+        .into_iter()
+        .map(|post| (score(&title_words, labels, post), post))
+        .filter(|(score, _)| *score > 0.0)
+        .collect();
+    scored.sort_by(|(a, post_a), (b, post_b)| {
+        b.partial_cmp(a)
+            .unwrap_or(Ordering::Equal)
+            .then(post_b.published.cmp(&post_a.published))
+    });
+    scored
+        .into_iter()
+        .take(count)
+        .map(|(_, post)| post)
+        .collect()
+}
@@ -0,0 +1,1788 @@
+use crate::codeblock;
+use crate::config::{Author, FootnoteStyle, MarkdownBackend, PageConfig, SiteConfig};
+use crate::details;
+use crate::docx;
+use crate::formats::{self, InputFormat};
+use crate::frontmatter::{
+    local_midnight, split_file_date, ChangelogEntry, Dir, FrontMatter, Labels,
+};
+use crate::manifest::Post;
+use crate::media::{self, Audio, FileStarts, Media};
+use crate::related;
+use crate::sidenote;
+use crate::table;
+use crate::template::{Template, Value};
+use chrono::{DateTime, Local, Locale, Utc};
+use comrak::Anchorizer;
+use pulldown_cmark::{
+    html::{push_html, write_html_fmt},
+    Event, HeadingLevel, Options, Parser as MarkdownParser, Tag, TagEnd,
+};
+use std::{
+    borrow::Cow,
+    collections::{BTreeMap, VecDeque},
+    error::Error,
+    fmt::{self, Write as _},
+    path::Path,
+};
+
+/// The stylesheet every page shares.
+pub const STYLE: &str = r#"<style>
+        :root {
+            --background: #121212;
+            --text: rgba(255, 255, 255, 0.87);
+            --text-secondary: rgba(255, 255, 255, 0.6);
+            --max-width: 800px;
+            --spacing: 2rem;
+        }
+
+        * {
+            margin: 0;
+            padding: 0;
+            box-sizing: border-box;
+        }
+
+        body {
+            font-family: -apple-system, BlinkMacSystemFont, "Segoe UI", Roboto, Oxygen-Sans, Ubuntu, Cantarell, "Helvetica Neue", sans-serif;
+            background: var(--background);
+            color: var(--text);
+            line-height: 1.6;
+            padding: var(--spacing);
+        }
+
+        .container {
+            max-width: var(--max-width);
+            margin: 0 auto;
+            padding: var(--spacing);
+        }
+
+        .site-header {
+            margin-bottom: 2rem;
+            font-weight: 600;
+        }
+
+        .site-header a {
+            color: var(--text);
+        }
+
+        .site-footer {
+            margin-top: 3rem;
+            padding-top: 1rem;
+            border-top: 1px solid rgba(255, 255, 255, 0.2);
+            color: var(--text-secondary);
+            display: flex;
+            flex-wrap: wrap;
+            gap: 1rem;
+        }
+
+        .changelog time {
+            color: var(--text-secondary);
+            margin-inline-end: 0.5rem;
+        }
+
+        input[type="search"] {
+            width: 100%;
+            padding: 0.5rem 0.75rem;
+            margin-bottom: 1.5rem;
+            font: inherit;
+            color: var(--text);
+            background: rgba(255, 255, 255, 0.05);
+            border: 1px solid var(--text-secondary);
+            border-radius: 4px;
+        }
+
+        .posts {
+            list-style: none;
+            padding: 0;
+        }
+
+        .posts li {
+            margin-bottom: 1rem;
+        }
+
+        .posts time {
+            color: var(--text-secondary);
+            margin-inline-start: 0.5rem;
+        }
+
+        .posts .sections {
+            display: flex;
+            flex-wrap: wrap;
+            gap: 0.25rem 1rem;
+            list-style: none;
+            font-size: 0.875rem;
+            margin-top: 0.25rem;
+        }
+
+        .posts .sections li {
+            margin: 0;
+        }
+
+        .archive-year h3 {
+            margin: 1rem 0 0.5rem;
+        }
+
+        .count {
+            color: var(--text-secondary);
+            font-weight: normal;
+        }
+
+        .languages {
+            color: var(--text-secondary);
+            margin-bottom: 1rem;
+        }
+
+        .languages a[aria-current] {
+            color: var(--text);
+            font-weight: 600;
+        }
+
+        .date {
+            color: var(--text-secondary);
+            margin-bottom: 1rem;
+            font-size: 1rem;
+        }
+
+        .byline {
+            color: var(--text-secondary);
+            margin-bottom: 1.5rem;
+        }
+
+        .byline .avatar {
+            width: 1.5em;
+            height: 1.5em;
+            border-radius: 50%;
+            margin: 0 0.4em 0 0;
+            vertical-align: middle;
+        }
+
+        h1 {
+            font-size: 2.5rem;
+            font-weight: 600;
+            margin-bottom: 0.5rem;
+            line-height: 1.2;
+        }
+
+        h2 {
+            font-size: 1.75rem;
+            color: var(--text);
+            margin: 2rem 0 1rem;
+        }
+
+        p {
+            margin-bottom: 1.5rem;
+            font-size: 1.1rem;
+        }
+
+        a {
+            color: #3B82F6;
+            text-decoration: none;
+        }
+
+        a:hover {
+            text-decoration: underline;
+        }
+
+        code {
+            font-family: "SF Mono", "Segoe UI Mono", "Roboto Mono", Menlo, Courier, monospace;
+            background: rgba(255, 255, 255, 0.1);
+            padding: 0.2em 0.4em;
+            border-radius: 3px;
+            font-size: 0.9em;
+        }
+
+        pre {
+            background: rgba(255, 255, 255, 0.1);
+            padding: 1rem;
+            border-radius: 4px;
+            overflow-x: auto;
+            margin: 1.5rem 0;
+        }
+
+        pre code {
+            background: none;
+            padding: 0;
+        }
+
+        ul, ol {
+            padding-inline-start: 1.5rem;
+            margin-bottom: 1.5rem;
+        }
+
+        blockquote {
+            border-inline-start: 3px solid var(--text-secondary);
+            padding-inline-start: 1rem;
+            margin: 1.5rem 0;
+            color: var(--text-secondary);
+        }
+
+        .sidenote-number {
+            font-size: 0.75em;
+            line-height: 0;
+            vertical-align: super;
+        }
+
+        label.sidenote-number {
+            cursor: pointer;
+            color: #3B82F6;
+        }
+
+        .sidenote-toggle {
+            display: none;
+        }
+
+        .sidenote {
+            display: none;
+            font-size: 0.875rem;
+            color: var(--text-secondary);
+        }
+
+        .sidenote-toggle:checked + .sidenote {
+            display: block;
+            margin: 0.75rem 0;
+            padding-inline-start: 1rem;
+            border-inline-start: 2px solid rgba(255, 255, 255, 0.2);
+        }
+
+        @media (min-width: 1280px) {
+            .sidenote,
+            .sidenote-toggle:checked + .sidenote {
+                display: block;
+                float: inline-end;
+                clear: inline-end;
+                width: 12rem;
+                margin-block: 0 1rem;
+                margin-inline: 0 -14rem;
+                padding: 0;
+                border: none;
+            }
+
+            label.sidenote-number {
+                cursor: default;
+            }
+        }
+
+        details {
+            border: 1px solid rgba(255, 255, 255, 0.15);
+            border-radius: 8px;
+            padding: 0.5rem 1rem;
+            margin: 1.5rem 0;
+        }
+
+        details > summary {
+            cursor: pointer;
+            font-weight: 600;
+        }
+
+        details[open] > summary {
+            margin-bottom: 1rem;
+        }
+
+        img {
+            max-width: 100%;
+            height: auto;
+            border-radius: 8px;
+            margin: 1.5rem 0;
+        }
+
+        figure.audio {
+            margin: 1.5rem 0;
+        }
+
+        figure.audio audio {
+            width: 100%;
+        }
+
+        figure.audio figcaption {
+            font-size: 0.875rem;
+            color: var(--text-secondary);
+        }
+
+        li:has(> input[type="checkbox"]),
+        li:has(> p > input[type="checkbox"]) {
+            list-style: none;
+        }
+
+        li input[type="checkbox"] {
+            margin-inline: -1.5rem 0.5rem;
+        }
+
+        .markdown-alert,
+        blockquote[class^="markdown-alert-"] {
+            border-inline-start: 4px solid var(--alert-color);
+            background: rgba(255, 255, 255, 0.04);
+            padding: 0.5rem 1rem;
+            margin: 1.5rem 0;
+            border-radius: 4px;
+        }
+
+        .markdown-alert-title,
+        blockquote[class^="markdown-alert-"]::before {
+            display: block;
+            margin: 0.5rem 0;
+            font-weight: bold;
+            color: var(--alert-color);
+        }
+
+        blockquote.markdown-alert-note::before {
+            content: "Note";
+        }
+
+        blockquote.markdown-alert-tip::before {
+            content: "Tip";
+        }
+
+        blockquote.markdown-alert-important::before {
+            content: "Important";
+        }
+
+        blockquote.markdown-alert-warning::before {
+            content: "Warning";
+        }
+
+        blockquote.markdown-alert-caution::before {
+            content: "Caution";
+        }
+
+        .markdown-alert-note {
+            --alert-color: #4493f8;
+        }
+
+        .markdown-alert-tip {
+            --alert-color: #3fb950;
+        }
+
+        .markdown-alert-important {
+            --alert-color: #ab7df8;
+        }
+
+        .markdown-alert-warning {
+            --alert-color: #d29922;
+        }
+
+        .markdown-alert-caution {
+            --alert-color: #f85149;
+        }
+
+        .attachment {
+            display: flex;
+            gap: 0.75rem;
+            align-items: center;
+            margin: 1.5rem 0;
+            padding: 0.75rem 1rem;
+            border: 1px solid rgba(255, 255, 255, 0.2);
+            border-radius: 8px;
+        }
+
+        .attachment-type {
+            min-width: 3rem;
+            padding: 0.5rem 0.25rem;
+            font-size: 0.75rem;
+            font-weight: bold;
+            text-align: center;
+            background: rgba(255, 255, 255, 0.1);
+            border-radius: 4px;
+        }
+
+        .attachment-size {
+            margin-inline-start: auto;
+            color: var(--text-secondary);
+            white-space: nowrap;
+        }
+
+        video {
+            display: block;
+            max-width: 100%;
+            height: auto;
+            border-radius: 8px;
+            margin: 1.5rem 0;
+        }
+
+        .subtitle {
+            color: var(--text-secondary);
+            font-size: 1.25rem;
+            margin-bottom: 2rem;
+        }
+
+        table {
+            width: 100%;
+            border-collapse: collapse;
+            margin-bottom: 1.5rem;
+        }
+
+        th, td {
+            border: 1px solid rgba(255, 255, 255, 0.2);
+            padding: 0.75rem;
+            text-align: start;
+        }
+
+        thead {
+            background-color: rgba(255, 255, 255, 0.1);
+        }
+
+        pre.code {
+            padding: 1rem 0;
+            counter-reset: line;
+            direction: ltr;
+        }
+
+        pre.code .line {
+            display: inline-block;
+            padding-inline-end: 1rem;
+        }
+
+        pre.code .line::before {
+            counter-increment: line;
+            content: counter(line);
+            display: inline-block;
+            width: 3rem;
+            margin-inline-end: 1rem;
+            text-align: end;
+            color: var(--text-secondary);
+            user-select: none;
+        }
+
+        pre.code.no-linenos .line {
+            padding-inline-start: 1rem;
+        }
+
+        pre.code.no-linenos .line::before {
+            content: none;
+        }
+
+        pre.code .line.hl {
+            min-width: 100%;
+            box-sizing: border-box;
+            background: rgba(255, 255, 255, 0.12);
+        }
+
+        .snippet-toolbar {
+            display: flex;
+            gap: 1rem;
+            align-items: center;
+            margin-bottom: 1rem;
+        }
+
+        .snippet-toolbar button {
+            font: inherit;
+            color: inherit;
+            background: rgba(255, 255, 255, 0.1);
+            border: 1px solid rgba(255, 255, 255, 0.2);
+            border-radius: 4px;
+            padding: 0.25rem 0.75rem;
+            cursor: pointer;
+        }
+
+        .table-scroll {
+            overflow-x: auto;
+            margin-bottom: 1.5rem;
+        }
+
+        .table-scroll table {
+            margin-bottom: 0;
+        }
+
+        .data-table.sortable th {
+            cursor: pointer;
+            white-space: nowrap;
+        }
+
+        .data-table th[aria-sort="ascending"]::after {
+            content: " ▲";
+        }
+
+        .data-table th[aria-sort="descending"]::after {
+            content: " ▼";
+        }
+
+        .gallery {
+            display: grid;
+            grid-template-columns: repeat(auto-fill, minmax(180px, 1fr));
+            gap: 0.5rem;
+            margin-bottom: 1.5rem;
+        }
+
+        .gallery img {
+            display: block;
+            width: 100%;
+            aspect-ratio: 1;
+            object-fit: cover;
+            margin: 0;
+            border-radius: 4px;
+        }
+
+        dialog.lightbox {
+            max-width: 95vw;
+            max-height: 95vh;
+            padding: 0;
+            border: none;
+            background: transparent;
+        }
+
+        dialog.lightbox::backdrop {
+            background: rgba(0, 0, 0, 0.85);
+        }
+
+        dialog.lightbox img {
+            display: block;
+            max-width: 95vw;
+            max-height: 95vh;
+            margin: 0;
+        }
+
+        dialog.lightbox button {
+            position: fixed;
+            font-size: 2rem;
+            color: #fff;
+            background: rgba(0, 0, 0, 0.4);
+            border: none;
+            padding: 0.25rem 0.75rem;
+            cursor: pointer;
+        }
+
+        dialog.lightbox .previous {
+            top: 50%;
+            left: 0.5rem;
+        }
+
+        dialog.lightbox .next {
+            top: 50%;
+            right: 0.5rem;
+        }
+
+        dialog.lightbox .close {
+            top: 0.5rem;
+            right: 0.5rem;
+        }
+    </style>"#;
+
+/// Everything needed to wrap rendered markdown into a standalone HTML page.
+pub struct Page<'a> {
+    pub title: &'a str,
+    /// Rendered as the page heading when the title was lifted out of the body.
+    pub heading: Option<&'a str>,
+    pub description: Option<&'a str>,
+    pub lang: &'a str,
+    pub dir: Dir,
+    pub date: &'a str,
+    pub body: &'a str,
+    /// Other language versions of this page, including the page itself.
+    pub alternates: &'a [Alternate],
+    pub image_url: Option<&'a str>,
+    /// Site title, author, footer and links.
+    pub site: &'a SiteConfig,
+    /// Shown in the byline under the date.
+    pub authors: &'a [Author],
+    /// Where the post's markdown is published, linked from the footer.
+    pub source_url: Option<&'a str>,
+}
+
+/// A translation of the page, linked via `hreflang` and the language switcher.
+pub struct Alternate {
+    pub lang: String,
+    pub url: String,
+}
+
+/// Markdown rendered into a full page, along with the title it ended up with.
+pub struct RenderedPost {
+    pub title: String,
+    pub description: Option<String>,
+    /// The date as shown on the page.
+    pub date: String,
+    /// Absolute `http(s)` links in the post, without duplicates.
+    pub links: Vec<String>,
+    pub html: String,
+    /// Labels from the front matter.
+    pub labels: Labels,
+    /// Names of the authors in the byline.
+    pub authors: Vec<String>,
+    /// Local files the page embeds, to upload next to it.
+    pub media: Vec<Media>,
+    /// The audio file from the front matter, one of `media`.
+    pub audio: Option<Audio>,
+    /// Headings of the top two levels, for linking to sections.
+    pub sections: Vec<Heading>,
+    /// Roughly how many words the post has.
+    pub words: usize,
+}
+
+/// A heading in a post, with the id the page gives it.
+pub struct Heading {
+    pub title: String,
+    pub anchor: String,
+}
+
+/// Extensions enabled for pulldown-cmark.
+pub fn markdown_options() -> Options {
+    let mut options = Options::empty();
+    options.insert(Options::ENABLE_TABLES);
+    options.insert(Options::ENABLE_TASKLISTS);
+    // GitHub's `> [!NOTE]` alerts.
+    options.insert(Options::ENABLE_GFM);
+    options.insert(Options::ENABLE_FOOTNOTES);
+    // `$inline$` and `$$display$$` math, left for a script like KaTeX to typeset.
+    options.insert(Options::ENABLE_MATH);
+    options
+}
+
+/// Converts markdown to HTML with the given backend, optionally leaving out
+/// the first `# Heading`.
+pub fn markdown_to_html(
+    markdown: &str,
+    strip_first_heading: bool,
+    backend: MarkdownBackend,
+) -> Result<String, Box<dyn Error>> {
+    let markdown = if strip_first_heading {
+        without_first_heading(markdown)
+    } else {
+        Cow::Borrowed(markdown)
+    };
+
+    match backend {
+        MarkdownBackend::Pulldown => {
+            let mut html_output = String::new();
+            push_html(
+                &mut html_output,
+                HeadingIds::new(MarkdownParser::new_ext(&markdown, markdown_options())),
+            );
+            Ok(html_output)
+        }
+        MarkdownBackend::Comrak => Ok(comrak::markdown_to_html(&markdown, &comrak_options())),
+        MarkdownBackend::Pandoc => pandoc_to_html(&markdown),
+    }
+}
+
+/// Gives every heading an id made from its text, the way GitHub and comrak
+/// do, so links can point at a section.
+struct HeadingIds<'a, I> {
+    events: I,
+    anchorizer: Anchorizer,
+    /// The rest of the heading whose start was just returned.
+    buffered: VecDeque<Event<'a>>,
+}
+
+impl<'a, I: Iterator<Item = Event<'a>>> HeadingIds<'a, I> {
+    fn new(events: I) -> Self {
+        Self {
+            events,
+            anchorizer: Anchorizer::new(),
+            buffered: VecDeque::new(),
+        }
+    }
+}
+
+impl<'a, I: Iterator<Item = Event<'a>>> Iterator for HeadingIds<'a, I> {
+    type Item = Event<'a>;
+
+    fn next(&mut self) -> Option<Event<'a>> {
+        if let Some(event) = self.buffered.pop_front() {
+            return Some(event);
+        }
+        match self.events.next()? {
+            Event::Start(Tag::Heading {
+                level,
+                id: None,
+                classes,
+                attrs,
+            }) => {
+                // The same text comrak takes the id from.
+                let mut text = String::new();
+                for event in self.events.by_ref() {
+                    match &event {
+                        Event::Text(part) | Event::Code(part) | Event::InlineMath(part) => {
+                            text.push_str(part)
+                        }
+                        Event::SoftBreak | Event::HardBreak => text.push(' '),
+                        _ => {}
+                    }
+                    let end = matches!(event, Event::End(TagEnd::Heading(_)));
+                    self.buffered.push_back(event);
+                    if end {
+                        break;
+                    }
+                }
+                Some(Event::Start(Tag::Heading {
+                    level,
+                    id: Some(self.anchorizer.anchorize(text).into()),
+                    classes,
+                    attrs,
+                }))
+            }
+            event => Some(event),
+        }
+    }
+}
+
+/// The headings of the top two levels in `markdown`, with the ids the page
+/// gives them. With `title_heading`, the first `# Heading` is the post's
+/// title rather than a section.
+pub fn sections(markdown: &str, title_heading: bool) -> Vec<Heading> {
+    let mut headings = Vec::new();
+    let mut current: Option<(HeadingLevel, String, String)> = None;
+    for event in HeadingIds::new(MarkdownParser::new_ext(markdown, markdown_options())) {
+        match event {
+            Event::Start(Tag::Heading {
+                level,
+                id: Some(id),
+                ..
+            }) => current = Some((level, id.to_string(), String::new())),
+            Event::Text(text) | Event::Code(text) | Event::InlineMath(text) => {
+                if let Some((_, _, title)) = &mut current {
+                    title.push_str(&text);
+                }
+            }
+            Event::End(TagEnd::Heading(_)) => headings.extend(current.take()),
+            _ => {}
+        }
+    }
+    if title_heading {
+        if let Some(title) = headings
+            .iter()
+            .position(|(level, _, _)| *level == HeadingLevel::H1)
+        {
+            headings.remove(title);
+        }
+    }
+    let Some(top) = headings.iter().map(|(level, _, _)| *level).min() else {
+        return Vec::new();
+    };
+    headings
+        .into_iter()
+        .filter(|(level, _, _)| (*level as usize) <= top as usize + 1)
+        .map(|(_, anchor, title)| Heading {
+            title: title.trim().to_string(),
+            anchor,
+        })
+        .collect()
+}
+
+/// `markdown` with its first `# Heading` cut out of the source text, so
+/// every backend sees the same body.
+fn without_first_heading(markdown: &str) -> Cow<'_, str> {
+    let heading = MarkdownParser::new_ext(markdown, markdown_options())
+        .into_offset_iter()
+        .find_map(|(event, range)| {
+            matches!(
+                event,
+                Event::Start(Tag::Heading {
+                    level: HeadingLevel::H1,
+                    ..
+                })
+            )
+            .then_some(range)
+        });
+    match heading {
+        Some(range) => Cow::Owned(format!(
+            "{}{}",
+            &markdown[..range.start],
+            &markdown[range.end..]
+        )),
+        None => Cow::Borrowed(markdown),
+    }
+}
+
+fn comrak_options() -> comrak::Options<'static> {
+    let mut options = comrak::Options::default();
+    options.extension.table = true;
+    options.extension.strikethrough = true;
+    options.extension.tasklist = true;
+    options.extension.autolink = true;
+    options.extension.footnotes = true;
+    options.extension.description_lists = true;
+    options.extension.alerts = true;
+    options.extension.math_dollars = true;
+    // Ids from the heading text, as `HeadingIds` gives them with pulldown.
+    options.extension.header_ids = Some(String::new());
+    // Like pulldown-cmark, pass raw HTML through.
+    options.render.unsafe_ = true;
+    options
+}
+
+fn pandoc_to_html(markdown: &str) -> Result<String, Box<dyn Error>> {
+    formats::run_converter("pandoc", &["--from", "markdown", "--to", "html5"], markdown)
+}
+
+/// Plain text of the first `# Heading` in the document, if there is one.
+pub fn first_heading(markdown: &str) -> Option<String> {
+    let mut parser = MarkdownParser::new_ext(markdown, markdown_options());
+    parser.find(|event| {
+        matches!(
+            event,
+            Event::Start(Tag::Heading {
+                level: HeadingLevel::H1,
+                ..
+            })
+        )
+    })?;
+
+    let mut text = String::new();
+    for event in parser {
+        match event {
+            Event::Text(t) | Event::Code(t) | Event::InlineMath(t) => text.push_str(&t),
+            Event::SoftBreak | Event::HardBreak => text.push(' '),
+            Event::End(TagEnd::Heading(_)) => break,
+            _ => {}
+        }
+    }
+    let text = text.trim();
+    (!text.is_empty()).then(|| text.to_string())
+}
+
+/// Longest description we'll put in meta tags before cutting it off.
+const MAX_DESCRIPTION_CHARS: usize = 160;
+
+/// Short plain-text summary of the post: everything before a `<!--more-->`
+/// marker, or otherwise the first paragraph.
+pub fn summary(markdown: &str) -> Option<String> {
+    let mut paragraphs: Vec<String> = Vec::new();
+    let mut in_paragraph = false;
+    for event in MarkdownParser::new_ext(markdown, markdown_options()) {
+        match event {
+            Event::Start(Tag::Paragraph) => {
+                in_paragraph = true;
+                paragraphs.push(String::new());
+            }
+            Event::End(TagEnd::Paragraph) => in_paragraph = false,
+            Event::Text(t) | Event::Code(t) | Event::InlineMath(t) if in_paragraph => {
+                if let Some(p) = paragraphs.last_mut() {
+                    p.push_str(&t);
+                }
+            }
+            Event::SoftBreak | Event::HardBreak if in_paragraph => {
+                if let Some(p) = paragraphs.last_mut() {
+                    p.push(' ');
+                }
+            }
+            Event::Html(html) | Event::InlineHtml(html) if html.contains("<!--more-->") => {
+                let text = paragraphs.join(" ");
+                return truncate_description(&text);
+            }
+            _ => {}
+        }
+    }
+    paragraphs
+        .into_iter()
+        .find(|p| !p.trim().is_empty())
+        .and_then(|p| truncate_description(&p))
+}
+
+fn truncate_description(text: &str) -> Option<String> {
+    let text = text.split_whitespace().collect::<Vec<_>>().join(" ");
+    if text.is_empty() {
+        return None;
+    }
+    if text.chars().count() <= MAX_DESCRIPTION_CHARS {
+        return Some(text);
+    }
+    let cut: String = text.chars().take(MAX_DESCRIPTION_CHARS).collect();
+    let cut = match cut.rfind(' ') {
+        Some(space) => &cut[..space],
+        None => &cut,
+    };
+    Some(format!(
+        "{}…",
+        cut.trim_end_matches(|c: char| c.is_ascii_punctuation())
+    ))
+}
+
+/// All the text of a post with the front matter and markup removed, for
+/// search.
+pub fn plain_text(source: &str, format: InputFormat) -> Result<String, Box<dyn Error>> {
+    let (_, body) = FrontMatter::parse(source)?;
+    let text = match format {
+        InputFormat::Markdown => {
+            let mut text = String::new();
+            for event in MarkdownParser::new_ext(body, markdown_options()) {
+                match event {
+                    Event::Text(t)
+                    | Event::Code(t)
+                    | Event::InlineMath(t)
+                    | Event::DisplayMath(t) => text.push_str(&t),
+                    Event::SoftBreak | Event::HardBreak | Event::End(_) => text.push(' '),
+                    _ => {}
+                }
+            }
+            text
+        }
+        format => strip_tags(&formats::convert(format, body)?.html),
+    };
+    Ok(text.split_whitespace().collect::<Vec<_>>().join(" "))
+}
+
+/// Destinations of all absolute `http(s)` links in the document, in order.
+pub fn external_links(markdown: &str) -> Vec<String> {
+    let mut links: Vec<String> = Vec::new();
+    for event in MarkdownParser::new_ext(markdown, markdown_options()) {
+        if let Event::Start(Tag::Link { dest_url, .. }) = event {
+            let is_web = dest_url.starts_with("https://") || dest_url.starts_with("http://");
+            if is_web && !links.iter().any(|l| l.as_str() == dest_url.as_ref()) {
+                links.push(dest_url.to_string());
+            }
+        }
+    }
+    links
+}
+
+/// Like [`summary`], for documents that were converted straight to HTML:
+/// the text of the first non-empty paragraph.
+pub fn html_summary(html: &str) -> Option<String> {
+    html.split("<p").skip(1).find_map(|rest| {
+        // Skip tags that merely start with "p", like <pre>.
+        if !rest.starts_with(['>', ' ']) {
+            return None;
+        }
+        let inner = rest.split_once('>')?.1.split("</p>").next()?;
+        truncate_description(&strip_tags(inner))
+    })
+}
+
+/// Like [`external_links`], for documents that were converted straight to HTML.
+pub fn html_links(html: &str) -> Vec<String> {
+    let mut links: Vec<String> = Vec::new();
+    for tag in html.split('<').skip(1) {
+        let tag = tag.split('>').next().unwrap_or("");
+        if !tag.to_ascii_lowercase().starts_with("a ") {
+            continue;
+        }
+        let attributes = parse_attributes(tag);
+        if let Some((_, href)) = attributes.iter().find(|(name, _)| name == "href") {
+            let href = unescape_html(href);
+            let is_web = href.starts_with("https://") || href.starts_with("http://");
+            if is_web && !links.contains(&href) {
+                links.push(href);
+            }
+        }
+    }
+    links
+}
+
+fn strip_tags(html: &str) -> String {
+    let mut text = String::with_capacity(html.len());
+    let mut in_tag = false;
+    for c in html.chars() {
+        match c {
+            '<' => in_tag = true,
+            '>' => in_tag = false,
+            _ if !in_tag => text.push(c),
+            _ => {}
+        }
+    }
+    unescape_html(&text)
+}
+
+/// Reverses [`escape_html`] (plus `&nbsp;`), which is what converters emit.
+pub fn unescape_html(text: &str) -> String {
+    text.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&#39;", "'")
+        .replace("&nbsp;", "\u{a0}")
+        .replace("&amp;", "&")
+}
+
+/// Parses `name="value"` pairs from the inside of an HTML tag. Names are
+/// lowercased; unquoted and single-quoted values are accepted.
+pub fn parse_attributes(tag: &str) -> Vec<(String, String)> {
+    let mut attributes = Vec::new();
+    // Skip the element name.
+    let mut rest = tag
+        .trim_start()
+        .split_once(char::is_whitespace)
+        .map_or("", |(_, r)| r);
+    loop {
+        rest = rest.trim_start().trim_start_matches('/');
+        let Some(eq) = rest.find('=') else {
+            break;
+        };
+        let name = rest[..eq].trim().to_ascii_lowercase();
+        let value_part = rest[eq + 1..].trim_start();
+        let (value, remaining) = match value_part.chars().next() {
+            Some(quote @ ('"' | '\'')) => {
+                let inner = &value_part[1..];
+                match inner.find(quote) {
+                    Some(end) => (&inner[..end], &inner[end + 1..]),
+                    None => (inner, ""),
+                }
+            }
+            _ => {
+                let end = value_part
+                    .find(char::is_whitespace)
+                    .unwrap_or(value_part.len());
+                (&value_part[..end], &value_part[end..])
+            }
+        };
+        // Attributes without values ("async", "hidden") end up glued to the
+        // next name; keep only the last word.
+        let name = name
+            .rsplit(char::is_whitespace)
+            .next()
+            .unwrap_or("")
+            .to_string();
+        attributes.push((name, value.to_string()));
+        rest = remaining;
+    }
+    attributes
+}
+
+/// Escapes text for use in HTML element content and quoted attributes.
+pub fn escape_html(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Where a post is being published, as far as rendering is concerned.
+pub struct PostContext<'a> {
+    /// Used when neither the front matter nor a `# Heading` gives a title.
+    pub fallback_title: &'a str,
+    /// Page language unless the front matter says otherwise.
+    pub lang: &'a str,
+    pub alternates: &'a [Alternate],
+    /// Absolute URL of the Open Graph preview image, if one is published.
+    pub image_url: Option<&'a str>,
+    /// What the source is written in.
+    pub format: InputFormat,
+    /// Directory of the source file, for resolving files it references.
+    pub base_dir: Option<&'a Path>,
+    pub site: &'a SiteConfig,
+    /// When the post was first published; now if unset.
+    pub published: Option<DateTime<Utc>>,
+    /// When the post was last updated, shown next to the published date.
+    pub updated: Option<DateTime<Utc>>,
+    /// Other posts to pick related posts from, if `related_posts` is enabled.
+    pub related: &'a [Post],
+    /// Absolute URL of the `[views]` pixel to load at the end of the page.
+    pub view_pixel: Option<&'a str>,
+    /// Absolute URL of the published markdown, if `[sources] upload` is set.
+    pub source_url: Option<&'a str>,
+    /// `[upload] content_types`, for the types shown on attachments and audio.
+    pub content_types: &'a BTreeMap<String, String>,
+    /// The starts of those files, from [`media::read_starts`].
+    pub file_starts: &'a FileStarts,
+}
+
+/// Renders a markdown (or AsciiDoc/RST) file into a complete HTML page.
+///
+/// The title comes from the front matter, then the first `# Heading` (or the
+/// document title), then the context's fallback title.
+pub fn render_post(
+    markdown_content: &str,
+    page_config: &PageConfig,
+    context: &PostContext,
+) -> Result<RenderedPost, Box<dyn Error>> {
+    let mut html = String::new();
+    let post = render_post_to(markdown_content, page_config, context, &mut html)?;
+    Ok(RenderedPost { html, ..post })
+}
+
+/// Renders markdown into a complete page the way `klistra -f` does, but
+/// from a string and with no file, manifest or bucket involved. Posts
+/// without a title are called "Untitled"; set `reproducible` to leave out
+/// today's date.
+pub fn render_to_string(
+    markdown: &str,
+    page_config: &PageConfig,
+    site: &SiteConfig,
+) -> Result<String, Box<dyn Error>> {
+    let context = PostContext {
+        fallback_title: "Untitled",
+        lang: &page_config.lang,
+        alternates: &[],
+        image_url: None,
+        format: InputFormat::Markdown,
+        base_dir: None,
+        site,
+        published: None,
+        updated: None,
+        related: &[],
+        view_pixel: None,
+        source_url: None,
+        content_types: &BTreeMap::new(),
+        file_starts: &FileStarts::new(),
+    };
+    Ok(render_post(markdown, page_config, &context)?.html)
+}
+
+/// Like [`render_post`], but writes the page to `out` instead of returning
+/// it in `html`, which is left empty. With the pulldown-cmark backend the
+/// body goes straight from the parser to `out`, so a huge post never has to
+/// fit in memory as HTML.
+pub fn render_post_to(
+    markdown_content: &str,
+    page_config: &PageConfig,
+    context: &PostContext,
+    out: &mut dyn fmt::Write,
+) -> Result<RenderedPost, Box<dyn Error>> {
+    let (front_matter, markdown_body) = FrontMatter::parse(markdown_content)?;
+    // Like Jekyll, a file named `2024-06-01-title.md` was written that day.
+    let (file_date, fallback_title) = split_file_date(context.fallback_title);
+    let written = match front_matter.date()? {
+        Some(date) => Some(date),
+        None => file_date.and_then(local_midnight),
+    };
+
+    let sectioned;
+    let expanded;
+    let attached;
+    let numbered;
+    let noted;
+    let embedded;
+    let mut sections = Vec::new();
+    let mut media = Vec::new();
+    let (title, strip_heading, summary, body, links) = match context.format {
+        InputFormat::Markdown => {
+            sectioned = details::expand_details(markdown_body)?;
+            expanded = table::expand_shortcodes(&sectioned, context.base_dir)?;
+            attached = media::embed_attachments(
+                &expanded,
+                context.base_dir,
+                context.content_types,
+                context.file_starts,
+                &mut media,
+            )?;
+            numbered = codeblock::expand_code_options(&attached)?;
+            noted = match page_config.footnotes {
+                FootnoteStyle::Sidenotes => sidenote::footnotes_to_sidenotes(&numbered),
+                FootnoteStyle::Bottom => Cow::Borrowed(&*numbered),
+            };
+            embedded = media::embed_videos(&noted, context.base_dir, &mut media);
+            let markdown_body = &*embedded;
+            let (title, strip_heading, title_heading) =
+                match (front_matter.title, first_heading(markdown_body)) {
+                    (Some(title), _) => (title, false, false),
+                    (None, Some(heading)) => (heading, page_config.strip_title, true),
+                    (None, None) => (fallback_title.to_string(), false, false),
+                };
+            let shown = if strip_heading {
+                without_first_heading(markdown_body)
+            } else {
+                Cow::Borrowed(markdown_body)
+            };
+            // Pandoc makes its own heading ids.
+            sections = match page_config.parser {
+                MarkdownBackend::Pandoc => Vec::new(),
+                _ => self::sections(&shown, title_heading && !strip_heading),
+            };
+            let body = match page_config.parser {
+                MarkdownBackend::Pulldown => Body::Markdown(shown),
+                backend => Body::Html(markdown_to_html(&shown, false, backend)?),
+            };
+            (
+                title,
+                strip_heading,
+                summary(markdown_body),
+                body,
+                external_links(markdown_body),
+            )
+        }
+        // Converters leave the document title out of the body, so it's always
+        // shown as the page heading.
+        format => {
+            let mut converted = formats::convert(format, markdown_body)?;
+            match format {
+                InputFormat::Docx => {
+                    converted.html = docx::extract_images(&converted.html, &mut media)?
+                }
+                InputFormat::Html => media::html_files(markdown_body, context.base_dir, &mut media),
+                _ => {}
+            }
+            let title = front_matter
+                .title
+                .or(converted.title)
+                .unwrap_or_else(|| fallback_title.to_string());
+            let links = html_links(&converted.html);
+            (
+                title,
+                true,
+                html_summary(&converted.html),
+                Body::Html(converted.html),
+                links,
+            )
+        }
+    };
+
+    let words = markdown_body.split_whitespace().count();
+    let description = front_matter.description.or(summary);
+    let audio = front_matter
+        .audio
+        .as_deref()
+        .map(|reference| {
+            media::audio(
+                reference,
+                context.base_dir,
+                context.content_types,
+                context.file_starts,
+                &mut media,
+            )
+        })
+        .transpose()?;
+    let audio_player = audio.as_ref().map(media::audio_html).unwrap_or_default();
+    let sort_script = if page_config.sortable_tables && body.contains("class=\"data-table\"") {
+        table::SORT_SCRIPT
+    } else {
+        ""
+    };
+    let task_list_script = if page_config.interactive_task_lists && body.has_task_list() {
+        TASK_LIST_SCRIPT
+    } else {
+        ""
+    };
+
+    let after_body = changelog_html(&front_matter.changelog, page_config)
+        + &related_html(
+            &related::find(
+                &title,
+                &front_matter.labels,
+                context.related,
+                page_config.related_posts,
+            ),
+            page_config,
+        );
+
+    // A front matter date wins over when the post went out. Reproducible
+    // builds show no other date, since those depend on when they ran.
+    let published = match written {
+        Some(date) => Some(date),
+        None if page_config.reproducible => None,
+        None => Some(context.published.unwrap_or_else(Utc::now)),
+    };
+    let published = match published {
+        Some(date) => format_date(date.with_timezone(&Local), page_config)?,
+        None => String::new(),
+    };
+    let updated = context.updated.filter(|_| !page_config.reproducible);
+    let current_date = match updated {
+        Some(updated) => {
+            let updated = format_date(updated.with_timezone(&Local), page_config)?;
+            if updated == published {
+                published
+            } else {
+                page_config
+                    .updated_format
+                    .replace("{published}", &published)
+                    .replace("{updated}", &updated)
+            }
+        }
+        None => published,
+    };
+
+    // Front matter authors, or the site's default author.
+    let mut author_keys: Vec<&String> = front_matter
+        .author
+        .iter()
+        .chain(&front_matter.authors)
+        .collect();
+    if author_keys.is_empty() {
+        author_keys.extend(&context.site.author);
+    }
+    let authors: Vec<Author> = author_keys
+        .into_iter()
+        .map(|key| context.site.author(key))
+        .collect();
+
+    let lang = front_matter.lang.as_deref().unwrap_or(context.lang);
+    let dir = front_matter.dir.unwrap_or_else(|| Dir::from_lang(lang));
+
+    let page = Page {
+        title: &title,
+        heading: strip_heading.then_some(title.as_str()),
+        description: description.as_deref(),
+        lang,
+        dir,
+        date: &current_date,
+        body: "",
+        alternates: context.alternates,
+        image_url: context.image_url,
+        site: context.site,
+        authors: &authors,
+        source_url: context.source_url,
+    };
+    let mut write_body = |out: &mut dyn fmt::Write| -> Result<(), Box<dyn Error>> {
+        out.write_str(&audio_player)?;
+        match &body {
+            Body::Html(html) => out.write_str(html)?,
+            Body::Markdown(markdown) => write_html_fmt(
+                &mut *out,
+                HeadingIds::new(MarkdownParser::new_ext(markdown, markdown_options())),
+            )?,
+        }
+        out.write_str(sort_script)?;
+        out.write_str(task_list_script)?;
+        out.write_str(&after_body)?;
+        if let Some(pixel) = context.view_pixel {
+            write!(
+                out,
+                "<img class=\"view-pixel\" src=\"{}\" alt=\"\" width=\"1\" height=\"1\">",
+                escape_html(pixel)
+            )?;
+        }
+        Ok(())
+    };
+    let template = front_matter
+        .template
+        .as_ref()
+        .or(page_config.template.as_ref());
+    match template {
+        // The page goes out as it was written.
+        _ if context.format == InputFormat::Html && !page_config.wrap_html => {
+            out.write_str(markdown_body)?
+        }
+        Some(name) => {
+            let dir = page_config
+                .templates_dir
+                .as_deref()
+                .ok_or_else(|| format!("Template '{}' needs [page] templates_dir", name))?;
+            Template::load(dir, name)?.render_to(&page.template_values(), out, &mut write_body)?;
+        }
+        None => {
+            out.write_str(&page.head())?;
+            write_body(out)?;
+            out.write_str(&page.tail())?;
+        }
+    }
+
+    Ok(RenderedPost {
+        title,
+        description,
+        date: current_date,
+        links,
+        html: String::new(),
+        labels: front_matter.labels,
+        authors: authors.into_iter().map(|author| author.name).collect(),
+        media,
+        audio,
+        sections,
+        words,
+    })
+}
+
+/// Makes task list checkboxes clickable, remembering which are ticked in
+/// `localStorage`, per page.
+const TASK_LIST_SCRIPT: &str = r#"<script>
+(() => {
+    const key = "klistra-tasks:" + location.pathname;
+    const boxes = Array.from(document.querySelectorAll("li input[type=checkbox][disabled]"));
+    const saved = JSON.parse(localStorage.getItem(key) || "[]");
+    boxes.forEach((box, index) => {
+        box.disabled = false;
+        if (index < saved.length) box.checked = saved[index];
+        box.addEventListener("change", () => {
+            localStorage.setItem(key, JSON.stringify(boxes.map(box => box.checked)));
+        });
+    });
+})();
+</script>"#;
+
+/// A post body: already converted, or markdown that pulldown-cmark renders
+/// as the page is written.
+enum Body<'a> {
+    Html(String),
+    Markdown(Cow<'a, str>),
+}
+
+impl Body<'_> {
+    /// Whether the source text contains `needle`. Raw HTML passes through
+    /// markdown unchanged, so this also finds markup from shortcodes.
+    fn contains(&self, needle: &str) -> bool {
+        match self {
+            Body::Html(html) => html.contains(needle),
+            Body::Markdown(markdown) => markdown.contains(needle),
+        }
+    }
+
+    /// Whether the body may have a task list, judging by its checkboxes.
+    fn has_task_list(&self) -> bool {
+        match self {
+            Body::Html(html) => html.contains("type=\"checkbox\""),
+            Body::Markdown(markdown) => ["[ ]", "[x]", "[X]"]
+                .iter()
+                .any(|checkbox| markdown.contains(checkbox)),
+        }
+    }
+}
+
+/// The front matter changelog as a list at the end of the post.
+fn changelog_html(changelog: &[ChangelogEntry], page_config: &PageConfig) -> String {
+    if changelog.is_empty() {
+        return String::new();
+    }
+    let items: String = changelog
+        .iter()
+        .map(|entry| match entry {
+            ChangelogEntry::Dated { date, note } => format!(
+                "<li><time>{}</time> {}</li>\n",
+                escape_html(date),
+                escape_html(note)
+            ),
+            ChangelogEntry::Note(note) => format!("<li>{}</li>\n", escape_html(note)),
+        })
+        .collect();
+    format!(
+        "\n<section class=\"changelog\">\n<h2>{}</h2>\n<ul>\n{}</ul>\n</section>",
+        escape_html(&page_config.changelog_heading),
+        items
+    )
+}
+
+/// Links to related posts at the end of the post.
+fn related_html(related: &[&Post], page_config: &PageConfig) -> String {
+    if related.is_empty() {
+        return String::new();
+    }
+    let items: String = related
+        .iter()
+        .map(|post| {
+            format!(
+                "<li><a href=\"{}\">{}</a></li>\n",
+                escape_html(&post.url),
+                escape_html(&post.title)
+            )
+        })
+        .collect();
+    format!(
+        "\n<nav class=\"related\">\n<h2>{}</h2>\n<ul>\n{}</ul>\n</nav>",
+        escape_html(&page_config.related_heading),
+        items
+    )
+}
+
+/// Formats `date` using the configured format, localized if a locale is set.
+pub fn format_date(date: DateTime<Local>, page: &PageConfig) -> Result<String, Box<dyn Error>> {
+    format_date_as(date, &page.date_format, page)
+}
+
+/// Today's date for pages that aren't posts (listings, the index), or
+/// nothing in reproducible builds.
+pub fn today(page: &PageConfig) -> Result<String, Box<dyn Error>> {
+    if page.reproducible {
+        return Ok(String::new());
+    }
+    format_date(Local::now(), page)
+}
+
+/// Formats `date` with a strftime-style `format`, localized if a locale is set.
+pub fn format_date_as(
+    date: DateTime<Local>,
+    format: &str,
+    page: &PageConfig,
+) -> Result<String, Box<dyn Error>> {
+    let mut formatted = String::new();
+    // `to_string` would panic on a bad specifier like `%Q`.
+    let written = match &page.locale {
+        Some(locale) => {
+            let locale: Locale = locale
+                .replace('-', "_")
+                .parse()
+                .map_err(|_| format!("Unknown locale '{}'", locale))?;
+            write!(formatted, "{}", date.format_localized(format, locale))
+        }
+        None => write!(formatted, "{}", date.format(format)),
+    };
+    written.map_err(|_| format!("Invalid date format '{}'", format))?;
+    Ok(formatted)
+}
+
+/// `<link rel="alternate">` tags pointing search engines at each translation.
+fn alternate_links(alternates: &[Alternate]) -> String {
+    alternates
+        .iter()
+        .map(|alt| {
+            format!(
+                "\n    <link rel=\"alternate\" hreflang=\"{}\" href=\"{}\">",
+                alt.lang, alt.url
+            )
+        })
+        .collect()
+}
+
+/// The page served for missing objects, in the site's layout, linking home
+/// if `base_url` is set.
+pub fn not_found_page(page_config: &PageConfig, site: &SiteConfig) -> String {
+    let home = site
+        .base_url
+        .as_deref()
+        .map(|url| format!("\n<p><a href=\"{}\">Home</a></p>", escape_html(url)))
+        .unwrap_or_default();
+    let body = format!(
+        "<p>{}</p>{}",
+        escape_html(&page_config.not_found_text),
+        home
+    );
+    Page {
+        title: &page_config.not_found_title,
+        heading: Some(&page_config.not_found_title),
+        description: None,
+        lang: &page_config.lang,
+        dir: Dir::from_lang(&page_config.lang),
+        date: "",
+        body: &body,
+        alternates: &[],
+        image_url: None,
+        site,
+        authors: &[],
+        source_url: None,
+    }
+    .render()
+}
+
+/// A minimal page that immediately sends the reader on to `target`.
+pub fn redirect_page(target: &str, alternates: &[Alternate]) -> String {
+    let links = alternate_links(alternates);
+    format!(
+        r#"<!DOCTYPE html>
+<html>
+<head>
+    <meta charset="UTF-8">
+    <meta http-equiv="refresh" content="0; url={target}">{links}
+</head>
+<body>
+    <a href="{target}">{target}</a>
+</body>
+</html>"#
+    )
+}
+
+impl Page<'_> {
+    fn site_meta(&self) -> String {
+        let mut meta = String::new();
+        if let Some(title) = &self.site.title {
+            meta.push_str(&format!(
+                "\n    <meta property=\"og:site_name\" content=\"{}\">",
+                escape_html(title)
+            ));
+        }
+        if !self.authors.is_empty() {
+            let names: Vec<&str> = self.authors.iter().map(|a| a.name.as_str()).collect();
+            meta.push_str(&format!(
+                "\n    <meta name=\"author\" content=\"{}\">",
+                escape_html(&names.join(", "))
+            ));
+        }
+        meta
+    }
+
+    /// The site title, linking home if `base_url` is set.
+    fn site_header(&self) -> String {
+        let Some(title) = &self.site.title else {
+            return String::new();
+        };
+        let title = escape_html(title);
+        match &self.site.base_url {
+            Some(url) => format!(
+                "<header class=\"site-header\"><a href=\"{}\">{}</a></header>\n        ",
+                escape_html(url),
+                title
+            ),
+            None => format!("<header class=\"site-header\">{}</header>\n        ", title),
+        }
+    }
+
+    /// "By" and each author, with their avatar and link if configured.
+    fn byline(&self) -> String {
+        if self.authors.is_empty() {
+            return String::new();
+        }
+        let names: Vec<String> = self
+            .authors
+            .iter()
+            .map(|author| {
+                let avatar = author
+                    .avatar
+                    .as_deref()
+                    .map(|src| {
+                        format!(
+                            "<img class=\"avatar\" src=\"{}\" alt=\"\">",
+                            escape_html(src)
+                        )
+                    })
+                    .unwrap_or_default();
+                let name = format!("{}{}", avatar, escape_html(&author.name));
+                match &author.url {
+                    Some(url) => format!(
+                        "<a href=\"{}\" rel=\"author\">{}</a>",
+                        escape_html(url),
+                        name
+                    ),
+                    None => format!("<span>{}</span>", name),
+                }
+            })
+            .collect();
+        format!(
+            "<div class=\"byline\">By {}</div>\n        ",
+            names.join(", ")
+        )
+    }
+
+    fn site_footer(&self) -> String {
+        let mut parts = Vec::new();
+        if let Some(footer) = &self.site.footer {
+            parts.push(format!("<span>{}</span>", escape_html(footer)));
+        }
+        for link in &self.site.links {
+            parts.push(format!(
+                "<a href=\"{}\" rel=\"me\">{}</a>",
+                escape_html(&link.url),
+                escape_html(&link.name)
+            ));
+        }
+        if let Some(url) = self.source_url {
+            parts.push(format!("<a href=\"{}\">View source</a>", escape_html(url)));
+        }
+        if parts.is_empty() {
+            return String::new();
+        }
+        format!(
+            "\n        <footer class=\"site-footer\">{}</footer>",
+            parts.join("")
+        )
+    }
+
+    fn language_switcher(&self) -> String {
+        if self.alternates.len() < 2 {
+            return String::new();
+        }
+        let links: Vec<String> = self
+            .alternates
+            .iter()
+            .map(|alt| {
+                if alt.lang == self.lang {
+                    format!(
+                        "<a href=\"{}\" aria-current=\"page\">{}</a>",
+                        alt.url, alt.lang
+                    )
+                } else {
+                    format!("<a href=\"{}\">{}</a>", alt.url, alt.lang)
+                }
+            })
+            .collect();
+        format!("<nav class=\"languages\">{}</nav>", links.join(" · "))
+    }
+
+    pub fn render(&self) -> String {
+        format!("{}{}{}", self.head(), self.body, self.tail())
+    }
+
+    /// Everything a [`Template`] can show besides the post: the parts of the
+    /// built-in layout, and the page's details on their own.
+    pub fn template_values(&self) -> BTreeMap<&'static str, Value> {
+        let heading = self
+            .heading
+            .map(|h| format!("<h1>{}</h1>", escape_html(h)))
+            .unwrap_or_default();
+        let authors: Vec<&str> = self.authors.iter().map(|a| a.name.as_str()).collect();
+        BTreeMap::from([
+            ("title", Value::Text(self.title.to_string())),
+            ("full_title", Value::Text(self.full_title())),
+            (
+                "description",
+                Value::Text(self.description.unwrap_or_default().to_string()),
+            ),
+            ("lang", Value::Text(self.lang.to_string())),
+            ("dir", Value::Text(self.dir.as_str().to_string())),
+            ("date", Value::Text(self.date.to_string())),
+            ("authors", Value::Text(authors.join(", "))),
+            (
+                "site_title",
+                Value::Text(self.site.title.clone().unwrap_or_default()),
+            ),
+            (
+                "image_url",
+                Value::Text(self.image_url.unwrap_or_default().to_string()),
+            ),
+            ("meta", Value::Html(self.meta())),
+            ("style", Value::Html(STYLE.to_string())),
+            ("heading", Value::Html(heading)),
+            ("site_header", Value::Html(self.site_header())),
+            ("language_switcher", Value::Html(self.language_switcher())),
+            ("byline", Value::Html(self.byline())),
+            ("site_footer", Value::Html(self.site_footer())),
+        ])
+    }
+
+    /// The title for `<title>`: with the site title after it, if there is one.
+    pub fn full_title(&self) -> String {
+        match &self.site.title {
+            Some(site_title) if site_title != self.title => {
+                format!("{} · {}", self.title, site_title)
+            }
+            _ => self.title.to_string(),
+        }
+    }
+
+    /// The `<meta>` and `<link>` tags describing the page: Open Graph,
+    /// description, preview image and translations.
+    pub fn meta(&self) -> String {
+        format!(
+            "<meta property=\"og:type\" content=\"article\">\n    <meta property=\"og:title\" content=\"{title}\">{meta_site}{meta_description}{meta_image}{alternate_links}",
+            title = escape_html(self.title),
+            meta_site = self.site_meta(),
+            meta_description = self
+                .description
+                .map(|d| {
+                    let d = escape_html(d);
+                    format!(
+                        "\n    <meta name=\"description\" content=\"{d}\">\n    <meta property=\"og:description\" content=\"{d}\">"
+                    )
+                })
+                .unwrap_or_default(),
+            meta_image = self
+                .image_url
+                .map(|url| {
+                    format!(
+                        "\n    <meta property=\"og:image\" content=\"{}\">\n    <meta name=\"twitter:card\" content=\"summary_large_image\">",
+                        escape_html(url)
+                    )
+                })
+                .unwrap_or_default(),
+            alternate_links = alternate_links(self.alternates),
+        )
+    }
+
+    /// Everything before the body, for writing the page out in pieces.
+    pub fn head(&self) -> String {
+        format!(
+            r#"<!DOCTYPE html>
+<html lang="{lang}" dir="{dir}">
+<head>
+    <meta charset="UTF-8">
+    <meta name="viewport" content="width=device-width, initial-scale=1.0">
+    <title>{full_title}</title>
+    {meta}
+    {style}
+</head>
+<body>
+    <div class="container">
+        {site_header}{language_switcher}
+        {heading}<div class="date">{date}</div>
+        {byline}
+        "#,
+            meta = self.meta(),
+            style = STYLE,
+            full_title = escape_html(&self.full_title()),
+            site_header = self.site_header(),
+            byline = self.byline(),
+            heading = self
+                .heading
+                .map(|h| format!("<h1>{}</h1>\n        ", escape_html(h)))
+                .unwrap_or_default(),
+            lang = escape_html(self.lang),
+            dir = self.dir.as_str(),
+            date = self.date,
+            language_switcher = self.language_switcher(),
+        )
+    }
+
+    /// Everything after the body.
+    pub fn tail(&self) -> String {
+        format!("{}\n    </div>\n</body>\n</html>", self.site_footer())
+    }
+}
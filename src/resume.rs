@@ -0,0 +1,157 @@
+use crate::frontmatter::Labels;
+use crate::publisher::Publisher;
+use crate::shutdown;
+use serde::{Deserialize, Serialize};
+use std::{
+    error::Error,
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+use tokio::{
+    fs,
+    io::{AsyncReadExt, AsyncSeekExt, SeekFrom},
+};
+
+/// Files larger than this are uploaded in parts that can be resumed.
+pub const MULTIPART_THRESHOLD: u64 = 64 * 1024 * 1024;
+
+/// Size of each part. B2 and S3 require at least 5 MB for all but the last.
+pub const PART_SIZE: u64 = 16 * 1024 * 1024;
+
+/// Progress of a multipart upload, saved after every part so an interrupted
+/// upload of the same, unchanged file can pick up where it stopped.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct UploadState {
+    /// Object path in the bucket, relative to the prefix.
+    pub path: String,
+    upload_id: String,
+    size: u64,
+    modified: SystemTime,
+    /// Completed parts as `(part number, ETag)`.
+    parts: Vec<(i32, String)>,
+}
+
+/// Where the state for uploading `source` is kept:
+/// `<data dir>/klistra/uploads/<absolute path with separators replaced>.json`.
+fn state_path(source: &Path) -> Result<PathBuf, Box<dyn Error>> {
+    let absolute = std::path::absolute(source)?;
+    let name: String = absolute
+        .to_string_lossy()
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect();
+    let dir = dirs::data_dir().ok_or("Could not determine data directory")?;
+    Ok(dir
+        .join("klistra")
+        .join("uploads")
+        .join(format!("{}.json", name)))
+}
+
+/// The unfinished upload of `source`, if there is one and the file hasn't
+/// changed since it started.
+pub async fn pending(source: &Path) -> Result<Option<UploadState>, Box<dyn Error>> {
+    let state_path = state_path(source)?;
+    let content = match fs::read_to_string(state_path).await {
+        Ok(content) => content,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e.into()),
+    };
+    let Ok(state) = serde_json::from_str::<UploadState>(&content) else {
+        return Ok(None);
+    };
+    let metadata = fs::metadata(source).await?;
+    let unchanged = state.size == metadata.len() && state.modified == metadata.modified()?;
+    Ok(unchanged.then_some(state))
+}
+
+async fn save(source: &Path, state: &UploadState) -> Result<(), Box<dyn Error>> {
+    let path = state_path(source)?;
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).await?;
+    }
+    fs::write(path, serde_json::to_string(state)?).await?;
+    Ok(())
+}
+
+/// Uploads `source` to `path` in parts, resuming a pending upload to the same
+/// path. The state file is removed once the upload is complete.
+pub async fn upload(
+    bucket: &dyn Publisher,
+    source: &Path,
+    path: &str,
+    content_type: &str,
+) -> Result<(), Box<dyn Error>> {
+    // Bound first, so no error is held across the awaits below.
+    let pending = pending(source).await?;
+    let mut state = match pending {
+        Some(state) if state.path == path => {
+            eprintln!(
+                "Resuming upload of {} ({} parts done)",
+                source.display(),
+                state.parts.len()
+            );
+            state
+        }
+        _ => {
+            let metadata = fs::metadata(source).await?;
+            let state = UploadState {
+                path: path.to_string(),
+                upload_id: bucket
+                    .create_multipart(path, content_type, &Labels::new())
+                    .await?,
+                size: metadata.len(),
+                modified: metadata.modified()?,
+                parts: Vec::new(),
+            };
+            save(source, &state).await?;
+            state
+        }
+    };
+
+    let part_count = state.size.div_ceil(PART_SIZE).max(1);
+    let mut file = fs::File::open(source).await?;
+    for index in 0..part_count {
+        let number = index as i32 + 1;
+        if state.parts.iter().any(|(done, _)| *done == number) {
+            continue;
+        }
+        if shutdown::requested() {
+            // Nothing will come back for it, so the parts aren't left to
+            // take up space in the bucket.
+            // As a string, so no error is held across the await below.
+            let aborted = bucket
+                .abort_multipart(path, &state.upload_id)
+                .await
+                .map_err(|e| e.to_string());
+            let state_path = state_path(source)?;
+            fs::remove_file(state_path).await?;
+            aborted?;
+            return Err(format!("Stopped uploading {}", source.display()).into());
+        }
+        let offset = index * PART_SIZE;
+        let mut chunk = vec![0; PART_SIZE.min(state.size - offset) as usize];
+        file.seek(SeekFrom::Start(offset)).await?;
+        file.read_exact(&mut chunk).await?;
+
+        let etag = bucket
+            .upload_part(path, &state.upload_id, number, chunk)
+            .await
+            .map_err(|e| {
+                format!(
+                    "Upload of part {} failed: {} (run the same command again to resume)",
+                    number, e
+                )
+            })?;
+        state.parts.push((number, etag));
+        save(source, &state).await?;
+        eprintln!("Uploaded part {}/{}", number, part_count);
+    }
+
+    state.parts.sort_by_key(|(number, _)| *number);
+    bucket
+        .complete_multipart(path, &state.upload_id, &state.parts)
+        .await?;
+    let state_path = state_path(source)?;
+    fs::remove_file(state_path).await?;
+    Ok(())
+}
@@ -0,0 +1,132 @@
+use crate::config::{FrontMatterSchema, ValueType};
+use crate::frontmatter;
+use chrono::{DateTime, NaiveDate};
+use serde_yaml::Value;
+use std::error::Error;
+
+/// Checks the front matter of `content` against `[front_matter]` in the
+/// config, listing every key that's missing, of the wrong type or has a
+/// value that isn't allowed.
+pub fn validate(content: &str, schema: &FrontMatterSchema) -> Result<(), Box<dyn Error>> {
+    if schema.required.is_empty() && schema.types.is_empty() && schema.labels.is_empty() {
+        return Ok(());
+    }
+    let front_matter = match frontmatter::split(content)? {
+        (Some(yaml), _) if !yaml.trim().is_empty() => {
+            serde_yaml::from_str(yaml).map_err(|e| format!("Invalid front matter: {}", e))?
+        }
+        _ => Value::Null,
+    };
+
+    let mut problems = Vec::new();
+    for key in &schema.required {
+        if lookup(&front_matter, key).is_none_or(is_empty) {
+            problems.push(format!("'{}' is required", key));
+        }
+    }
+    for (key, expected) in &schema.types {
+        let Some(value) = lookup(&front_matter, key) else {
+            continue;
+        };
+        if !value.is_null() && !has_type(value, *expected) {
+            problems.push(format!(
+                "'{}' should be {}, found {}",
+                key,
+                type_name(*expected),
+                describe(value)
+            ));
+        }
+    }
+    for (label, allowed) in &schema.labels {
+        let Some(value) = front_matter
+            .get("labels")
+            .and_then(|labels| labels.get(label))
+        else {
+            continue;
+        };
+        let value = scalar_text(value);
+        if !allowed
+            .iter()
+            .any(|allowed| Some(allowed.as_str()) == value.as_deref())
+        {
+            problems.push(format!(
+                "label '{}' is {}, expected one of: {}",
+                label,
+                value.map_or_else(
+                    || "not a plain value".to_string(),
+                    |value| format!("'{}'", value)
+                ),
+                allowed.join(", ")
+            ));
+        }
+    }
+
+    if problems.is_empty() {
+        return Ok(());
+    }
+    Err(format!(
+        "Front matter doesn't match [front_matter] in the config: {}",
+        problems.join("; ")
+    )
+    .into())
+}
+
+/// The value at `key`, where `labels.project` looks inside `labels`.
+fn lookup<'a>(front_matter: &'a Value, key: &str) -> Option<&'a Value> {
+    key.split('.')
+        .try_fold(front_matter, |value, part| value.get(part))
+}
+
+/// Null, an empty or blank string, or an empty list or map.
+fn is_empty(value: &Value) -> bool {
+    match value {
+        Value::Null => true,
+        Value::String(text) => text.trim().is_empty(),
+        Value::Sequence(items) => items.is_empty(),
+        Value::Mapping(map) => map.is_empty(),
+        _ => false,
+    }
+}
+
+fn has_type(value: &Value, expected: ValueType) -> bool {
+    match expected {
+        ValueType::String => value.is_string(),
+        ValueType::Number => value.is_number(),
+        ValueType::Bool => value.is_bool(),
+        ValueType::Date => value.as_str().is_some_and(|date| {
+            NaiveDate::parse_from_str(date, "%Y-%m-%d").is_ok()
+                || DateTime::parse_from_rfc3339(date).is_ok()
+        }),
+        ValueType::List => value.is_sequence(),
+        ValueType::Map => value.is_mapping(),
+    }
+}
+
+fn type_name(value_type: ValueType) -> &'static str {
+    match value_type {
+        ValueType::String => "a string",
+        ValueType::Number => "a number",
+        ValueType::Bool => "true or false",
+        ValueType::Date => "a date (YYYY-MM-DD)",
+        ValueType::List => "a list",
+        ValueType::Map => "a map",
+    }
+}
+
+/// `value` as it would read in an error message.
+fn describe(value: &Value) -> String {
+    match scalar_text(value) {
+        Some(text) => format!("'{}'", text),
+        None if value.is_sequence() => "a list".to_string(),
+        None => "a map".to_string(),
+    }
+}
+
+fn scalar_text(value: &Value) -> Option<String> {
+    match value {
+        Value::String(text) => Some(text.clone()),
+        Value::Number(number) => Some(number.to_string()),
+        Value::Bool(flag) => Some(flag.to_string()),
+        _ => None,
+    }
+}
@@ -0,0 +1,196 @@
+use crate::config::CheckConfig;
+use std::{error::Error, fmt};
+
+/// Prefixes of tokens that are credentials whatever surrounds them, with
+/// what they are and the shortest a real one is.
+const TOKEN_PREFIXES: &[(&str, &str, usize)] = &[
+    ("AKIA", "AWS access key", 20),
+    ("ASIA", "AWS access key", 20),
+    ("ghp_", "GitHub token", 40),
+    ("gho_", "GitHub token", 40),
+    ("ghu_", "GitHub token", 40),
+    ("ghs_", "GitHub token", 40),
+    ("ghr_", "GitHub token", 40),
+    ("github_pat_", "GitHub token", 40),
+    ("glpat-", "GitLab token", 26),
+    ("xoxb-", "Slack token", 20),
+    ("xoxp-", "Slack token", 20),
+    ("xoxa-", "Slack token", 20),
+    ("xapp-", "Slack token", 20),
+    ("sk_live_", "Stripe key", 24),
+    ("rk_live_", "Stripe key", 24),
+    ("AIza", "Google API key", 39),
+    ("sk-ant-", "Anthropic API key", 40),
+    ("sk-proj-", "OpenAI API key", 40),
+    ("npm_", "npm token", 40),
+];
+
+/// Words that name a secret in `name = value` assignments.
+const SECRET_NAMES: &[&str] = &[
+    "password",
+    "passwd",
+    "secret",
+    "token",
+    "api_key",
+    "apikey",
+    "api-key",
+    "access_key",
+    "private_key",
+    "client_secret",
+];
+
+/// Something in a post that looks like it shouldn't be published.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Finding {
+    /// 1-based line in the source.
+    pub line: usize,
+    /// What it looks like, e.g. `AWS access key`.
+    pub kind: &'static str,
+    /// The start of it, enough to find it but not to use it.
+    pub excerpt: String,
+}
+
+impl fmt::Display for Finding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}: {} ({})", self.line, self.kind, self.excerpt)
+    }
+}
+
+/// Refuses `markdown` if it seems to contain credentials or email
+/// addresses, unless `[check] secrets` is off. With `--allow-secrets`,
+/// what was found is only printed.
+pub fn check(markdown: &str, config: &CheckConfig) -> Result<(), Box<dyn Error>> {
+    if !config.secrets {
+        return Ok(());
+    }
+    let findings = scan(markdown, &config.allow);
+    if findings.is_empty() {
+        return Ok(());
+    }
+    if config.allow_secrets {
+        for finding in &findings {
+            eprintln!(
+                "Warning: publishing what looks like a secret on {}",
+                finding
+            );
+        }
+        return Ok(());
+    }
+    let findings: Vec<String> = findings.iter().map(Finding::to_string).collect();
+    Err(format!(
+        "Not publishing, this looks like it contains secrets: {}. Remove them, add them to \
+         [check] allow, or use --allow-secrets",
+        findings.join("; ")
+    )
+    .into())
+}
+
+/// Private keys, API keys and tokens, passwords assigned in code or
+/// config, and email addresses in `text`, prose and code blocks alike.
+/// Anything containing one of the `allow` strings is left out, as are
+/// placeholders and documentation examples.
+pub fn scan(text: &str, allow: &[String]) -> Vec<Finding> {
+    let mut findings = Vec::new();
+    for (index, line) in text.lines().enumerate() {
+        let mut found = |kind, secret: &str| {
+            let allowed = allow
+                .iter()
+                .any(|allowed| secret.contains(allowed.as_str()));
+            let finding = Finding {
+                line: index + 1,
+                kind,
+                excerpt: redact(secret),
+            };
+            // The key in `AWS_KEY=AKIA...` is found as both.
+            let repeated = findings
+                .iter()
+                .any(|seen: &Finding| seen.line == finding.line && seen.excerpt == finding.excerpt);
+            if !allowed && !is_placeholder(secret) && !repeated {
+                findings.push(finding);
+            }
+        };
+
+        if line.contains("-----BEGIN") && line.contains("PRIVATE KEY") {
+            found("private key", line.trim());
+            continue;
+        }
+        for token in line.split(|c: char| !is_token_char(c)) {
+            let token = token.trim_matches(|c: char| matches!(c, '.' | '-' | '/'));
+            if let Some((_, kind, _)) = TOKEN_PREFIXES
+                .iter()
+                .find(|(prefix, _, min)| token.starts_with(prefix) && token.len() >= *min)
+            {
+                found(kind, token);
+            } else if is_email(token) {
+                found("email address", token);
+            }
+        }
+        if let Some(value) = assigned_secret(line) {
+            found("password or key", value);
+        }
+    }
+    findings
+}
+
+fn is_token_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || matches!(c, '_' | '-' | '+' | '/' | '.' | '@')
+}
+
+fn is_email(token: &str) -> bool {
+    let Some((user, domain)) = token.split_once('@') else {
+        return false;
+    };
+    // Not `logo@2x.png`.
+    const FILE_EXTENSIONS: &[&str] = &["png", "jpg", "jpeg", "gif", "svg", "webp", "avif"];
+    !user.is_empty()
+        && domain.rsplit_once('.').is_some_and(|(name, tld)| {
+            !name.is_empty()
+                && tld.len() >= 2
+                && tld.chars().all(|c| c.is_ascii_alphabetic())
+                && !FILE_EXTENSIONS.contains(&tld.to_ascii_lowercase().as_str())
+        })
+}
+
+/// The value in a line like `password = "hunter22"` or `API_KEY: abc123...`,
+/// if it looks like a real secret rather than prose.
+fn assigned_secret(line: &str) -> Option<&str> {
+    let lower = line.to_ascii_lowercase();
+    SECRET_NAMES.iter().find_map(|name| {
+        let start = lower.find(name)? + name.len();
+        // The rest of the name, like `_key` in `secret_key`, then the
+        // assignment.
+        let rest = line[start..]
+            .trim_start_matches(|c: char| c.is_ascii_alphanumeric() || c == '_' || c == '-');
+        let rest = rest.trim_start_matches(|c: char| c.is_whitespace() || c == '"' || c == '\'');
+        let rest = rest
+            .strip_prefix(":=")
+            .or_else(|| rest.strip_prefix("=>"))
+            .or_else(|| rest.strip_prefix('='))
+            .or_else(|| rest.strip_prefix(':'))?;
+        let rest = rest.trim_start_matches(|c: char| c.is_whitespace() || c == '"' || c == '\'');
+        let end = rest
+            .find(|c: char| c.is_whitespace() || matches!(c, '"' | '\'' | ',' | ';' | '`'))
+            .unwrap_or(rest.len());
+        let value = &rest[..end];
+        let mixed = value.chars().any(|c| c.is_ascii_digit())
+            && value.chars().any(|c| c.is_ascii_alphabetic());
+        (value.len() >= 8 && mixed).then_some(value)
+    })
+}
+
+/// Whether `secret` is obviously not a real one: an example from
+/// documentation or a template placeholder.
+fn is_placeholder(secret: &str) -> bool {
+    let lower = secret.to_ascii_lowercase();
+    [
+        "example", "xxxx", "your", "changeme", "dummy", "<", "${", "{{", "***",
+    ]
+    .iter()
+    .any(|marker| lower.contains(marker))
+}
+
+/// The first few characters of `secret`.
+fn redact(secret: &str) -> String {
+    let start: String = secret.chars().take(6).collect();
+    format!("{}…", start)
+}
@@ -0,0 +1,161 @@
+use crate::config::TlsConfig;
+use crate::http;
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use std::{cmp::Ordering, error::Error, path::Path};
+
+/// Checksums of every asset in a release, as written by `sha256sum`.
+const CHECKSUMS: &str = "SHA256SUMS";
+
+#[derive(Deserialize)]
+struct Release {
+    tag_name: String,
+    html_url: String,
+    assets: Vec<Asset>,
+}
+
+#[derive(Deserialize)]
+struct Asset {
+    name: String,
+    browser_download_url: String,
+}
+
+/// The release asset for this platform, e.g. `klistra-linux-x86_64` or
+/// `klistra-windows-x86_64.exe`.
+fn asset_name() -> String {
+    format!(
+        "klistra-{}-{}{}",
+        std::env::consts::OS,
+        std::env::consts::ARCH,
+        std::env::consts::EXE_SUFFIX
+    )
+}
+
+/// `owner/repo` from the package's repository URL.
+fn repository() -> &'static str {
+    env!("CARGO_PKG_REPOSITORY").trim_start_matches("https://github.com/")
+}
+
+/// Compares dotted versions like `0.2.10` and `v0.3.0` number by number.
+pub fn compare_versions(a: &str, b: &str) -> Ordering {
+    let parse = |version: &str| -> Vec<u64> {
+        version
+            .trim_start_matches('v')
+            .split(['.', '-', '+'])
+            .map_while(|part| part.parse().ok())
+            .collect()
+    };
+    parse(a).cmp(&parse(b))
+}
+
+/// The expected SHA-256 of `name` in a `SHA256SUMS` file.
+pub fn expected_checksum<'a>(sums: &'a str, name: &str) -> Option<&'a str> {
+    sums.lines().find_map(|line| {
+        let (hash, file) = line.split_once(char::is_whitespace)?;
+        // `sha256sum -b` marks binary files with a leading '*'.
+        (file.trim_start().trim_start_matches('*') == name).then_some(hash)
+    })
+}
+
+async fn download(client: &reqwest::Client, url: &str) -> Result<Vec<u8>, Box<dyn Error>> {
+    let response = client.get(url).send().await?.error_for_status()?;
+    Ok(response.bytes().await?.to_vec())
+}
+
+/// Swaps the running binary for `binary`. The new file is written next to it
+/// and renamed over it, so a failure leaves the old one in place. Windows
+/// can't replace a running executable, but it can rename it out of the way.
+fn replace_executable(exe: &Path, binary: &[u8]) -> Result<(), Box<dyn Error>> {
+    let new = exe.with_extension("new");
+    std::fs::write(&new, binary)
+        .map_err(|e| format!("Could not write {}: {}", new.display(), e))?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        std::fs::set_permissions(&new, std::fs::Permissions::from_mode(0o755))?;
+    }
+    if cfg!(windows) {
+        let old = exe.with_extension("old");
+        let _ = std::fs::remove_file(&old);
+        std::fs::rename(exe, &old)?;
+    }
+    std::fs::rename(&new, exe).map_err(|e| {
+        let _ = std::fs::remove_file(&new);
+        format!("Could not replace {}: {}", exe.display(), e)
+    })?;
+    Ok(())
+}
+
+/// Checks the latest GitHub release and, unless `check_only`, replaces the
+/// running binary with it after verifying its SHA-256 against the release's
+/// `SHA256SUMS`.
+pub async fn run(tls: &TlsConfig, check_only: bool) -> Result<(), Box<dyn Error>> {
+    let current = env!("CARGO_PKG_VERSION");
+    let client = http::builder(tls)?
+        .user_agent(concat!("klistra/", env!("CARGO_PKG_VERSION")))
+        .build()?;
+    let url = format!(
+        "https://api.github.com/repos/{}/releases/latest",
+        repository()
+    );
+    let release: Release = async {
+        client
+            .get(&url)
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await
+    }
+    .await
+    .map_err(|e: reqwest::Error| format!("Could not look up the latest release: {}", e))?;
+
+    if compare_versions(&release.tag_name, current) != Ordering::Greater {
+        println!("klistra {} is up to date", current);
+        return Ok(());
+    }
+    println!(
+        "klistra {} is available (you have {}): {}",
+        release.tag_name.trim_start_matches('v'),
+        current,
+        release.html_url
+    );
+    if check_only {
+        return Ok(());
+    }
+
+    let name = asset_name();
+    let find = |wanted: &str| {
+        release
+            .assets
+            .iter()
+            .find(|asset| asset.name == wanted)
+            .ok_or_else(|| format!("The release has no {}", wanted))
+    };
+    let asset = find(&name)?;
+    let sums = find(CHECKSUMS)?;
+
+    let sums = String::from_utf8(download(&client, &sums.browser_download_url).await?)?;
+    let expected = expected_checksum(&sums, &name)
+        .ok_or_else(|| format!("{} has no entry for {}", CHECKSUMS, name))?
+        .to_ascii_lowercase();
+    eprintln!("Downloading {}", asset.browser_download_url);
+    let binary = download(&client, &asset.browser_download_url).await?;
+    let actual = hex::encode(Sha256::digest(&binary));
+    if actual != expected {
+        return Err(format!(
+            "Checksum mismatch for {}: expected {}, got {}. Nothing was replaced.",
+            name, expected, actual
+        )
+        .into());
+    }
+
+    let exe = std::env::current_exe()?;
+    replace_executable(&exe, &binary)?;
+    println!(
+        "Updated {} to {}",
+        exe.display(),
+        release.tag_name.trim_start_matches('v')
+    );
+    Ok(())
+}
@@ -0,0 +1,59 @@
+use crate::config::AppConfig;
+use crate::manifest::Manifest;
+use crate::publisher::{self, Publisher};
+use crate::site::Site;
+use axum::http::{header, HeaderMap};
+use std::{error::Error, path::PathBuf};
+use tokio::sync::Mutex;
+
+/// What the long-running servers share between requests. Publishes hold the
+/// manifest lock for their whole duration, so they never interleave.
+pub struct SharedSite {
+    pub site: Site<'static>,
+    pub manifest: Mutex<Manifest>,
+    pub manifest_path: PathBuf,
+}
+
+impl SharedSite {
+    pub async fn new(app_config: &'static AppConfig) -> Result<Self, Box<dyn Error>> {
+        Self::with_publisher(app_config, publisher::from_config(app_config)?).await
+    }
+
+    /// Publishes through `bucket` instead of the configured backend.
+    pub async fn with_publisher(
+        app_config: &'static AppConfig,
+        bucket: Box<dyn Publisher>,
+    ) -> Result<Self, Box<dyn Error>> {
+        let manifest_path = Manifest::path_for(app_config)?;
+        Ok(Self {
+            site: Site::with_publisher(app_config, bucket)?,
+            manifest: Mutex::new(Manifest::load(&manifest_path).await?),
+            manifest_path,
+        })
+    }
+}
+
+/// Keeps the config alive for the rest of the process, which servers need
+/// since their handlers outlive `main`'s stack frame.
+pub fn leak_config(app_config: AppConfig) -> &'static AppConfig {
+    Box::leak(Box::new(app_config))
+}
+
+/// The token from an `Authorization: Bearer ...` header.
+pub fn bearer_token(headers: &HeaderMap) -> Option<&str> {
+    headers
+        .get(header::AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "))
+}
+
+/// Whether `token` is the `expected` one. Every byte is compared, so how
+/// long the answer takes doesn't give away how much of a guess was right.
+/// (ring's `verify_slices_are_equal` did this, but is deprecated.)
+pub fn token_matches(token: &str, expected: &str) -> bool {
+    let difference = token
+        .bytes()
+        .zip(expected.bytes())
+        .fold(0u8, |difference, (a, b)| difference | (a ^ b));
+    token.len() == expected.len() && std::hint::black_box(difference) == 0
+}
@@ -0,0 +1,298 @@
+use std::{
+    error::Error,
+    path::{Path, PathBuf},
+    process::Command,
+    str::FromStr,
+};
+use tokio::fs;
+
+/// The long-running and scheduled modes `klistra service` can install.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Service {
+    Daemon,
+    Micropub,
+    Mail,
+    Telegram,
+    /// `klistra reap` every hour, deleting expired posts.
+    Reap,
+}
+
+impl FromStr for Service {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "daemon" => Ok(Service::Daemon),
+            "micropub" => Ok(Service::Micropub),
+            "mail" => Ok(Service::Mail),
+            "telegram" => Ok(Service::Telegram),
+            "reap" => Ok(Service::Reap),
+            other => Err(format!(
+                "Unknown service '{}', expected daemon, micropub, mail, telegram or reap",
+                other
+            )),
+        }
+    }
+}
+
+impl Service {
+    /// The unit or job name, e.g. `klistra-daemon`.
+    pub fn name(self) -> String {
+        let name = match self {
+            Service::Daemon => "daemon",
+            Service::Micropub => "micropub",
+            Service::Mail => "mail",
+            Service::Telegram => "telegram",
+            Service::Reap => "reap",
+        };
+        format!("klistra-{}", name)
+    }
+
+    fn args(self) -> &'static [&'static str] {
+        match self {
+            Service::Daemon => &["daemon"],
+            Service::Micropub => &["micropub-serve"],
+            Service::Mail => &["mail-fetch", "--watch"],
+            Service::Telegram => &["bot", "telegram"],
+            Service::Reap => &["reap"],
+        }
+    }
+
+    /// Runs on a timer rather than staying up.
+    fn is_scheduled(self) -> bool {
+        self == Service::Reap
+    }
+}
+
+/// The systemd user units for `service`: the `.service` file, and for
+/// scheduled ones the `.timer` starting it. Output goes to the journal.
+pub fn systemd_units(service: Service, exe: &Path, config: &Path) -> Vec<(String, String)> {
+    let name = service.name();
+    let command = command_line(service, exe, config);
+    let mut units = Vec::new();
+    if service.is_scheduled() {
+        units.push((
+            format!("{}.service", name),
+            format!(
+                "[Unit]\nDescription=klistra {args}\n\n[Service]\nType=oneshot\nEnvironmentFile=-%h/.config/klistra/env\nExecStart={command}\nSyslogIdentifier={name}\n",
+                args = service.args().join(" "),
+            ),
+        ));
+        units.push((
+            format!("{}.timer", name),
+            format!(
+                "[Unit]\nDescription=Run klistra {args} hourly\n\n[Timer]\nOnCalendar=hourly\nPersistent=true\n\n[Install]\nWantedBy=timers.target\n",
+                args = service.args().join(" "),
+            ),
+        ));
+    } else {
+        units.push((
+            format!("{}.service", name),
+            format!(
+                "[Unit]\nDescription=klistra {args}\nAfter=network-online.target\nWants=network-online.target\n\n[Service]\nEnvironmentFile=-%h/.config/klistra/env\nExecStart={command}\nRestart=on-failure\nRestartSec=10\nSyslogIdentifier={name}\n\n[Install]\nWantedBy=default.target\n",
+                args = service.args().join(" "),
+            ),
+        ));
+    }
+    units
+}
+
+/// The launchd agent for `service`, logging to `log_dir`.
+pub fn launchd_plist(service: Service, exe: &Path, config: &Path, log_dir: &Path) -> String {
+    let mut arguments = vec![
+        exe.display().to_string(),
+        "--config".to_string(),
+        config.display().to_string(),
+    ];
+    arguments.extend(service.args().iter().map(|arg| arg.to_string()));
+    let arguments: String = arguments
+        .iter()
+        .map(|arg| format!("        <string>{}</string>\n", xml_escape(arg)))
+        .collect();
+    let schedule = if service.is_scheduled() {
+        "    <key>StartInterval</key>\n    <integer>3600</integer>\n"
+    } else {
+        "    <key>RunAtLoad</key>\n    <true/>\n    <key>KeepAlive</key>\n    <dict>\n        <key>SuccessfulExit</key>\n        <false/>\n    </dict>\n"
+    };
+    let log = log_dir.join(format!("{}.log", service.name()));
+    let log = xml_escape(&log.display().to_string());
+    format!(
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<!DOCTYPE plist PUBLIC "-//Apple//DTD PLIST 1.0//EN" "http://www.apple.com/DTDs/PropertyList-1.0.dtd">
+<plist version="1.0">
+<dict>
+    <key>Label</key>
+    <string>{label}</string>
+    <key>ProgramArguments</key>
+    <array>
+{arguments}    </array>
+{schedule}    <key>StandardOutPath</key>
+    <string>{log}</string>
+    <key>StandardErrorPath</key>
+    <string>{log}</string>
+</dict>
+</plist>
+"#,
+        label = launchd_label(service),
+    )
+}
+
+/// Writes and starts the user-level unit (Linux) or launch agent (macOS)
+/// running `service` with the config at `config`. With `print`, only shows
+/// the files instead.
+pub async fn install(service: Service, config: &Path, print: bool) -> Result<(), Box<dyn Error>> {
+    let exe = std::env::current_exe()?;
+    let config = std::path::absolute(config)?;
+    let home = dirs::home_dir().ok_or("Could not determine the home directory")?;
+
+    if cfg!(target_os = "macos") {
+        let log_dir = home.join("Library/Logs/klistra");
+        let plist = launchd_plist(service, &exe, &config, &log_dir);
+        let path = launchd_path(&home, service);
+        if print {
+            println!("# {}\n{}", path.display(), plist);
+            return Ok(());
+        }
+        fs::create_dir_all(&log_dir).await?;
+        write(&path, &plist).await?;
+        // Unloading first picks up changes when installing again.
+        let _ = run("launchctl", &["unload", &path.to_string_lossy()]);
+        run("launchctl", &["load", "-w", &path.to_string_lossy()])?;
+        println!(
+            "Installed {}, logging to {}",
+            path.display(),
+            log_dir.join(format!("{}.log", service.name())).display()
+        );
+        return Ok(());
+    }
+
+    let dir = systemd_dir(&home);
+    let units = systemd_units(service, &exe, &config);
+    if print {
+        for (file, unit) in &units {
+            println!("# {}\n{}", dir.join(file).display(), unit);
+        }
+        return Ok(());
+    }
+    for (file, unit) in &units {
+        write(&dir.join(file), unit).await?;
+    }
+    run("systemctl", &["--user", "daemon-reload"])?;
+    let started = started_unit(service);
+    run("systemctl", &["--user", "enable", "--now", &started])?;
+    println!(
+        "Installed and started {}. Logs: journalctl --user -u {}",
+        started,
+        service.name()
+    );
+    println!("To keep it running while you're logged out: loginctl enable-linger $USER");
+    Ok(())
+}
+
+/// Stops `service` and removes what [`install`] wrote.
+pub async fn uninstall(service: Service) -> Result<(), Box<dyn Error>> {
+    let home = dirs::home_dir().ok_or("Could not determine the home directory")?;
+    if cfg!(target_os = "macos") {
+        let path = launchd_path(&home, service);
+        let _ = run("launchctl", &["unload", "-w", &path.to_string_lossy()]);
+        remove(&path).await?;
+        println!("Removed {}", path.display());
+        return Ok(());
+    }
+
+    let _ = run(
+        "systemctl",
+        &["--user", "disable", "--now", &started_unit(service)],
+    );
+    let dir = systemd_dir(&home);
+    for suffix in ["service", "timer"] {
+        remove(&dir.join(format!("{}.{}", service.name(), suffix))).await?;
+    }
+    run("systemctl", &["--user", "daemon-reload"])?;
+    println!("Removed {}", service.name());
+    Ok(())
+}
+
+fn command_line(service: Service, exe: &Path, config: &Path) -> String {
+    let mut parts = vec![
+        systemd_quote(&exe.display().to_string()),
+        "--config".to_string(),
+        systemd_quote(&config.display().to_string()),
+    ];
+    parts.extend(service.args().iter().map(|arg| arg.to_string()));
+    parts.join(" ")
+}
+
+/// The unit `systemctl enable` gets: the timer for scheduled services.
+fn started_unit(service: Service) -> String {
+    let suffix = if service.is_scheduled() {
+        "timer"
+    } else {
+        "service"
+    };
+    format!("{}.{}", service.name(), suffix)
+}
+
+fn systemd_dir(home: &Path) -> PathBuf {
+    std::env::var_os("XDG_CONFIG_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .unwrap_or_else(|| home.join(".config"))
+        .join("systemd/user")
+}
+
+fn launchd_label(service: Service) -> String {
+    format!("com.klistra.{}", service.name())
+}
+
+fn launchd_path(home: &Path, service: Service) -> PathBuf {
+    home.join("Library/LaunchAgents")
+        .join(format!("{}.plist", launchd_label(service)))
+}
+
+/// `value` quoted for `ExecStart` if it has spaces or quotes in it, with
+/// `%` escaped from systemd's specifiers.
+fn systemd_quote(value: &str) -> String {
+    let value = value.replace('%', "%%");
+    if value.contains([' ', '"', '\\']) {
+        format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\""))
+    } else {
+        value
+    }
+}
+
+fn xml_escape(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+async fn write(path: &Path, contents: &str) -> Result<(), Box<dyn Error>> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).await?;
+    }
+    fs::write(path, contents)
+        .await
+        .map_err(|e| format!("Could not write {}: {}", path.display(), e).into())
+}
+
+async fn remove(path: &Path) -> Result<(), Box<dyn Error>> {
+    match fs::remove_file(path).await {
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => {
+            Err(format!("Could not remove {}: {}", path.display(), e).into())
+        }
+        _ => Ok(()),
+    }
+}
+
+fn run(program: &str, args: &[&str]) -> Result<(), Box<dyn Error>> {
+    let status = Command::new(program)
+        .args(args)
+        .status()
+        .map_err(|e| format!("Could not run {}: {}", program, e))?;
+    if !status.success() {
+        return Err(format!("{} {} failed with {}", program, args.join(" "), status).into());
+    }
+    Ok(())
+}
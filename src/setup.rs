@@ -0,0 +1,65 @@
+use crate::config::AppConfig;
+use crate::frontmatter::Labels;
+use crate::publisher::Publisher;
+use crate::render::not_found_page;
+use crate::upload::Bucket;
+use std::error::Error;
+
+/// Prefix (under the configured one) that `--expire-drafts` applies to.
+pub const DRAFTS_PREFIX: &str = "drafts/";
+
+/// Prepares the configured bucket for publishing: creates it if needed, makes
+/// posts publicly readable, allows cross-origin fonts and scripts, uploads
+/// `404.html` and optionally expires drafts. Steps the server doesn't support are reported
+/// and skipped, so the command can be rerun safely.
+pub async fn setup_bucket(
+    config: &AppConfig,
+    expire_drafts: Option<i32>,
+) -> Result<(), Box<dyn Error>> {
+    let bucket = Bucket::new(&config.s3, &config.tls)?;
+
+    if bucket.exists().await? {
+        eprintln!("Bucket {} exists", bucket.name());
+    } else {
+        bucket
+            .create()
+            .await
+            .map_err(|e| format!("Could not create bucket {}: {}", bucket.name(), e))?;
+        eprintln!("Created bucket {}", bucket.name());
+    }
+
+    let mut skipped = 0;
+    let mut step = |name: &str, result: Result<(), Box<dyn Error>>| match result {
+        Ok(()) => eprintln!("{}: done", name),
+        Err(e) => {
+            eprintln!("{}: failed ({})", name, e);
+            skipped += 1;
+        }
+    };
+    step("Public read access", bucket.allow_public_read().await);
+    step("CORS for fonts and scripts", bucket.allow_cors().await);
+    step(
+        "404 page",
+        bucket
+            .put_html(
+                "404.html",
+                not_found_page(&config.page, &config.site),
+                &Labels::new(),
+            )
+            .await,
+    );
+    if let Some(days) = expire_drafts {
+        step(
+            &format!("Expire {} after {} days", DRAFTS_PREFIX, days),
+            bucket.expire_after(DRAFTS_PREFIX, days).await,
+        );
+    }
+
+    if skipped > 0 {
+        eprintln!(
+            "{} step(s) failed; apply them in the provider's web console or CLI instead",
+            skipped
+        );
+    }
+    Ok(())
+}
@@ -0,0 +1,53 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use tokio::sync::Notify;
+
+static REQUESTED: AtomicBool = AtomicBool::new(false);
+static NOTIFY: Notify = Notify::const_new();
+
+/// Catches SIGINT and SIGTERM from now on. The first asks the running work
+/// to stop at the next safe point ([`requested`]); a second quits at once.
+pub fn listen() {
+    tokio::spawn(async {
+        if signal().await.is_err() {
+            return;
+        }
+        REQUESTED.store(true, Ordering::SeqCst);
+        NOTIFY.notify_waiters();
+        eprintln!("Stopping once the work in progress is done; interrupt again to quit now");
+        let _ = signal().await;
+        std::process::exit(130);
+    });
+}
+
+/// Whether a stop was asked for. Long loops check it between items of work.
+pub fn requested() -> bool {
+    REQUESTED.load(Ordering::SeqCst)
+}
+
+/// Resolves once a stop was asked for, e.g. for
+/// `axum::serve(...).with_graceful_shutdown`.
+pub async fn wait() {
+    let notified = NOTIFY.notified();
+    tokio::pin!(notified);
+    // Registered before checking, so a stop in between isn't missed.
+    notified.as_mut().enable();
+    if requested() {
+        return;
+    }
+    notified.await;
+}
+
+#[cfg(unix)]
+async fn signal() -> std::io::Result<()> {
+    use tokio::signal::unix::{signal, SignalKind};
+    let mut terminate = signal(SignalKind::terminate())?;
+    tokio::select! {
+        result = tokio::signal::ctrl_c() => result,
+        _ = terminate.recv() => Ok(()),
+    }
+}
+
+#[cfg(not(unix))]
+async fn signal() -> std::io::Result<()> {
+    tokio::signal::ctrl_c().await
+}
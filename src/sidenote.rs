@@ -0,0 +1,94 @@
+use crate::render::markdown_options;
+use pulldown_cmark::{Event, Parser as MarkdownParser, Tag};
+use std::{borrow::Cow, collections::HashMap, ops::Range};
+
+/// Replaces footnotes (`text[^1]` with a `[^1]: note` definition) with
+/// sidenotes: each note goes right after its first reference, shown in the
+/// margin on wide screens and opened by tapping its number on narrow ones.
+/// Works the same with every markdown backend, since the notes are raw HTML
+/// by the time the body is rendered. A note's lines are joined into one
+/// paragraph.
+pub fn footnotes_to_sidenotes(markdown: &str) -> Cow<'_, str> {
+    if !markdown.contains("[^") {
+        return Cow::Borrowed(markdown);
+    }
+
+    let mut definitions = Vec::new();
+    let mut references = Vec::new();
+    for (event, range) in MarkdownParser::new_ext(markdown, markdown_options()).into_offset_iter() {
+        match event {
+            Event::Start(Tag::FootnoteDefinition(label)) => {
+                definitions.push((label.to_lowercase(), range))
+            }
+            Event::FootnoteReference(label) => references.push((label.to_lowercase(), range)),
+            _ => {}
+        }
+    }
+    if definitions.is_empty() {
+        return Cow::Borrowed(markdown);
+    }
+
+    let notes: HashMap<&str, String> = definitions
+        .iter()
+        .map(|(label, range)| (label.as_str(), note_text(&markdown[range.clone()])))
+        .collect();
+    let inside_definition = |range: &Range<usize>| {
+        definitions
+            .iter()
+            .any(|(_, definition)| definition.start <= range.start && range.end <= definition.end)
+    };
+    let mut numbers: HashMap<&str, usize> = HashMap::new();
+    let mut replacements: Vec<(Range<usize>, String)> = Vec::new();
+    for (label, range) in &references {
+        let Some(note) = notes.get(label.as_str()) else {
+            continue;
+        };
+        if inside_definition(range) {
+            continue;
+        }
+        let html = match numbers.get(label.as_str()) {
+            // Later references open the same note.
+            Some(number) => format!(
+                "<label for=\"sidenote-{number}\" class=\"sidenote-number\">{number}</label>"
+            ),
+            None => {
+                let number = numbers.len() + 1;
+                numbers.insert(label, number);
+                sidenote_html(number, note)
+            }
+        };
+        replacements.push((range.clone(), html));
+    }
+    replacements.extend(
+        definitions
+            .into_iter()
+            .map(|(_, range)| (range, String::new())),
+    );
+    replacements.sort_by_key(|(range, _)| range.start);
+
+    let mut expanded = String::with_capacity(markdown.len());
+    let mut end = 0;
+    for (range, html) in replacements {
+        expanded.push_str(&markdown[end..range.start]);
+        expanded.push_str(&html);
+        end = range.end;
+    }
+    expanded.push_str(&markdown[end..]);
+    Cow::Owned(expanded)
+}
+
+/// The markdown of a `[^label]: note` definition without its label, on one
+/// line so it fits inside a paragraph.
+fn note_text(definition: &str) -> String {
+    let text = definition
+        .split_once("]:")
+        .map_or(definition, |(_, text)| text);
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// The number of note `number`, a hidden checkbox it toggles, and the note.
+fn sidenote_html(number: usize, note: &str) -> String {
+    format!(
+        "<label for=\"sidenote-{number}\" class=\"sidenote-number\">{number}</label><input type=\"checkbox\" id=\"sidenote-{number}\" class=\"sidenote-toggle\"><span class=\"sidenote\"><span class=\"sidenote-number\">{number}</span> {note}</span>"
+    )
+}
@@ -0,0 +1,2621 @@
+use crate::activitypub;
+use crate::announce;
+use crate::audit;
+use crate::budget;
+use crate::config::{AppConfig, MarkdownBackend, RedirectStyle, SlugCollision, SlugStyle};
+use crate::exif;
+use crate::expiry::Ttl;
+use crate::feed;
+use crate::formats::{self, InputFormat};
+use crate::frontmatter::{local_midnight, slug, split_file_date, FrontMatter, Labels};
+use crate::gallery;
+use crate::highlight::Highlighter;
+use crate::history;
+use crate::http;
+use crate::import::Conversion;
+use crate::index;
+use crate::integrity;
+use crate::lock;
+use crate::manifest::{self, content_hash, Enclosure, Manifest, Post, Preview, Section};
+use crate::media::{self, Audio, FileStarts};
+use crate::mime;
+use crate::ogimage::OgImageRenderer;
+use crate::publisher::{self, Precondition, Publisher};
+use crate::quick;
+use crate::raw;
+use crate::redirects;
+use crate::related;
+use crate::render::{
+    escape_html, first_heading, redirect_page, render_post, render_post_to, Alternate, Heading,
+    PostContext, RenderedPost,
+};
+use crate::resume;
+use crate::schema;
+use crate::secrets;
+use crate::setup::DRAFTS_PREFIX;
+use crate::shutdown;
+use crate::sources;
+use crate::stream::{self, HashWriter, PartWriter};
+use crate::svg;
+use crate::variants;
+use crate::views;
+use crate::webhook;
+use crate::webmention::{self, OutboundLink};
+use chrono::{DateTime, Utc};
+use sha2::{Digest, Sha256};
+use std::{
+    error::Error,
+    fmt,
+    path::{Path, PathBuf},
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+use tokio::fs;
+use uuid::Uuid;
+
+pub fn file_title(path: &Path) -> &str {
+    path.file_stem()
+        .and_then(|s| s.to_str())
+        .unwrap_or("Document")
+}
+
+/// When a new post went out: the day its file name starts with, if it's
+/// named like `2024-06-01-title.md`, so posts moved over from elsewhere keep
+/// their place in the index and feed. Otherwise now.
+fn published_at(file_title: &str) -> DateTime<Utc> {
+    split_file_date(file_title)
+        .0
+        .and_then(local_midnight)
+        .unwrap_or_else(Utc::now)
+}
+
+/// Adds `entries` to the history next to the manifest. A failure is only
+/// reported; the change itself already happened.
+async fn record_history(manifest_path: &Path, entries: &[history::Entry]) {
+    if let Err(e) = history::append(&history::path_for(manifest_path), entries).await {
+        eprintln!("Could not write the history: {}", e);
+    }
+}
+
+/// The file name of `path` as bytes, for seeding reproducible ids.
+fn file_name(path: &Path) -> &[u8] {
+    path.file_name()
+        .map(|name| name.as_encoded_bytes())
+        .unwrap_or_default()
+}
+
+/// The path a group of translations is recorded under in the manifest:
+/// `notes/post.md` for `notes/post.sv.md`.
+fn group_source(source: &Path, base: &str) -> std::io::Result<PathBuf> {
+    Ok(
+        std::path::absolute(source)?.with_file_name(match source.extension() {
+            Some(ext) => format!("{}.{}", base, ext.to_string_lossy()),
+            None => base.to_string(),
+        }),
+    )
+}
+
+/// How long a presigned URL can last with SigV4.
+const MAX_PREVIEW: chrono::TimeDelta = chrono::TimeDelta::weeks(1);
+
+/// The local files `post` embeds, by name, with their sizes.
+async fn media_sizes(post: &RenderedPost) -> std::io::Result<Vec<(String, u64)>> {
+    let mut sizes = Vec::with_capacity(post.media.len());
+    for item in &post.media {
+        sizes.push((item.path.clone(), fs::metadata(&item.source).await?.len()));
+    }
+    Ok(sizes)
+}
+
+/// Posts with at least this much markdown are rendered straight into a
+/// multipart upload instead of into memory.
+const STREAM_THRESHOLD: usize = 32 * 1024 * 1024;
+
+/// A markdown post ready to be rendered into `p/<folder_name>/`, with what
+/// it needs from the manifest, so several can render in parallel.
+struct PostJob {
+    folder_name: String,
+    /// Where the post goes in the bucket, e.g. `p/<folder_name>`.
+    dir: String,
+    markdown: String,
+    fallback_title: String,
+    source: Option<PathBuf>,
+    image_url: Option<String>,
+    view_pixel: Option<String>,
+    /// Where the markdown is published, with `[sources] upload`.
+    source_url: Option<String>,
+    published: Option<DateTime<Utc>>,
+    updated: Option<DateTime<Utc>>,
+    /// Hash of the page currently in the bucket, if any.
+    previous_output: Option<String>,
+    related: Vec<Post>,
+    file_starts: FileStarts,
+}
+
+/// Where a post's objects are uploaded before they go live: `files/` and
+/// `page/` under a fresh directory in `staging/`, with `[upload] staged`.
+/// Without it, straight into the post's directory. Files big enough to be
+/// uploaded resumably always go straight to the post's directory, since
+/// resuming needs the same path on the next run.
+struct Staging {
+    dir: String,
+    root: Option<String>,
+    /// Multipart uploads started for the post and not completed yet, as
+    /// `(path, upload id)`.
+    uploads: Mutex<Vec<(String, String)>>,
+}
+
+impl Staging {
+    fn new(config: &AppConfig, dir: &str) -> Self {
+        Self {
+            dir: dir.to_string(),
+            root: config
+                .upload
+                .staged
+                .then(|| format!("staging/{}", uuid::Uuid::new_v4())),
+            uploads: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// Notes a multipart upload for [`Staging::discard`] to abort.
+    fn started(&self, path: &str, upload_id: &str) {
+        let mut uploads = self.uploads.lock().unwrap_or_else(|e| e.into_inner());
+        uploads.push((path.to_string(), upload_id.to_string()));
+    }
+
+    /// Forgets a multipart upload once it's complete.
+    fn completed(&self, upload_id: &str) {
+        let mut uploads = self.uploads.lock().unwrap_or_else(|e| e.into_inner());
+        uploads.retain(|(_, id)| id != upload_id);
+    }
+
+    /// Where the page's files (images, media, `og.png`) go.
+    fn files(&self) -> String {
+        match &self.root {
+            Some(root) => format!("{}/files", root),
+            None => self.dir.clone(),
+        }
+    }
+
+    /// Where `index.html` goes.
+    fn page(&self) -> String {
+        match &self.root {
+            Some(root) => format!("{}/page", root),
+            None => self.dir.clone(),
+        }
+    }
+
+    /// Moves the staged files into the post's directory, then the page, so
+    /// the new page only goes live once everything it links to has. Whatever
+    /// is left in staging after a failure is removed.
+    async fn go_live(&self, bucket: &dyn Publisher) -> Result<(), Box<dyn Error>> {
+        let Some(root) = &self.root else {
+            return Ok(());
+        };
+        let live = format!("{}/", self.dir);
+        // As strings, since the errors can't be held across the next await.
+        let mut moved = bucket
+            .move_prefix(&format!("{}/files/", root), &live)
+            .await
+            .map_err(|e| e.to_string());
+        if moved.is_ok() {
+            moved = bucket
+                .move_prefix(&format!("{}/page/", root), &live)
+                .await
+                .map_err(|e| e.to_string());
+        }
+        if moved.is_err() {
+            self.discard(bucket).await;
+        }
+        moved.map(|_| ()).map_err(Into::into)
+    }
+
+    /// Aborts the multipart uploads still open and removes what was staged,
+    /// e.g. after a failed upload. Best effort.
+    async fn discard(&self, bucket: &dyn Publisher) {
+        let uploads = std::mem::take(&mut *self.uploads.lock().unwrap_or_else(|e| e.into_inner()));
+        for (path, upload_id) in uploads {
+            let _ = bucket.abort_multipart(&path, &upload_id).await;
+        }
+        if let Some(root) = &self.root {
+            let _ = bucket.delete_prefix(&format!("{}/", root)).await;
+        }
+    }
+}
+
+impl PostJob {
+    fn render(&self, config: &AppConfig) -> Result<RenderedPost, Box<dyn Error>> {
+        schema::validate(&self.markdown, &config.front_matter)?;
+        secrets::check(&self.markdown, &config.check)?;
+        render_post(&self.markdown, &config.page, &self.context(config))
+    }
+
+    /// Renders the page into `out` rather than memory; see [`render_post_to`].
+    fn render_to(
+        &self,
+        config: &AppConfig,
+        out: &mut dyn fmt::Write,
+    ) -> Result<RenderedPost, Box<dyn Error>> {
+        schema::validate(&self.markdown, &config.front_matter)?;
+        secrets::check(&self.markdown, &config.check)?;
+        render_post_to(&self.markdown, &config.page, &self.context(config), out)
+    }
+
+    /// Whether the post is big enough to stream to the bucket while it
+    /// renders, which only pulldown-cmark can do.
+    fn streams(&self, config: &AppConfig) -> bool {
+        self.markdown.len() >= STREAM_THRESHOLD
+            && config.page.parser == MarkdownBackend::Pulldown
+            && self
+                .source
+                .as_deref()
+                .map(InputFormat::from_path)
+                .unwrap_or_default()
+                == InputFormat::Markdown
+    }
+
+    fn context<'a>(&'a self, config: &'a AppConfig) -> PostContext<'a> {
+        PostContext {
+            fallback_title: &self.fallback_title,
+            lang: &config.page.lang,
+            alternates: &[],
+            image_url: self.image_url.as_deref(),
+            format: self
+                .source
+                .as_deref()
+                .map(InputFormat::from_path)
+                .unwrap_or_default(),
+            base_dir: self.source.as_deref().and_then(Path::parent),
+            site: &config.site,
+            published: self.published,
+            updated: self.updated,
+            related: &self.related,
+            view_pixel: self.view_pixel.as_deref(),
+            source_url: self.source_url.as_deref(),
+            content_types: &config.upload.content_types,
+            file_starts: &self.file_starts,
+        }
+    }
+}
+
+/// A file in a directory being published that changed since last time.
+enum Change {
+    /// A single-language post, new or already at `p/<id>/`.
+    Single { source: PathBuf, id: String },
+    /// One of a group of translations named `<base>.<lang>.<ext>`.
+    Translations { source: PathBuf, base: String },
+}
+
+/// The result of publishing a file.
+pub struct Published {
+    pub post: Post,
+    /// Whether an existing post was replaced rather than a new one created.
+    pub updated: bool,
+    /// The rendered page (the default language's for translated posts).
+    pub html: String,
+    /// Links from the published pages to other sites.
+    pub links: Vec<OutboundLink>,
+}
+
+impl Published {
+    /// What webhooks and the history call this publish.
+    pub fn event(&self) -> webhook::Event {
+        if self.updated {
+            webhook::Event::Updated
+        } else {
+            webhook::Event::Published
+        }
+    }
+}
+
+/// Whether to announce a post on the services configured under `[announce]`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Announce {
+    Yes,
+    No,
+    /// Print what would be posted.
+    DryRun,
+}
+
+/// The bucket posts are published to, along with everything needed to render them.
+pub struct Site<'a> {
+    config: &'a AppConfig,
+    bucket: Box<dyn Publisher>,
+    og_images: Option<OgImageRenderer>,
+    http: reqwest::Client,
+    /// Uploads not yet in the integrity record, if `[integrity]` is enabled.
+    integrity: Option<integrity::Changes>,
+}
+
+impl<'a> Site<'a> {
+    /// Publishes to the backend in `config`.
+    pub fn new(config: &'a AppConfig) -> Result<Self, Box<dyn Error>> {
+        Self::with_publisher(config, publisher::from_config(config)?)
+    }
+
+    /// Publishes through `bucket` instead, e.g. a
+    /// [`publisher::MemoryPublisher`] in tests.
+    pub fn with_publisher(
+        config: &'a AppConfig,
+        bucket: Box<dyn Publisher>,
+    ) -> Result<Self, Box<dyn Error>> {
+        let og_images = if config.og_image.enabled {
+            Some(OgImageRenderer::new(&config.og_image)?)
+        } else {
+            None
+        };
+        let bucket: Box<dyn Publisher> = if config.audit.enabled {
+            Box::new(audit::Auditor::new(bucket, config)?)
+        } else {
+            bucket
+        };
+        let (bucket, integrity) = if config.integrity.enabled {
+            let changes = integrity::Changes::default();
+            let recorder: Box<dyn Publisher> =
+                Box::new(integrity::Recorder::new(bucket, changes.clone()));
+            (recorder, Some(changes))
+        } else {
+            (bucket, None)
+        };
+        Ok(Self {
+            config,
+            bucket,
+            og_images,
+            http: http::client(&config.tls)?,
+            integrity,
+        })
+    }
+
+    /// The client for outgoing requests other than uploads, using the `[tls]` settings.
+    pub fn http(&self) -> &reqwest::Client {
+        &self.http
+    }
+
+    /// Publishes `source`, or all of its translations if it's named like `post.sv.md`.
+    pub async fn publish(
+        &self,
+        source: &Path,
+        manifest: &mut Manifest,
+    ) -> Result<Published, Box<dyn Error>> {
+        match variants::split_lang(source) {
+            Some((base, _)) => {
+                self.publish_translations(source, &base, manifest, true)
+                    .await
+            }
+            None => self.publish_single(source, manifest).await,
+        }
+    }
+
+    /// Publishes every note under `dir` as its own post (translations as one
+    /// post per group). Files published before are updated in place, and only
+    /// if their content changed since, so publishing a whole directory again
+    /// is cheap. Posts render in parallel, a batch per CPU at a time, and
+    /// upload one by one; `timings` prints how long each took. Files that fail
+    /// are reported and skipped; returns the posts that were published and how
+    /// many files failed.
+    pub async fn publish_dir(
+        &self,
+        dir: &Path,
+        manifest: &mut Manifest,
+        timings: bool,
+    ) -> Result<(Vec<Published>, usize), Box<dyn Error>> {
+        let mut files: Vec<PathBuf> = quick::note_files(dir)
+            .await?
+            .into_iter()
+            .map(|(path, _)| path)
+            .collect();
+        files.sort();
+
+        let mut published = Vec::new();
+        let mut failed = 0;
+        let mut report = |path: &Path, result: Result<Published, Box<dyn Error>>| match result {
+            Ok(post) => {
+                let verb = if post.updated { "Updated" } else { "Published" };
+                eprintln!("{} {}", verb, path.display());
+                published.push(post);
+            }
+            Err(e) => {
+                eprintln!("Could not publish {}: {}", path.display(), e);
+                failed += 1;
+            }
+        };
+
+        let mut singles = Vec::new();
+        let mut groups_seen = Vec::new();
+        let mut new_ids = Vec::new();
+        for path in files {
+            // What's left is still changed, so the next run picks it up.
+            if shutdown::requested() {
+                break;
+            }
+            match self
+                .changed(&path, &mut groups_seen, &mut new_ids, manifest)
+                .await
+            {
+                Ok(Some(Change::Single { source, id })) => singles.push((path, source, id)),
+                Ok(Some(Change::Translations { source, base })) => {
+                    let result = self
+                        .publish_translations(&source, &base, manifest, true)
+                        .await;
+                    report(&path, result);
+                }
+                Ok(None) => {}
+                Err(e) => report(&path, Err(e)),
+            }
+        }
+
+        let workers = std::thread::available_parallelism().map_or(4, usize::from);
+        for batch in singles.chunks(workers) {
+            if shutdown::requested() {
+                eprintln!("Stopped before publishing every file");
+                break;
+            }
+            let mut jobs = Vec::with_capacity(batch.len());
+            for (path, source, id) in batch {
+                let markdown = match formats::read_source(source).await {
+                    Ok(markdown) => markdown,
+                    Err(e) => {
+                        report(path, Err(e));
+                        continue;
+                    }
+                };
+                let job = self
+                    .prepare(
+                        id.clone(),
+                        markdown,
+                        file_title(source),
+                        Some(source.clone()),
+                        manifest,
+                        true,
+                    )
+                    .await;
+                // Huge posts go out on their own rather than in parallel.
+                if job.streams(self.config) {
+                    let start = Instant::now();
+                    let result = self.publish_streamed(job, manifest).await;
+                    if timings {
+                        eprintln!(
+                            "{:>8.1} ms render and upload  {}",
+                            start.elapsed().as_secs_f64() * 1000.0,
+                            path.display()
+                        );
+                    }
+                    report(path, result);
+                    continue;
+                }
+                jobs.push((path, job));
+            }
+
+            // Errors become strings to leave the render threads. Waiting for
+            // them blocks, so it mustn't hold up the runtime's other tasks.
+            let rendered: Vec<(Result<RenderedPost, String>, Duration)> = stream::blocking(|| {
+                std::thread::scope(|scope| {
+                    let handles: Vec<_> = jobs
+                        .iter()
+                        .map(|(_, job)| {
+                            scope.spawn(|| {
+                                let start = Instant::now();
+                                let post = job.render(self.config).map_err(|e| e.to_string());
+                                (post, start.elapsed())
+                            })
+                        })
+                        .collect();
+                    handles
+                        .into_iter()
+                        .map(|handle| {
+                            handle.join().unwrap_or_else(|_| {
+                                (Err("rendering panicked".to_string()), Duration::ZERO)
+                            })
+                        })
+                        .collect()
+                })
+            });
+
+            for ((path, job), (post, render_time)) in jobs.into_iter().zip(rendered) {
+                let start = Instant::now();
+                let result = match post {
+                    Ok(post) => self.store(job, post, manifest).await,
+                    Err(e) => Err(e.into()),
+                };
+                if timings {
+                    eprintln!(
+                        "{:>8.1} ms render {:>8.1} ms upload  {}",
+                        render_time.as_secs_f64() * 1000.0,
+                        start.elapsed().as_secs_f64() * 1000.0,
+                        path.display()
+                    );
+                }
+                report(path, result);
+            }
+        }
+        Ok((published, failed))
+    }
+
+    /// What [`Site::publish_dir`] has to publish for the file at `path`, or
+    /// `None` if its source hash matches the manifest. Translation groups are
+    /// published once, for the first of their files; `groups_seen` remembers
+    /// them. Ids given to new posts are added to `reserved`, so two new files
+    /// can't get the same slug.
+    async fn changed(
+        &self,
+        path: &Path,
+        groups_seen: &mut Vec<PathBuf>,
+        reserved: &mut Vec<String>,
+        manifest: &mut Manifest,
+    ) -> Result<Option<Change>, Box<dyn Error>> {
+        let source = std::path::absolute(path)?;
+        if let Some((base, _)) = variants::split_lang(&source) {
+            let group_source = group_source(&source, &base)?;
+            if groups_seen.contains(&group_source) {
+                return Ok(None);
+            }
+            groups_seen.push(group_source.clone());
+            let translations = variants::find_variants(&source, &base).await?;
+            let hash = variants::source_hash(&translations).await?;
+            let unchanged = manifest
+                .translated_post_mut(&group_source)
+                .is_some_and(|post| post.source_hash.as_ref() == Some(&hash));
+            return Ok((!unchanged).then_some(Change::Translations { source, base }));
+        }
+
+        let content = fs::read(&source).await?;
+        let hash = content_hash([content.as_slice()]);
+        let existing = manifest.posts.iter().find(|post| {
+            post.source.as_deref() == Some(source.as_path())
+                && post.languages.is_empty()
+                && !post.raw
+        });
+        let id = match existing {
+            Some(post) if post.source_hash.as_ref() == Some(&hash) => return Ok(None),
+            Some(post) => post.id.clone(),
+            None => {
+                let text = formats::read_source(&source).await?;
+                let id = self
+                    .post_id(
+                        &text,
+                        file_title(&source),
+                        [file_name(&source), content.as_slice()],
+                        manifest,
+                        reserved,
+                    )
+                    .await?;
+                reserved.push(id.clone());
+                id
+            }
+        };
+        Ok(Some(Change::Single { source, id }))
+    }
+
+    /// Moves a post to the trash: its objects from `p/<id>/` to
+    /// `trash/p/<id>/` (or from a team member's directory to the same place
+    /// under `trash/`) and the post to the manifest's trash, so it can be
+    /// restored with [`Site::undelete`]. Returns the deleted post, or `None`
+    /// if the manifest doesn't know `id`.
+    pub async fn delete(
+        &self,
+        id: &str,
+        manifest: &mut Manifest,
+    ) -> Result<Option<Post>, Box<dyn Error>> {
+        let Some(index) = manifest.posts.iter().position(|post| post.id == id) else {
+            return Ok(None);
+        };
+        let dir = manifest.posts[index].dir();
+        self.bucket
+            .move_prefix(&format!("{}/", dir), &format!("trash/{}/", dir))
+            .await?;
+        let mut post = manifest.posts.remove(index);
+        post.deleted = Some(chrono::Utc::now());
+        manifest.trash.push(post.clone());
+        Ok(Some(post))
+    }
+
+    /// Moves a post back out of the trash. Returns `None` if there's no
+    /// deleted post with that id.
+    pub async fn undelete(
+        &self,
+        id: &str,
+        manifest: &mut Manifest,
+    ) -> Result<Option<Post>, Box<dyn Error>> {
+        let Some(index) = manifest.trash.iter().position(|post| post.id == id) else {
+            return Ok(None);
+        };
+        let dir = manifest.trash[index].dir();
+        self.bucket
+            .move_prefix(&format!("trash/{}/", dir), &format!("{}/", dir))
+            .await?;
+        let mut post = manifest.trash.remove(index);
+        post.deleted = None;
+        post.restored = Some(chrono::Utc::now());
+        manifest.posts.push(post.clone());
+        Ok(Some(post))
+    }
+
+    /// Permanently removes a post's objects, whether it's live or in the
+    /// trash, and drops it from the manifest. A live post that's purged has
+    /// `deleted` unset, so callers know to run [`Site::finish_delete`].
+    pub async fn purge(
+        &self,
+        id: &str,
+        manifest: &mut Manifest,
+    ) -> Result<Option<Post>, Box<dyn Error>> {
+        if let Some(index) = manifest.posts.iter().position(|post| post.id == id) {
+            let dir = manifest.posts[index].dir();
+            self.bucket.delete_prefix(&format!("{}/", dir)).await?;
+            manifest.purged.insert(id.to_string(), chrono::Utc::now());
+            return Ok(Some(manifest.posts.remove(index)));
+        }
+        let Some(index) = manifest.trash.iter().position(|post| post.id == id) else {
+            return Ok(None);
+        };
+        let dir = manifest.trash[index].dir();
+        self.bucket
+            .delete_prefix(&format!("trash/{}/", dir))
+            .await?;
+        manifest.purged.insert(id.to_string(), chrono::Utc::now());
+        Ok(Some(manifest.trash.remove(index)))
+    }
+
+    /// Builds the front page, its search index, `posts.json` and the RSS and
+    /// JSON feeds from the manifest and uploads them to the bucket root,
+    /// along with `archive/` if `archive` is set or configured, the blogroll
+    /// if there is one, and the ActivityPub documents if `[activitypub]` is
+    /// enabled. Returns the page's URL. With `[upload] lock`, this happens
+    /// while holding the bucket's lock object.
+    pub async fn publish_index(
+        &self,
+        manifest: &Manifest,
+        archive: bool,
+    ) -> Result<String, Box<dyn Error>> {
+        if !self.config.upload.lock {
+            return self.upload_index(manifest, archive).await;
+        }
+        let wait = Duration::from_secs(self.config.upload.lock_wait);
+        let lock = lock::acquire(self.bucket.as_ref(), wait).await?;
+        // As a string, since the error is kept across the release.
+        let url = self
+            .upload_index(manifest, archive)
+            .await
+            .map_err(|e| e.to_string());
+        if let Err(e) = lock.release().await {
+            eprintln!("Could not release {}: {}", lock::LOCK_PATH, e);
+        }
+        Ok(url?)
+    }
+
+    async fn upload_index(
+        &self,
+        manifest: &Manifest,
+        archive: bool,
+    ) -> Result<String, Box<dyn Error>> {
+        let archive = archive || self.config.index.archive;
+        let posts = index::listed(manifest);
+        let search_index = index::search_index(&posts).await?;
+        let html = index::index_page(&posts, self.config, archive)?;
+        let feed = feed::rss(&posts, self.config, &self.bucket.public_url(""));
+        let json_feed = feed::json_feed(&posts, self.config, &self.bucket.public_url(""))?;
+        let posts_json = index::posts_json(&posts, self.config, &self.bucket.public_url(""))?;
+        if archive {
+            let archive_html = index::archive_page(&posts, self.config)?;
+            self.bucket
+                .put_html("archive/index.html", archive_html, &Labels::new())
+                .await?;
+        }
+        self.bucket
+            .put(
+                "search.json",
+                search_index.into_bytes(),
+                "application/json",
+                &Labels::new(),
+            )
+            .await?;
+        self.bucket
+            .put(
+                index::POSTS_PATH,
+                posts_json.into_bytes(),
+                "application/json",
+                &Labels::new(),
+            )
+            .await?;
+        self.bucket
+            .put(
+                feed::JSON_FEED_PATH,
+                json_feed.into_bytes(),
+                "application/feed+json",
+                &Labels::new(),
+            )
+            .await?;
+        self.bucket
+            .put(
+                feed::FEED_PATH,
+                feed.into_bytes(),
+                "application/rss+xml",
+                &Labels::new(),
+            )
+            .await?;
+        if !self.config.blogroll.sites.is_empty() {
+            let blogroll = index::blogroll_page(self.config)?;
+            self.bucket
+                .put_html(
+                    &format!("{}index.html", index::BLOGROLL_DIR),
+                    blogroll,
+                    &Labels::new(),
+                )
+                .await?;
+            self.bucket
+                .put(
+                    index::BLOGROLL_OPML_PATH,
+                    index::blogroll_opml(self.config).into_bytes(),
+                    "text/x-opml",
+                    &Labels::new(),
+                )
+                .await?;
+        }
+        if self.config.activitypub.enabled {
+            let site_url = self.bucket.public_url("");
+            let documents =
+                activitypub::documents(&posts, self.config, &site_url, self.bucket.host())?;
+            for document in documents {
+                self.bucket
+                    .put(
+                        document.path,
+                        document.json.into_bytes(),
+                        document.content_type,
+                        &Labels::new(),
+                    )
+                    .await?;
+            }
+        }
+        self.bucket
+            .put_html("index.html", html, &Labels::new())
+            .await?;
+        Ok(self.bucket.public_url(""))
+    }
+
+    /// Publishes the notes [`crate::import::convert`] wrote to `out` with their
+    /// page bundles' files, uploads the old site's static files under their
+    /// old keys, and points each post's old URLs at its new one in
+    /// `redirects.toml`. Returns what was
+    /// published and how many notes failed.
+    pub async fn import(
+        &self,
+        conversion: &Conversion,
+        out: &Path,
+        manifest: &mut Manifest,
+    ) -> Result<(Vec<Published>, usize), Box<dyn Error>> {
+        let (published, failed) = self.publish_dir(out, manifest, false).await?;
+
+        for (file, key) in &conversion.assets {
+            let uploaded = self.upload_static(file, key).await;
+            if let Err(e) = uploaded {
+                eprintln!("Could not upload {}: {}", file.display(), e);
+            }
+        }
+
+        let mut moved = Vec::new();
+        for post in &conversion.posts {
+            let note = std::path::absolute(&post.note)?;
+            let Some(dir) = manifest
+                .posts
+                .iter()
+                .find(|published| published.source.as_deref() == Some(note.as_path()))
+                .map(Post::dir)
+            else {
+                continue;
+            };
+            let note_dir = note.parent().unwrap_or(Path::new("."));
+            for resource in &post.resources {
+                let file = note_dir.join(resource);
+                let uploaded = self
+                    .upload_static(&file, &format!("{}/{}", dir, resource))
+                    .await;
+                if let Err(e) = uploaded {
+                    eprintln!("Could not upload {}: {}", file.display(), e);
+                }
+            }
+            for old in &post.old_paths {
+                if !old.is_empty() && *old != dir {
+                    moved.push((old.clone(), dir.clone()));
+                }
+            }
+        }
+        match &self.config.redirects.file {
+            Some(path) => {
+                let added = redirects::add(path, &moved).await?;
+                eprintln!("Added {} redirects to {}", added, path.display());
+            }
+            None if !moved.is_empty() => {
+                eprintln!("Old URLs aren't redirected: no [redirects] file is configured")
+            }
+            None => {}
+        }
+        Ok((published, failed))
+    }
+
+    /// Uploads `file` as it is to `key`, as a post's media is.
+    pub async fn upload_static(&self, file: &Path, key: &str) -> Result<(), Box<dyn Error>> {
+        let start = mime::read_start(file).await?;
+        let content_type = mime::content_type(file, &start, &self.config.upload.content_types);
+        let size = fs::metadata(file).await?.len();
+        if size > resume::MULTIPART_THRESHOLD {
+            return resume::upload(self.bucket.as_ref(), file, key, &content_type).await;
+        }
+        let bytes = fs::read(file).await?;
+        let bytes = self.clean_svg(file, &content_type, bytes)?;
+        let bytes = self.scrub_photo(file, &content_type, bytes);
+        self.bucket
+            .put(key, bytes, &content_type, &Labels::new())
+            .await
+    }
+
+    /// Uploads the redirects in `redirects.toml` that aren't in the bucket yet
+    /// (or point somewhere new) and deletes the ones removed from the file.
+    /// Returns how many were uploaded and how many deleted.
+    pub async fn sync_redirects(
+        &self,
+        manifest: &mut Manifest,
+    ) -> Result<(usize, usize), Box<dyn Error>> {
+        let Some(path) = &self.config.redirects.file else {
+            return Ok((0, 0));
+        };
+        let wanted = redirects::load(path).await?;
+
+        let mut uploaded = 0;
+        for (slug, target) in &wanted {
+            if manifest.redirects.get(slug) == Some(target) {
+                continue;
+            }
+            if manifest.posts.iter().any(|post| post.dir() == *slug) {
+                eprintln!("Not redirecting {}: a published post lives there", slug);
+                continue;
+            }
+            let location = if redirects::is_absolute(target) {
+                target.clone()
+            } else {
+                self.bucket.public_url(target.trim_start_matches('/'))
+            };
+            let key = redirects::object_key(slug);
+            match self.config.redirects.style {
+                RedirectStyle::Page => {
+                    self.bucket
+                        .put_html(&key, redirect_page(&location, &[]), &Labels::new())
+                        .await?
+                }
+                RedirectStyle::Object => self.bucket.put_redirect(&key, &location).await?,
+            }
+            manifest.redirects.insert(slug.clone(), target.clone());
+            uploaded += 1;
+        }
+
+        let removed: Vec<String> = manifest
+            .redirects
+            .keys()
+            .filter(|slug| !wanted.contains_key(*slug))
+            .cloned()
+            .collect();
+        for slug in &removed {
+            self.bucket.delete(&redirects::object_key(slug)).await?;
+            manifest.redirects.remove(slug);
+        }
+        Ok((uploaded, removed.len()))
+    }
+
+    /// Saves the manifest to `path`. With `[manifest] remote`, it's first
+    /// merged with the copy in the bucket and then uploaded over exactly the
+    /// version it was merged with, starting over if someone else saved in
+    /// between. Returns how many posts came from the bucket's copy.
+    pub async fn save_manifest(
+        &self,
+        manifest: &mut Manifest,
+        path: &Path,
+    ) -> Result<usize, Box<dyn Error>> {
+        if !self.config.manifest.remote {
+            manifest.save(path).await?;
+            return Ok(0);
+        }
+        let mut taken = 0;
+        loop {
+            let remote = self.bucket.fetch_tagged(manifest::REMOTE_PATH).await?;
+            let precondition = match remote {
+                Some((body, etag)) => {
+                    let theirs: Manifest = serde_json::from_slice(&body).map_err(|e| {
+                        format!("Invalid {} in the bucket: {}", manifest::REMOTE_PATH, e)
+                    })?;
+                    taken += manifest.merge(theirs);
+                    Precondition::Matches(etag)
+                }
+                None => Precondition::Absent,
+            };
+            manifest.save(path).await?;
+            let body = manifest.remote_copy()?;
+            if self
+                .bucket
+                .put_if(
+                    manifest::REMOTE_PATH,
+                    body,
+                    "application/json",
+                    &precondition,
+                )
+                .await?
+            {
+                return Ok(taken);
+            }
+        }
+    }
+
+    /// Rebuilds the index if `[index]` is enabled. Failures are only
+    /// reported, since the change itself already went through.
+    async fn refresh_index(&self, manifest: &Manifest) {
+        if !self.config.index.enabled {
+            return;
+        }
+        if let Err(e) = self.publish_index(manifest, false).await {
+            eprintln!("Could not update the index: {}", e);
+        }
+    }
+
+    /// Adds what was uploaded and removed since the last call to the local
+    /// integrity record and publishes the signed `integrity.json`, if
+    /// `[integrity]` is enabled. Failures are only reported.
+    pub async fn update_integrity(&self, manifest_path: &Path) {
+        let Some(changes) = &self.integrity else {
+            return;
+        };
+        let changes = changes.take();
+        if let Err(e) = integrity::publish(self.bucket.as_ref(), manifest_path, changes).await {
+            eprintln!("Could not update {}: {}", integrity::REMOTE_PATH, e);
+        }
+    }
+
+    /// Saves the manifest, updates the index and notifies webhooks about a
+    /// deleted post.
+    pub async fn finish_delete(
+        &self,
+        post: &Post,
+        manifest: &mut Manifest,
+        manifest_path: &Path,
+    ) -> Result<(), Box<dyn Error>> {
+        self.save_manifest(manifest, manifest_path).await?;
+        record_history(
+            manifest_path,
+            &[history::Entry::new(webhook::Event::Deleted, post)],
+        )
+        .await;
+        self.refresh_index(manifest).await;
+        self.update_integrity(manifest_path).await;
+        webhook::notify(
+            &self.http,
+            &self.config.webhooks,
+            webhook::Event::Deleted,
+            post,
+        )
+        .await;
+        Ok(())
+    }
+
+    /// Saves the manifest, updates the index and notifies webhooks about a
+    /// restored post.
+    pub async fn finish_undelete(
+        &self,
+        post: &Post,
+        manifest: &mut Manifest,
+        manifest_path: &Path,
+    ) -> Result<(), Box<dyn Error>> {
+        self.save_manifest(manifest, manifest_path).await?;
+        record_history(
+            manifest_path,
+            &[history::Entry::new(webhook::Event::Restored, post)],
+        )
+        .await;
+        self.refresh_index(manifest).await;
+        self.update_integrity(manifest_path).await;
+        webhook::notify(
+            &self.http,
+            &self.config.webhooks,
+            webhook::Event::Restored,
+            post,
+        )
+        .await;
+        Ok(())
+    }
+
+    /// Everything that follows a successful publish: sending webmentions
+    /// (remembered in the manifest), linking new posts from related ones,
+    /// uploading new redirects, saving the manifest, updating the index,
+    /// calling webhooks and announcing the post if it's new.
+    pub async fn finish_publish(
+        &self,
+        published: &Published,
+        manifest: &mut Manifest,
+        manifest_path: &Path,
+        announce: Announce,
+    ) -> Result<(), Box<dyn Error>> {
+        self.finish_publish_all(
+            std::slice::from_ref(published),
+            manifest,
+            manifest_path,
+            announce,
+        )
+        .await
+    }
+
+    /// [`Site::finish_publish`] for several posts published together, saving
+    /// the manifest and rebuilding the index only once.
+    pub async fn finish_publish_all(
+        &self,
+        published: &[Published],
+        manifest: &mut Manifest,
+        manifest_path: &Path,
+        announce: Announce,
+    ) -> Result<(), Box<dyn Error>> {
+        for published in published {
+            if self.config.webmention.enabled {
+                if let Some(post) = manifest.post_mut(&published.post.id) {
+                    webmention::send_all(&self.http, &published.links, post).await;
+                }
+            }
+            if !published.updated {
+                self.refresh_related(&published.post, manifest).await;
+            }
+        }
+        if let Err(e) = self.sync_redirects(manifest).await {
+            eprintln!("Could not update redirects: {}", e);
+        }
+        self.save_manifest(manifest, manifest_path).await?;
+        let entries: Vec<history::Entry> = published
+            .iter()
+            .map(|published| history::Entry::new(published.event(), &published.post))
+            .collect();
+        record_history(manifest_path, &entries).await;
+        self.refresh_index(manifest).await;
+        self.update_integrity(manifest_path).await;
+
+        for published in published {
+            webhook::notify(
+                &self.http,
+                &self.config.webhooks,
+                published.event(),
+                &published.post,
+            )
+            .await;
+
+            // Republishing a changed post doesn't announce it again.
+            if self.config.announce.is_enabled() && announce != Announce::No && !published.updated {
+                announce::announce(
+                    &self.http,
+                    &self.config.announce,
+                    &published.post.title,
+                    &published.post.url,
+                    announce == Announce::DryRun,
+                )
+                .await;
+            }
+        }
+        Ok(())
+    }
+
+    /// The feed enclosure for a post's audio file, uploaded into `dir`.
+    fn enclosure(&self, dir: &str, audio: Option<Audio>) -> Option<Enclosure> {
+        audio.map(|audio| Enclosure {
+            url: self.bucket.public_url(&format!("{}/{}", dir, audio.path)),
+            length: audio.length,
+            content_type: audio.content_type,
+        })
+    }
+
+    /// Links to the sections of the page in `dir`, if the post is long
+    /// enough to list them.
+    fn section_links(&self, dir: &str, sections: Vec<Heading>, words: usize) -> Vec<Section> {
+        let min_words = self.config.index.section_links;
+        if min_words == 0 || words < min_words {
+            return Vec::new();
+        }
+        sections
+            .into_iter()
+            .map(|heading| Section {
+                url: self
+                    .bucket
+                    .public_url(&format!("{}/#{}", dir, heading.anchor)),
+                title: heading.title,
+            })
+            .collect()
+    }
+
+    /// Links in the page at `source` that point away from this site.
+    fn outbound_links(&self, source: &str, links: Vec<String>) -> Vec<OutboundLink> {
+        let own_site = self.bucket.public_url("");
+        links
+            .into_iter()
+            .filter(|target| !target.starts_with(&own_site))
+            .map(|target| OutboundLink {
+                source: source.to_string(),
+                target,
+            })
+            .collect()
+    }
+
+    /// Id for a new post: random, or in reproducible builds derived from
+    /// `seed` (the content and file names), so the same input gets the same
+    /// URL every time.
+    fn new_id<'s>(&self, seed: impl IntoIterator<Item = &'s [u8]>) -> String {
+        if !self.config.page.reproducible {
+            return Uuid::new_v4().to_string();
+        }
+        let mut hasher = Sha256::new();
+        for part in seed {
+            hasher.update(part);
+        }
+        let mut bytes = [0; 16];
+        bytes.copy_from_slice(&hasher.finalize()[..16]);
+        uuid::Builder::from_random_bytes(bytes)
+            .into_uuid()
+            .to_string()
+    }
+
+    /// Folder name for a new post: its slug if the front matter sets one or
+    /// `[page] slug_from_title` derives one, otherwise [`Site::new_id`] of
+    /// `seed`. A slug another post already has (in the manifest, among the
+    /// ids in `reserved`, or only in the bucket, say from someone else sharing
+    /// it) gets a `-2`, `-3`, ... suffix or is refused, per `[page]
+    /// slug_collision`.
+    async fn post_id<'s>(
+        &self,
+        markdown: &str,
+        fallback_title: &str,
+        seed: impl IntoIterator<Item = &'s [u8]>,
+        manifest: &Manifest,
+        reserved: &[String],
+    ) -> Result<String, Box<dyn Error>> {
+        let (front_matter, body) = FrontMatter::parse(markdown)?;
+        let style = self.config.page.slug_style;
+        let wanted = match front_matter.slug {
+            Some(wanted) if style == SlugStyle::Hash => slug(&wanted, SlugStyle::Translit),
+            Some(wanted) => slug(&wanted, style),
+            None if self.config.page.slug_from_title => slug(
+                &front_matter
+                    .title
+                    .or_else(|| first_heading(body))
+                    .unwrap_or_else(|| split_file_date(fallback_title).1.to_string()),
+                style,
+            ),
+            None => String::new(),
+        };
+        if wanted.is_empty() {
+            return Ok(self.new_id(seed));
+        }
+        if !self.slug_taken(&wanted, manifest, reserved).await? {
+            return Ok(wanted);
+        }
+        if self.config.page.slug_collision == SlugCollision::Refuse {
+            return Err(format!(
+                "Another post is already at {}/; pick a different slug in the front matter",
+                self.new_post_dir(&wanted)
+            )
+            .into());
+        }
+        for n in 2.. {
+            let candidate = format!("{}-{}", wanted, n);
+            if !self.slug_taken(&candidate, manifest, reserved).await? {
+                eprintln!(
+                    "{}/ is taken, publishing at {}/",
+                    self.new_post_dir(&wanted),
+                    self.new_post_dir(&candidate)
+                );
+                return Ok(candidate);
+            }
+        }
+        unreachable!("some suffix is free")
+    }
+
+    /// Whether a post (live, trashed or unknown to the manifest) is at `p/<slug>/`.
+    async fn slug_taken(
+        &self,
+        slug: &str,
+        manifest: &Manifest,
+        reserved: &[String],
+    ) -> Result<bool, Box<dyn Error>> {
+        let known = manifest
+            .posts
+            .iter()
+            .chain(&manifest.trash)
+            .map(|post| &post.id)
+            .chain(reserved)
+            .any(|id| id == slug);
+        if known {
+            return Ok(true);
+        }
+        let dir = self.new_post_dir(slug);
+        let in_bucket = !self.bucket.list(&format!("{}/", dir)).await?.is_empty();
+        Ok(in_bucket
+            || !self
+                .bucket
+                .list(&format!("trash/{}/", dir))
+                .await?
+                .is_empty())
+    }
+
+    /// `authors` from a post's front matter, or the team member publishing
+    /// it if there are none, so the merged index and feed say whose it is.
+    fn byline(&self, authors: Vec<String>) -> Vec<String> {
+        match &self.config.team.author {
+            Some(member) if authors.is_empty() => vec![member.clone()],
+            _ => authors,
+        }
+    }
+
+    /// Where a new post goes: `p/<id>`, or under `authors/<author>/` for a
+    /// member of a `[team]`.
+    fn new_post_dir(&self, id: &str) -> String {
+        manifest::post_dir(self.config.team.author.as_deref(), id)
+    }
+
+    /// URL the preview image for the page in `dir` will have, if previews are enabled.
+    fn og_image_url(&self, dir: &str) -> Option<String> {
+        self.og_images
+            .as_ref()
+            .map(|_| self.bucket.public_url(&format!("{}/og.png", dir)))
+    }
+
+    /// Checks `upload` against the `[upload]` size limits, counting the
+    /// rest of the site without post `id`'s current size.
+    fn check_budget(
+        &self,
+        upload: &budget::Upload,
+        id: Option<&str>,
+        manifest: &Manifest,
+    ) -> Result<(), Box<dyn Error>> {
+        let site = manifest
+            .posts
+            .iter()
+            .filter(|post| Some(post.id.as_str()) != id)
+            .filter_map(|post| post.size)
+            .sum();
+        budget::check(&self.config.upload, upload, site)
+    }
+
+    /// Where the `[views]` pixel of the post in `dir` is, if there is one.
+    fn view_pixel_url(&self, dir: &str) -> Option<String> {
+        self.config.views.enabled.then(|| {
+            self.bucket
+                .public_url(&format!("{}/{}", dir, views::PIXEL_PATH))
+        })
+    }
+
+    async fn upload_view_pixel(&self, dir: &str) -> Result<(), Box<dyn Error>> {
+        if !self.config.views.enabled {
+            return Ok(());
+        }
+        self.bucket
+            .put(
+                &format!("{}/{}", dir, views::PIXEL_PATH),
+                views::PIXEL_GIF.to_vec(),
+                "image/gif",
+                &Labels::new(),
+            )
+            .await
+    }
+
+    /// `bytes` of `file` cleaned as `[svg]` asks, if they're an SVG.
+    fn clean_svg(
+        &self,
+        file: &Path,
+        content_type: &str,
+        bytes: Vec<u8>,
+    ) -> Result<Vec<u8>, Box<dyn Error>> {
+        let config = &self.config.svg;
+        if !content_type.starts_with("image/svg+xml") || !(config.sanitize || config.optimize) {
+            return Ok(bytes);
+        }
+        svg::clean(&bytes, config)
+            .map_err(|e| format!("Could not clean SVG {}: {}", file.display(), e).into())
+    }
+
+    /// `bytes` of the photo `file` without its camera metadata, unless
+    /// `[upload] keep_exif` is set. Either way, warns if it says where it
+    /// was taken.
+    fn scrub_photo(&self, file: &Path, content_type: &str, bytes: Vec<u8>) -> Vec<u8> {
+        if !content_type.starts_with("image/") || !exif::has_location(&bytes) {
+            return if self.config.upload.keep_exif {
+                bytes
+            } else {
+                exif::strip(bytes)
+            };
+        }
+        if self.config.upload.keep_exif {
+            eprintln!(
+                "Warning: {} has its GPS position in it, and --keep-exif publishes it",
+                file.display()
+            );
+            bytes
+        } else {
+            eprintln!(
+                "Removed the GPS position and other EXIF data from {}",
+                file.display()
+            );
+            exif::strip(bytes)
+        }
+    }
+
+    async fn upload_og_image(&self, dir: &str, post: &RenderedPost) -> Result<(), Box<dyn Error>> {
+        let Some(renderer) = &self.og_images else {
+            return Ok(());
+        };
+        let site_name = self
+            .config
+            .og_image
+            .site_name
+            .as_deref()
+            .or(self.config.site.title.as_deref())
+            .unwrap_or(self.bucket.host());
+        let png = renderer.render(&post.title, &post.date, site_name)?;
+        self.bucket
+            .put(&format!("{}/og.png", dir), png, "image/png", &post.labels)
+            .await
+    }
+
+    /// Uploads the local files `post` embeds into `dir`, next to its page,
+    /// with a poster frame for each video and attachments marked as
+    /// downloads. Files big enough to resume go to `live`, the page's own
+    /// directory, even when `dir` is a staging one. Returns the bytes uploaded.
+    async fn upload_media(
+        &self,
+        dir: &str,
+        live: &str,
+        post: &RenderedPost,
+    ) -> Result<u64, Box<dyn Error>> {
+        let mut uploaded = 0;
+        for item in &post.media {
+            let path = format!("{}/{}", dir, item.path);
+            let start = mime::read_start(&item.source).await?;
+            let content_type =
+                mime::content_type(&item.source, &start, &self.config.upload.content_types);
+            let size = fs::metadata(&item.source).await?.len();
+            if item.download {
+                // Resumable uploads don't carry a Content-Disposition, so
+                // attachments always go up in one piece.
+                let bytes = fs::read(&item.source).await?;
+                let bytes = self.scrub_photo(&item.source, &content_type, bytes);
+                let file_name = item.path.rsplit('/').next().unwrap_or(&item.path);
+                self.bucket
+                    .put_download(&path, bytes, &content_type, file_name, &post.labels)
+                    .await?;
+            } else if size > resume::MULTIPART_THRESHOLD {
+                let path = format!("{}/{}", live, item.path);
+                resume::upload(self.bucket.as_ref(), &item.source, &path, &content_type).await?;
+            } else {
+                let bytes = fs::read(&item.source).await?;
+                let bytes = self.clean_svg(&item.source, &content_type, bytes)?;
+                let bytes = self.scrub_photo(&item.source, &content_type, bytes);
+                self.bucket
+                    .put(&path, bytes, &content_type, &post.labels)
+                    .await?;
+            }
+            uploaded += size;
+
+            let Some(poster) = &item.poster else {
+                continue;
+            };
+            let source = item.source.clone();
+            // As a string, since the error has to leave the blocking thread.
+            let frame = tokio::task::spawn_blocking(move || {
+                media::poster_frame(&source).map_err(|e| e.to_string())
+            })
+            .await?;
+            match frame {
+                Ok(jpeg) => {
+                    uploaded += jpeg.len() as u64;
+                    self.bucket
+                        .put(
+                            &format!("{}/{}", dir, poster),
+                            jpeg,
+                            "image/jpeg",
+                            &post.labels,
+                        )
+                        .await?;
+                }
+                Err(e) => eprintln!("No poster for {}: {}", item.source.display(), e),
+            }
+        }
+        Ok(uploaded)
+    }
+
+    /// Publishes a single markdown file under a fresh `p/<id>/`.
+    async fn publish_single(
+        &self,
+        source: &Path,
+        manifest: &mut Manifest,
+    ) -> Result<Published, Box<dyn Error>> {
+        let markdown_content = formats::read_source(source).await?;
+        self.publish_markdown(
+            &markdown_content,
+            file_title(source),
+            Some(std::path::absolute(source)?),
+            manifest,
+        )
+        .await
+    }
+
+    /// Publishes markdown that may not come from a file (e.g. received over
+    /// Micropub) under a fresh `p/<id>/`.
+    pub async fn publish_markdown(
+        &self,
+        markdown_content: &str,
+        fallback_title: &str,
+        source: Option<PathBuf>,
+        manifest: &mut Manifest,
+    ) -> Result<Published, Box<dyn Error>> {
+        let folder_name = self
+            .post_id(
+                markdown_content,
+                fallback_title,
+                [markdown_content.as_bytes()],
+                manifest,
+                &[],
+            )
+            .await?;
+        self.publish_markdown_as(
+            folder_name,
+            markdown_content,
+            fallback_title,
+            source,
+            manifest,
+            true,
+        )
+        .await
+    }
+
+    /// Publishes a post again from its source file, keeping its URL.
+    pub async fn update(
+        &self,
+        id: &str,
+        manifest: &mut Manifest,
+    ) -> Result<Published, Box<dyn Error>> {
+        self.republish(id, manifest, true).await
+    }
+
+    /// Renders every post in the manifest again with the current templates
+    /// and uploads the pages that came out different, e.g. after a theme
+    /// change. Posts are read from their source file, or from the copy
+    /// `[sources] keep` made if the file is gone, and aren't marked as
+    /// updated. Raw uploads are left alone. Returns the posts whose page
+    /// changed and how many couldn't be rebuilt.
+    pub async fn rebuild(
+        &self,
+        manifest: &mut Manifest,
+    ) -> Result<(Vec<Published>, usize), Box<dyn Error>> {
+        let posts: Vec<(String, Option<String>)> = manifest
+            .posts
+            .iter()
+            .filter(|post| !post.raw)
+            .map(|post| (post.id.clone(), post.output_hash.clone()))
+            .collect();
+        let mut changed = Vec::new();
+        let mut failed = 0;
+        let mut translated = Vec::new();
+        for (id, before) in posts {
+            if shutdown::requested() {
+                eprintln!("Stopped before rebuilding every post");
+                break;
+            }
+            // A translation group is published together, from any of its posts.
+            let group = manifest
+                .posts
+                .iter()
+                .find(|post| post.id == id && !post.languages.is_empty())
+                .and_then(|post| post.source.clone());
+            if let Some(source) = group {
+                if translated.contains(&source) {
+                    continue;
+                }
+                translated.push(source);
+            }
+            // As a string, since the error can't be held across the next publish.
+            let result = self
+                .rebuild_post(&id, manifest)
+                .await
+                .map_err(|e| e.to_string());
+            match result {
+                Ok(published) if published.post.output_hash != before => changed.push(published),
+                Ok(_) => {}
+                Err(e) => {
+                    eprintln!("Could not rebuild {}: {}", id, e);
+                    failed += 1;
+                }
+            }
+        }
+        Ok((changed, failed))
+    }
+
+    /// Renders the post `id` again for [`Site::rebuild`].
+    async fn rebuild_post(
+        &self,
+        id: &str,
+        manifest: &mut Manifest,
+    ) -> Result<Published, Box<dyn Error>> {
+        let post = manifest
+            .posts
+            .iter()
+            .find(|post| post.id == id)
+            .ok_or_else(|| format!("No post with id {}", id))?;
+        let source = post.source.clone();
+        if !post.languages.is_empty() || source.as_ref().is_some_and(|source| source.exists()) {
+            return self.republish(id, manifest, false).await;
+        }
+        let fallback_title = source.as_deref().map_or_else(
+            || post.title.clone(),
+            |source| file_title(source).to_string(),
+        );
+        let mut markdown = sources::load(self.config, id).await?;
+        if markdown.is_none() {
+            markdown = self.fetch_source(post).await?;
+        }
+        let markdown = markdown.ok_or(
+            "The source file is gone and there's no copy of it (see [sources] keep and upload)",
+        )?;
+        self.publish_markdown_as(
+            id.to_string(),
+            &markdown,
+            &fallback_title,
+            source,
+            manifest,
+            false,
+        )
+        .await
+    }
+
+    /// Publishes again the posts that would now list `new_post` among their
+    /// related posts. They aren't marked as updated, and failures are only
+    /// reported since the new post itself is already out.
+    async fn refresh_related(&self, new_post: &Post, manifest: &mut Manifest) {
+        let count = self.config.page.related_posts;
+        if count == 0 {
+            return;
+        }
+        let stale: Vec<String> = manifest
+            .posts
+            .iter()
+            .filter(|post| !post.raw && post.source.is_some() && post.id != new_post.id)
+            .filter(|post| {
+                let candidates = manifest
+                    .posts
+                    .iter()
+                    .filter(|other| !other.raw && other.id != post.id);
+                related::find(&post.title, &post.labels, candidates, count)
+                    .iter()
+                    .any(|related| related.id == new_post.id)
+            })
+            .map(|post| post.id.clone())
+            .collect();
+        for id in stale {
+            match self.republish(&id, manifest, false).await {
+                Ok(published) => eprintln!("Updated related posts on {}", published.post.url),
+                Err(e) => eprintln!("Could not update related posts on {}: {}", id, e),
+            }
+        }
+    }
+
+    /// Publishes a post again from its source file, keeping its URL, and
+    /// with `mark_updated` showing it as updated now.
+    async fn republish(
+        &self,
+        id: &str,
+        manifest: &mut Manifest,
+        mark_updated: bool,
+    ) -> Result<Published, Box<dyn Error>> {
+        let post = manifest
+            .posts
+            .iter()
+            .find(|post| post.id == id)
+            .ok_or_else(|| format!("No post with id {}", id))?;
+        let source = post
+            .source
+            .clone()
+            .ok_or("The post wasn't published from a file")?;
+        if !post.languages.is_empty() {
+            // Translations are found next to the group's base path and keep their id.
+            let base = file_title(&source).to_string();
+            return self
+                .publish_translations(&source, &base, manifest, mark_updated)
+                .await;
+        }
+        if post.raw {
+            return Err("Raw uploads can't be updated; upload the files again".into());
+        }
+        let markdown_content = formats::read_source(&source).await?;
+        self.publish_markdown_as(
+            id.to_string(),
+            &markdown_content,
+            file_title(&source),
+            Some(source.clone()),
+            manifest,
+            mark_updated,
+        )
+        .await
+    }
+
+    /// Other posts the related posts section can link to, if it's enabled.
+    fn related_candidates(&self, manifest: &Manifest, id: Option<&str>) -> Vec<Post> {
+        if self.config.page.related_posts == 0 {
+            return Vec::new();
+        }
+        related::candidates(manifest, id)
+    }
+
+    /// Renders and uploads markdown to `p/<folder_name>/`, replacing the
+    /// manifest entry for that folder if there is one. Unless `mark_updated`
+    /// is set, a replaced post keeps its last update date.
+    async fn publish_markdown_as(
+        &self,
+        folder_name: String,
+        markdown_content: &str,
+        fallback_title: &str,
+        source: Option<PathBuf>,
+        manifest: &mut Manifest,
+        mark_updated: bool,
+    ) -> Result<Published, Box<dyn Error>> {
+        let job = self
+            .prepare(
+                folder_name,
+                markdown_content.to_string(),
+                fallback_title,
+                source,
+                manifest,
+                mark_updated,
+            )
+            .await;
+        if job.streams(self.config) {
+            return self.publish_streamed(job, manifest).await;
+        }
+        let post = job.render(self.config)?;
+        self.store(job, post, manifest).await
+    }
+
+    /// Uploads `source` as a draft under `drafts/<random id>/` and returns
+    /// it with a presigned URL good for `ttl`, recording it in the manifest
+    /// so `klistra reap` deletes it once the URL has run out. The media the
+    /// page embeds are linked by presigned URLs of their own, so the draft
+    /// works in a private bucket. Presigned URLs last a week at most.
+    pub async fn preview(
+        &self,
+        source: &Path,
+        ttl: Ttl,
+        manifest: &mut Manifest,
+    ) -> Result<Preview, Box<dyn Error>> {
+        if ttl.duration() > MAX_PREVIEW {
+            return Err("Presigned URLs last a week at most; use --expires 7d or less".into());
+        }
+        let expires_in = ttl.duration().to_std()?;
+        let id = Uuid::new_v4().simple().to_string();
+        let dir = format!("{}{}", DRAFTS_PREFIX, id);
+        let source = std::path::absolute(source)?;
+        let markdown = formats::read_source(&source).await?;
+        let job = PostJob {
+            folder_name: id.clone(),
+            dir: dir.clone(),
+            file_starts: media::read_starts(&markdown, source.parent()).await,
+            markdown,
+            fallback_title: file_title(&source).to_string(),
+            source: Some(source),
+            image_url: None,
+            view_pixel: None,
+            source_url: None,
+            published: None,
+            updated: None,
+            previous_output: None,
+            related: Vec::new(),
+        };
+        let post = job.render(self.config)?;
+        self.upload_media(&dir, &dir, &post).await?;
+
+        let mut html = post.html;
+        for item in &post.media {
+            for path in std::iter::once(&item.path).chain(&item.poster) {
+                let url = self
+                    .bucket
+                    .presign(&format!("{}/{}", dir, path), expires_in)
+                    .await?;
+                html = html.replace(
+                    &format!("=\"{}\"", escape_html(path)),
+                    &format!("=\"{}\"", escape_html(&url)),
+                );
+            }
+        }
+        let page = format!("{}/index.html", dir);
+        self.bucket.put_html(&page, html, &post.labels).await?;
+
+        let preview = Preview {
+            id,
+            source: job.source.unwrap_or_default(),
+            title: post.title,
+            url: self.bucket.presign(&page, expires_in).await?,
+            expires: ttl.expires(Utc::now()),
+        };
+        manifest.previews.push(preview.clone());
+        Ok(preview)
+    }
+
+    /// Deletes the objects of a draft uploaded with [`Site::preview`].
+    pub async fn delete_preview(&self, preview: &Preview) -> Result<(), Box<dyn Error>> {
+        self.bucket
+            .delete_prefix(&format!("{}/", preview.dir()))
+            .await?;
+        Ok(())
+    }
+
+    /// Publishes a huge post without holding its page in memory: one pass
+    /// hashes the page, and if it changed, a second streams it to the bucket
+    /// in parts as it renders.
+    async fn publish_streamed(
+        &self,
+        job: PostJob,
+        manifest: &mut Manifest,
+    ) -> Result<Published, Box<dyn Error>> {
+        let mut hasher = HashWriter::default();
+        let post = job.render_to(self.config, &mut hasher)?;
+        let mut size = hasher.written();
+        let output_hash = hasher.finish();
+        if job.previous_output.as_ref() != Some(&output_hash) {
+            let assets = media_sizes(&post).await?;
+            let upload = budget::Upload {
+                post: &post.title,
+                pages: size,
+                assets,
+            };
+            self.check_budget(&upload, Some(&job.folder_name), manifest)?;
+            let staging = Staging::new(self.config, &job.dir);
+            // As a string, since the error can't be held across the cleanup.
+            let uploaded = self
+                .upload_streamed(&staging, &job, &post)
+                .await
+                .map_err(|e| e.to_string());
+            match uploaded {
+                Ok(media) => size += media,
+                Err(e) => {
+                    staging.discard(self.bucket.as_ref()).await;
+                    return Err(e.into());
+                }
+            }
+            staging.go_live(self.bucket.as_ref()).await?;
+        }
+        self.keep_source(&job).await;
+        Ok(self.record(job, post, output_hash, size, manifest))
+    }
+
+    /// Uploads a huge post's files, then renders its page again straight
+    /// into a multipart upload, to where `staging` says. Returns the bytes
+    /// of files uploaded.
+    async fn upload_streamed(
+        &self,
+        staging: &Staging,
+        job: &PostJob,
+        post: &RenderedPost,
+    ) -> Result<u64, Box<dyn Error>> {
+        let size = self.upload_files(staging, job, post).await?;
+        let path = format!("{}/index.html", staging.page());
+        let upload_id = self
+            .bucket
+            .create_multipart(&path, "text/html", &post.labels)
+            .await?;
+        staging.started(&path, &upload_id);
+        // As a string, since the error can't be held across the awaits below.
+        let parts = tokio::task::block_in_place(|| {
+            let mut writer = PartWriter::new(self.bucket.as_ref(), &path, &upload_id);
+            match job.render_to(self.config, &mut writer) {
+                Ok(_) => writer.finish(),
+                Err(e) => Err(writer.take_error().unwrap_or(e)),
+            }
+        })
+        .map_err(|e| e.to_string())?;
+        // Left open on failure, for the caller's discard to abort.
+        self.bucket
+            .complete_multipart(&path, &upload_id, &parts)
+            .await?;
+        staging.completed(&upload_id);
+        Ok(size)
+    }
+
+    /// Looks up everything rendering markdown into `p/<folder_name>/` needs
+    /// from the manifest, so it can happen away from it.
+    async fn prepare(
+        &self,
+        folder_name: String,
+        markdown: String,
+        fallback_title: &str,
+        source: Option<PathBuf>,
+        manifest: &Manifest,
+        mark_updated: bool,
+    ) -> PostJob {
+        let existing = manifest.posts.iter().find(|post| post.id == folder_name);
+        let updated = match existing {
+            Some(_) if mark_updated => Some(chrono::Utc::now()),
+            Some(post) => post.updated,
+            None => None,
+        };
+        let dir = existing.map_or_else(|| self.new_post_dir(&folder_name), Post::dir);
+        let file_starts =
+            media::read_starts(&markdown, source.as_deref().and_then(Path::parent)).await;
+        PostJob {
+            image_url: self.og_image_url(&dir),
+            view_pixel: self.view_pixel_url(&dir),
+            source_url: self.config.sources.upload.then(|| {
+                self.bucket
+                    .public_url(&format!("{}/{}", dir, sources::REMOTE_NAME))
+            }),
+            dir,
+            published: existing.map(|post| post.published),
+            updated,
+            previous_output: existing.and_then(|post| post.output_hash.clone()),
+            related: self.related_candidates(manifest, Some(&folder_name)),
+            folder_name,
+            markdown,
+            fallback_title: fallback_title.to_string(),
+            source,
+            file_starts,
+        }
+    }
+
+    /// Uploads a rendered post (unless the page is unchanged) and records it
+    /// in the manifest.
+    async fn store(
+        &self,
+        job: PostJob,
+        post: RenderedPost,
+        manifest: &mut Manifest,
+    ) -> Result<Published, Box<dyn Error>> {
+        let dir = &job.dir;
+        let output_hash = content_hash([post.html.as_bytes()]);
+        let mut size = post.html.len() as u64;
+        if job.previous_output.as_ref() != Some(&output_hash) {
+            let assets = media_sizes(&post).await?;
+            let upload = budget::Upload {
+                post: &post.title,
+                pages: size,
+                assets,
+            };
+            self.check_budget(&upload, Some(&job.folder_name), manifest)?;
+            let staging = Staging::new(self.config, dir);
+            // As a string, since the error can't be held across the cleanup.
+            let uploaded = self
+                .upload_post(&staging, &job, &post)
+                .await
+                .map_err(|e| e.to_string());
+            match uploaded {
+                Ok(media) => size += media,
+                Err(e) => {
+                    staging.discard(self.bucket.as_ref()).await;
+                    return Err(e.into());
+                }
+            }
+            staging.go_live(self.bucket.as_ref()).await?;
+        }
+        self.keep_source(&job).await;
+        Ok(self.record(job, post, output_hash, size, manifest))
+    }
+
+    /// Uploads a rendered post's files and page to where `staging` says.
+    /// Returns the bytes of files uploaded.
+    async fn upload_post(
+        &self,
+        staging: &Staging,
+        job: &PostJob,
+        post: &RenderedPost,
+    ) -> Result<u64, Box<dyn Error>> {
+        let size = self.upload_files(staging, job, post).await?;
+        self.bucket
+            .put_html(
+                &format!("{}/index.html", staging.page()),
+                post.html.clone(),
+                &post.labels,
+            )
+            .await?;
+        Ok(size)
+    }
+
+    /// Uploads what a post's page links to where `staging` says: its preview
+    /// image, view pixel, media and source. Returns the bytes of media uploaded.
+    async fn upload_files(
+        &self,
+        staging: &Staging,
+        job: &PostJob,
+        post: &RenderedPost,
+    ) -> Result<u64, Box<dyn Error>> {
+        let dir = &staging.files();
+        self.upload_og_image(dir, post).await?;
+        self.upload_view_pixel(dir).await?;
+        let size = self.upload_media(dir, &staging.dir, post).await?;
+        self.upload_source(dir, job).await?;
+        Ok(size)
+    }
+
+    /// Publishes the post's markdown as `index.md` in `dir`, if `[sources]
+    /// upload` is set.
+    async fn upload_source(&self, dir: &str, job: &PostJob) -> Result<(), Box<dyn Error>> {
+        if !self.config.sources.upload {
+            return Ok(());
+        }
+        self.bucket
+            .put(
+                &format!("{}/{}", dir, sources::REMOTE_NAME),
+                job.markdown.clone().into_bytes(),
+                sources::CONTENT_TYPE,
+                &Labels::new(),
+            )
+            .await
+    }
+
+    /// The markdown `[sources] upload` published for `post`, if it's there.
+    pub async fn fetch_source(&self, post: &Post) -> Result<Option<String>, Box<dyn Error>> {
+        let path = format!("{}/{}", post.dir(), sources::REMOTE_NAME);
+        let Some(body) = self.bucket.fetch(&path).await? else {
+            return Ok(None);
+        };
+        Ok(Some(String::from_utf8(body).map_err(|_| {
+            format!("{} isn't UTF-8", self.bucket.public_url(&path))
+        })?))
+    }
+
+    /// Saves the post's markdown for `klistra rebuild`, if `[sources] keep`
+    /// is set. Failures are only reported.
+    async fn keep_source(&self, job: &PostJob) {
+        if !self.config.sources.keep {
+            return;
+        }
+        if let Err(e) = sources::save(self.config, &job.folder_name, &job.markdown).await {
+            eprintln!("Could not keep the source of {}: {}", job.folder_name, e);
+        }
+    }
+
+    /// Adds an uploaded post to the manifest, or updates its entry.
+    fn record(
+        &self,
+        job: PostJob,
+        post: RenderedPost,
+        output_hash: String,
+        size: u64,
+        manifest: &mut Manifest,
+    ) -> Published {
+        let dir = job.dir.clone();
+        let source_hash = content_hash([job.markdown.as_bytes()]);
+        let url = self.bucket.public_url(&dir);
+        let links = self.outbound_links(&url, post.links);
+        let enclosure = self.enclosure(&dir, post.audio);
+        let sections = self.section_links(&dir, post.sections, post.words);
+        if let Some(existing) = manifest.post_mut(&job.folder_name) {
+            existing.title = post.title;
+            existing.description = post.description;
+            existing.labels = post.labels;
+            existing.authors = self.byline(post.authors);
+            existing.updated = job.updated;
+            existing.source_hash = Some(source_hash);
+            existing.output_hash = Some(output_hash);
+            existing.size = Some(size);
+            existing.image = job.image_url.clone();
+            existing.enclosure = enclosure;
+            existing.sections = sections;
+            return Published {
+                post: existing.clone(),
+                updated: true,
+                html: post.html,
+                links,
+            };
+        }
+        let record = Post {
+            id: job.folder_name,
+            source: job.source,
+            title: post.title,
+            description: post.description,
+            url,
+            published: published_at(&job.fallback_title),
+            updated: None,
+            languages: Vec::new(),
+            webmentions: Vec::new(),
+            expires: None,
+            labels: post.labels,
+            authors: self.byline(post.authors),
+            raw: false,
+            deleted: None,
+            member: self.config.team.author.clone(),
+            restored: None,
+            source_hash: Some(source_hash),
+            output_hash: Some(output_hash),
+            size: Some(size),
+            image: job.image_url,
+            enclosure,
+            sections,
+        };
+        manifest.posts.push(record.clone());
+        Published {
+            post: record,
+            updated: false,
+            html: post.html,
+            links,
+        }
+    }
+
+    /// Uploads files as-is under `p/<id>/<filename>`; directories contribute
+    /// the files directly inside them. Several files get an `index.html`
+    /// listing them. With `wrap`, text files also get a syntax-highlighted
+    /// page, which for a single file becomes the post's URL.
+    pub async fn publish_raw(
+        &self,
+        sources: &[PathBuf],
+        wrap: bool,
+        manifest: &mut Manifest,
+    ) -> Result<Published, Box<dyn Error>> {
+        let files = raw::collect_files(sources).await?;
+        let max_size = self.config.raw.max_size_mb * 1024 * 1024;
+        let mut assets = Vec::with_capacity(files.len());
+        for file in &files {
+            let size = fs::metadata(file).await?.len();
+            if size > max_size {
+                return Err(format!(
+                    "{} is {} bytes, over the {} MB limit ([raw] max_size_mb)",
+                    file.display(),
+                    size,
+                    self.config.raw.max_size_mb
+                )
+                .into());
+            }
+            assets.push((file.display().to_string(), size));
+        }
+        let upload = budget::Upload {
+            post: "the upload",
+            pages: 0,
+            assets,
+        };
+        self.check_budget(&upload, None, manifest)?;
+
+        let single = files.len() == 1;
+        // An interrupted upload of the same file goes back into its old folder.
+        let resumed = match files.as_slice() {
+            [file] => resume::pending(file).await?.and_then(|state| {
+                let (dir, name) = state.path.rsplit_once('/')?;
+                let id = dir.rsplit('/').next()?;
+                Some((id.to_string(), name.to_string()))
+            }),
+            _ => None,
+        };
+        let folder_name = match &resumed {
+            Some((id, _)) => id.clone(),
+            None => {
+                let names: Vec<&[u8]> = files.iter().map(|file| file_name(file)).collect();
+                self.new_id(names)
+            }
+        };
+        let dir = self.new_post_dir(&folder_name);
+        let highlighter = wrap.then(Highlighter::new);
+
+        let mut entries: Vec<raw::Entry> = Vec::with_capacity(files.len());
+        // Names already used in the folder, so no file overwrites another,
+        // its listing or a highlighted page.
+        let mut taken = Vec::with_capacity(files.len() * 2 + 1);
+        if !single || wrap {
+            taken.push("index.html".to_string());
+        }
+        let mut html = None;
+        for file in &files {
+            let name = match &resumed {
+                Some((_, name)) => name.clone(),
+                None => raw::unique_name(&raw::safe_file_name(file), &taken),
+            };
+            taken.push(name.clone());
+            let size = fs::metadata(file).await?.len();
+            if size > resume::MULTIPART_THRESHOLD {
+                // Too big to hold in memory or highlight: sniff the start and
+                // upload in resumable parts.
+                let start = mime::read_start(file).await?;
+                let content_type =
+                    mime::content_type(file, &start, &self.config.upload.content_types);
+                resume::upload(
+                    self.bucket.as_ref(),
+                    file,
+                    &format!("{}/{}", dir, name),
+                    &content_type,
+                )
+                .await?;
+                entries.push(raw::Entry {
+                    name,
+                    size,
+                    content_type,
+                    page: None,
+                });
+                continue;
+            }
+
+            let bytes = fs::read(file).await?;
+            let text = mime::is_text(&bytes);
+
+            let page = match (&highlighter, text) {
+                (Some(highlighter), true) => {
+                    let code = std::str::from_utf8(&bytes)?;
+                    let page = raw::wrapper_page(
+                        &name,
+                        code,
+                        highlighter,
+                        &self.config.page,
+                        &self.config.site,
+                    )?;
+                    let page_name = if single {
+                        "index.html".to_string()
+                    } else {
+                        raw::unique_name(&format!("{}.html", name), &taken)
+                    };
+                    taken.push(page_name.clone());
+                    self.bucket
+                        .put_html(
+                            &format!("{}/{}", dir, page_name),
+                            page.clone(),
+                            &Labels::new(),
+                        )
+                        .await?;
+                    html = Some(page);
+                    Some(page_name)
+                }
+                (Some(_), false) => {
+                    eprintln!("{} isn't text; uploading without a page", file.display());
+                    None
+                }
+                (None, _) => None,
+            };
+
+            let content_type = mime::content_type(file, &bytes, &self.config.upload.content_types);
+            let bytes = self.clean_svg(file, &content_type, bytes)?;
+            let bytes = self.scrub_photo(file, &content_type, bytes);
+            let size = bytes.len() as u64;
+            self.bucket
+                .put(
+                    &format!("{}/{}", dir, name),
+                    bytes,
+                    &content_type,
+                    &Labels::new(),
+                )
+                .await?;
+            if !single {
+                eprintln!("Uploaded {}", file.display());
+            }
+            entries.push(raw::Entry {
+                name,
+                size,
+                content_type,
+                page,
+            });
+        }
+
+        let (title, url) = match entries.as_slice() {
+            [entry] => {
+                let url = match entry.page {
+                    Some(_) => self.bucket.public_url(&format!("{}/", dir)),
+                    None => self.bucket.public_url(&format!("{}/{}", dir, entry.name)),
+                };
+                (entry.name.clone(), url)
+            }
+            _ => {
+                let title = raw::listing_title(sources, entries.len());
+                let listing =
+                    raw::listing_page(&title, &entries, &self.config.page, &self.config.site)?;
+                self.bucket
+                    .put_html(
+                        &format!("{}/index.html", dir),
+                        listing.clone(),
+                        &Labels::new(),
+                    )
+                    .await?;
+                html = Some(listing);
+                (title, self.bucket.public_url(&format!("{}/", dir)))
+            }
+        };
+
+        let record = Post {
+            id: folder_name,
+            source: match sources {
+                [source] => Some(std::path::absolute(source)?),
+                _ => None,
+            },
+            title,
+            description: None,
+            url,
+            published: chrono::Utc::now(),
+            updated: None,
+            languages: Vec::new(),
+            webmentions: Vec::new(),
+            expires: None,
+            labels: Labels::new(),
+            authors: self.byline(Vec::new()),
+            raw: true,
+            deleted: None,
+            member: self.config.team.author.clone(),
+            restored: None,
+            source_hash: None,
+            output_hash: None,
+            size: Some(entries.iter().map(|entry| entry.size).sum()),
+            image: None,
+            enclosure: None,
+            sections: Vec::new(),
+        };
+        manifest.posts.push(record.clone());
+        Ok(Published {
+            post: record,
+            updated: false,
+            html: html.unwrap_or_default(),
+            links: Vec::new(),
+        })
+    }
+
+    /// Publishes the images in `source` as an album under `p/<id>/`: each
+    /// photo as uploaded, a thumbnail of it under `thumbs/`, and an
+    /// `index.html` showing them as a grid with a lightbox.
+    pub async fn publish_gallery(
+        &self,
+        source: &Path,
+        title: Option<&str>,
+        manifest: &mut Manifest,
+    ) -> Result<Published, Box<dyn Error>> {
+        if !fs::metadata(source).await?.is_dir() {
+            return Err(format!("{} is not a directory", source.display()).into());
+        }
+        let files: Vec<PathBuf> = raw::collect_files(&[source.to_path_buf()])
+            .await?
+            .into_iter()
+            .filter(|file| gallery::is_image(file))
+            .collect();
+        if files.is_empty() {
+            return Err(format!("No images in {}", source.display()).into());
+        }
+        let max_size = self.config.raw.max_size_mb * 1024 * 1024;
+        let mut assets = Vec::with_capacity(files.len());
+        for file in &files {
+            let size = fs::metadata(file).await?.len();
+            if size > max_size {
+                return Err(format!(
+                    "{} is {} bytes, over the {} MB limit ([raw] max_size_mb)",
+                    file.display(),
+                    size,
+                    self.config.raw.max_size_mb
+                )
+                .into());
+            }
+            assets.push((file.display().to_string(), size));
+        }
+        let album = source.display().to_string();
+        let upload = budget::Upload {
+            post: &album,
+            pages: 0,
+            assets,
+        };
+        self.check_budget(&upload, None, manifest)?;
+
+        let names: Vec<&[u8]> = files.iter().map(|file| file_name(file)).collect();
+        let folder_name = self.new_id(names);
+        let dir = self.new_post_dir(&folder_name);
+
+        let mut entries: Vec<raw::Entry> = Vec::with_capacity(files.len());
+        let mut photos = Vec::with_capacity(files.len());
+        let mut taken = vec!["index.html".to_string()];
+        for file in &files {
+            let name = raw::unique_name(&raw::safe_file_name(file), &taken);
+            taken.push(name.clone());
+            let bytes = fs::read(file).await?;
+            let thumbnail = match gallery::thumbnail(file, &bytes) {
+                Some(thumbnail) => {
+                    let thumbnail_name = format!("thumbs/{}.{}", name, thumbnail.extension);
+                    self.bucket
+                        .put(
+                            &format!("{}/{}", dir, thumbnail_name),
+                            thumbnail.body,
+                            thumbnail.content_type,
+                            &Labels::new(),
+                        )
+                        .await?;
+                    thumbnail_name
+                }
+                None => name.clone(),
+            };
+            let content_type = mime::content_type(file, &bytes, &self.config.upload.content_types);
+            let bytes = self.clean_svg(file, &content_type, bytes)?;
+            let bytes = self.scrub_photo(file, &content_type, bytes);
+            let size = bytes.len() as u64;
+            self.bucket
+                .put(
+                    &format!("{}/{}", dir, name),
+                    bytes,
+                    &content_type,
+                    &Labels::new(),
+                )
+                .await?;
+            eprintln!("Uploaded {}", file.display());
+            photos.push(gallery::Photo {
+                name: name.clone(),
+                thumbnail,
+                alt: gallery::alt_text(file),
+            });
+            entries.push(raw::Entry {
+                name,
+                size,
+                content_type,
+                page: None,
+            });
+        }
+
+        let title = match title {
+            Some(title) => title.to_string(),
+            None => raw::listing_title(&[source.to_path_buf()], photos.len()),
+        };
+        let html = gallery::gallery_page(&title, &photos, &self.config.page, &self.config.site)?;
+        self.bucket
+            .put_html(&format!("{}/index.html", dir), html.clone(), &Labels::new())
+            .await?;
+
+        let record = Post {
+            id: folder_name,
+            source: Some(std::path::absolute(source)?),
+            title,
+            description: None,
+            url: self.bucket.public_url(&format!("{}/", dir)),
+            published: chrono::Utc::now(),
+            updated: None,
+            languages: Vec::new(),
+            webmentions: Vec::new(),
+            expires: None,
+            labels: Labels::new(),
+            authors: self.byline(Vec::new()),
+            raw: true,
+            deleted: None,
+            member: self.config.team.author.clone(),
+            restored: None,
+            source_hash: None,
+            output_hash: None,
+            size: Some(entries.iter().map(|entry| entry.size).sum()),
+            image: None,
+            enclosure: None,
+            sections: Vec::new(),
+        };
+        manifest.posts.push(record.clone());
+        Ok(Published {
+            post: record,
+            updated: false,
+            html,
+            links: Vec::new(),
+        })
+    }
+
+    /// Uploads every translation's files and page under `staging`'s files,
+    /// then `redirect` (labelled like the translation at `default_index`)
+    /// as the post's page. Returns the bytes uploaded.
+    async fn upload_translations(
+        &self,
+        staging: &Staging,
+        translations: &[variants::Variant],
+        pages: &[RenderedPost],
+        redirect: String,
+        default_index: usize,
+    ) -> Result<u64, Box<dyn Error>> {
+        let files = staging.files();
+        let mut size = 0;
+        for (variant, post) in translations.iter().zip(pages) {
+            let dir = format!("{}/{}", files, variant.lang);
+            self.upload_og_image(&dir, post).await?;
+            let live = format!("{}/{}", staging.dir, variant.lang);
+            size += self.upload_media(&dir, &live, post).await?;
+            size += post.html.len() as u64;
+            self.bucket
+                .put_html(
+                    &format!("{}/index.html", dir),
+                    post.html.clone(),
+                    &post.labels,
+                )
+                .await?;
+            eprintln!(
+                "Uploaded {} version: {}",
+                variant.lang,
+                variant.path.display()
+            );
+        }
+        self.upload_view_pixel(&files).await?;
+        size += redirect.len() as u64;
+        self.bucket
+            .put_html(
+                &format!("{}/index.html", staging.page()),
+                redirect,
+                &pages[default_index].labels,
+            )
+            .await?;
+        Ok(size)
+    }
+
+    /// Publishes every translation of a post (`post.en.md`, `post.sv.md`, ...)
+    /// under `p/<id>/<lang>/`. The manifest keeps the id stable, so publishing
+    /// again after adding a translation updates the existing post.
+    async fn publish_translations(
+        &self,
+        source: &Path,
+        base: &str,
+        manifest: &mut Manifest,
+        mark_updated: bool,
+    ) -> Result<Published, Box<dyn Error>> {
+        let translations = variants::find_variants(source, base).await?;
+        let source_hash = variants::source_hash(&translations).await?;
+        let group_source = group_source(source, base)?;
+
+        let existing = manifest.translated_post_mut(&group_source);
+        let folder_name = existing
+            .as_ref()
+            .map(|post| post.id.clone())
+            .unwrap_or_else(|| self.new_id([base.as_bytes(), source_hash.as_bytes()]));
+        let post_dir = existing
+            .as_ref()
+            .map_or_else(|| self.new_post_dir(&folder_name), |post| post.dir());
+        let published = existing.as_ref().map(|post| post.published);
+        let updated = match &existing {
+            Some(_) if mark_updated => Some(chrono::Utc::now()),
+            Some(post) => post.updated,
+            None => None,
+        };
+        let related = self.related_candidates(manifest, Some(&folder_name));
+        // One pixel for all the translations.
+        let view_pixel = self.view_pixel_url(&post_dir);
+
+        let alternates: Vec<Alternate> = translations
+            .iter()
+            .map(|variant| Alternate {
+                lang: variant.lang.clone(),
+                url: self
+                    .bucket
+                    .public_url(&format!("{}/{}/", post_dir, variant.lang)),
+            })
+            .collect();
+
+        // Every version must pass before any of them goes up.
+        for variant in &translations {
+            let markdown_content = fs::read_to_string(&variant.path).await?;
+            schema::validate(&markdown_content, &self.config.front_matter)
+                .and_then(|()| secrets::check(&markdown_content, &self.config.check))
+                .map_err(|e| format!("{}: {}", variant.path.display(), e))?;
+        }
+
+        let mut pages = Vec::with_capacity(translations.len());
+        for variant in &translations {
+            let markdown_content = fs::read_to_string(&variant.path).await?;
+            let file_starts = media::read_starts(&markdown_content, variant.path.parent()).await;
+            let dir = format!("{}/{}", post_dir, variant.lang);
+            let image_url = self.og_image_url(&dir);
+
+            pages.push(render_post(
+                &markdown_content,
+                &self.config.page,
+                &PostContext {
+                    fallback_title: base,
+                    lang: &variant.lang,
+                    alternates: &alternates,
+                    image_url: image_url.as_deref(),
+                    format: InputFormat::from_path(&variant.path),
+                    base_dir: variant.path.parent(),
+                    site: &self.config.site,
+                    published,
+                    updated,
+                    related: &related,
+                    view_pixel: view_pixel.as_deref(),
+                    source_url: None,
+                    content_types: &self.config.upload.content_types,
+                    file_starts: &file_starts,
+                },
+            )?);
+        }
+        let mut upload = budget::Upload {
+            post: base,
+            pages: 0,
+            assets: Vec::new(),
+        };
+        for post in &pages {
+            upload.pages += post.html.len() as u64;
+            upload.assets.extend(media_sizes(post).await?);
+        }
+        self.check_budget(&upload, Some(&folder_name), manifest)?;
+
+        // The bare post URL sends readers to the site language, or the first translation.
+        let default_index = translations
+            .iter()
+            .position(|variant| variant.lang == self.config.page.lang)
+            .unwrap_or(0);
+        let default_lang = translations
+            .get(default_index)
+            .map(|variant| variant.lang.as_str())
+            .ok_or("No translations found")?;
+        let redirect = redirect_page(&format!("{}/", default_lang), &alternates);
+
+        let staging = Staging::new(self.config, &post_dir);
+        // As a string, since the error can't be held across the cleanup.
+        let uploaded = self
+            .upload_translations(&staging, &translations, &pages, redirect, default_index)
+            .await
+            .map_err(|e| e.to_string());
+        let size = match uploaded {
+            Ok(size) => size,
+            Err(e) => {
+                staging.discard(self.bucket.as_ref()).await;
+                return Err(e.into());
+            }
+        };
+        staging.go_live(self.bucket.as_ref()).await?;
+
+        let mut rendered = Vec::with_capacity(translations.len());
+        let mut links = Vec::new();
+        for (variant, post) in translations.iter().zip(pages) {
+            let dir = format!("{}/{}", post_dir, variant.lang);
+            links.extend(
+                self.outbound_links(&self.bucket.public_url(&format!("{}/", dir)), post.links),
+            );
+            let enclosure = self.enclosure(&dir, post.audio);
+            let sections = self.section_links(&dir, post.sections, post.words);
+            rendered.push((
+                post.title,
+                post.description,
+                post.html,
+                post.labels,
+                self.byline(post.authors),
+                enclosure,
+                sections,
+            ));
+        }
+
+        let image = self.og_image_url(&format!("{}/{}", post_dir, default_lang));
+        let languages = translations
+            .into_iter()
+            .map(|variant| variant.lang)
+            .collect();
+        let (title, description, html, labels, authors, enclosure, sections) =
+            rendered.swap_remove(default_index);
+        match manifest.translated_post_mut(&group_source) {
+            Some(post) => {
+                post.title = title;
+                post.description = description;
+                post.languages = languages;
+                post.labels = labels;
+                post.authors = authors;
+                post.updated = updated;
+                post.source_hash = Some(source_hash);
+                post.size = Some(size);
+                post.image = image;
+                post.enclosure = enclosure;
+                post.sections = sections;
+                Ok(Published {
+                    post: post.clone(),
+                    updated: true,
+                    html,
+                    links,
+                })
+            }
+            None => {
+                let record = Post {
+                    url: self.bucket.public_url(&post_dir),
+                    id: folder_name,
+                    source: Some(group_source),
+                    title,
+                    description,
+                    published: published_at(base),
+                    updated: None,
+                    languages,
+                    webmentions: Vec::new(),
+                    expires: None,
+                    labels,
+                    authors,
+                    raw: false,
+                    deleted: None,
+                    member: self.config.team.author.clone(),
+                    restored: None,
+                    source_hash: Some(source_hash),
+                    output_hash: None,
+                    size: Some(size),
+                    image,
+                    enclosure,
+                    sections,
+                };
+                manifest.posts.push(record.clone());
+                Ok(Published {
+                    post: record,
+                    updated: false,
+                    html,
+                    links,
+                })
+            }
+        }
+    }
+}
@@ -0,0 +1,43 @@
+use crate::config::AppConfig;
+use crate::manifest::Manifest;
+use std::{error::Error, path::PathBuf};
+use tokio::fs;
+
+/// What the markdown is published as next to the page, with `[sources]
+/// upload`.
+pub const REMOTE_NAME: &str = "index.md";
+
+/// Plain text, so browsers show it instead of offering it as a download.
+pub const CONTENT_TYPE: &str = "text/plain; charset=utf-8";
+
+/// Where `[sources] keep` puts copies: `[sources] dir`, or `sources/` next
+/// to the manifest (`sources-memory/` for the memory backend's).
+pub fn dir(config: &AppConfig) -> Result<PathBuf, Box<dyn Error>> {
+    if let Some(dir) = &config.sources.dir {
+        return Ok(dir.clone());
+    }
+    let manifest_path = Manifest::path_for(config)?;
+    let name = manifest_path
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().replacen("manifest", "sources", 1))
+        .unwrap_or_else(|| "sources".to_string());
+    Ok(manifest_path.with_file_name(name))
+}
+
+/// Keeps `markdown` as the source of the post `id`, replacing an older copy.
+pub async fn save(config: &AppConfig, id: &str, markdown: &str) -> Result<(), Box<dyn Error>> {
+    let dir = dir(config)?;
+    fs::create_dir_all(&dir).await?;
+    fs::write(dir.join(format!("{}.md", id)), markdown).await?;
+    Ok(())
+}
+
+/// The kept source of the post `id`, if there is one.
+pub async fn load(config: &AppConfig, id: &str) -> Result<Option<String>, Box<dyn Error>> {
+    let path = dir(config)?.join(format!("{}.md", id));
+    match fs::read_to_string(&path).await {
+        Ok(markdown) => Ok(Some(markdown)),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(format!("Could not read {}: {}", path.display(), e).into()),
+    }
+}
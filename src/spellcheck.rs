@@ -0,0 +1,155 @@
+use crate::config::CheckConfig;
+use crate::formats::{self, InputFormat};
+use crate::frontmatter::FrontMatter;
+use crate::render::{markdown_options, plain_text};
+use pulldown_cmark::{Event, LinkType, Parser as MarkdownParser, Tag, TagEnd};
+use std::{collections::BTreeMap, error::Error, fmt};
+
+/// A word hunspell doesn't know.
+pub struct Misspelling {
+    /// Line in the source file, when it can be told.
+    pub line: Option<usize>,
+    pub word: String,
+    pub suggestions: Vec<String>,
+}
+
+impl fmt::Display for Misspelling {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.word)?;
+        if !self.suggestions.is_empty() {
+            write!(f, " (did you mean {}?)", self.suggestions.join(", "))?;
+        }
+        Ok(())
+    }
+}
+
+/// Runs the prose of `source` through hunspell, leaving out code, raw HTML
+/// and bare URLs, and returns the words it doesn't know that aren't in
+/// `[check] words`.
+pub fn check(
+    source: &str,
+    format: InputFormat,
+    config: &CheckConfig,
+) -> Result<Vec<Misspelling>, Box<dyn Error>> {
+    let lines = match format {
+        InputFormat::Markdown => prose_lines(source)?,
+        // Converted documents have no offsets to go by.
+        format => BTreeMap::from([(None, plain_text(source, format)?)]),
+    };
+    let lines: Vec<(Option<usize>, String)> = lines
+        .into_iter()
+        .filter(|(_, text)| !text.trim().is_empty())
+        .collect();
+    if lines.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    // `^` keeps a line from being read as a command in hunspell's pipe mode.
+    let input: String = lines
+        .iter()
+        .map(|(_, text)| format!("^{}\n", text))
+        .collect();
+    let mut args = vec!["-a"];
+    if let Some(dictionary) = &config.dictionary {
+        args.extend(["-d", dictionary]);
+    }
+    let output = formats::run_converter(&config.hunspell, &args, &input)?;
+
+    let accepted = |word: &str| {
+        let word = word.to_lowercase();
+        config
+            .words
+            .iter()
+            .any(|known| known.to_lowercase() == word)
+    };
+    let mut misspellings = Vec::new();
+    // After the version banner, every input line gets one result line per
+    // word and then an empty line.
+    let mut current = 0;
+    for result in output.lines().skip(1) {
+        if result.is_empty() {
+            current += 1;
+            continue;
+        }
+        let (word, suggestions) = if let Some(rest) = result.strip_prefix('&') {
+            // `& word count offset: suggestion, suggestion`
+            let (head, suggestions) = rest.split_once(':').unwrap_or((rest, ""));
+            let suggestions = suggestions
+                .split(',')
+                .map(|suggestion| suggestion.trim().to_string())
+                .filter(|suggestion| !suggestion.is_empty())
+                .take(3)
+                .collect();
+            (head.split_whitespace().next(), suggestions)
+        } else if let Some(rest) = result.strip_prefix('#') {
+            // `# word offset`, with nothing to suggest.
+            (rest.split_whitespace().next(), Vec::new())
+        } else {
+            continue;
+        };
+        let Some(word) = word else {
+            continue;
+        };
+        if accepted(word) {
+            continue;
+        }
+        misspellings.push(Misspelling {
+            line: lines.get(current).and_then(|(line, _)| *line),
+            word: word.to_string(),
+            suggestions,
+        });
+    }
+    Ok(misspellings)
+}
+
+/// The prose of a markdown file by line number: the front matter title and
+/// description, and the text of the body outside code, HTML and bare URLs.
+fn prose_lines(source: &str) -> Result<BTreeMap<Option<usize>, String>, Box<dyn Error>> {
+    let (front_matter, body) = FrontMatter::parse(source)?;
+    let body_start = source.len() - body.len();
+    let line_starts: Vec<usize> = std::iter::once(0)
+        .chain(source.match_indices('\n').map(|(index, _)| index + 1))
+        .collect();
+    let line_of = |offset: usize| Some(line_starts.partition_point(|&start| start <= offset));
+    let mut lines: BTreeMap<Option<usize>, String> = BTreeMap::new();
+    let mut add = |line: Option<usize>, text: &str| {
+        let prose = lines.entry(line).or_default();
+        if !prose.is_empty() {
+            prose.push(' ');
+        }
+        // Hunspell reads one line at a time.
+        prose.push_str(&text.replace(['\n', '\r'], " "));
+    };
+
+    let front_matter_line = |key: &str| {
+        source[..body_start]
+            .lines()
+            .position(|line| line.starts_with(key))
+            .map(|index| index + 1)
+    };
+    if let Some(title) = &front_matter.title {
+        add(front_matter_line("title:"), title);
+    }
+    if let Some(description) = &front_matter.description {
+        add(front_matter_line("description:"), description);
+    }
+
+    let mut skipping = 0;
+    for (event, range) in MarkdownParser::new_ext(body, markdown_options()).into_offset_iter() {
+        match event {
+            Event::Start(Tag::CodeBlock(_))
+            | Event::Start(Tag::Link {
+                link_type: LinkType::Autolink | LinkType::Email,
+                ..
+            }) => skipping += 1,
+            // Links don't nest, so a link end while skipping closes the
+            // autolink.
+            Event::End(TagEnd::CodeBlock) | Event::End(TagEnd::Link) if skipping > 0 => {
+                skipping -= 1
+            }
+            Event::Text(text) if skipping == 0 => add(line_of(body_start + range.start), &text),
+            _ => {}
+        }
+    }
+    Ok(lines)
+}
@@ -0,0 +1,220 @@
+use crate::config::AppConfig;
+use crate::formats::{self, InputFormat};
+use crate::manifest::Manifest;
+use crate::media;
+use crate::render::{render_post, PostContext, RenderedPost};
+use crate::site::{file_title, Announce, Site};
+use crate::variants;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::{
+    error::Error,
+    path::{Path, PathBuf},
+};
+use tokio::{
+    fs,
+    io::{self, AsyncBufRead, AsyncBufReadExt, AsyncWrite, AsyncWriteExt, BufReader},
+};
+
+/// One line of input. `markdown` is the editor buffer; without it the file at
+/// `path` is read. `path` also supplies the fallback title and language.
+#[derive(Debug, Deserialize)]
+struct Request {
+    #[serde(default)]
+    id: Value,
+    #[serde(flatten)]
+    command: Command,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(tag = "command", rename_all = "lowercase")]
+enum Command {
+    /// Reply with the rendered HTML.
+    Render {
+        markdown: Option<String>,
+        path: Option<PathBuf>,
+    },
+    /// Write the rendered page to a temporary file and reply with its path.
+    Preview {
+        markdown: Option<String>,
+        path: Option<PathBuf>,
+    },
+    /// Publish the buffer (or the file at `path`) and reply with the post.
+    Publish {
+        markdown: Option<String>,
+        path: Option<PathBuf>,
+        #[serde(default)]
+        no_announce: bool,
+    },
+}
+
+/// Reads newline-delimited JSON commands from stdin and writes one JSON reply
+/// per command to stdout. Replies echo the request's `id`; progress messages
+/// go to stderr so stdout stays machine readable.
+pub async fn serve(app_config: AppConfig) -> Result<(), Box<dyn Error>> {
+    serve_on(&app_config, BufReader::new(io::stdin()), &mut io::stdout()).await
+}
+
+/// [`serve`] with commands from `input` and replies to `output`.
+pub async fn serve_on<R, W>(
+    app_config: &AppConfig,
+    input: R,
+    output: &mut W,
+) -> Result<(), Box<dyn Error>>
+where
+    R: AsyncBufRead + Unpin,
+    W: AsyncWrite + Unpin,
+{
+    let mut session = Session {
+        config: app_config,
+        site: None,
+        manifest: None,
+    };
+    let mut lines = input.lines();
+
+    while let Some(line) = lines.next_line().await? {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let reply = match serde_json::from_str::<Request>(&line) {
+            Ok(request) => match session.handle(request.command).await {
+                Ok(result) => json!({ "id": request.id, "ok": true, "result": result }),
+                Err(e) => json!({ "id": request.id, "ok": false, "error": e.to_string() }),
+            },
+            Err(e) => {
+                json!({ "id": null, "ok": false, "error": format!("Invalid request: {}", e) })
+            }
+        };
+        output.write_all(format!("{}\n", reply).as_bytes()).await?;
+        output.flush().await?;
+    }
+    Ok(())
+}
+
+struct Session<'a> {
+    config: &'a AppConfig,
+    /// Created on the first publish, so rendering works without bucket settings.
+    site: Option<Site<'a>>,
+    manifest: Option<(Manifest, PathBuf)>,
+}
+
+impl<'a> Session<'a> {
+    async fn handle(&mut self, command: Command) -> Result<Value, Box<dyn Error>> {
+        match command {
+            Command::Render { markdown, path } => {
+                let post = self.render(markdown, path).await?;
+                Ok(json!({
+                    "title": post.title,
+                    "description": post.description,
+                    "html": post.html,
+                }))
+            }
+            Command::Preview { markdown, path } => {
+                let name = path
+                    .as_deref()
+                    .map(file_title)
+                    .unwrap_or("preview")
+                    .to_string();
+                let post = self.render(markdown, path).await?;
+                let dir = std::env::temp_dir().join("klistra-preview");
+                fs::create_dir_all(&dir).await?;
+                let output = dir.join(format!("{}.html", name));
+                fs::write(&output, &post.html).await?;
+                Ok(json!({ "title": post.title, "path": output }))
+            }
+            Command::Publish {
+                markdown,
+                path,
+                no_announce,
+            } => self.publish(markdown, path, no_announce).await,
+        }
+    }
+
+    async fn render(
+        &self,
+        markdown: Option<String>,
+        path: Option<PathBuf>,
+    ) -> Result<RenderedPost, Box<dyn Error>> {
+        let markdown = read_source(markdown, path.as_ref()).await?;
+        let file_starts =
+            media::read_starts(&markdown, path.as_deref().and_then(Path::parent)).await;
+        let (title, lang) = match path.as_deref() {
+            Some(path) => match variants::split_lang(path) {
+                Some((base, lang)) => (base, lang),
+                None => (file_title(path).to_string(), self.config.page.lang.clone()),
+            },
+            None => ("Document".to_string(), self.config.page.lang.clone()),
+        };
+        render_post(
+            &markdown,
+            &self.config.page,
+            &PostContext {
+                fallback_title: &title,
+                lang: &lang,
+                alternates: &[],
+                image_url: None,
+                format: path
+                    .as_deref()
+                    .map(InputFormat::from_path)
+                    .unwrap_or_default(),
+                base_dir: path.as_deref().and_then(Path::parent),
+                site: &self.config.site,
+                published: None,
+                updated: None,
+                related: &[],
+                view_pixel: None,
+                source_url: None,
+                content_types: &self.config.upload.content_types,
+                file_starts: &file_starts,
+            },
+        )
+    }
+
+    async fn publish(
+        &mut self,
+        markdown: Option<String>,
+        path: Option<PathBuf>,
+        no_announce: bool,
+    ) -> Result<Value, Box<dyn Error>> {
+        if self.site.is_none() {
+            self.site = Some(Site::new(self.config)?);
+        }
+        if self.manifest.is_none() {
+            let manifest_path = Manifest::path_for(self.config)?;
+            self.manifest = Some((Manifest::load(&manifest_path).await?, manifest_path));
+        }
+        let site = self.site.as_ref().expect("site initialized above");
+        let (manifest, manifest_path) = self.manifest.as_mut().expect("manifest loaded above");
+
+        let published = match (markdown, path) {
+            // Saved files go through the normal pipeline, so translations work too.
+            (None, Some(path)) => site.publish(&path, manifest).await?,
+            (markdown, path) => {
+                let markdown = read_source(markdown, path.as_ref()).await?;
+                let source = path.as_deref().map(std::path::absolute).transpose()?;
+                let title = path.as_deref().map(file_title).unwrap_or("Document");
+                site.publish_markdown(&markdown, title, source, manifest)
+                    .await?
+            }
+        };
+        let announce = if no_announce {
+            Announce::No
+        } else {
+            Announce::Yes
+        };
+        site.finish_publish(&published, manifest, manifest_path, announce)
+            .await?;
+        Ok(serde_json::to_value(&published.post)?)
+    }
+}
+
+async fn read_source(
+    markdown: Option<String>,
+    path: Option<&PathBuf>,
+) -> Result<String, Box<dyn Error>> {
+    match (markdown, path) {
+        (Some(markdown), _) => Ok(markdown),
+        (None, Some(path)) => formats::read_source(path).await,
+        (None, None) => Err("Request needs either markdown or a path".into()),
+    }
+}
@@ -0,0 +1,113 @@
+use crate::publisher::Publisher;
+use crate::resume::PART_SIZE;
+use sha2::{Digest, Sha256};
+use std::{error::Error, fmt};
+use tokio::runtime::{Handle, RuntimeFlavor};
+
+/// Runs `f`, which blocks, without holding up other tasks: on a
+/// multi-threaded runtime through [`tokio::task::block_in_place`]. Anywhere
+/// else (a current-thread runtime, or none) it just runs, since there's no
+/// other worker to hand the tasks to.
+pub fn blocking<R>(f: impl FnOnce() -> R) -> R {
+    match Handle::try_current() {
+        Ok(runtime) if runtime.runtime_flavor() == RuntimeFlavor::MultiThread => {
+            tokio::task::block_in_place(f)
+        }
+        _ => f(),
+    }
+}
+
+/// Hashes everything written to it like [`crate::manifest::content_hash`],
+/// without keeping it.
+#[derive(Default)]
+pub struct HashWriter {
+    hasher: Sha256,
+    written: u64,
+}
+
+impl HashWriter {
+    /// How many bytes have been written so far.
+    pub fn written(&self) -> u64 {
+        self.written
+    }
+
+    pub fn finish(self) -> String {
+        hex::encode(self.hasher.finalize())
+    }
+}
+
+impl fmt::Write for HashWriter {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.hasher.update(s.as_bytes());
+        self.written += s.len() as u64;
+        Ok(())
+    }
+}
+
+/// Uploads everything written to it as the parts of a multipart upload, one
+/// [`PART_SIZE`] buffer at a time. Uploads block the writing thread, so use
+/// it inside [`tokio::task::block_in_place`].
+pub struct PartWriter<'a> {
+    bucket: &'a dyn Publisher,
+    path: &'a str,
+    upload_id: &'a str,
+    runtime: Handle,
+    buffer: Vec<u8>,
+    parts: Vec<(i32, String)>,
+    /// Why the last write failed; `fmt::Write` can't carry it.
+    error: Option<Box<dyn Error>>,
+}
+
+impl<'a> PartWriter<'a> {
+    pub fn new(bucket: &'a dyn Publisher, path: &'a str, upload_id: &'a str) -> Self {
+        Self {
+            bucket,
+            path,
+            upload_id,
+            runtime: Handle::current(),
+            buffer: Vec::new(),
+            parts: Vec::new(),
+            error: None,
+        }
+    }
+
+    fn upload_buffer(&mut self) -> Result<(), Box<dyn Error>> {
+        let number = self.parts.len() as i32 + 1;
+        let body = std::mem::take(&mut self.buffer);
+        let etag = self.runtime.block_on(self.bucket.upload_part(
+            self.path,
+            self.upload_id,
+            number,
+            body,
+        ))?;
+        self.parts.push((number, etag));
+        Ok(())
+    }
+
+    /// The error behind a failed write, if an upload was what failed.
+    pub fn take_error(&mut self) -> Option<Box<dyn Error>> {
+        self.error.take()
+    }
+
+    /// Uploads what's left as the last part and returns every part's number
+    /// and ETag for completing the upload.
+    pub fn finish(mut self) -> Result<Vec<(i32, String)>, Box<dyn Error>> {
+        if !self.buffer.is_empty() || self.parts.is_empty() {
+            self.upload_buffer()?;
+        }
+        Ok(self.parts)
+    }
+}
+
+impl fmt::Write for PartWriter<'_> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.buffer.extend_from_slice(s.as_bytes());
+        if self.buffer.len() as u64 >= PART_SIZE {
+            self.upload_buffer().map_err(|e| {
+                self.error = Some(e);
+                fmt::Error
+            })?;
+        }
+        Ok(())
+    }
+}
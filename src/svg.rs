@@ -0,0 +1,262 @@
+use crate::config::SvgConfig;
+use quick_xml::events::{BytesStart, Event};
+use quick_xml::{Reader, Writer, XmlVersion};
+use std::error::Error;
+
+/// Elements that run code or embed other documents. They're dropped with
+/// everything in them.
+const UNSAFE_ELEMENTS: &[&str] = &[
+    "script",
+    "foreignobject",
+    "iframe",
+    "object",
+    "embed",
+    "handler",
+    "listener",
+];
+
+/// Namespaces drawing programs keep their own state in. Browsers ignore
+/// them.
+const EDITOR_NAMESPACES: &[&str] = &["inkscape", "sodipodi", "sketch", "serif", "figma"];
+
+/// Entities XML defines itself; any other reference needs a DOCTYPE.
+const PREDEFINED_ENTITIES: &[&str] = &["lt", "gt", "amp", "apos", "quot"];
+
+/// Inline images that can't carry script.
+const RASTER_DATA: &[&str] = &[
+    "data:image/png",
+    "data:image/jpeg",
+    "data:image/gif",
+    "data:image/webp",
+    "data:image/avif",
+];
+
+/// Elements whose whitespace is text someone will see.
+const TEXT_ELEMENTS: &[&str] = &["text", "tspan", "textpath", "title", "desc", "style"];
+
+/// `svg` cleaned as `[svg]` asks.
+///
+/// Sanitizing drops scripts, event handlers, embedded documents,
+/// `javascript:` and external links (other than plain `<a>` links),
+/// animations that rewrite links, and styles that load anything, so a
+/// file opened straight from the bucket can't run code on the site's
+/// domain. Optimizing drops what editors leave behind: the XML
+/// declaration, comments, doctype, `<metadata>`, editor namespaces, and
+/// whitespace between elements.
+pub fn clean(svg: &[u8], config: &SvgConfig) -> Result<Vec<u8>, Box<dyn Error>> {
+    let mut reader = Reader::from_reader(svg);
+    let mut writer = Writer::new(Vec::with_capacity(svg.len()));
+    let mut buf = Vec::new();
+    // Local names of the elements we're in, and how deep into a dropped
+    // one we are.
+    let mut open: Vec<String> = Vec::new();
+    let mut dropping = 0usize;
+    loop {
+        let event = reader.read_event_into(&mut buf)?;
+        if dropping > 0 {
+            match event {
+                Event::Start(_) => dropping += 1,
+                Event::End(_) => dropping -= 1,
+                Event::Eof => break,
+                _ => {}
+            }
+            buf.clear();
+            continue;
+        }
+        match event {
+            Event::Eof => break,
+            Event::Start(element) => {
+                if drops(&element, config) {
+                    dropping = 1;
+                } else {
+                    open.push(local_name(&element));
+                    writer.write_event(Event::Start(kept_attributes(&element, config)?))?;
+                }
+            }
+            Event::Empty(element) => {
+                if !drops(&element, config) {
+                    writer.write_event(Event::Empty(kept_attributes(&element, config)?))?;
+                }
+            }
+            Event::End(element) => {
+                open.pop();
+                writer.write_event(Event::End(element))?;
+            }
+            Event::Text(text) => {
+                let in_text = open
+                    .last()
+                    .is_some_and(|name| TEXT_ELEMENTS.contains(&name.as_str()));
+                let blank = text.chars().all(char::is_whitespace);
+                let unsafe_style = config.sanitize
+                    && open.last().is_some_and(|name| name == "style")
+                    && loads_anything(&text);
+                if !(unsafe_style || (config.optimize && blank && !in_text)) {
+                    writer.write_event(Event::Text(text))?;
+                }
+            }
+            Event::CData(data) => {
+                let unsafe_style = config.sanitize
+                    && open.last().is_some_and(|name| name == "style")
+                    && loads_anything(&data);
+                if !unsafe_style {
+                    writer.write_event(Event::CData(data))?;
+                }
+            }
+            Event::GeneralRef(reference) => {
+                // Entities from a DOCTYPE can expand to markup, so only
+                // character references and XML's own entities stay.
+                let known = reference.is_char_ref() || PREDEFINED_ENTITIES.contains(&&*reference);
+                if known || !config.sanitize {
+                    writer.write_event(Event::GeneralRef(reference))?;
+                }
+            }
+            Event::Decl(decl) => {
+                if !config.optimize {
+                    writer.write_event(Event::Decl(decl))?;
+                }
+            }
+            Event::Comment(comment) => {
+                if !config.optimize {
+                    writer.write_event(Event::Comment(comment))?;
+                }
+            }
+            // Doctypes can declare entities and processing instructions can
+            // load stylesheets.
+            Event::DocType(doctype) => {
+                if !(config.sanitize || config.optimize) {
+                    writer.write_event(Event::DocType(doctype))?;
+                }
+            }
+            Event::PI(instruction) => {
+                if !(config.sanitize || config.optimize) {
+                    writer.write_event(Event::PI(instruction))?;
+                }
+            }
+        }
+        buf.clear();
+    }
+    Ok(writer.into_inner())
+}
+
+fn local_name(element: &BytesStart) -> String {
+    element.local_name().as_ref().to_ascii_lowercase()
+}
+
+/// Whether `element` goes, with everything in it.
+fn drops(element: &BytesStart, config: &SvgConfig) -> bool {
+    let name = local_name(element);
+    if config.sanitize {
+        if UNSAFE_ELEMENTS.contains(&name.as_str()) {
+            return true;
+        }
+        // `<set attributeName="href" to="javascript:...">` and the like.
+        if matches!(
+            name.as_str(),
+            "set" | "animate" | "animatemotion" | "animatetransform"
+        ) {
+            let rewrites_links = element.attributes().flatten().any(|attribute| {
+                attribute
+                    .key
+                    .local_name()
+                    .as_ref()
+                    .eq_ignore_ascii_case("attributename")
+                    && attribute
+                        .normalized_value(XmlVersion::Implicit1_0)
+                        .is_ok_and(|target| {
+                            let target = target.trim().to_ascii_lowercase();
+                            target.ends_with("href") || target.starts_with("on")
+                        })
+            });
+            if rewrites_links {
+                return true;
+            }
+        }
+    }
+    if config.optimize {
+        if name == "metadata" {
+            return true;
+        }
+        let prefix = element.name().prefix();
+        if prefix.is_some_and(|prefix| is_editor_namespace(prefix.as_ref())) {
+            return true;
+        }
+    }
+    false
+}
+
+/// `element` with only the attributes that may stay.
+fn kept_attributes(
+    element: &BytesStart,
+    config: &SvgConfig,
+) -> Result<BytesStart<'static>, Box<dyn Error>> {
+    let name = element.name().as_ref().to_string();
+    let is_link = local_name(element) == "a";
+    let mut kept = BytesStart::new(name);
+    for attribute in element.attributes() {
+        let attribute = attribute?;
+        let key = attribute.key.as_ref();
+        let local = attribute.key.local_name().as_ref().to_ascii_lowercase();
+        let value = attribute.normalized_value(XmlVersion::Implicit1_0)?;
+        if config.sanitize {
+            let lower = value.to_ascii_lowercase();
+            let compact: String = lower.chars().filter(|c| !c.is_whitespace()).collect();
+            if local.starts_with("on")
+                || compact.contains("javascript:")
+                || compact.contains("vbscript:")
+                || (local == "href" && !safe_href(&compact, is_link))
+                || loads_anything(&lower)
+            {
+                continue;
+            }
+        }
+        if config.optimize {
+            let prefix = attribute.key.prefix();
+            let editor_attribute =
+                prefix.is_some_and(|prefix| is_editor_namespace(prefix.as_ref()));
+            let editor_declaration = key.strip_prefix("xmlns:").is_some_and(is_editor_namespace);
+            if editor_attribute || editor_declaration {
+                continue;
+            }
+        }
+        kept.push_attribute(attribute.clone());
+    }
+    Ok(kept)
+}
+
+fn is_editor_namespace(prefix: &str) -> bool {
+    EDITOR_NAMESPACES
+        .iter()
+        .any(|namespace| namespace.eq_ignore_ascii_case(prefix))
+}
+
+/// Whether a link may stay: references within the file, inline raster
+/// images, and, on `<a>`, ordinary links to follow.
+fn safe_href(href: &str, is_link: bool) -> bool {
+    href.starts_with('#')
+        || is_raster_data(href)
+        || (is_link
+            && ["https:", "http:", "mailto:"]
+                .iter()
+                .any(|scheme| href.starts_with(scheme)))
+}
+
+/// Whether CSS (or an attribute like `fill="url(...)"`) fetches something
+/// from outside the file, or runs script in old browsers.
+fn loads_anything(css: &str) -> bool {
+    let css: String = css
+        .to_ascii_lowercase()
+        .chars()
+        .filter(|c| !c.is_whitespace())
+        .collect();
+    if css.contains("@import") || css.contains("expression(") || css.contains("javascript:") {
+        return true;
+    }
+    css.match_indices("url(").any(|(start, _)| {
+        let target = css[start + 4..].trim_start_matches(['"', '\'']);
+        !(target.starts_with('#') || is_raster_data(target))
+    })
+}
+
+fn is_raster_data(url: &str) -> bool {
+    RASTER_DATA.iter().any(|prefix| url.starts_with(prefix))
+}
@@ -0,0 +1,96 @@
+use crate::render::escape_html;
+use std::{borrow::Cow, error::Error, path::Path};
+
+/// Sorts `.data-table`s by the clicked column; numbers compare as numbers.
+pub const SORT_SCRIPT: &str = r#"<script>
+document.querySelectorAll("table.data-table th").forEach((th, index) => {
+    th.closest("table").classList.add("sortable");
+    th.addEventListener("click", () => {
+        const table = th.closest("table");
+        const body = table.tBodies[0];
+        const ascending = th.getAttribute("aria-sort") !== "ascending";
+        table.querySelectorAll("th").forEach(other => other.removeAttribute("aria-sort"));
+        th.setAttribute("aria-sort", ascending ? "ascending" : "descending");
+        const value = row => row.cells[index] ? row.cells[index].textContent.trim() : "";
+        const rows = Array.from(body.rows).sort((a, b) => {
+            const [x, y] = [value(a), value(b)];
+            const [nx, ny] = [parseFloat(x), parseFloat(y)];
+            const order = !isNaN(nx) && !isNaN(ny) ? nx - ny : x.localeCompare(y, undefined, { numeric: true });
+            return ascending ? order : -order;
+        });
+        body.append(...rows);
+    });
+});
+</script>"#;
+
+/// The field delimiter for a data file: tabs for `.tsv`, commas otherwise.
+pub fn delimiter_for(path: &Path) -> u8 {
+    match path.extension().and_then(|ext| ext.to_str()) {
+        Some("tsv") => b'\t',
+        _ => b',',
+    }
+}
+
+/// Renders delimited text as an HTML table, using the first row as the header.
+pub fn to_html(text: &str, delimiter: u8) -> Result<String, Box<dyn Error>> {
+    let mut reader = csv::ReaderBuilder::new()
+        .delimiter(delimiter)
+        .flexible(true)
+        .from_reader(text.as_bytes());
+
+    let mut html =
+        String::from("<div class=\"table-scroll\">\n<table class=\"data-table\">\n<thead>\n<tr>");
+    for header in reader.headers()? {
+        html.push_str(&format!("<th>{}</th>", escape_html(header)));
+    }
+    html.push_str("</tr>\n</thead>\n<tbody>\n");
+    for record in reader.records() {
+        html.push_str("<tr>");
+        for field in &record? {
+            html.push_str(&format!("<td>{}</td>", escape_html(field)));
+        }
+        html.push_str("</tr>\n");
+    }
+    html.push_str("</tbody>\n</table>\n</div>\n");
+    Ok(html)
+}
+
+/// Replaces lines like `{{< table "data.csv" >}}` with the rendered table.
+/// Paths are relative to `base_dir`, the directory of the post.
+pub fn expand_shortcodes<'a>(
+    markdown: &'a str,
+    base_dir: Option<&Path>,
+) -> Result<Cow<'a, str>, Box<dyn Error>> {
+    if !markdown.contains("{{<") {
+        return Ok(Cow::Borrowed(markdown));
+    }
+
+    let mut expanded = String::with_capacity(markdown.len());
+    for line in markdown.lines() {
+        let Some(file) = table_shortcode(line) else {
+            expanded.push_str(line);
+            expanded.push('\n');
+            continue;
+        };
+        let path = base_dir.unwrap_or(Path::new(".")).join(file);
+        let text = std::fs::read_to_string(&path)
+            .map_err(|e| format!("Could not read table {}: {}", path.display(), e))?;
+        // Blank lines keep the HTML block separate from surrounding markdown.
+        expanded.push('\n');
+        expanded.push_str(&to_html(&text, delimiter_for(&path))?);
+        expanded.push('\n');
+    }
+    Ok(Cow::Owned(expanded))
+}
+
+/// The file named by a `{{< table "file" >}}` line.
+fn table_shortcode(line: &str) -> Option<&str> {
+    let inner = line
+        .trim()
+        .strip_prefix("{{<")?
+        .strip_suffix(">}}")?
+        .trim()
+        .strip_prefix("table")?
+        .trim();
+    inner.strip_prefix('"')?.strip_suffix('"')
+}
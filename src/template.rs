@@ -0,0 +1,91 @@
+use minijinja::{path_loader, AutoEscape, Environment, ErrorKind, UndefinedBehavior};
+use std::{
+    collections::BTreeMap,
+    error::Error,
+    fmt,
+    path::{Path, PathBuf},
+};
+
+/// A value a template can show. Text is HTML-escaped; HTML goes in as is.
+pub enum Value {
+    Text(String),
+    Html(String),
+}
+
+/// Writes the post where a template has `{{ content }}`.
+pub type ContentWriter<'a> = dyn FnMut(&mut dyn fmt::Write) -> Result<(), Box<dyn Error>> + 'a;
+
+/// A page layout from `[page] templates_dir`, rendered with MiniJinja. The
+/// post is the `content` value; other templates in the directory can be
+/// included or extended.
+pub struct Template {
+    path: PathBuf,
+    name: String,
+    env: Environment<'static>,
+}
+
+impl Template {
+    /// Loads and compiles the template called `name` (a file name like
+    /// `photo-essay.html`) from `dir`.
+    pub fn load(dir: &Path, name: &str) -> Result<Self, Box<dyn Error>> {
+        if name.is_empty() || name.contains(['/', '\\']) || name.starts_with('.') {
+            return Err(format!(
+                "Invalid template name '{}': give a file name in the templates directory",
+                name
+            )
+            .into());
+        }
+        let mut env = Environment::new();
+        env.set_loader(path_loader(dir));
+        // Escape every template, whatever its extension.
+        env.set_auto_escape_callback(|_| AutoEscape::Html);
+        env.set_undefined_behavior(UndefinedBehavior::Strict);
+        env.set_keep_trailing_newline(true);
+        let path = dir.join(name);
+        env.get_template(name).map_err(|e| match e.kind() {
+            ErrorKind::TemplateNotFound => {
+                format!("Could not read template {}: not found", path.display())
+            }
+            _ => format!("Invalid template {}: {}", path.display(), e),
+        })?;
+        Ok(Self {
+            path,
+            name: name.to_string(),
+            env,
+        })
+    }
+
+    /// Writes the template rendered with `values`, plus `content` as the post.
+    pub fn render_to(
+        &self,
+        values: &BTreeMap<&str, Value>,
+        out: &mut dyn fmt::Write,
+        content: &mut ContentWriter,
+    ) -> Result<(), Box<dyn Error>> {
+        let mut post = String::new();
+        content(&mut post)?;
+        let mut context: BTreeMap<&str, minijinja::Value> = values
+            .iter()
+            .map(|(name, value)| {
+                let value = match value {
+                    Value::Text(text) => minijinja::Value::from(text.as_str()),
+                    Value::Html(html) => minijinja::Value::from_safe_string(html.clone()),
+                };
+                (*name, value)
+            })
+            .collect();
+        context.insert("content", minijinja::Value::from_safe_string(post));
+
+        let template = self.env.get_template(&self.name)?;
+        let html = template.render(&context).map_err(|e| {
+            let mut message = format!("Could not render template {}: {}", self.path.display(), e);
+            if e.kind() == ErrorKind::UndefinedError {
+                let known: Vec<&str> = values.keys().copied().collect();
+                message.push_str(&format!(". Available: content, {}", known.join(", ")));
+            }
+            message
+        })?;
+        out.write_str(&html)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,178 @@
+use aws_sdk_s3::primitives::{ByteStream, SdkBody};
+use bytes::Bytes;
+use http_body::{Body, Frame, SizeHint};
+use std::{
+    convert::Infallible,
+    future::Future,
+    pin::Pin,
+    str::FromStr,
+    sync::Mutex,
+    task::{Context, Poll},
+    time::Duration,
+};
+use tokio::time::{sleep_until, Instant, Sleep};
+
+/// An upload speed limit in bytes per second, written like `2MB/s`, `500KB`
+/// or `1048576`. Units are powers of 1024.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Deserialize)]
+#[serde(try_from = "String")]
+pub struct Rate(u64);
+
+impl FromStr for Rate {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let trimmed = s.trim();
+        let trimmed = trimmed.strip_suffix("/s").unwrap_or(trimmed).trim_end();
+        let split = trimmed
+            .find(|c: char| !(c.is_ascii_digit() || c == '.'))
+            .unwrap_or(trimmed.len());
+        let (number, unit) = trimmed.split_at(split);
+        let multiplier: u64 = match unit.trim().to_ascii_uppercase().as_str() {
+            "" | "B" => 1,
+            "K" | "KB" => 1024,
+            "M" | "MB" => 1024 * 1024,
+            "G" | "GB" => 1024 * 1024 * 1024,
+            _ => return Err(format!("Unknown unit in rate '{}'", s)),
+        };
+        let number: f64 = number
+            .parse()
+            .map_err(|_| format!("Invalid rate '{}', expected e.g. 2MB/s", s))?;
+        let bytes = (number * multiplier as f64) as u64;
+        if bytes == 0 {
+            return Err(format!("Rate '{}' must be above zero", s));
+        }
+        Ok(Rate(bytes))
+    }
+}
+
+impl TryFrom<String> for Rate {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+/// Bytes are released in pieces of this size, or less for slow rates.
+const CHUNK_SIZE: u64 = 64 * 1024;
+
+/// An upload body that sends `data` no faster than `rate`.
+pub fn throttled(data: Vec<u8>, rate: Rate) -> ByteStream {
+    let data = Bytes::from(data);
+    // Retries rebuild the body and start the clock over.
+    ByteStream::new(SdkBody::retryable(move || {
+        SdkBody::from_body_1_x(ThrottledBody {
+            data: data.clone(),
+            rate,
+            started: None,
+            sent: 0,
+            wait: None,
+        })
+    }))
+}
+
+struct ThrottledBody {
+    data: Bytes,
+    rate: Rate,
+    /// Set when the first byte is requested.
+    started: Option<Instant>,
+    sent: u64,
+    wait: Option<Pin<Box<Sleep>>>,
+}
+
+impl Body for ThrottledBody {
+    type Data = Bytes;
+    type Error = Infallible;
+
+    fn poll_frame(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Bytes>, Infallible>>> {
+        let this = &mut *self;
+        let remaining = this.data.len() as u64 - this.sent;
+        if remaining == 0 {
+            return Poll::Ready(None);
+        }
+        if let Some(wait) = &mut this.wait {
+            if wait.as_mut().poll(cx).is_pending() {
+                return Poll::Pending;
+            }
+            this.wait = None;
+        }
+
+        let started = *this.started.get_or_insert_with(Instant::now);
+        let chunk = remaining.min(CHUNK_SIZE).min(this.rate.0);
+        // When the bytes sent so far plus this chunk are allowed to have gone out.
+        let due =
+            started + Duration::from_secs_f64((this.sent + chunk) as f64 / this.rate.0 as f64);
+        if due > Instant::now() {
+            let mut wait = Box::pin(sleep_until(due));
+            if wait.as_mut().poll(cx).is_pending() {
+                this.wait = Some(wait);
+                return Poll::Pending;
+            }
+        }
+
+        let start = this.sent as usize;
+        this.sent += chunk;
+        let frame = this.data.slice(start..start + chunk as usize);
+        Poll::Ready(Some(Ok(Frame::data(frame))))
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.sent == self.data.len() as u64
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        SizeHint::with_exact(self.data.len() as u64 - self.sent)
+    }
+}
+
+/// The shortest and longest gap [`Pacing`] leaves between uploads.
+const MIN_GAP: Duration = Duration::from_millis(100);
+const MAX_GAP: Duration = Duration::from_secs(10);
+
+/// Spaces out the uploads of one bucket client once the bucket throttles,
+/// so concurrent uploads slow down together. The gap doubles with every
+/// throttled response and shrinks again as uploads go through.
+#[derive(Debug, Default)]
+pub struct Pacing(Mutex<Gap>);
+
+#[derive(Debug, Default)]
+struct Gap {
+    gap: Duration,
+    /// When the next upload may start.
+    next: Option<Instant>,
+}
+
+impl Pacing {
+    /// Waits for this upload's turn.
+    pub async fn wait(&self) {
+        let start = {
+            let mut state = self.0.lock().unwrap();
+            let now = Instant::now();
+            let start = state.next.map_or(now, |next| next.max(now));
+            state.next = Some(start + state.gap);
+            start
+        };
+        sleep_until(start).await;
+    }
+
+    /// The bucket turned a request away, asking to wait `retry_after` if it
+    /// said how long.
+    pub fn throttled(&self, retry_after: Option<Duration>) {
+        let mut state = self.0.lock().unwrap();
+        state.gap = (state.gap * 2).clamp(MIN_GAP, MAX_GAP);
+        let resume = Instant::now() + retry_after.unwrap_or(state.gap);
+        state.next = Some(state.next.map_or(resume, |next| next.max(resume)));
+    }
+
+    pub fn succeeded(&self) {
+        let mut state = self.0.lock().unwrap();
+        state.gap = state.gap * 3 / 4;
+        if state.gap < MIN_GAP / 2 {
+            state.gap = Duration::ZERO;
+        }
+    }
+}
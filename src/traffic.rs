@@ -0,0 +1,179 @@
+use crate::config::AppConfig;
+use crate::frontmatter::Dir;
+use crate::logs::Hit;
+use crate::manifest::Post;
+use crate::raw::format_size;
+use crate::render::{escape_html, format_date, today, Page};
+use crate::table::SORT_SCRIPT;
+use crate::views;
+use chrono::{DateTime, Local, NaiveDate, Utc};
+use std::{collections::BTreeMap, error::Error};
+
+/// What was requested from one post's directory.
+pub struct PostTraffic<'a> {
+    pub post: &'a Post,
+    pub requests: u64,
+    /// Bytes sent, for the page and everything next to it.
+    pub bytes: u64,
+    /// Times its `[views]` pixel was served.
+    pub views: u64,
+}
+
+/// Traffic to the site, by post.
+pub struct Report<'a> {
+    /// Posts that had any requests, most requested first.
+    pub posts: Vec<PostTraffic<'a>>,
+    /// Requests for everything else: the index, feeds, purged posts, ...
+    pub other_requests: u64,
+    pub other_bytes: u64,
+    /// Requests per day.
+    pub days: BTreeMap<NaiveDate, u64>,
+}
+
+impl Report<'_> {
+    pub fn requests(&self) -> u64 {
+        self.posts.iter().map(|post| post.requests).sum::<u64>() + self.other_requests
+    }
+
+    pub fn bytes(&self) -> u64 {
+        self.posts.iter().map(|post| post.bytes).sum::<u64>() + self.other_bytes
+    }
+}
+
+/// Adds up `hits` by the post they were for.
+pub fn report<'a>(posts: &'a [Post], hits: &[Hit]) -> Report<'a> {
+    let mut traffic: Vec<PostTraffic> = posts
+        .iter()
+        .map(|post| PostTraffic {
+            post,
+            requests: 0,
+            bytes: 0,
+            views: 0,
+        })
+        .collect();
+    let dirs: Vec<String> = posts.iter().map(Post::dir).collect();
+    let mut report = Report {
+        posts: Vec::new(),
+        other_requests: 0,
+        other_bytes: 0,
+        days: BTreeMap::new(),
+    };
+    for hit in hits {
+        *report.days.entry(hit.time.date_naive()).or_default() += hit.requests;
+        match dirs.iter().position(|dir| hit.is_in(dir)) {
+            Some(index) => {
+                let post = &mut traffic[index];
+                post.requests += hit.requests;
+                post.bytes += hit.bytes;
+                if views::is_view(hit, post.post) {
+                    post.views += hit.requests;
+                }
+            }
+            None => {
+                report.other_requests += hit.requests;
+                report.other_bytes += hit.bytes;
+            }
+        }
+    }
+    traffic.retain(|post| post.requests > 0);
+    traffic.sort_by(|a, b| {
+        b.requests
+            .cmp(&a.requests)
+            .then(b.bytes.cmp(&a.bytes))
+            .then_with(|| a.post.title.cmp(&b.post.title))
+    });
+    report.posts = traffic;
+    report
+}
+
+/// The report as a ranked table for the terminal.
+pub fn text(report: &Report) -> String {
+    let mut out = format!("{:>9} {:>10} {:>7}  Post\n", "Requests", "Sent", "Views");
+    for post in &report.posts {
+        out.push_str(&format!(
+            "{:>9} {:>10} {:>7}  {}  {}\n",
+            post.requests,
+            format_size(post.bytes),
+            post.views,
+            post.post.title,
+            post.post.url
+        ));
+    }
+    if report.other_requests > 0 {
+        out.push_str(&format!(
+            "{:>9} {:>10} {:>7}  Everything else\n",
+            report.other_requests,
+            format_size(report.other_bytes),
+            "",
+        ));
+    }
+    out.push_str(&format!(
+        "{:>9} {:>10} {:>7}  Total\n",
+        report.requests(),
+        format_size(report.bytes()),
+        ""
+    ));
+    out
+}
+
+/// The report as a page: totals, a bar per day, and a sortable table of
+/// the posts.
+pub fn html(
+    report: &Report,
+    since: DateTime<Utc>,
+    config: &AppConfig,
+) -> Result<String, Box<dyn Error>> {
+    let date = |date: DateTime<Utc>| format_date(date.with_timezone(&Local), &config.page);
+    let megabytes = |bytes: u64| format!("{:.2}", bytes as f64 / (1024.0 * 1024.0));
+
+    let busiest = report.days.values().copied().max().unwrap_or(0).max(1);
+    let mut bars = String::new();
+    for (day, requests) in &report.days {
+        bars.push_str(&format!(
+            "<div title=\"{day}: {requests}\" style=\"flex: 1; background: currentColor; opacity: .6; height: {height:.1}%\"></div>\n",
+            height = *requests as f64 * 100.0 / busiest as f64,
+        ));
+    }
+
+    let mut rows = String::new();
+    for post in &report.posts {
+        rows.push_str(&format!(
+            "<tr><td><a href=\"{url}\">{title}</a></td><td>{requests}</td><td>{megabytes}</td><td>{views}</td></tr>\n",
+            url = escape_html(&post.post.url),
+            title = escape_html(&post.post.title),
+            requests = post.requests,
+            megabytes = megabytes(post.bytes),
+            views = post.views,
+        ));
+    }
+    let body = format!(
+        "<p>{requests} requests and {sent} sent since {since}; {other} requests were for the index, feeds and other files.</p>\n\
+         <div class=\"traffic-days\" style=\"display: flex; align-items: flex-end; gap: 2px; height: 8em\">\n{bars}</div>\n\
+         <div class=\"table-scroll\">\n<table class=\"data-table\">\n<thead>\n<tr><th>Post</th><th>Requests</th><th>MB sent</th><th>Views</th></tr>\n</thead>\n<tbody>\n{rows}</tbody>\n</table>\n</div>\n{SORT_SCRIPT}",
+        requests = report.requests(),
+        sent = format_size(report.bytes()),
+        since = date(since)?,
+        other = report.other_requests,
+    );
+
+    let title = match &config.site.title {
+        Some(site) => format!("Traffic on {}", site),
+        None => "Traffic".to_string(),
+    };
+    let date = today(&config.page)?;
+    Ok(Page {
+        title: &title,
+        heading: Some(&title),
+        description: None,
+        lang: &config.page.lang,
+        dir: Dir::from_lang(&config.page.lang),
+        date: &date,
+        body: &body,
+        alternates: &[],
+        image_url: None,
+        site: &config.site,
+        authors: &[],
+        source_url: None,
+    }
+    .render())
+}
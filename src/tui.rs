@@ -0,0 +1,396 @@
+use crate::config::AppConfig;
+use crate::manifest::{Manifest, Post};
+use crate::site::{Announce, Site};
+use base64::Engine;
+use fuzzy_matcher::{skim::SkimMatcherV2, FuzzyMatcher};
+use ratatui::{
+    crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers},
+    layout::{Constraint, Layout},
+    style::{Style, Stylize},
+    text::{Line, Span, Text},
+    widgets::{Block, List, ListItem, ListState, Paragraph, Wrap},
+    DefaultTerminal, Frame,
+};
+use std::{
+    error::Error,
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+/// What keys currently do.
+enum Mode {
+    Browse,
+    /// Typing into the search box.
+    Search,
+    /// Waiting for `y` to delete the selected post.
+    ConfirmDelete,
+    /// Typing the path of a file to publish.
+    Publish(String),
+}
+
+struct App<'a> {
+    site: Site<'a>,
+    manifest: Manifest,
+    manifest_path: PathBuf,
+    mode: Mode,
+    query: String,
+    /// Indices into `manifest.posts` matching the query, best match first.
+    visible: Vec<usize>,
+    list: ListState,
+    status: String,
+    /// Set after actions that may have printed to the terminal behind our
+    /// back (warnings from webhooks, announcements, ...).
+    needs_clear: bool,
+}
+
+/// Browses published posts in the terminal, with fuzzy search and actions
+/// for publishing, updating, deleting, opening and copying URLs.
+pub async fn run(config: &AppConfig) -> Result<(), Box<dyn Error>> {
+    let manifest_path = Manifest::path_for(config)?;
+    let mut app = App {
+        site: Site::new(config)?,
+        manifest: Manifest::load(&manifest_path).await?,
+        manifest_path,
+        mode: Mode::Browse,
+        query: String::new(),
+        visible: Vec::new(),
+        list: ListState::default(),
+        status: String::new(),
+        needs_clear: false,
+    };
+    app.refilter();
+
+    let mut terminal = ratatui::init();
+    let result = app.event_loop(&mut terminal).await;
+    ratatui::restore();
+    result
+}
+
+impl App<'_> {
+    async fn event_loop(&mut self, terminal: &mut DefaultTerminal) -> Result<(), Box<dyn Error>> {
+        loop {
+            terminal.draw(|frame| self.draw(frame))?;
+            let Event::Key(key) = event::read()? else {
+                continue;
+            };
+            if key.kind != KeyEventKind::Press {
+                continue;
+            }
+            if !self.handle_key(key).await {
+                return Ok(());
+            }
+            if std::mem::take(&mut self.needs_clear) {
+                terminal.clear()?;
+            }
+        }
+    }
+
+    fn selected(&self) -> Option<&Post> {
+        let index = *self.visible.get(self.list.selected()?)?;
+        self.manifest.posts.get(index)
+    }
+
+    /// Recomputes the visible posts for the current query: newest first
+    /// without a query, best fuzzy match on title, id and URL first with one.
+    fn refilter(&mut self) {
+        let posts = &self.manifest.posts;
+        if self.query.is_empty() {
+            self.visible = (0..posts.len()).collect();
+            self.visible
+                .sort_by_key(|&i| std::cmp::Reverse(posts[i].published));
+        } else {
+            let matcher = SkimMatcherV2::default();
+            let mut scored: Vec<(i64, usize)> = posts
+                .iter()
+                .enumerate()
+                .filter_map(|(i, post)| {
+                    let haystack = format!("{} {} {}", post.title, post.id, post.url);
+                    matcher.fuzzy_match(&haystack, &self.query).map(|s| (s, i))
+                })
+                .collect();
+            scored.sort_by_key(|&(score, _)| std::cmp::Reverse(score));
+            self.visible = scored.into_iter().map(|(_, i)| i).collect();
+        }
+        self.list.select((!self.visible.is_empty()).then_some(0));
+    }
+
+    /// Handles a key press. Returns `false` to quit.
+    async fn handle_key(&mut self, key: KeyEvent) -> bool {
+        if key.modifiers.contains(KeyModifiers::CONTROL) && key.code == KeyCode::Char('c') {
+            return false;
+        }
+        if matches!(self.mode, Mode::Browse) {
+            self.status.clear();
+        }
+        match &mut self.mode {
+            Mode::Search => match key.code {
+                KeyCode::Enter => self.mode = Mode::Browse,
+                KeyCode::Esc => {
+                    self.query.clear();
+                    self.mode = Mode::Browse;
+                    self.refilter();
+                }
+                KeyCode::Backspace => {
+                    self.query.pop();
+                    self.refilter();
+                }
+                KeyCode::Up => self.list.select_previous(),
+                KeyCode::Down => self.list.select_next(),
+                KeyCode::Char(c) => {
+                    self.query.push(c);
+                    self.refilter();
+                }
+                _ => {}
+            },
+            Mode::ConfirmDelete => {
+                self.mode = Mode::Browse;
+                if key.code == KeyCode::Char('y') {
+                    self.delete_selected().await;
+                } else {
+                    self.status = "Not deleted".to_string();
+                }
+            }
+            Mode::Publish(path) => match key.code {
+                KeyCode::Enter => {
+                    let path = PathBuf::from(std::mem::take(path));
+                    self.mode = Mode::Browse;
+                    self.publish(&path).await;
+                }
+                KeyCode::Esc => self.mode = Mode::Browse,
+                KeyCode::Backspace => {
+                    path.pop();
+                }
+                KeyCode::Char(c) => path.push(c),
+                _ => {}
+            },
+            Mode::Browse => match key.code {
+                KeyCode::Char('q') | KeyCode::Esc => return false,
+                KeyCode::Up | KeyCode::Char('k') => self.list.select_previous(),
+                KeyCode::Down | KeyCode::Char('j') => self.list.select_next(),
+                KeyCode::Char('/') => self.mode = Mode::Search,
+                KeyCode::Char('p') => self.mode = Mode::Publish(String::new()),
+                KeyCode::Char('d') if self.selected().is_some() => {
+                    self.mode = Mode::ConfirmDelete;
+                }
+                KeyCode::Char('u') => self.update_selected().await,
+                KeyCode::Enter | KeyCode::Char('o') => self.open_selected(),
+                KeyCode::Char('y') => self.copy_selected(),
+                _ => {}
+            },
+        }
+        true
+    }
+
+    fn open_selected(&mut self) {
+        let Some(url) = self.selected().map(|post| post.url.clone()) else {
+            return;
+        };
+        self.status = match open::that_detached(&url) {
+            Ok(()) => format!("Opened {}", url),
+            Err(e) => format!("Could not open {}: {}", url, e),
+        };
+    }
+
+    /// Copies the URL with an OSC 52 escape sequence, which most terminals
+    /// support, also over SSH.
+    fn copy_selected(&mut self) {
+        let Some(url) = self.selected().map(|post| post.url.clone()) else {
+            return;
+        };
+        let encoded = base64::engine::general_purpose::STANDARD.encode(&url);
+        let mut stdout = std::io::stdout();
+        let result = write!(stdout, "\x1b]52;c;{}\x07", encoded).and_then(|()| stdout.flush());
+        self.status = match result {
+            Ok(()) => format!("Copied {}", url),
+            Err(e) => format!("Could not copy: {}", e),
+        };
+    }
+
+    async fn delete_selected(&mut self) {
+        self.needs_clear = true;
+        let Some(id) = self.selected().map(|post| post.id.clone()) else {
+            return;
+        };
+        let result = async {
+            let post = self
+                .site
+                .delete(&id, &mut self.manifest)
+                .await?
+                .ok_or("The post is no longer in the manifest")?;
+            self.site
+                .finish_delete(&post, &mut self.manifest, &self.manifest_path)
+                .await?;
+            Ok::<_, Box<dyn Error>>(post)
+        }
+        .await;
+        self.status = match result {
+            Ok(post) => format!(
+                "Moved {} to the trash (klistra undelete {})",
+                post.title, id
+            ),
+            Err(e) => format!("Delete failed: {}", e),
+        };
+        self.refilter();
+    }
+
+    async fn update_selected(&mut self) {
+        self.needs_clear = true;
+        let Some(id) = self.selected().map(|post| post.id.clone()) else {
+            return;
+        };
+        let result = async {
+            let published = self.site.update(&id, &mut self.manifest).await?;
+            self.site
+                .finish_publish(
+                    &published,
+                    &mut self.manifest,
+                    &self.manifest_path,
+                    Announce::No,
+                )
+                .await?;
+            Ok::<_, Box<dyn Error>>(published.post)
+        }
+        .await;
+        self.status = match result {
+            Ok(post) => format!("Updated {}", post.url),
+            Err(e) => format!("Update failed: {}", e),
+        };
+    }
+
+    async fn publish(&mut self, path: &Path) {
+        self.needs_clear = true;
+        let result = async {
+            let published = self.site.publish(path, &mut self.manifest).await?;
+            self.site
+                .finish_publish(
+                    &published,
+                    &mut self.manifest,
+                    &self.manifest_path,
+                    Announce::Yes,
+                )
+                .await?;
+            Ok::<_, Box<dyn Error>>(published.post)
+        }
+        .await;
+        self.status = match result {
+            Ok(post) => format!("Published {}", post.url),
+            Err(e) => format!("Publish failed: {}", e),
+        };
+        self.query.clear();
+        self.refilter();
+    }
+
+    fn draw(&mut self, frame: &mut Frame) {
+        let [search_area, main_area, status_area] = Layout::vertical([
+            Constraint::Length(3),
+            Constraint::Min(0),
+            Constraint::Length(1),
+        ])
+        .areas(frame.area());
+        let [list_area, details_area] =
+            Layout::horizontal([Constraint::Percentage(55), Constraint::Percentage(45)])
+                .areas(main_area);
+
+        let (input_title, input) = match &self.mode {
+            Mode::Publish(path) => ("Publish file", path.as_str()),
+            _ => ("Search", self.query.as_str()),
+        };
+        let mut input_block = Block::bordered().title(input_title);
+        if matches!(self.mode, Mode::Search | Mode::Publish(_)) {
+            input_block = input_block.border_style(Style::new().yellow());
+        }
+        frame.render_widget(Paragraph::new(input).block(input_block), search_area);
+
+        let items: Vec<ListItem> = self
+            .visible
+            .iter()
+            .map(|&i| {
+                let post = &self.manifest.posts[i];
+                ListItem::new(Line::from(vec![
+                    Span::raw(post.published.format("%Y-%m-%d  ").to_string()).dark_gray(),
+                    Span::raw(post.title.as_str()),
+                ]))
+            })
+            .collect();
+        let list = List::new(items)
+            .block(Block::bordered().title(format!("Posts ({})", self.visible.len())))
+            .highlight_style(Style::new().reversed())
+            .highlight_symbol("> ");
+        frame.render_stateful_widget(list, list_area, &mut self.list);
+
+        let details = self.selected().map(details).unwrap_or_default();
+        frame.render_widget(
+            Paragraph::new(details)
+                .block(Block::bordered().title("Details"))
+                .wrap(Wrap { trim: false }),
+            details_area,
+        );
+
+        let status = match self.mode {
+            Mode::ConfirmDelete => "Move this post to the trash? (y/n)".to_string(),
+            Mode::Search => "Type to filter, Enter to keep, Esc to clear".to_string(),
+            Mode::Publish(_) => {
+                "Path of the file to publish, Enter to publish, Esc to cancel".to_string()
+            }
+            Mode::Browse if !self.status.is_empty() => self.status.clone(),
+            Mode::Browse => {
+                "/ search  o open  y copy URL  u update  d delete  p publish  q quit".to_string()
+            }
+        };
+        frame.render_widget(Paragraph::new(status).dark_gray(), status_area);
+    }
+}
+
+/// The metadata shown for the selected post.
+fn details(post: &Post) -> Text<'static> {
+    let field = |name: &str, value: String| {
+        Line::from(vec![
+            Span::raw(format!("{:<12}", name)).bold(),
+            Span::raw(value),
+        ])
+    };
+    let mut lines = vec![
+        Line::from(post.title.clone()).bold(),
+        Line::default(),
+        field("URL", post.url.clone()),
+        field("Id", post.id.clone()),
+        field(
+            "Published",
+            post.published
+                .with_timezone(&chrono::Local)
+                .format("%Y-%m-%d %H:%M")
+                .to_string(),
+        ),
+    ];
+    if let Some(source) = &post.source {
+        lines.push(field("Source", source.display().to_string()));
+    }
+    if !post.languages.is_empty() {
+        lines.push(field("Languages", post.languages.join(", ")));
+    }
+    if let Some(expires) = post.expires {
+        lines.push(field(
+            "Expires",
+            expires
+                .with_timezone(&chrono::Local)
+                .format("%Y-%m-%d %H:%M")
+                .to_string(),
+        ));
+    }
+    if !post.labels.is_empty() {
+        let labels: Vec<String> = post
+            .labels
+            .iter()
+            .map(|(key, value)| format!("{}={}", key, value))
+            .collect();
+        lines.push(field("Labels", labels.join(", ")));
+    }
+    if post.raw {
+        lines.push(field("Kind", "raw upload".to_string()));
+    }
+    if let Some(description) = &post.description {
+        lines.push(Line::default());
+        lines.push(Line::from(description.clone()));
+    }
+    Text::from(lines)
+}
@@ -0,0 +1,863 @@
+use crate::b2::ScopedKey;
+use crate::config::{Provider, S3Config, TlsConfig};
+use crate::delegate::{SignRequest, Signed, SIGNED_FOR};
+use crate::frontmatter::{validate_labels, Labels};
+use crate::http;
+use crate::publisher::{attachment_disposition, Precondition, Publisher};
+use crate::throttle::{self, Pacing, Rate};
+use async_trait::async_trait;
+use aws_sdk_s3::config::retry::{ClassifyRetry, RetryAction, RetryConfig};
+use aws_sdk_s3::config::{
+    BehaviorVersion, Credentials, Region, RequestChecksumCalculation, ResponseChecksumValidation,
+};
+use aws_sdk_s3::presigning::PresigningConfig;
+use aws_sdk_s3::primitives::ByteStream;
+use aws_sdk_s3::types::{
+    BucketCannedAcl, BucketLifecycleConfiguration, CompletedMultipartUpload, CompletedPart,
+    CorsConfiguration, CorsRule, ExpirationStatus, LifecycleExpiration, LifecycleRule,
+    LifecycleRuleFilter,
+};
+use aws_sdk_s3::Client;
+use aws_smithy_runtime_api::client::interceptors::context::InterceptorContext;
+use aws_smithy_runtime_api::client::retries::classifiers::RetryClassifierPriority;
+use aws_smithy_types::retry::ErrorKind;
+use base64::Engine;
+use md5::{Digest, Md5};
+use std::{error::Error, sync::Arc, time::Duration};
+
+/// The configured bucket, with object keys relative to the configured prefix.
+pub struct Bucket {
+    client: Client,
+    bucket: String,
+    prefix: String,
+    domain: String,
+    provider: Provider,
+    /// Upload speed limit, if any.
+    limit_rate: Option<Rate>,
+    /// Slows uploads down while the bucket is throttling them.
+    pacing: Arc<Pacing>,
+    /// Object tags for every upload.
+    labels: Labels,
+}
+
+impl Bucket {
+    pub fn new(s3_conf: &S3Config, tls: &TlsConfig) -> Result<Self, Box<dyn Error>> {
+        let (endpoint, region) = match s3_conf.provider {
+            Provider::Backblaze => {
+                if s3_conf.region.is_empty() {
+                    return Err("s3.region is required for Backblaze".into());
+                }
+                let endpoint = s3_conf
+                    .endpoint
+                    .clone()
+                    .unwrap_or_else(|| format!("https://s3.{}.backblazeb2.com", s3_conf.region));
+                (endpoint, s3_conf.region.clone())
+            }
+            Provider::Minio => {
+                let endpoint = s3_conf
+                    .endpoint
+                    .clone()
+                    .ok_or("s3.endpoint is required for provider = \"minio\"")?;
+                // MinIO ignores the region unless one was configured on the server.
+                let region = if s3_conf.region.is_empty() {
+                    "us-east-1".to_string()
+                } else {
+                    s3_conf.region.clone()
+                };
+                (endpoint, region)
+            }
+        };
+        let endpoint = endpoint.trim_end_matches('/');
+        match endpoint.split_once("://") {
+            Some(("http" | "https", host)) if !host.is_empty() => {}
+            _ => {
+                return Err(format!(
+                    "s3.endpoint must be an http:// or https:// URL, got '{}'",
+                    endpoint
+                )
+                .into())
+            }
+        }
+
+        // Path-style requests (`host/bucket/key`) work everywhere, while
+        // virtual-hosted buckets need wildcard DNS. Only send checksums when
+        // required: B2 and older MinIO releases reject the SDK's default trailers.
+        // Uploads carry a Content-MD5 header instead and are checked afterwards.
+        let pacing = Arc::new(Pacing::default());
+        let mut aws_config = aws_sdk_s3::Config::builder()
+            .region(Region::new(region))
+            .endpoint_url(endpoint)
+            .force_path_style(true)
+            .behavior_version(BehaviorVersion::latest())
+            .use_fips(false)
+            .use_dual_stack(false)
+            .request_checksum_calculation(RequestChecksumCalculation::WhenRequired)
+            .response_checksum_validation(ResponseChecksumValidation::WhenRequired)
+            // Not the SDK's adaptive mode: its rate limiter overrides the
+            // bucket's Retry-After with its own, much longer, estimates.
+            // Pacing spaces out concurrent uploads instead.
+            .retry_config(
+                RetryConfig::standard()
+                    .with_max_attempts(s3_conf.max_attempts.max(1))
+                    .with_max_backoff(Duration::from_secs(60)),
+            )
+            .retry_classifier(Throttled(pacing.clone()));
+        if tls.is_custom() {
+            aws_config = aws_config.http_client(http::s3_client(tls)?);
+        }
+        aws_config = if s3_conf.scoped_key {
+            if s3_conf.provider != Provider::Backblaze {
+                return Err("s3.scoped_key only works with Backblaze".into());
+            }
+            aws_config.credentials_provider(ScopedKey::new(s3_conf, tls)?)
+        } else {
+            aws_config.credentials_provider(Credentials::new(
+                s3_conf.access_key_id.clone(),
+                s3_conf.secret_access_key.clone(),
+                s3_conf.session_token.clone(),
+                None,
+                "backblaze-credentials",
+            ))
+        };
+
+        Ok(Self {
+            client: Client::from_conf(aws_config.build()),
+            bucket: s3_conf.bucket.clone(),
+            prefix: s3_conf.prefix.trim_end_matches('/').to_string(),
+            domain: s3_conf.domain.trim_end_matches('/').to_string(),
+            provider: s3_conf.provider,
+            limit_rate: None,
+            pacing,
+            labels: Labels::new(),
+        })
+    }
+
+    /// Uses `bucket` and `prefix` instead of the configured ones, with the
+    /// same credentials, e.g. to read the logs delivered to another bucket.
+    pub fn with_location(mut self, bucket: &str, prefix: &str) -> Self {
+        self.bucket = bucket.to_string();
+        self.prefix = prefix.trim_end_matches('/').to_string();
+        self
+    }
+
+    /// The object key for `path`, under the prefix if there is one.
+    fn key(&self, path: &str) -> String {
+        if self.prefix.is_empty() {
+            path.to_string()
+        } else {
+            format!("{}/{}", self.prefix, path)
+        }
+    }
+
+    /// Throttles every upload to `rate`.
+    pub fn with_limit_rate(mut self, rate: Option<Rate>) -> Self {
+        self.limit_rate = rate;
+        self
+    }
+
+    /// Tags every upload with `labels`, in addition to per-post labels.
+    pub fn with_labels(mut self, labels: Labels) -> Self {
+        self.labels = labels;
+        self
+    }
+
+    /// The `x-amz-tagging` value for an upload with `labels`, or `None` if
+    /// there are no tags at all.
+    fn tagging(&self, labels: &Labels) -> Result<Option<String>, Box<dyn Error>> {
+        let mut all = self.labels.clone();
+        all.extend(labels.iter().map(|(k, v)| (k.clone(), v.clone())));
+        if all.is_empty() {
+            return Ok(None);
+        }
+        validate_labels(&all)?;
+        Ok(Some(serde_urlencoded::to_string(&all)?))
+    }
+
+    /// Checks what the bucket stored under `path` against the `md5` and
+    /// `size` of the body just uploaded. The SDK's own checksums are off (see
+    /// [`Bucket::new`]); `Content-MD5` makes the bucket reject a body
+    /// corrupted on the way, and this catches a server that ignores the
+    /// header or stores something else anyway. B2 and MinIO return the MD5 of
+    /// a single-part upload as its ETag; for anything else, such as an
+    /// encrypted object, the size from a HEAD request is compared instead.
+    async fn verify(
+        &self,
+        path: &str,
+        etag: Option<&str>,
+        md5: &str,
+        size: usize,
+    ) -> Result<(), Box<dyn Error>> {
+        match etag.map(|etag| etag.trim_matches('"')) {
+            Some(etag) if is_md5(etag) => {
+                if !etag.eq_ignore_ascii_case(md5) {
+                    return Err(mismatch(path, md5, etag));
+                }
+            }
+            _ => {
+                let head = self
+                    .client
+                    .head_object()
+                    .bucket(&self.bucket)
+                    .key(self.key(path))
+                    .send()
+                    .await?;
+                let stored = head.content_length().unwrap_or(-1);
+                if stored != size as i64 {
+                    return Err(format!(
+                        "Upload of {} is corrupt: sent {} bytes, the bucket has {}",
+                        path, size, stored
+                    )
+                    .into());
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Uploads `body` in one request, as a download if `disposition` is set,
+    /// and checks what the bucket stored.
+    async fn put_object(
+        &self,
+        path: &str,
+        body: Vec<u8>,
+        content_type: &str,
+        disposition: Option<String>,
+        labels: &Labels,
+    ) -> Result<(), Box<dyn Error>> {
+        let tagging = self.tagging(labels)?;
+        let digest = Md5::digest(&body);
+        let size = body.len();
+        self.pacing.wait().await;
+        let output = self
+            .client
+            .put_object()
+            .bucket(&self.bucket)
+            .key(self.key(path))
+            .content_md5(content_md5(&digest))
+            .body(self.body(body))
+            .content_type(content_type)
+            .set_content_disposition(disposition)
+            .set_tagging(tagging)
+            .send()
+            .await?;
+        self.verify(path, output.e_tag(), &hex::encode(digest), size)
+            .await
+    }
+
+    fn body(&self, data: Vec<u8>) -> ByteStream {
+        match self.limit_rate {
+            Some(rate) => throttle::throttled(data, rate),
+            None => ByteStream::from(data),
+        }
+    }
+
+    /// Full keys of every object whose key starts with `key_prefix`.
+    async fn list_keys(&self, key_prefix: &str) -> Result<Vec<String>, Box<dyn Error>> {
+        let mut keys = Vec::new();
+        let mut pages = self
+            .client
+            .list_objects_v2()
+            .bucket(&self.bucket)
+            .prefix(key_prefix)
+            .into_paginator()
+            .send();
+        while let Some(page) = pages.next().await {
+            keys.extend(
+                page?
+                    .contents()
+                    .iter()
+                    .filter_map(|o| o.key().map(str::to_string)),
+            );
+        }
+        Ok(keys)
+    }
+
+    /// Presigns `request` for a client without credentials (see
+    /// [`crate::delegate`]), with this bucket's labels added to uploads.
+    pub async fn sign(&self, request: &SignRequest) -> Result<Signed, Box<dyn Error>> {
+        let signing = PresigningConfig::expires_in(SIGNED_FOR)?;
+        let presigned = match request {
+            SignRequest::Put {
+                path,
+                content_type,
+                content_md5,
+                file_name,
+                labels,
+                location,
+                if_none_match,
+                if_match,
+            } => {
+                let tagging = self.tagging(labels)?;
+                self.client
+                    .put_object()
+                    .bucket(&self.bucket)
+                    .key(self.key(path))
+                    .content_md5(content_md5)
+                    .content_type(content_type)
+                    .set_content_disposition(file_name.as_deref().map(attachment_disposition))
+                    .set_website_redirect_location(location.clone())
+                    .set_if_none_match(if_none_match.clone())
+                    .set_if_match(if_match.clone())
+                    .set_tagging(tagging)
+                    .presigned(signing)
+                    .await?
+            }
+            SignRequest::Get { path, expires } => {
+                let expires = expires.map_or(SIGNED_FOR, Duration::from_secs);
+                self.client
+                    .get_object()
+                    .bucket(&self.bucket)
+                    .key(self.key(path))
+                    .presigned(PresigningConfig::expires_in(expires)?)
+                    .await?
+            }
+            SignRequest::Delete { path } => {
+                self.client
+                    .delete_object()
+                    .bucket(&self.bucket)
+                    .key(self.key(path))
+                    .presigned(signing)
+                    .await?
+            }
+            SignRequest::UploadPart {
+                path,
+                upload_id,
+                number,
+                content_md5,
+            } => {
+                self.client
+                    .upload_part()
+                    .bucket(&self.bucket)
+                    .key(self.key(path))
+                    .upload_id(upload_id)
+                    .part_number(*number)
+                    .content_md5(content_md5)
+                    .presigned(signing)
+                    .await?
+            }
+        };
+        Ok(Signed {
+            method: presigned.method().to_string(),
+            url: presigned.uri().to_string(),
+            // The client sets its own length for the body it sends.
+            headers: presigned
+                .headers()
+                .filter(|(name, _)| !name.eq_ignore_ascii_case("content-length"))
+                .map(|(name, value)| (name.to_string(), value.to_string()))
+                .collect(),
+        })
+    }
+
+    /// The bucket's name, as configured.
+    pub fn name(&self) -> &str {
+        &self.bucket
+    }
+
+    /// Whether the bucket exists and the credentials can reach it.
+    pub async fn exists(&self) -> Result<bool, Box<dyn Error>> {
+        match self.client.head_bucket().bucket(&self.bucket).send().await {
+            Ok(_) => Ok(true),
+            Err(e) => match e.into_service_error() {
+                e if e.is_not_found() => Ok(false),
+                e => Err(e.into()),
+            },
+        }
+    }
+
+    /// Creates the bucket. On B2 it's created public, since B2 has no bucket policies.
+    pub async fn create(&self) -> Result<(), Box<dyn Error>> {
+        let mut request = self.client.create_bucket().bucket(&self.bucket);
+        if self.provider == Provider::Backblaze {
+            request = request.acl(BucketCannedAcl::PublicRead);
+        }
+        request.send().await?;
+        Ok(())
+    }
+
+    /// Lets anyone download objects under the prefix: a read-only bucket
+    /// policy on MinIO that leaves out `trash/`, the `public-read` ACL (the
+    /// whole bucket, trash included) on B2.
+    pub async fn allow_public_read(&self) -> Result<(), Box<dyn Error>> {
+        match self.provider {
+            Provider::Backblaze => {
+                self.client
+                    .put_bucket_acl()
+                    .bucket(&self.bucket)
+                    .acl(BucketCannedAcl::PublicRead)
+                    .send()
+                    .await?;
+            }
+            Provider::Minio => {
+                // Deleted posts are only kept for restoring, not for reading.
+                let policy = serde_json::json!({
+                    "Version": "2012-10-17",
+                    "Statement": [{
+                        "Effect": "Allow",
+                        "Principal": {"AWS": ["*"]},
+                        "Action": ["s3:GetObject"],
+                        "Resource": [format!("arn:aws:s3:::{}/{}", self.bucket, self.key("*"))],
+                    }, {
+                        "Effect": "Deny",
+                        "Principal": {"AWS": ["*"]},
+                        "Action": ["s3:GetObject"],
+                        "Resource": [format!("arn:aws:s3:::{}/{}", self.bucket, self.key("trash/*"))],
+                    }],
+                });
+                self.client
+                    .put_bucket_policy()
+                    .bucket(&self.bucket)
+                    .policy(policy.to_string())
+                    .send()
+                    .await?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Allows `GET` and `HEAD` from any origin, so fonts and scripts load
+    /// when pages are embedded or served from another domain.
+    pub async fn allow_cors(&self) -> Result<(), Box<dyn Error>> {
+        let rule = CorsRule::builder()
+            .allowed_origins("*")
+            .allowed_methods("GET")
+            .allowed_methods("HEAD")
+            .allowed_headers("*")
+            .max_age_seconds(86400)
+            .build()?;
+        self.client
+            .put_bucket_cors()
+            .bucket(&self.bucket)
+            .cors_configuration(CorsConfiguration::builder().cors_rules(rule).build()?)
+            .send()
+            .await?;
+        Ok(())
+    }
+
+    /// Deletes objects under `path` (relative to the prefix) `days` after
+    /// they're uploaded. Other lifecycle rules on the bucket are kept; an
+    /// earlier klistra rule for the same path is replaced.
+    pub async fn expire_after(&self, path: &str, days: i32) -> Result<(), Box<dyn Error>> {
+        let id = format!("klistra-expire-{}", path.trim_end_matches('/'));
+        let rule = LifecycleRule::builder()
+            .id(&id)
+            .filter(
+                LifecycleRuleFilter::builder()
+                    .prefix(self.key(path))
+                    .build(),
+            )
+            .status(ExpirationStatus::Enabled)
+            .expiration(LifecycleExpiration::builder().days(days).build())
+            .build()?;
+        let existing = match self
+            .client
+            .get_bucket_lifecycle_configuration()
+            .bucket(&self.bucket)
+            .send()
+            .await
+        {
+            Ok(output) => output.rules.unwrap_or_default(),
+            // A bucket without rules answers 404 NoSuchLifecycleConfiguration.
+            Err(e) if e.raw_response().map(|r| r.status().as_u16()) == Some(404) => Vec::new(),
+            Err(e) => return Err(e.into()),
+        };
+        let mut rules: Vec<LifecycleRule> = existing
+            .into_iter()
+            .filter(|existing| existing.id() != Some(id.as_str()))
+            .collect();
+        rules.push(rule);
+        self.client
+            .put_bucket_lifecycle_configuration()
+            .bucket(&self.bucket)
+            .lifecycle_configuration(
+                BucketLifecycleConfiguration::builder()
+                    .set_rules(Some(rules))
+                    .build()?,
+            )
+            .send()
+            .await?;
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl Publisher for Bucket {
+    fn host(&self) -> &str {
+        self.domain
+            .split_once("://")
+            .map_or(self.domain.as_str(), |(_, host)| host)
+    }
+
+    fn public_url(&self, path: &str) -> String {
+        format!("{}/{}", self.domain, path)
+    }
+
+    async fn list(&self, path: &str) -> Result<Vec<String>, Box<dyn Error>> {
+        let key = self.key(path);
+        let start = key.len() - path.len();
+        Ok(self
+            .list_keys(&key)
+            .await?
+            .into_iter()
+            .map(|key| key[start..].to_string())
+            .collect())
+    }
+
+    async fn fetch(&self, path: &str) -> Result<Option<Vec<u8>>, Box<dyn Error>> {
+        let output = match self
+            .client
+            .get_object()
+            .bucket(&self.bucket)
+            .key(self.key(path))
+            .send()
+            .await
+        {
+            Ok(output) => output,
+            Err(e) => match e.into_service_error() {
+                e if e.is_no_such_key() => return Ok(None),
+                e => return Err(e.into()),
+            },
+        };
+        Ok(Some(output.body.collect().await?.into_bytes().to_vec()))
+    }
+
+    async fn fetch_tagged(&self, path: &str) -> Result<Option<(Vec<u8>, String)>, Box<dyn Error>> {
+        let output = match self
+            .client
+            .get_object()
+            .bucket(&self.bucket)
+            .key(self.key(path))
+            .send()
+            .await
+        {
+            Ok(output) => output,
+            Err(e) => match e.into_service_error() {
+                e if e.is_no_such_key() => return Ok(None),
+                e => return Err(e.into()),
+            },
+        };
+        let etag = output.e_tag().unwrap_or_default().to_string();
+        let body = output.body.collect().await?.into_bytes().to_vec();
+        Ok(Some((body, etag)))
+    }
+
+    async fn put_if(
+        &self,
+        path: &str,
+        body: Vec<u8>,
+        content_type: &str,
+        precondition: &Precondition,
+    ) -> Result<bool, Box<dyn Error>> {
+        let tagging = self.tagging(&Labels::new())?;
+        let digest = Md5::digest(&body);
+        let size = body.len();
+        self.pacing.wait().await;
+        let request = self
+            .client
+            .put_object()
+            .bucket(&self.bucket)
+            .key(self.key(path))
+            .content_md5(content_md5(&digest))
+            .body(ByteStream::from(body))
+            .content_type(content_type)
+            .set_tagging(tagging);
+        let request = match precondition {
+            Precondition::Absent => request.if_none_match("*"),
+            Precondition::Matches(etag) => request.if_match(etag),
+        };
+        let output = match request.send().await {
+            Ok(output) => output,
+            // 412 when the precondition fails, 409 when another conditional
+            // write to the key is in flight.
+            Err(e)
+                if matches!(
+                    e.raw_response().map(|r| r.status().as_u16()),
+                    Some(409 | 412)
+                ) =>
+            {
+                return Ok(false)
+            }
+            Err(e) => return Err(e.into()),
+        };
+        self.verify(path, output.e_tag(), &hex::encode(digest), size)
+            .await?;
+        Ok(true)
+    }
+
+    async fn presign(&self, path: &str, expires: Duration) -> Result<String, Box<dyn Error>> {
+        let request = self
+            .client
+            .get_object()
+            .bucket(&self.bucket)
+            .key(self.key(path))
+            .presigned(PresigningConfig::expires_in(expires)?)
+            .await?;
+        Ok(request.uri().to_string())
+    }
+
+    async fn delete_prefix(&self, path: &str) -> Result<usize, Box<dyn Error>> {
+        let keys = self.list_keys(&self.key(path)).await?;
+
+        // One request per object: B2 rejects the checksums the SDK adds to batch deletes.
+        for key in &keys {
+            self.client
+                .delete_object()
+                .bucket(&self.bucket)
+                .key(key)
+                .send()
+                .await?;
+        }
+        Ok(keys.len())
+    }
+
+    async fn move_prefix(&self, from: &str, to: &str) -> Result<usize, Box<dyn Error>> {
+        let from_key = self.key(from);
+        let to_key = self.key(to);
+        let keys = self.list_keys(&from_key).await?;
+        for key in &keys {
+            let destination = format!("{}{}", to_key, &key[from_key.len()..]);
+            self.client
+                .copy_object()
+                .bucket(&self.bucket)
+                .copy_source(copy_source(&self.bucket, key))
+                .key(destination)
+                .send()
+                .await?;
+            self.client
+                .delete_object()
+                .bucket(&self.bucket)
+                .key(key)
+                .send()
+                .await?;
+        }
+        Ok(keys.len())
+    }
+
+    async fn create_multipart(
+        &self,
+        path: &str,
+        content_type: &str,
+        labels: &Labels,
+    ) -> Result<String, Box<dyn Error>> {
+        let tagging = self.tagging(labels)?;
+        let output = self
+            .client
+            .create_multipart_upload()
+            .bucket(&self.bucket)
+            .key(self.key(path))
+            .content_type(content_type)
+            .set_tagging(tagging)
+            .send()
+            .await?;
+        Ok(output
+            .upload_id()
+            .ok_or("No upload id in response")?
+            .to_string())
+    }
+
+    async fn upload_part(
+        &self,
+        path: &str,
+        upload_id: &str,
+        number: i32,
+        body: Vec<u8>,
+    ) -> Result<String, Box<dyn Error>> {
+        let digest = Md5::digest(&body);
+        self.pacing.wait().await;
+        let output = self
+            .client
+            .upload_part()
+            .bucket(&self.bucket)
+            .key(self.key(path))
+            .upload_id(upload_id)
+            .part_number(number)
+            .content_md5(content_md5(&digest))
+            .body(self.body(body))
+            .send()
+            .await?;
+        let etag = output.e_tag().ok_or("No ETag in response")?;
+        let md5 = hex::encode(digest);
+        let bare = etag.trim_matches('"');
+        if is_md5(bare) && !bare.eq_ignore_ascii_case(&md5) {
+            return Err(mismatch(&format!("{} (part {})", path, number), &md5, bare));
+        }
+        Ok(etag.to_string())
+    }
+
+    async fn abort_multipart(&self, path: &str, upload_id: &str) -> Result<(), Box<dyn Error>> {
+        self.client
+            .abort_multipart_upload()
+            .bucket(&self.bucket)
+            .key(self.key(path))
+            .upload_id(upload_id)
+            .send()
+            .await?;
+        Ok(())
+    }
+
+    async fn complete_multipart(
+        &self,
+        path: &str,
+        upload_id: &str,
+        parts: &[(i32, String)],
+    ) -> Result<(), Box<dyn Error>> {
+        let parts = parts
+            .iter()
+            .map(|(number, etag)| {
+                CompletedPart::builder()
+                    .part_number(*number)
+                    .e_tag(etag)
+                    .build()
+            })
+            .collect();
+        self.client
+            .complete_multipart_upload()
+            .bucket(&self.bucket)
+            .key(self.key(path))
+            .upload_id(upload_id)
+            .multipart_upload(
+                CompletedMultipartUpload::builder()
+                    .set_parts(Some(parts))
+                    .build(),
+            )
+            .send()
+            .await?;
+        Ok(())
+    }
+
+    async fn put(
+        &self,
+        path: &str,
+        body: Vec<u8>,
+        content_type: &str,
+        labels: &Labels,
+    ) -> Result<(), Box<dyn Error>> {
+        self.put_object(path, body, content_type, None, labels)
+            .await
+    }
+
+    async fn put_download(
+        &self,
+        path: &str,
+        body: Vec<u8>,
+        content_type: &str,
+        file_name: &str,
+        labels: &Labels,
+    ) -> Result<(), Box<dyn Error>> {
+        let disposition = attachment_disposition(file_name);
+        self.put_object(path, body, content_type, Some(disposition), labels)
+            .await
+    }
+
+    async fn put_redirect(&self, path: &str, location: &str) -> Result<(), Box<dyn Error>> {
+        let tagging = self.tagging(&Labels::new())?;
+        let digest = Md5::digest(b"");
+        let output = self
+            .client
+            .put_object()
+            .bucket(&self.bucket)
+            .key(self.key(path))
+            .content_md5(content_md5(&digest))
+            .body(ByteStream::from_static(b""))
+            .content_type("text/html")
+            .website_redirect_location(location)
+            .set_tagging(tagging)
+            .send()
+            .await?;
+        self.verify(path, output.e_tag(), &hex::encode(digest), 0)
+            .await
+    }
+
+    async fn delete(&self, path: &str) -> Result<(), Box<dyn Error>> {
+        self.client
+            .delete_object()
+            .bucket(&self.bucket)
+            .key(self.key(path))
+            .send()
+            .await?;
+        Ok(())
+    }
+}
+
+/// The `Content-MD5` header for a body with MD5 `digest`. The bucket rejects
+/// the upload if what arrives doesn't match.
+fn content_md5(digest: &[u8]) -> String {
+    base64::engine::general_purpose::STANDARD.encode(digest)
+}
+
+/// Whether `etag` (without quotes) is a plain hex MD5.
+fn is_md5(etag: &str) -> bool {
+    etag.len() == 32 && etag.bytes().all(|b| b.is_ascii_hexdigit())
+}
+
+fn mismatch(path: &str, sent: &str, stored: &str) -> Box<dyn Error> {
+    format!(
+        "Checksum mismatch after uploading {}: sent MD5 {}, the bucket has {}. Publish again to replace it.",
+        path, sent, stored
+    )
+    .into()
+}
+
+/// The `x-amz-copy-source` value for `key`: `bucket/key`, percent-encoded
+/// except for `/`.
+fn copy_source(bucket: &str, key: &str) -> String {
+    let mut encoded = format!("{}/", bucket);
+    for byte in key.bytes() {
+        if byte.is_ascii_alphanumeric() || b"-_.~/".contains(&byte) {
+            encoded.push(byte as char);
+        } else {
+            encoded.push_str(&format!("%{:02X}", byte));
+        }
+    }
+    encoded
+}
+
+/// Treats 429 and 503 responses as throttling, waiting as long as their
+/// `Retry-After` asks, and tells the bucket's [`Pacing`] how requests fare.
+/// The SDK only knows AWS's own `x-amz-retry-after`, and doesn't retry a 429
+/// at all, which is what B2 sends when it's busy.
+#[derive(Debug)]
+struct Throttled(Arc<Pacing>);
+
+impl ClassifyRetry for Throttled {
+    fn classify_retry(&self, ctx: &InterceptorContext) -> RetryAction {
+        let Some(response) = ctx.response() else {
+            return RetryAction::NoActionIndicated;
+        };
+        let status = response.status();
+        if !matches!(status.as_u16(), 429 | 503) {
+            if status.is_success() {
+                self.0.succeeded();
+            }
+            return RetryAction::NoActionIndicated;
+        }
+        let delay = response.headers().get("retry-after").and_then(retry_after);
+        self.0.throttled(delay);
+        match delay {
+            Some(delay) => {
+                RetryAction::retryable_error_with_explicit_delay(ErrorKind::ThrottlingError, delay)
+            }
+            None => RetryAction::throttling_error(),
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        "Retry-After"
+    }
+
+    fn priority(&self) -> RetryClassifierPriority {
+        RetryClassifierPriority::run_after(RetryClassifierPriority::transient_error_classifier())
+    }
+}
+
+/// A `Retry-After` value: either seconds or an HTTP date.
+fn retry_after(value: &str) -> Option<Duration> {
+    let value = value.trim();
+    if let Ok(seconds) = value.parse::<u64>() {
+        return Some(Duration::from_secs(seconds));
+    }
+    let at = chrono::DateTime::parse_from_rfc2822(value).ok()?;
+    (at.with_timezone(&chrono::Utc) - chrono::Utc::now())
+        .to_std()
+        .ok()
+        .or(Some(Duration::ZERO))
+}
@@ -0,0 +1,87 @@
+use crate::manifest::content_hash;
+use std::{
+    io,
+    path::{Path, PathBuf},
+};
+
+/// One translation of a post, e.g. `post.sv.md`.
+#[derive(Debug)]
+pub struct Variant {
+    pub lang: String,
+    pub path: PathBuf,
+}
+
+/// Splits a file name like `post.sv.md` into its base stem (`post`) and
+/// language (`sv`). Files without a language suffix return `None`.
+pub fn split_lang(path: &Path) -> Option<(String, String)> {
+    let stem = path.file_stem()?.to_str()?;
+    let (base, lang) = stem.rsplit_once('.')?;
+    (!base.is_empty() && is_lang_tag(lang)).then(|| (base.to_string(), lang.to_string()))
+}
+
+/// The ISO 639-1 language codes, sorted.
+const LANGUAGES: [&str; 183] = [
+    "aa", "ab", "ae", "af", "ak", "am", "an", "ar", "as", "av", "ay", "az", "ba", "be", "bg", "bi",
+    "bm", "bn", "bo", "br", "bs", "ca", "ce", "ch", "co", "cr", "cs", "cu", "cv", "cy", "da", "de",
+    "dv", "dz", "ee", "el", "en", "eo", "es", "et", "eu", "fa", "ff", "fi", "fj", "fo", "fr", "fy",
+    "ga", "gd", "gl", "gn", "gu", "gv", "ha", "he", "hi", "ho", "hr", "ht", "hu", "hy", "hz", "ia",
+    "id", "ie", "ig", "ii", "ik", "io", "is", "it", "iu", "ja", "jv", "ka", "kg", "ki", "kj", "kk",
+    "kl", "km", "kn", "ko", "kr", "ks", "ku", "kv", "kw", "ky", "la", "lb", "lg", "li", "ln", "lo",
+    "lt", "lu", "lv", "mg", "mh", "mi", "mk", "ml", "mn", "mr", "ms", "mt", "my", "na", "nb", "nd",
+    "ne", "ng", "nl", "nn", "no", "nr", "nv", "ny", "oc", "oj", "om", "or", "os", "pa", "pi", "pl",
+    "ps", "pt", "qu", "rm", "rn", "ro", "ru", "rw", "sa", "sc", "sd", "se", "sg", "si", "sk", "sl",
+    "sm", "sn", "so", "sq", "sr", "ss", "st", "su", "sv", "sw", "ta", "te", "tg", "th", "ti", "tk",
+    "tl", "tn", "to", "tr", "ts", "tt", "tw", "ty", "ug", "uk", "ur", "uz", "ve", "vi", "vo", "wa",
+    "wo", "xh", "yi", "yo", "za", "zh", "zu",
+];
+
+/// Accepts simple BCP 47 tags whose language is an ISO 639-1 code: `sv`, `pt-BR`, `zh-Hant`.
+/// Other two-letter parts (`post.js.md`) and three-letter codes (`post.old.md`) aren't
+/// mistaken for translations.
+pub fn is_lang_tag(tag: &str) -> bool {
+    let mut parts = tag.split('-');
+    let primary = parts.next().unwrap_or("");
+    let primary_ok = LANGUAGES.binary_search(&primary).is_ok();
+    let rest_ok =
+        parts.all(|p| (2..=4).contains(&p.len()) && p.chars().all(|c| c.is_ascii_alphanumeric()));
+    primary_ok && rest_ok
+}
+
+/// Finds all translations that share `base` with the given file, sorted by language.
+pub async fn find_variants(path: &Path, base: &str) -> io::Result<Vec<Variant>> {
+    let dir = match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir.to_path_buf(),
+        _ => PathBuf::from("."),
+    };
+    let extension = path.extension();
+
+    let mut variants = Vec::new();
+    let mut entries = tokio::fs::read_dir(&dir).await?;
+    while let Some(entry) = entries.next_entry().await? {
+        let candidate = entry.path();
+        if candidate.extension() != extension {
+            continue;
+        }
+        if let Some((candidate_base, lang)) = split_lang(&candidate) {
+            if candidate_base == base {
+                variants.push(Variant {
+                    lang,
+                    path: candidate,
+                });
+            }
+        }
+    }
+    variants.sort_by(|a, b| a.lang.cmp(&b.lang));
+    Ok(variants)
+}
+
+/// [`content_hash`] over every translation's language and source, so
+/// editing, adding or removing one changes it.
+pub async fn source_hash(variants: &[Variant]) -> io::Result<String> {
+    let mut parts = Vec::with_capacity(variants.len() * 2);
+    for variant in variants {
+        parts.push(variant.lang.clone().into_bytes());
+        parts.push(tokio::fs::read(&variant.path).await?);
+    }
+    Ok(content_hash(parts.iter().map(Vec::as_slice)))
+}
@@ -0,0 +1,41 @@
+use crate::logs::Hit;
+use crate::manifest::Post;
+
+/// Where each post's pixel goes, next to its page.
+pub const PIXEL_PATH: &str = "pixel.gif";
+
+/// A transparent 1×1 GIF.
+pub const PIXEL_GIF: &[u8] = &[
+    0x47, 0x49, 0x46, 0x38, 0x39, 0x61, 0x01, 0x00, 0x01, 0x00, 0x80, 0x00, 0x00, 0x00, 0x00, 0x00,
+    0xff, 0xff, 0xff, 0x21, 0xf9, 0x04, 0x01, 0x00, 0x00, 0x00, 0x00, 0x2c, 0x00, 0x00, 0x00, 0x00,
+    0x01, 0x00, 0x01, 0x00, 0x00, 0x02, 0x02, 0x44, 0x01, 0x00, 0x3b,
+];
+
+/// How many times each post's pixel was loaded, most viewed first. Posts
+/// nobody loaded are left out. Bots rarely load images, and browsers cache
+/// the pixel, so these are rough counts of visits by people rather than of
+/// requests.
+pub fn count<'a>(posts: &'a [Post], hits: &[Hit]) -> Vec<(&'a Post, u64)> {
+    let mut views: Vec<(&Post, u64)> = posts
+        .iter()
+        .map(|post| {
+            let views = hits
+                .iter()
+                .filter(|hit| is_view(hit, post))
+                .map(|hit| hit.requests)
+                .sum();
+            (post, views)
+        })
+        .filter(|(_, views)| *views > 0)
+        .collect();
+    views.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.title.cmp(&b.0.title)));
+    views
+}
+
+/// Whether `hit` served the pixel of `post`.
+pub fn is_view(hit: &Hit, post: &Post) -> bool {
+    hit.served()
+        && hit
+            .path
+            .ends_with(&format!("/{}/{}", post.dir(), PIXEL_PATH))
+}
@@ -0,0 +1,81 @@
+use crate::config::WebhookConfig;
+use crate::manifest::Post;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+/// What happened to a post.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Event {
+    Published,
+    Updated,
+    Deleted,
+    /// Brought back from the trash.
+    Restored,
+}
+
+impl std::str::FromStr for Event {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "published" => Ok(Event::Published),
+            "updated" => Ok(Event::Updated),
+            "deleted" => Ok(Event::Deleted),
+            "restored" => Ok(Event::Restored),
+            _ => Err(format!(
+                "Unknown event '{}', expected published, updated, deleted or restored",
+                s
+            )),
+        }
+    }
+}
+
+/// POSTs a JSON payload about `post` to every webhook subscribed to `event`.
+///
+/// Besides the structured fields the payload carries a human-readable
+/// `content` (Discord) and `text` (Slack) line, so chat webhooks work as-is.
+/// Failures are reported but don't fail the command.
+pub async fn notify(
+    client: &reqwest::Client,
+    webhooks: &[WebhookConfig],
+    event: Event,
+    post: &Post,
+) {
+    let subscribed: Vec<&WebhookConfig> = webhooks
+        .iter()
+        .filter(|hook| hook.events.as_ref().is_none_or(|e| e.contains(&event)))
+        .collect();
+    if subscribed.is_empty() {
+        return;
+    }
+
+    let verb = match event {
+        Event::Published => "New post",
+        Event::Updated => "Updated post",
+        Event::Deleted => "Deleted post",
+        Event::Restored => "Restored post",
+    };
+    let message = format!("{}: {} {}", verb, post.title, post.url);
+    let payload = json!({
+        "event": event,
+        "title": post.title,
+        "url": post.url,
+        "slug": post.id,
+        "timestamp": chrono::Utc::now().to_rfc3339(),
+        "content": message,
+        "text": message,
+    });
+
+    for hook in subscribed {
+        let result = client
+            .post(&hook.url)
+            .json(&payload)
+            .send()
+            .await
+            .and_then(|response| response.error_for_status());
+        if let Err(e) = result {
+            eprintln!("Webhook {} failed: {}", hook.url, e);
+        }
+    }
+}
@@ -0,0 +1,116 @@
+use crate::manifest::Post;
+use crate::render::parse_attributes;
+use reqwest::{header::LINK, Client, Url};
+use std::error::Error;
+
+/// A link from one of our pages to somewhere else on the web.
+#[derive(Debug, Clone)]
+pub struct OutboundLink {
+    /// Public URL of our page containing the link.
+    pub source: String,
+    pub target: String,
+}
+
+/// Sends a webmention for each outbound link whose target hasn't been
+/// mentioned by `post` before, recording successful ones in the post so
+/// republishing doesn't send them again.
+pub async fn send_all(client: &Client, links: &[OutboundLink], post: &mut Post) {
+    for link in links {
+        if post.webmentions.contains(&link.target) {
+            continue;
+        }
+        match send(client, link).await {
+            Ok(Some(endpoint)) => {
+                eprintln!("Sent webmention to {} ({})", link.target, endpoint);
+                post.webmentions.push(link.target.clone());
+            }
+            Ok(None) => {}
+            Err(e) => eprintln!("Webmention for {} failed: {}", link.target, e),
+        }
+    }
+}
+
+/// Discovers the target's endpoint and notifies it. Returns the endpoint
+/// used, or `None` if the target doesn't accept webmentions.
+async fn send(client: &Client, link: &OutboundLink) -> Result<Option<Url>, Box<dyn Error>> {
+    let Some(endpoint) = discover_endpoint(client, &link.target).await? else {
+        return Ok(None);
+    };
+    client
+        .post(endpoint.clone())
+        .form(&[("source", &link.source), ("target", &link.target)])
+        .send()
+        .await?
+        .error_for_status()?;
+    Ok(Some(endpoint))
+}
+
+/// Looks for `rel="webmention"` in the `Link` headers, then in `<link>` and
+/// `<a>` elements of the target page.
+async fn discover_endpoint(client: &Client, target: &str) -> Result<Option<Url>, Box<dyn Error>> {
+    let response = client.get(target).send().await?.error_for_status()?;
+    let base = response.url().clone();
+
+    for header in response.headers().get_all(LINK) {
+        let Ok(header) = header.to_str() else {
+            continue;
+        };
+        for value in header.split(',') {
+            let Some((url_part, params)) = value.split_once(';') else {
+                continue;
+            };
+            if has_webmention_rel(params) {
+                let href = url_part
+                    .trim()
+                    .trim_start_matches('<')
+                    .trim_end_matches('>');
+                return Ok(Some(base.join(href)?));
+            }
+        }
+    }
+
+    let is_html = response
+        .headers()
+        .get(reqwest::header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| v.contains("html"));
+    if !is_html {
+        return Ok(None);
+    }
+
+    let body = response.text().await?;
+    for tag in body.split('<').skip(1) {
+        let tag = tag.split('>').next().unwrap_or("");
+        let lowercase = tag.to_ascii_lowercase();
+        if !(lowercase.starts_with("link ") || lowercase.starts_with("a ")) {
+            continue;
+        }
+        let attributes = parse_attributes(tag);
+        let rel = attributes.iter().find(|(name, _)| name == "rel");
+        let href = attributes.iter().find(|(name, _)| name == "href");
+        if let (Some((_, rel)), Some((_, href))) = (rel, href) {
+            if rel
+                .split_whitespace()
+                .any(|r| r.eq_ignore_ascii_case("webmention"))
+            {
+                return Ok(Some(base.join(href)?));
+            }
+        }
+    }
+    Ok(None)
+}
+
+/// Whether a `Link` header's parameters include `rel="webmention"`.
+fn has_webmention_rel(params: &str) -> bool {
+    params.split(';').any(|param| {
+        let Some((name, value)) = param.split_once('=') else {
+            return false;
+        };
+        name.trim().eq_ignore_ascii_case("rel")
+            && value
+                .trim()
+                .trim_matches('"')
+                .split_whitespace()
+                .any(|r| r.eq_ignore_ascii_case("webmention"))
+    })
+}
@@ -0,0 +1,166 @@
+//! Finding and loading the config file. These change the process's
+//! environment, so each takes [`ENV`] first.
+
+use klistra::config::{get_config_path, AppConfig};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    sync::{Mutex, MutexGuard},
+};
+
+static ENV: Mutex<()> = Mutex::new(());
+
+const TOML: &str =
+    "[s3]\ndomain = \"https://paste.example.com\"\nbucket = \"from-file\"\nprefix = \"\"\n";
+
+/// A fake home directory with its own config directory, removed afterwards.
+/// Holds [`ENV`] until then.
+struct Home {
+    dir: PathBuf,
+    _env: MutexGuard<'static, ()>,
+}
+
+impl Home {
+    fn new() -> Self {
+        let env = ENV.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        let dir = std::env::temp_dir().join(format!("klistra-home-{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(&dir).unwrap();
+        std::env::set_var("HOME", &dir);
+        std::env::set_var("XDG_CONFIG_HOME", dir.join("xdg"));
+        std::env::remove_var("KLISTRA_CONFIG");
+        Self { dir, _env: env }
+    }
+
+    fn write(&self, path: &str, content: &str) -> PathBuf {
+        let path = self.dir.join(path);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(&path, content).unwrap();
+        path
+    }
+
+    /// Where the config goes now.
+    fn config_dir(&self) -> PathBuf {
+        self.dir.join("xdg/klistra")
+    }
+
+    /// Where older versions kept it.
+    fn legacy_dir(&self) -> PathBuf {
+        self.dir.join(".config/klistra")
+    }
+}
+
+impl Drop for Home {
+    fn drop(&mut self) {
+        std::env::remove_var("KLISTRA_CONFIG");
+        let _ = fs::remove_dir_all(&self.dir);
+    }
+}
+
+#[test]
+fn the_flag_wins_then_the_variable_then_the_config_directory() {
+    let home = Home::new();
+    let yaml = home.write("xdg/klistra/config.yaml", "s3: {}\n");
+    assert_eq!(get_config_path(None).unwrap(), yaml);
+
+    let toml = home.write("xdg/klistra/config.toml", "[s3]\n");
+    assert_eq!(get_config_path(None).unwrap(), toml, "TOML comes first");
+
+    std::env::set_var("KLISTRA_CONFIG", home.dir.join("from-env.toml"));
+    assert_eq!(
+        get_config_path(None).unwrap(),
+        home.dir.join("from-env.toml")
+    );
+
+    let flag = PathBuf::from("/somewhere/else.toml");
+    assert_eq!(get_config_path(Some(flag.clone())).unwrap(), flag);
+}
+
+#[test]
+fn a_config_only_in_the_legacy_directory_is_moved() {
+    let home = Home::new();
+    home.write(".config/klistra/config.toml", "[s3]\nbucket = \"old\"\n");
+    home.write(".config/klistra/redirects.toml", "[redirects]\n");
+    home.write(".config/klistra/manifest.json", "{}");
+
+    let path = get_config_path(None).unwrap();
+    assert_eq!(path, home.config_dir().join("config.toml"));
+    assert_eq!(
+        fs::read_to_string(&path).unwrap(),
+        "[s3]\nbucket = \"old\"\n"
+    );
+    assert!(home.config_dir().join("redirects.toml").exists());
+    assert!(!home.legacy_dir().join("config.toml").exists());
+    assert!(!home.legacy_dir().join("redirects.toml").exists());
+    // Only the config and redirects move.
+    assert!(home.legacy_dir().join("manifest.json").exists());
+}
+
+#[test]
+fn a_config_in_both_places_is_left_where_it_is() {
+    let home = Home::new();
+    let new = home.write("xdg/klistra/config.toml", "new");
+    let old = home.write(".config/klistra/config.toml", "old");
+
+    assert_eq!(get_config_path(None).unwrap(), new);
+    assert_eq!(fs::read_to_string(&new).unwrap(), "new");
+    assert!(Path::new(&old).exists());
+}
+
+#[test]
+fn the_format_follows_the_extension() {
+    let home = Home::new();
+    let yaml = home.write(
+        "config.yaml",
+        "s3:\n  domain: https://paste.example.com\n  bucket: from-yaml\n  prefix: ''\n",
+    );
+    let json = home.write(
+        "config.json",
+        r#"{"s3": {"domain": "https://paste.example.com", "bucket": "from-json", "prefix": ""}}"#,
+    );
+    let toml = home.write("config.toml", TOML);
+    let bare = home.write("config", TOML);
+    assert_eq!(AppConfig::load(&yaml).unwrap().s3.bucket, "from-yaml");
+    assert_eq!(AppConfig::load(&json).unwrap().s3.bucket, "from-json");
+    assert_eq!(AppConfig::load(&toml).unwrap().s3.bucket, "from-file");
+    assert_eq!(AppConfig::load(&bare).unwrap().s3.bucket, "from-file");
+
+    let ini = home.write("config.ini", "[s3]\n");
+    let error = AppConfig::load(&ini).unwrap_err().to_string();
+    assert!(
+        error.contains("Unsupported config format '.ini'"),
+        "{}",
+        error
+    );
+}
+
+#[test]
+fn environment_variables_override_the_file() {
+    let home = Home::new();
+    let path = home.write("config.toml", TOML);
+    std::env::set_var("KLISTRA__S3__BUCKET", "from-env");
+    let loaded = AppConfig::load(&path).map(|config| config.s3.bucket);
+    std::env::remove_var("KLISTRA__S3__BUCKET");
+    assert_eq!(loaded.unwrap(), "from-env");
+    assert_eq!(AppConfig::load(&path).unwrap().s3.bucket, "from-file");
+}
+
+#[test]
+fn too_many_upload_labels_are_refused_when_loading() {
+    let home = Home::new();
+    let labels: String = (0..11).map(|n| format!("label{} = \"x\"\n", n)).collect();
+    let path = home.write(
+        "config.toml",
+        &format!("{}\n[upload.labels]\n{}", TOML, labels),
+    );
+    let error = AppConfig::load(&path).unwrap_err().to_string();
+    assert_eq!(error, "[upload] labels: At most 10 labels are allowed");
+
+    let path = home.write(
+        "config.toml",
+        &format!("{}\n[upload.labels]\nmanaged-by = \"klistra\"\n", TOML),
+    );
+    assert_eq!(
+        AppConfig::load(&path).unwrap().upload.labels["managed-by"],
+        "klistra"
+    );
+}
@@ -0,0 +1,148 @@
+//! Setting up, publishing, updating and deleting against a real MinIO server.
+//! Ignored by default; start one and run
+//!
+//! ```sh
+//! docker run -d -p 9000:9000 minio/minio server /data
+//! cargo test --test minio -- --ignored
+//! ```
+//!
+//! `KLISTRA_MINIO_ENDPOINT` (default `http://127.0.0.1:9000`),
+//! `KLISTRA_MINIO_ACCESS_KEY` and `KLISTRA_MINIO_SECRET_KEY` (both default
+//! `minioadmin`) point it elsewhere. Each run works under a new prefix in the
+//! `klistra-test` bucket and removes it afterwards.
+
+use klistra::config::AppConfig;
+use klistra::manifest::Manifest;
+use klistra::publisher::Publisher;
+use klistra::setup;
+use klistra::site::{Announce, Site};
+use klistra::upload::Bucket;
+use reqwest::{header, StatusCode};
+use std::{fs, path::PathBuf};
+
+const BUCKET: &str = "klistra-test";
+
+fn env_or(name: &str, default: &str) -> String {
+    std::env::var(name).unwrap_or_else(|_| default.to_string())
+}
+
+/// A config for the server in the environment, under a prefix of its own.
+fn config() -> AppConfig {
+    let endpoint = env_or("KLISTRA_MINIO_ENDPOINT", "http://127.0.0.1:9000");
+    let endpoint = endpoint.trim_end_matches('/');
+    let prefix = format!("run-{}", uuid::Uuid::new_v4());
+    toml::from_str(&format!(
+        r#"
+[s3]
+provider = "minio"
+endpoint = "{endpoint}"
+domain = "{endpoint}/{BUCKET}/{prefix}"
+bucket = "{BUCKET}"
+prefix = "{prefix}"
+access_key_id = "{}"
+secret_access_key = "{}"
+
+[index]
+enabled = true
+"#,
+        env_or("KLISTRA_MINIO_ACCESS_KEY", "minioadmin"),
+        env_or("KLISTRA_MINIO_SECRET_KEY", "minioadmin"),
+    ))
+    .unwrap()
+}
+
+/// Notes and the manifest, removed afterwards.
+struct Scratch(PathBuf);
+
+impl Scratch {
+    fn new() -> Self {
+        let dir = std::env::temp_dir().join(format!("klistra-minio-{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(&dir).unwrap();
+        Self(dir)
+    }
+
+    fn write(&self, name: &str, content: &str) -> PathBuf {
+        let path = self.0.join(name);
+        fs::write(&path, content).unwrap();
+        path
+    }
+}
+
+impl Drop for Scratch {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
+
+/// Fetches `url` without credentials, the way a reader would.
+async fn fetch(url: &str) -> (StatusCode, Option<String>, String) {
+    let response = reqwest::Client::new()
+        .get(url)
+        .header(header::ORIGIN, "https://elsewhere.example")
+        .send()
+        .await
+        .unwrap();
+    let status = response.status();
+    let allowed = response
+        .headers()
+        .get(header::ACCESS_CONTROL_ALLOW_ORIGIN)
+        .map(|value| value.to_str().unwrap().to_string());
+    (status, allowed, response.text().await.unwrap())
+}
+
+#[tokio::test]
+#[ignore = "needs a MinIO server, see the top of tests/minio.rs"]
+async fn setup_publish_update_and_delete_on_minio() {
+    let scratch = Scratch::new();
+    let config = config();
+    let bucket = Bucket::new(&config.s3, &config.tls).unwrap();
+
+    setup::setup_bucket(&config, Some(7)).await.unwrap();
+    assert!(bucket.exists().await.unwrap());
+    // setup-bucket only reports the steps that fail, so check them here.
+    bucket.allow_public_read().await.unwrap();
+    bucket.expire_after(setup::DRAFTS_PREFIX, 7).await.unwrap();
+
+    let domain = &config.s3.domain;
+    let (status, _, body) = fetch(&format!("{}/404.html", domain)).await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(body.contains(&config.page.not_found_title), "{}", body);
+
+    let site = Site::new(&config).unwrap();
+    let mut manifest = Manifest::default();
+    let manifest_path = scratch.0.join("manifest.json");
+    let source = scratch.write("post.md", "# On MinIO\n\nFirst version.\n");
+    let published = site.publish(&source, &mut manifest).await.unwrap();
+    site.finish_publish(&published, &mut manifest, &manifest_path, Announce::No)
+        .await
+        .unwrap();
+    let id = published.post.id.clone();
+    let url = format!("{}/p/{}/index.html", domain, id);
+
+    let (status, allowed, body) = fetch(&url).await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(body.contains("First version."), "{}", body);
+    assert!(allowed.is_some(), "no CORS header for another origin");
+    let (status, _, index) = fetch(&format!("{}/index.html", domain)).await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(index.contains("On MinIO"), "{}", index);
+
+    scratch.write("post.md", "# On MinIO\n\nSecond version.\n");
+    let updated = site.update(&id, &mut manifest).await.unwrap();
+    assert_eq!(updated.post.id, id);
+    let (status, _, body) = fetch(&url).await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(body.contains("Second version."), "{}", body);
+    assert!(!body.contains("First version."), "{}", body);
+
+    site.delete(&id, &mut manifest).await.unwrap().unwrap();
+    let (status, _, _) = fetch(&url).await;
+    assert!(status.is_client_error(), "{} after deleting", status);
+    let trashed = format!("trash/p/{}/index.html", id);
+    assert!(bucket.list("trash/").await.unwrap().contains(&trashed));
+    let (status, _, _) = fetch(&format!("{}/{}", domain, trashed)).await;
+    assert_eq!(status, StatusCode::FORBIDDEN, "trash/ is readable");
+
+    bucket.delete_prefix("").await.unwrap();
+    assert!(bucket.list("").await.unwrap().is_empty());
+}
@@ -0,0 +1,52 @@
+//! Stopping on SIGTERM. The stop is process-wide, so it gets a test binary
+//! of its own rather than stopping everything in `tests/publish.rs`.
+#![cfg(unix)]
+
+use klistra::publisher::{MemoryPublisher, Publisher};
+use klistra::{resume, shutdown};
+use std::{fs, time::Duration};
+
+#[tokio::test]
+async fn an_upload_stopped_by_a_signal_is_aborted_and_forgotten() {
+    let dir = std::env::temp_dir().join(format!("klistra-shutdown-{}", uuid::Uuid::new_v4()));
+    fs::create_dir_all(&dir).unwrap();
+    // Where the upload state goes.
+    std::env::set_var("XDG_DATA_HOME", dir.join("data"));
+    let file = dir.join("big.bin");
+    fs::write(&file, vec![7u8; 1024]).unwrap();
+
+    shutdown::listen();
+    // Let the listener install its handlers before the signal arrives.
+    tokio::time::sleep(Duration::from_millis(200)).await;
+    let sent = std::process::Command::new("kill")
+        .args(["-TERM", &std::process::id().to_string()])
+        .status()
+        .unwrap();
+    assert!(sent.success());
+    for _ in 0..100 {
+        if shutdown::requested() {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    assert!(shutdown::requested());
+
+    let memory = MemoryPublisher::new("https://paste.example.com");
+    let error = resume::upload(&memory, &file, "p/x/big.bin", "application/octet-stream")
+        .await
+        .unwrap_err()
+        .to_string();
+    assert!(error.starts_with("Stopped uploading"), "{}", error);
+
+    // The multipart upload was started, then aborted.
+    let part = memory
+        .upload_part("p/x/big.bin", "memory-1", 1, b"late".to_vec())
+        .await;
+    assert!(part.is_err(), "the upload is still open");
+    assert!(resume::pending(&file).await.unwrap().is_none());
+    let uploads = dir.join("data/klistra/uploads");
+    assert_eq!(fs::read_dir(uploads).unwrap().count(), 0);
+    assert!(memory.paths().is_empty());
+
+    let _ = fs::remove_dir_all(&dir);
+}
@@ -0,0 +1,154 @@
+//! Rendered pages for every fixture in `tests/fixtures`, so changes to the
+//! templates or parsers show up as snapshot diffs. Review them with
+//! `cargo insta review` (or `INSTA_UPDATE=always cargo test`).
+
+use klistra::config::{FootnoteStyle, MarkdownBackend, PageConfig, SiteConfig};
+use klistra::render_to_string;
+use std::{fs, path::Path};
+
+fn fixtures() -> Vec<(String, String)> {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures");
+    let mut fixtures: Vec<(String, String)> = fs::read_dir(&dir)
+        .expect("tests/fixtures exists")
+        .map(|entry| entry.expect("fixture entry").path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "md"))
+        .map(|path| {
+            let name = path.file_stem().unwrap().to_string_lossy().into_owned();
+            (name, fs::read_to_string(&path).expect("fixture is UTF-8"))
+        })
+        .collect();
+    fixtures.sort();
+    fixtures
+}
+
+fn snapshot_all(backend: MarkdownBackend, suffix: &str) {
+    let page = PageConfig {
+        parser: backend,
+        reproducible: true,
+        ..PageConfig::default()
+    };
+    let site = SiteConfig::default();
+    let fixtures = fixtures();
+    assert!(!fixtures.is_empty(), "no fixtures in tests/fixtures");
+    for (name, markdown) in fixtures {
+        let html = render_to_string(&markdown, &page, &site)
+            .unwrap_or_else(|e| panic!("{} failed to render: {}", name, e));
+        insta::assert_snapshot!(format!("{}_{}", name, suffix), html);
+    }
+}
+
+#[test]
+fn pulldown() {
+    snapshot_all(MarkdownBackend::Pulldown, "pulldown");
+}
+
+#[test]
+fn comrak() {
+    snapshot_all(MarkdownBackend::Comrak, "comrak");
+}
+
+#[test]
+fn render_is_deterministic() {
+    let page = PageConfig {
+        reproducible: true,
+        ..PageConfig::default()
+    };
+    let site = SiteConfig::default();
+    for (name, markdown) in fixtures() {
+        let first = render_to_string(&markdown, &page, &site).unwrap();
+        let second = render_to_string(&markdown, &page, &site).unwrap();
+        assert_eq!(first, second, "{} renders differently twice", name);
+    }
+}
+
+#[test]
+fn front_matter_picks_a_template() {
+    let page = PageConfig {
+        reproducible: true,
+        templates_dir: Some(Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/templates")),
+        ..PageConfig::default()
+    };
+    let site = SiteConfig::default();
+    let markdown = "---\ntemplate: essay.html\ntitle: Slow <mornings>\nauthors: [Ann, Bo]\n---\n\nCoffee *first*.\n";
+    let html = render_to_string(markdown, &page, &site).unwrap();
+    insta::assert_snapshot!("template_essay", html);
+
+    let missing = "---\ntemplate: missing.html\n---\n\nText.\n";
+    assert!(render_to_string(missing, &page, &site).is_err());
+}
+
+#[test]
+fn templates_can_branch_loop_include_and_extend() {
+    let page = PageConfig {
+        reproducible: true,
+        templates_dir: Some(Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/templates")),
+        ..PageConfig::default()
+    };
+    let site = SiteConfig::default();
+    let markdown = "---\ntemplate: note.html\ntitle: Field <notes>\ndescription: From the shore.\nauthors: [Ann, Bo]\n---\n\nGulls *everywhere*.\n";
+    let html = render_to_string(markdown, &page, &site).unwrap();
+    insta::assert_snapshot!("template_note", html);
+
+    let unknown = "---\ntemplate: unknown.html\n---\n\nText.\n";
+    let error = render_to_string(unknown, &page, &site)
+        .unwrap_err()
+        .to_string();
+    assert!(error.contains("undefined value"), "{}", error);
+    assert!(error.contains("Available: content, authors"), "{}", error);
+}
+
+#[test]
+fn task_lists_are_interactive_only_when_enabled() {
+    let markdown = "- [x] done\n- [ ] to do\n";
+    let site = SiteConfig::default();
+    for parser in [MarkdownBackend::Pulldown, MarkdownBackend::Comrak] {
+        let mut page = PageConfig {
+            parser,
+            reproducible: true,
+            ..PageConfig::default()
+        };
+        let html = render_to_string(markdown, &page, &site).unwrap();
+        assert!(html.contains("disabled"));
+        assert!(!html.contains("localStorage"));
+
+        page.interactive_task_lists = true;
+        let html = render_to_string(markdown, &page, &site).unwrap();
+        assert!(html.contains("localStorage"));
+        let plain = render_to_string("No tasks here.\n", &page, &site).unwrap();
+        assert!(!plain.contains("localStorage"));
+    }
+}
+
+#[test]
+fn sidenotes_replace_footnotes_with_every_parser() {
+    let markdown = "A claim.[^a] The same again.[^a]\n\n[^a]: Source *here*.\n";
+    let site = SiteConfig::default();
+    for parser in [MarkdownBackend::Pulldown, MarkdownBackend::Comrak] {
+        let page = PageConfig {
+            parser,
+            footnotes: FootnoteStyle::Sidenotes,
+            reproducible: true,
+            ..PageConfig::default()
+        };
+        let html = render_to_string(markdown, &page, &site).unwrap();
+        assert_eq!(html.matches("<span class=\"sidenote\">").count(), 1);
+        assert_eq!(html.matches("for=\"sidenote-1\"").count(), 2);
+        assert!(html.contains("Source <em>here</em>."));
+        assert!(!html.contains("class=\"footnotes\""));
+        assert!(!html.contains("[^a]"));
+    }
+}
+
+#[test]
+fn a_bad_date_format_is_an_error_not_a_panic() {
+    let site = SiteConfig::default();
+    for locale in [None, Some("sv_SE".to_string())] {
+        let page = PageConfig {
+            date_format: "%B %Q".to_string(),
+            locale,
+            ..PageConfig::default()
+        };
+        let error = render_to_string("# Dated\n", &page, &site).unwrap_err();
+        assert!(error.to_string().contains("Invalid date format '%B %Q'"));
+    }
+}